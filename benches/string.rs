@@ -0,0 +1,48 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Measures converting Rust strings to `JSString`s.
+//!
+//! Run with `cargo bench --bench string`. Strings that are longer than
+//! the 64 bytes that the cache of names keeps are converted each time,
+//! so these measure the conversion itself: short ASCII strings are
+//! widened to UTF-16 on the stack, while the others are copied to a
+//! `CString` and decoded from UTF-8 by JavaScriptCore.
+
+use javascriptcore::JSString;
+use std::hint::black_box;
+use std::time::Instant;
+
+const ITERATIONS: u32 = 1_000_000;
+
+fn bench(name: &str, s: &str) {
+    for _ in 0..ITERATIONS / 10 {
+        black_box(JSString::from(black_box(s)));
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(JSString::from(black_box(s)));
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<24} {:>8.1} ns/iter",
+        name,
+        elapsed.as_nanos() as f64 / f64::from(ITERATIONS)
+    );
+}
+
+fn main() {
+    for len in [65, 100, 128] {
+        let ascii = "a".repeat(len);
+        // The same length in bytes, with one character that is not
+        // ASCII, so that it takes the UTF-8 path.
+        let utf8 = format!("é{}", "a".repeat(len - 2));
+        bench(&format!("ascii, {} bytes", len), &ascii);
+        bench(&format!("utf-8, {} bytes", len), &utf8);
+    }
+    // Too long for the ASCII path, for comparison.
+    bench("ascii, 256 bytes", &"a".repeat(256));
+}
@@ -0,0 +1,286 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::function::{arguments, check_signature, ts_result, ts_type, Arguments};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Attribute, Data, DeriveInput, Fields, FnArg, ImplItem, ImplItemFn, ItemImpl, LitStr};
+
+/// Expands `#[derive(JsClass)]`.
+pub(crate) fn derive(input: DeriveInput) -> syn::Result<TokenStream> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "JsClass can not be derived for generic types",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "JsClass can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "JsClass can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut class_name = input.ident.to_string();
+    for attr in js_attributes(&input.attrs) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                class_name = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("unknown js attribute for a struct"))
+            }
+        })?;
+    }
+
+    let mut properties = vec![];
+    let mut declarations = vec![];
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named field");
+        let mut name = ident.to_string();
+        let mut skip = false;
+        let mut read_only = false;
+        for attr in js_attributes(&field.attrs) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    skip = true;
+                } else if meta.path.is_ident("readonly") {
+                    read_only = true;
+                } else if meta.path.is_ident("rename") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                } else {
+                    return Err(meta.error("unknown js attribute for a field"));
+                }
+                Ok(())
+            })?;
+        }
+        if skip {
+            continue;
+        }
+        let ty = &field.ty;
+        let ts_type = ts_type(&quote!(#ty));
+        declarations.push(if read_only {
+            quote!(.readonly_property(#name, &#ts_type))
+        } else {
+            quote!(.property(#name, &#ts_type))
+        });
+        let getter = quote! {
+            |ctx, object| ::javascriptcore::__private::get_field::<Self, _>(
+                ctx,
+                object,
+                |this| &this.#ident,
+            )
+        };
+        properties.push(if read_only {
+            quote! {
+                .static_value_read_only(
+                    #name,
+                    #getter,
+                    ::javascriptcore::PropertyAttributes::DONT_DELETE,
+                )
+            }
+        } else {
+            quote! {
+                .static_value(
+                    #name,
+                    #getter,
+                    |ctx, object, value| ::javascriptcore::__private::set_field::<Self, _>(
+                        ctx,
+                        object,
+                        value,
+                        |this| &mut this.#ident,
+                    ),
+                    ::javascriptcore::PropertyAttributes::DONT_DELETE,
+                )
+            }
+        });
+    }
+
+    let ident = &input.ident;
+    Ok(quote! {
+        impl ::javascriptcore::JsClass for #ident {
+            const NAME: &'static str = #class_name;
+
+            fn define(
+                builder: ::javascriptcore::JSClassBuilder,
+            ) -> ::javascriptcore::JSClassBuilder {
+                // Types without `#[js_methods]` have no methods of their
+                // own, and get them from this trait instead.
+                #[allow(unused_imports)]
+                use ::javascriptcore::__private::NoMethods as _;
+                let builder = builder #(#properties)*;
+                Self::__js_methods(builder)
+            }
+
+            fn declare(
+                declaration: ::javascriptcore::ClassDeclaration,
+            ) -> ::javascriptcore::ClassDeclaration {
+                #[allow(unused_imports)]
+                use ::javascriptcore::__private::NoMethods as _;
+                let declaration = declaration #(#declarations)*;
+                Self::__js_method_declarations(declaration)
+            }
+        }
+
+        impl ::javascriptcore::TypeScript for #ident {
+            fn ts_type() -> ::std::string::String {
+                ::std::string::String::from(#class_name)
+            }
+        }
+    })
+}
+
+/// Expands `#[js_methods]`.
+pub(crate) fn methods(mut input: ItemImpl) -> syn::Result<TokenStream> {
+    if let Some((_, path, _)) = &input.trait_ {
+        return Err(syn::Error::new_spanned(
+            path,
+            "js_methods can not be used on trait implementations",
+        ));
+    }
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "js_methods can not be used on generic implementations",
+        ));
+    }
+    let mut functions = vec![];
+    let mut declarations = vec![];
+    for item in &mut input.items {
+        if let ImplItem::Fn(function) = item {
+            let mut name = None;
+            let mut marked = false;
+            let mut error = None;
+            function.attrs.retain(|attr| {
+                if !attr.path().is_ident("js_method") {
+                    return true;
+                }
+                marked = true;
+                if !matches!(attr.meta, syn::Meta::Path(_)) {
+                    let r = attr.parse_nested_meta(|meta| {
+                        if meta.path.is_ident("name") {
+                            name = Some(meta.value()?.parse::<LitStr>()?.value());
+                            Ok(())
+                        } else {
+                            Err(meta.error("unknown js_method attribute"))
+                        }
+                    });
+                    if let Err(e) = r {
+                        error = Some(e);
+                    }
+                }
+                false
+            });
+            if let Some(e) = error {
+                return Err(e);
+            }
+            if marked {
+                let name = name.unwrap_or_else(|| function.sig.ident.to_string());
+                let (function, declaration) = method(function, &name)?;
+                functions.push(function);
+                declarations.push(declaration);
+            }
+        }
+    }
+
+    let self_ty = &input.self_ty;
+    Ok(quote! {
+        #input
+
+        impl #self_ty {
+            #[doc(hidden)]
+            pub fn __js_methods(
+                builder: ::javascriptcore::JSClassBuilder,
+            ) -> ::javascriptcore::JSClassBuilder {
+                builder #(#functions)*
+            }
+
+            #[doc(hidden)]
+            pub fn __js_method_declarations(
+                declaration: ::javascriptcore::ClassDeclaration,
+            ) -> ::javascriptcore::ClassDeclaration {
+                declaration #(#declarations)*
+            }
+        }
+    })
+}
+
+/// Generates the static function that calls a `#[js_method]`, and the
+/// TypeScript declaration of the method.
+fn method(function: &mut ImplItemFn, name: &str) -> syn::Result<(TokenStream, TokenStream)> {
+    let sig = &mut function.sig;
+    check_signature(sig, "js_method")?;
+    let borrow = match sig.receiver() {
+        Some(receiver) if receiver.reference.is_some() => {
+            if receiver.mutability.is_some() {
+                quote!(let mut this = ::javascriptcore::__private::borrow_mut(ctx, this)?;)
+            } else {
+                quote!(let this = ::javascriptcore::__private::borrow(ctx, this)?;)
+            }
+        }
+        _ => {
+            return Err(syn::Error::new_spanned(
+                sig,
+                "js_method functions must take `&self` or `&mut self`",
+            ))
+        }
+    };
+    let this = if sig.receiver().and_then(|r| r.mutability).is_some() {
+        quote!(&mut *this)
+    } else {
+        quote!(&*this)
+    };
+
+    let inputs = sig.inputs.iter_mut().filter_map(|input| match input {
+        FnArg::Typed(input) => Some(input),
+        FnArg::Receiver(_) => None,
+    });
+    let Arguments {
+        conversion,
+        values,
+        parameters,
+    } = arguments(inputs, "js_method")?;
+
+    let ident = &sig.ident;
+    let result = ts_result(&sig.output);
+    let declaration = quote! {
+        .method(::javascriptcore::__private::function_declaration(
+            #name,
+            ::std::vec![#(#parameters),*],
+            #result,
+        ))
+    };
+    let function = quote! {
+        .static_function(
+            #name,
+            |ctx, this, arguments| {
+                let this = ::javascriptcore::__private::this::<Self>(ctx, this)?;
+                #conversion
+                #borrow
+                let result = Self::#ident(#this, #(#values),*);
+                ::javascriptcore::IntoJsResult::into_js_result(result, ctx)
+            },
+            ::javascriptcore::PropertyAttributes::DONT_ENUM,
+        )
+    };
+    Ok((function, declaration))
+}
+
+/// Gets the `#[js(...)]` attributes.
+pub(crate) fn js_attributes(attrs: &[Attribute]) -> impl Iterator<Item = &Attribute> {
+    attrs.iter().filter(|attr| attr.path().is_ident("js"))
+}
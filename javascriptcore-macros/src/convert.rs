@@ -0,0 +1,488 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::class::js_attributes;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{
+    parse_quote, Attribute, Data, DataEnum, DeriveInput, Expr, Fields, Generics, Ident, LitStr,
+    Member, Token, Type, Variant,
+};
+
+/// How a struct, or the fields of an enum variant, is represented in
+/// JavaScript.
+enum Shape {
+    /// An object with a property for each field.
+    Object,
+    /// The value of the only field.
+    Newtype,
+    /// An array with an element for each field.
+    Array,
+}
+
+/// A field of a struct or an enum variant that is converted.
+struct Field<'a> {
+    member: Member,
+    ty: &'a Type,
+    /// A reference to the value of the field, when converting it to
+    /// JavaScript.
+    binding: TokenStream,
+    /// The name of the property of the field.
+    name: String,
+    /// The value of the field when the property is missing, `undefined`
+    /// or `null`.
+    default: Option<TokenStream>,
+    skip: bool,
+    /// The `OptionPolicy` of the field, if it has one.
+    policy: Option<TokenStream>,
+}
+
+impl Field<'_> {
+    /// Converts the field to a value, or fails with an exception.
+    fn convert_to_js(&self) -> TokenStream {
+        let binding = &self.binding;
+        match &self.policy {
+            Some(policy) => quote!(#policy.to_js(ctx, #binding)),
+            None => quote!(::javascriptcore::ToJs::to_js(#binding, ctx)),
+        }
+    }
+
+    /// Converts the field from `value`, or from its property of
+    /// `object`.
+    fn convert_from_js(&self, shape: &Shape) -> TokenStream {
+        let Field {
+            ty,
+            name,
+            default,
+            policy,
+            ..
+        } = self;
+        if self.skip {
+            return quote!(::std::default::Default::default());
+        }
+        if let Shape::Newtype = shape {
+            return match (default, policy) {
+                (Some(default), _) => quote! {
+                    ::javascriptcore::__private::from_js_or::<#ty>(ctx, value, || #default)?
+                },
+                (None, Some(policy)) => quote!(#policy.from_js(ctx, value)?),
+                (None, None) => quote!(<#ty as ::javascriptcore::FromJs>::from_js(ctx, value)?),
+            };
+        }
+        match (default, policy) {
+            (Some(default), _) => quote! {
+                ::javascriptcore::__private::get_property_or::<#ty>(ctx, &object, #name, || #default)?
+            },
+            (None, Some(policy)) => quote! {
+                ::javascriptcore::__private::get_property_with(ctx, &object, #name, |ctx, value| {
+                    #policy.from_js(ctx, value)
+                })?
+            },
+            (None, None) => quote! {
+                ::javascriptcore::__private::get_property::<#ty>(ctx, &object, #name)?
+            },
+        }
+    }
+}
+
+/// Expands `#[derive(ToJs)]`.
+pub(crate) fn derive_to_js(input: DeriveInput) -> syn::Result<TokenStream> {
+    let body = match &input.data {
+        Data::Struct(data) => {
+            struct_attributes(&input.attrs)?;
+            let shape = shape(&data.fields).ok_or_else(|| unit_struct(&input, "ToJs"))?;
+            let fields = fields(&data.fields, |member| quote!(&self.#member))?;
+            to_js(&shape, &fields, quote!())
+        }
+        Data::Enum(data) => enum_to_js(&input, data)?,
+        Data::Union(_) => return Err(union(&input, "ToJs")),
+    };
+
+    let ident = &input.ident;
+    let generics = bounded(&input.generics, quote!(::javascriptcore::ToJs));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::javascriptcore::ToJs for #ident #ty_generics #where_clause {
+            fn to_js(
+                &self,
+                ctx: &::javascriptcore::JSContext,
+            ) -> ::std::result::Result<::javascriptcore::JSValue, ::javascriptcore::JSException> {
+                #body
+            }
+        }
+    })
+}
+
+/// Expands `#[derive(FromJs)]`.
+pub(crate) fn derive_from_js(input: DeriveInput) -> syn::Result<TokenStream> {
+    let body = match &input.data {
+        Data::Struct(data) => {
+            struct_attributes(&input.attrs)?;
+            let shape = shape(&data.fields).ok_or_else(|| unit_struct(&input, "FromJs"))?;
+            let fields = fields(&data.fields, |_| quote!())?;
+            from_js(&shape, &fields, quote!(Self))
+        }
+        Data::Enum(data) => enum_from_js(&input, data)?,
+        Data::Union(_) => return Err(union(&input, "FromJs")),
+    };
+
+    let ident = &input.ident;
+    let generics = bounded(&input.generics, quote!(::javascriptcore::FromJs));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::javascriptcore::FromJs for #ident #ty_generics #where_clause {
+            fn from_js(
+                ctx: &::javascriptcore::JSContext,
+                value: &::javascriptcore::JSValue,
+            ) -> ::std::result::Result<Self, ::javascriptcore::JSException> {
+                #body
+            }
+        }
+    })
+}
+
+/// Converts `fields` to a value of the given shape. `tag` runs before
+/// the properties of an `Object` are set on its `object`.
+fn to_js(shape: &Shape, fields: &[Field], tag: TokenStream) -> TokenStream {
+    match shape {
+        Shape::Object => {
+            let properties = fields.iter().filter(|field| !field.skip).map(|field| {
+                let name = &field.name;
+                let value = field.convert_to_js();
+                quote! {
+                    ::javascriptcore::__private::set_property(ctx, &object, #name, &#value?)?;
+                }
+            });
+            quote! {
+                let object = ::javascriptcore::JSObject::new(ctx);
+                #tag
+                #(#properties)*
+                ::std::result::Result::Ok(::std::convert::From::from(object))
+            }
+        }
+        Shape::Newtype => fields[0].convert_to_js(),
+        Shape::Array => {
+            let elements = fields.iter().map(Field::convert_to_js);
+            quote! {
+                let elements = [#(#elements?),*];
+                ::javascriptcore::__private::new_array(ctx, &elements)
+            }
+        }
+    }
+}
+
+/// Converts `value`, of the given shape, to `path`, which is a struct
+/// or an enum variant with `fields`.
+fn from_js(shape: &Shape, fields: &[Field], path: TokenStream) -> TokenStream {
+    let values = fields.iter().map(|field| field.convert_from_js(shape));
+    let members = fields.iter().map(|field| &field.member);
+    let object = match shape {
+        Shape::Newtype => quote!(),
+        Shape::Object | Shape::Array => quote! {
+            let object = ::javascriptcore::__private::object(ctx, value)?;
+        },
+    };
+    quote! {
+        #object
+        ::std::result::Result::Ok(#path {
+            #(#members: #values,)*
+        })
+    }
+}
+
+/// Expands `#[derive(ToJs)]` for an enum.
+fn enum_to_js(input: &DeriveInput, data: &DataEnum) -> syn::Result<TokenStream> {
+    let tag = enum_tag(&input.attrs)?;
+    let mut arms = vec![];
+    for variant in &data.variants {
+        let ident = &variant.ident;
+        let name = variant_name(variant)?;
+        let fields = fields(&variant.fields, |member| {
+            let binding = binding(member);
+            quote!(#binding)
+        })?;
+        let bound = fields.iter().filter(|field| !field.skip);
+        let members = bound.clone().map(|field| &field.member);
+        let bindings = bound.map(|field| binding(&field.member));
+        let pattern = match &variant.fields {
+            Fields::Unit => quote!(Self::#ident),
+            _ => quote!(Self::#ident { #(#members: #bindings,)* .. }),
+        };
+        let body = match (shape(&variant.fields), &tag) {
+            (None, None) => quote!(::javascriptcore::ToJs::to_js(#name, ctx)),
+            (Some(shape), None) => {
+                let value = to_js(&shape, &fields, quote!());
+                quote!(::javascriptcore::__private::variant_object(ctx, #name, { #value }?))
+            }
+            (None, Some(tag)) => to_js(&Shape::Object, &[], tag_property(tag, &name)),
+            (Some(Shape::Object), Some(tag)) => {
+                to_js(&Shape::Object, &fields, tag_property(tag, &name))
+            }
+            (Some(Shape::Newtype), Some(tag)) => {
+                let value = fields[0].convert_to_js();
+                quote!(::javascriptcore::__private::tagged(ctx, #value?, #tag, #name))
+            }
+            (Some(Shape::Array), Some(_)) => return Err(tagged_tuple(variant)),
+        };
+        arms.push(quote!(#pattern => { #body }));
+    }
+    Ok(quote! {
+        match self {
+            #(#arms)*
+        }
+    })
+}
+
+/// Expands `#[derive(FromJs)]` for an enum.
+fn enum_from_js(input: &DeriveInput, data: &DataEnum) -> syn::Result<TokenStream> {
+    let tag = enum_tag(&input.attrs)?;
+    let mut units = vec![];
+    let mut others = vec![];
+    let mut arms = vec![];
+    for variant in &data.variants {
+        let ident = &variant.ident;
+        let name = variant_name(variant)?;
+        let fields = fields(&variant.fields, |_| quote!())?;
+        let shape = shape(&variant.fields);
+        let body = match &shape {
+            None => quote!(::std::result::Result::Ok(Self::#ident)),
+            Some(shape) => from_js(shape, &fields, quote!(Self::#ident)),
+        };
+        arms.push(match (&shape, &tag) {
+            (None, None) => quote!((#name, ::std::option::Option::None) => { #body }),
+            (Some(_), None) => quote! {
+                (#name, ::std::option::Option::Some(value)) => {
+                    let value = &value;
+                    #body
+                }
+            },
+            (Some(Shape::Array), Some(_)) => return Err(tagged_tuple(variant)),
+            (_, Some(_)) => quote!(#name => { #body }),
+        });
+        match shape {
+            None => units.push(name),
+            Some(_) => others.push(name),
+        }
+    }
+
+    Ok(match tag {
+        None => {
+            let units_only = others.is_empty();
+            quote! {
+                let (variant, value) =
+                    ::javascriptcore::__private::external_variant(ctx, value, #units_only)?;
+                match (variant.as_str(), value) {
+                    #(#arms)*
+                    (variant, _) => ::std::result::Result::Err(
+                        ::javascriptcore::__private::unknown_variant(
+                            ctx,
+                            variant,
+                            &[#(#units),*],
+                            &[#(#others),*],
+                        ),
+                    ),
+                }
+            }
+        }
+        Some(tag) => {
+            let names = units.iter().chain(&others);
+            quote! {
+                let object = ::javascriptcore::__private::object(ctx, value)?;
+                let variant: ::std::string::String =
+                    ::javascriptcore::__private::get_property(ctx, &object, #tag)?;
+                match variant.as_str() {
+                    #(#arms)*
+                    variant => ::std::result::Result::Err(
+                        ::javascriptcore::__private::unknown_variant(
+                            ctx,
+                            variant,
+                            &[],
+                            &[#(#names),*],
+                        ),
+                    ),
+                }
+            }
+        }
+    })
+}
+
+/// Gets how the fields of a struct or a variant are represented, or
+/// `None` if there are no fields.
+fn shape(fields: &Fields) -> Option<Shape> {
+    match fields {
+        Fields::Named(_) => Some(Shape::Object),
+        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => Some(Shape::Newtype),
+        Fields::Unnamed(_) => Some(Shape::Array),
+        Fields::Unit => None,
+    }
+}
+
+/// Checks the `#[js(...)]` attributes of a struct.
+///
+/// The attributes of `#[derive(JsClass)]` are accepted and ignored, so
+/// that a struct can derive both.
+fn struct_attributes(attrs: &[Attribute]) -> syn::Result<()> {
+    for attr in js_attributes(attrs) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                meta.value()?.parse::<LitStr>()?;
+                Ok(())
+            } else {
+                Err(meta.error("unknown js attribute for a struct"))
+            }
+        })?;
+    }
+    Ok(())
+}
+
+/// Gets the name of the property that holds the variant of an enum,
+/// from `#[js(tag = "...")]`, if it has one.
+fn enum_tag(attrs: &[Attribute]) -> syn::Result<Option<String>> {
+    let mut tag = None;
+    for attr in js_attributes(attrs) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("tag") {
+                tag = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("unknown js attribute for an enum"))
+            }
+        })?;
+    }
+    Ok(tag)
+}
+
+/// Gets the name of a variant, which `#[js(rename = "...")]` changes.
+fn variant_name(variant: &Variant) -> syn::Result<String> {
+    let mut name = variant.ident.unraw().to_string();
+    for attr in js_attributes(&variant.attrs) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                name = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("unknown js attribute for a variant"))
+            }
+        })?;
+    }
+    Ok(name)
+}
+
+/// Sets the property that holds the variant on `object`.
+fn tag_property(tag: &str, name: &str) -> TokenStream {
+    quote! {
+        ::javascriptcore::__private::set_property(ctx, &object, #tag, #name)?;
+    }
+}
+
+/// Gets the variable that a field of a variant is bound to.
+fn binding(member: &Member) -> Ident {
+    match member {
+        Member::Named(ident) => format_ident!("__field_{}", ident.unraw()),
+        Member::Unnamed(index) => format_ident!("__field_{}", index.index),
+    }
+}
+
+fn union(input: &DeriveInput, derive: &str) -> syn::Error {
+    syn::Error::new_spanned(
+        &input.ident,
+        format!("{} can only be derived for structs and enums", derive),
+    )
+}
+
+fn unit_struct(input: &DeriveInput, derive: &str) -> syn::Error {
+    syn::Error::new_spanned(
+        &input.ident,
+        format!("{} can not be derived for unit structs", derive),
+    )
+}
+
+fn tagged_tuple(variant: &Variant) -> syn::Error {
+    syn::Error::new_spanned(
+        variant,
+        "tuple variants with more than one field can not be used with tag",
+    )
+}
+
+/// Gets `fields`, with their `#[js(...)]` attributes. `binding` makes a
+/// reference to the value of a field, from its member.
+fn fields<'a>(
+    fields: &'a Fields,
+    binding: impl Fn(&Member) -> TokenStream,
+) -> syn::Result<Vec<Field<'a>>> {
+    let mut result = vec![];
+    for (index, field) in fields.iter().enumerate() {
+        let (member, mut name) = match &field.ident {
+            Some(ident) => (Member::Named(ident.clone()), ident.unraw().to_string()),
+            None => (Member::Unnamed(index.into()), index.to_string()),
+        };
+        let mut default = None;
+        let mut skip = false;
+        let mut none_as_null = false;
+        let mut reject_undefined = false;
+        for attr in js_attributes(&field.attrs) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("none_as_null") {
+                    none_as_null = true;
+                } else if meta.path.is_ident("reject_undefined") {
+                    reject_undefined = true;
+                } else if meta.path.is_ident("default") {
+                    default = Some(if meta.input.peek(Token![=]) {
+                        let expr: Expr = meta.value()?.parse()?;
+                        quote!(#expr)
+                    } else {
+                        quote!(::std::default::Default::default())
+                    });
+                } else if meta.path.is_ident("readonly") {
+                    // Only used by `#[derive(JsClass)]`.
+                } else if field.ident.is_none() {
+                    return Err(meta.error("unknown js attribute for a field of a tuple struct"));
+                } else if meta.path.is_ident("rename") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                } else if meta.path.is_ident("skip") {
+                    skip = true;
+                } else {
+                    return Err(meta.error("unknown js attribute for a field"));
+                }
+                Ok(())
+            })?;
+        }
+        if reject_undefined && default.is_some() {
+            return Err(syn::Error::new_spanned(
+                field,
+                "reject_undefined can not be used with default",
+            ));
+        }
+        let policy = (none_as_null || reject_undefined).then(|| {
+            quote! {
+                ::javascriptcore::OptionPolicy {
+                    none_as_null: #none_as_null,
+                    reject_undefined: #reject_undefined,
+                }
+            }
+        });
+        result.push(Field {
+            binding: binding(&member),
+            member,
+            ty: &field.ty,
+            name,
+            default,
+            skip,
+            policy,
+        });
+    }
+    Ok(result)
+}
+
+/// Adds `bound` to the type parameters of `generics`.
+fn bounded(generics: &Generics, bound: TokenStream) -> Generics {
+    let mut generics = generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#bound));
+    }
+    generics
+}
@@ -0,0 +1,259 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{Expr, FnArg, ItemFn, Pat, PatType, ReturnType, Signature, Token, Type};
+
+/// Expands `#[js_function]`.
+pub(crate) fn function(mut input: ItemFn) -> syn::Result<TokenStream> {
+    let sig = &mut input.sig;
+    check_signature(sig, "js_function")?;
+    if let Some(receiver) = sig.receiver() {
+        return Err(syn::Error::new_spanned(
+            receiver,
+            "js_function functions can not take `self`",
+        ));
+    }
+    let inputs = sig.inputs.iter_mut().filter_map(|input| match input {
+        FnArg::Typed(input) => Some(input),
+        FnArg::Receiver(_) => None,
+    });
+    let Arguments {
+        conversion,
+        values,
+        parameters,
+    } = arguments(inputs, "js_function")?;
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = &input;
+    let ident = &sig.ident;
+    let name = ident.unraw().to_string();
+    let declaration = format_ident!("__js_declaration_{}", name);
+    let result = ts_result(&sig.output);
+    // The function keeps its name inside the callback, so that it can
+    // call itself.
+    Ok(quote! {
+        #(#attrs)*
+        #vis fn #ident(
+            ctx: &::javascriptcore::JSContext,
+            _this: &::javascriptcore::JSObject,
+            arguments: &[::javascriptcore::JSValue],
+        ) -> ::std::result::Result<::javascriptcore::JSValue, ::javascriptcore::JSException> {
+            #sig #block
+            #conversion
+            ::javascriptcore::IntoJsResult::into_js_result(#ident(#(#values),*), ctx)
+        }
+
+        #[doc(hidden)]
+        #[allow(dead_code)]
+        #vis fn #declaration() -> ::javascriptcore::FunctionDeclaration {
+            ::javascriptcore::__private::function_declaration(
+                #name,
+                ::std::vec![#(#parameters),*],
+                #result,
+            )
+        }
+    })
+}
+
+/// Expands `js_declaration!(path)` to a call of the function that
+/// declares the `#[js_function]` at `path`.
+pub(crate) fn declaration(mut path: syn::Path) -> TokenStream {
+    let last = path.segments.last_mut().expect("a path is not empty");
+    last.ident = format_ident!("__js_declaration_{}", last.ident.unraw());
+    quote!(#path())
+}
+
+/// Rejects the signatures that can not be called from scripts.
+pub(crate) fn check_signature(sig: &Signature, attribute: &str) -> syn::Result<()> {
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &sig.generics,
+            format!("{} functions can not be generic", attribute),
+        ));
+    }
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(syn::Error::new_spanned(
+            asyncness,
+            format!("{} functions can not be async", attribute),
+        ));
+    }
+    Ok(())
+}
+
+/// The code that passes the arguments of a call to a function.
+pub(crate) struct Arguments {
+    /// The statement that converts the arguments.
+    pub(crate) conversion: TokenStream,
+    /// The values to pass for the parameters of the function.
+    pub(crate) values: Vec<TokenStream>,
+    /// The expressions that declare the parameters in TypeScript.
+    pub(crate) parameters: Vec<TokenStream>,
+}
+
+/// Generates the code that passes the arguments of a call to a function
+/// with the parameters `inputs`.
+///
+/// A `&JSContext` parameter is given the context of the call, and a
+/// `&str` parameter is given a string that is converted like `String`.
+/// A parameter that is marked with `#[js(default)]` or
+/// `#[js(default = ...)]` is given a default value for a missing,
+/// `undefined` or `null` argument. These attributes are removed from
+/// the parameters.
+pub(crate) fn arguments<'a>(
+    inputs: impl Iterator<Item = &'a mut PatType>,
+    attribute: &str,
+) -> syn::Result<Arguments> {
+    let mut types = vec![];
+    let mut names = vec![];
+    let mut idents = vec![];
+    let mut values = vec![];
+    let mut parameters = vec![];
+    for input in inputs {
+        let mut default = None;
+        let mut error = None;
+        input.attrs.retain(|attr| {
+            if !attr.path().is_ident("js") {
+                return true;
+            }
+            let r = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("default") {
+                    default = Some(if meta.input.peek(Token![=]) {
+                        let expr: Expr = meta.value()?.parse()?;
+                        quote!(#expr)
+                    } else {
+                        quote!(::std::default::Default::default())
+                    });
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown js attribute for a parameter"))
+                }
+            });
+            if let Err(e) = r {
+                error = Some(e);
+            }
+            false
+        });
+        if let Some(e) = error {
+            return Err(e);
+        }
+        if is_context(&input.ty) {
+            values.push(quote!(ctx));
+            continue;
+        }
+        let ident = format_ident!("argument{}", idents.len());
+        let (ty, value) = match &*input.ty {
+            Type::Reference(reference) if last_segment_is(&reference.elem, "str") => {
+                match default {
+                    Some(default) => (
+                        quote!(::std::option::Option<::std::string::String>),
+                        quote!(#ident.as_deref().unwrap_or(#default)),
+                    ),
+                    None => (quote!(::std::string::String), quote!(&#ident)),
+                }
+            }
+            Type::Reference(_) => {
+                return Err(syn::Error::new_spanned(
+                    &input.ty,
+                    format!(
+                        "{} arguments must be owned, except for `&JSContext` and `&str`",
+                        attribute
+                    ),
+                ))
+            }
+            ty => match default {
+                Some(default) => (
+                    quote!(::std::option::Option<#ty>),
+                    quote!(#ident.unwrap_or_else(|| #default)),
+                ),
+                None => (quote!(#ty), quote!(#ident)),
+            },
+        };
+        let (name, ts_name) = match &*input.pat {
+            Pat::Ident(pat) => (pat.ident.unraw().to_string(), pat.ident.unraw().to_string()),
+            _ => {
+                let position = idents.len() + 1;
+                (position.to_string(), format!("arg{}", position))
+            }
+        };
+        parameters.push(ts_parameter(&ty, &ts_name));
+        names.push(name);
+        types.push(ty);
+        values.push(value);
+        idents.push(ident);
+    }
+    let conversion = if idents.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            let (#(#idents,)*) = <(#(#types,)*) as ::javascriptcore::FromJsArgs>::from_js_named_args(
+                ctx,
+                arguments,
+                &[#(#names),*],
+            )?;
+        }
+    };
+    Ok(Arguments {
+        conversion,
+        values,
+        parameters,
+    })
+}
+
+/// Generates the expression that gets the TypeScript type of `ty`, which
+/// is `unknown` for types that do not implement `TypeScript`.
+pub(crate) fn ts_type(ty: &TokenStream) -> TokenStream {
+    quote! {{
+        #[allow(unused_imports)]
+        use ::javascriptcore::__private::{KnownType as _, UnknownType as _};
+        (&::javascriptcore::__private::TypeOf::<#ty>::new()).ts_type()
+    }}
+}
+
+/// Generates the expression that declares a parameter called `name`
+/// of the type `ty`.
+fn ts_parameter(ty: &TokenStream, name: &str) -> TokenStream {
+    quote! {{
+        #[allow(unused_imports)]
+        use ::javascriptcore::__private::{KnownType as _, UnknownType as _};
+        (&::javascriptcore::__private::TypeOf::<#ty>::new()).ts_parameter(#name)
+    }}
+}
+
+/// Generates the expression that gets the TypeScript type of the result
+/// of a function.
+pub(crate) fn ts_result(output: &ReturnType) -> TokenStream {
+    match output {
+        ReturnType::Default => quote!(::std::string::String::from("void")),
+        ReturnType::Type(_, ty) => ts_type(&quote!(#ty)),
+    }
+}
+
+/// Tests whether `ty` is `&JSContext`.
+fn is_context(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(reference) => last_segment_is(&reference.elem, "JSContext"),
+        _ => false,
+    }
+}
+
+/// Tests whether `ty` is a path that ends with `name`.
+fn last_segment_is(ty: &Type, name: &str) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == name),
+        _ => false,
+    }
+}
@@ -0,0 +1,89 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Procedural macros for the `javascriptcore` crate.
+//!
+//! These are re-exported by `javascriptcore` when its `macros` feature
+//! is enabled, and are documented there.
+
+#![warn(missing_docs)]
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput, ItemFn, ItemImpl, Path};
+
+mod class;
+mod convert;
+mod function;
+
+/// Derives `JsClass` for a struct, whose fields become properties of
+/// the objects of the class.
+#[proc_macro_derive(JsClass, attributes(js))]
+pub fn derive_js_class(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    class::derive(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `ToJs` for a struct, which converts it to an object with a
+/// property for each field, or for an enum.
+#[proc_macro_derive(ToJs, attributes(js))]
+pub fn derive_to_js(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    convert::derive_to_js(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `FromJs` for a struct, which converts it from an object with
+/// a property for each field, or for an enum.
+#[proc_macro_derive(FromJs, attributes(js))]
+pub fn derive_from_js(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    convert::derive_from_js(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Makes the functions of an `impl` block that are marked with
+/// `#[js_method]` methods of the class of a `#[derive(JsClass)]` type.
+#[proc_macro_attribute]
+pub fn js_methods(args: TokenStream, input: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        let args = proc_macro2::TokenStream::from(args);
+        return syn::Error::new_spanned(args, "js_methods takes no arguments")
+            .into_compile_error()
+            .into();
+    }
+    let input = parse_macro_input!(input as ItemImpl);
+    class::methods(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Turns a function with typed parameters and result into the callback
+/// of a native function.
+#[proc_macro_attribute]
+pub fn js_function(args: TokenStream, input: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        let args = proc_macro2::TokenStream::from(args);
+        return syn::Error::new_spanned(args, "js_function takes no arguments")
+            .into_compile_error()
+            .into();
+    }
+    let input = parse_macro_input!(input as ItemFn);
+    function::function(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Gets the TypeScript declaration of a function marked with
+/// `#[js_function]`.
+#[proc_macro]
+pub fn js_declaration(input: TokenStream) -> TokenStream {
+    let path = parse_macro_input!(input as Path);
+    function::declaration(path).into()
+}
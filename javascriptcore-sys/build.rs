@@ -12,7 +12,20 @@ fn main() {
     if let Ok(_) = std::env::var("DOCS_RS") {
         return;
     }
-    let r = pkg_config::probe_library("javascriptcoregtk-3.0");
+    // Version 3.0 does not have the functions that some features need.
+    let newer = [
+        "CARGO_FEATURE_DEFERRED_PROMISES",
+        "CARGO_FEATURE_MEMORY_USAGE",
+        "CARGO_FEATURE_RUNTIME_OPTIONS",
+        "CARGO_FEATURE_UNHANDLED_REJECTIONS",
+    ]
+    .iter()
+        .any(|feature| std::env::var(feature).is_ok());
+    let r = if newer {
+        Err(())
+    } else {
+        pkg_config::probe_library("javascriptcoregtk-3.0").map_err(|_| ())
+    };
     if r.is_err() {
         let r = pkg_config::probe_library("javascriptcoregtk-4.0");
         if r.is_err() {
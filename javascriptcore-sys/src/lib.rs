@@ -8,7 +8,7 @@
 //! API. It is a pretty direct mapping of the underlying C API
 //! provided by JavaScriptCore.
 
-#![allow(non_camel_case_types, non_snake_case)]
+#![allow(non_camel_case_types, non_snake_case, non_upper_case_globals)]
 
 use std::ptr;
 
@@ -246,6 +246,10 @@ pub enum JSTypedArrayType {
     ArrayBuffer = 9,
     /// Not a Typed Array
     None = 10,
+    /// `BigInt64Array`
+    BigInt64Array = 11,
+    /// `BigUint64Array`
+    BigUint64Array = 12,
 }
 
 extern "C" {
@@ -564,11 +568,29 @@ extern "C" {
 /// Combine multiple attributes by logically ORing them together.
 pub type JSPropertyAttributes = ::std::os::raw::c_uint;
 
+/// Specifies that a property has no special attributes.
+pub const kJSPropertyAttributeNone: JSPropertyAttributes = 0;
+/// Specifies that a property is read-only.
+pub const kJSPropertyAttributeReadOnly: JSPropertyAttributes = 1 << 1;
+/// Specifies that a property should not be enumerated by
+/// `JSPropertyEnumerators` and JavaScript `for...in` loops.
+pub const kJSPropertyAttributeDontEnum: JSPropertyAttributes = 1 << 2;
+/// Specifies that the delete operation should fail on a property.
+pub const kJSPropertyAttributeDontDelete: JSPropertyAttributes = 1 << 3;
+
 /// A set of `JSClassAttribute`s.
 ///
 /// Combine multiple attributes by logically ORing them together.
 pub type JSClassAttributes = ::std::os::raw::c_uint;
 
+/// Specifies that a class has no special attributes.
+pub const kJSClassAttributeNone: JSClassAttributes = 0;
+/// Specifies that a class should not automatically generate a shared
+/// prototype for its instance objects. Use
+/// `kJSClassAttributeNoAutomaticPrototype` in combination with
+/// `JSObjectSetPrototype` to manage prototypes manually.
+pub const kJSClassAttributeNoAutomaticPrototype: JSClassAttributes = 1 << 1;
+
 /// The callback invoked when an object is first created.
 ///
 /// * `ctx`: The execution context to use.
@@ -1197,6 +1219,30 @@ extern "C" {
         exception: *mut JSValueRef,
     ) -> JSObjectRef;
 
+    #[cfg(feature = "deferred-promises")]
+    /// Creates a JavaScript promise object by invoking the provided
+    /// executor.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `resolve`: A pointer to a `JSObjectRef` in which to store the
+    ///   resolve function for the new promise. Pass `NULL` if you do not
+    ///   care to store the resolve callback.
+    /// * `reject`: A pointer to a `JSObjectRef` in which to store the
+    ///   reject function for the new promise. Pass `NULL` if you do not
+    ///   care to store the reject callback.
+    /// * `exception`: A pointer to a `JSValueRef` in which to store
+    ///   an exception, if any. Pass `NULL` if you do not care to
+    ///   store an exception.
+    ///
+    /// Returns a `JSObject` that is a promise or `NULL` if an exception
+    /// occurred.
+    pub fn JSObjectMakeDeferredPromise(
+        ctx: JSContextRef,
+        resolve: *mut JSObjectRef,
+        reject: *mut JSObjectRef,
+        exception: *mut JSValueRef,
+    ) -> JSObjectRef;
+
     /// Creates a JavaScript `RegExp` object, as if by invoking the
     /// built-in `RegExp` constructor.
     ///
@@ -1343,6 +1389,103 @@ extern "C" {
         exception: *mut JSValueRef,
     ) -> bool;
 
+    #[cfg(feature = "property-keys")]
+    /// Tests whether an object has a given property, using a `JSValueRef`
+    /// as the property key.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `object`: The `JSObject` to test.
+    /// * `propertyKey`: A `JSValueRef` containing the property key to use
+    ///   when looking up the property.
+    /// * `exception`: A pointer to a `JSValueRef` in which to store
+    ///   an exception, if any. Pass `NULL` if you do not care to
+    ///   store an exception.
+    ///
+    /// Returns `true` if the object has a property whose name matches
+    /// `propertyKey`, otherwise `false`.
+    ///
+    /// This function is the same as performing `propertyKey in object`
+    /// from JavaScript.
+    pub fn JSObjectHasPropertyForKey(
+        ctx: JSContextRef,
+        object: JSObjectRef,
+        propertyKey: JSValueRef,
+        exception: *mut JSValueRef,
+    ) -> bool;
+
+    #[cfg(feature = "property-keys")]
+    /// Gets a property from an object using a `JSValueRef` as the
+    /// property key.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `object`: The `JSObject` whose property you want to get.
+    /// * `propertyKey`: A `JSValueRef` containing the property key to use
+    ///   when looking up the property.
+    /// * `exception`: A pointer to a `JSValueRef` in which to store
+    ///   an exception, if any. Pass `NULL` if you do not care to
+    ///   store an exception.
+    ///
+    /// Returns the property's value if object has the property key,
+    /// otherwise the undefined value.
+    ///
+    /// This function is the same as performing `object[propertyKey]`
+    /// from JavaScript.
+    pub fn JSObjectGetPropertyForKey(
+        ctx: JSContextRef,
+        object: JSObjectRef,
+        propertyKey: JSValueRef,
+        exception: *mut JSValueRef,
+    ) -> JSValueRef;
+
+    #[cfg(feature = "property-keys")]
+    /// Sets a property on an object using a `JSValueRef` as the property
+    /// key.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `object`: The `JSObject` whose property you want to set.
+    /// * `propertyKey`: A `JSValueRef` containing the property key to use
+    ///   when looking up the property.
+    /// * `value`: A `JSValue` to use as the property's value.
+    /// * `attributes`: A logically ORed set of `JSPropertyAttributes` to give to the property.
+    /// * `exception`: A pointer to a `JSValueRef` in which to store
+    ///   an exception, if any. Pass `NULL` if you do not care to
+    ///   store an exception.
+    ///
+    /// This function is the same as performing `object[propertyKey] = value`
+    /// from JavaScript.
+    pub fn JSObjectSetPropertyForKey(
+        ctx: JSContextRef,
+        object: JSObjectRef,
+        propertyKey: JSValueRef,
+        value: JSValueRef,
+        attributes: JSPropertyAttributes,
+        exception: *mut JSValueRef,
+    );
+
+    #[cfg(feature = "property-keys")]
+    /// Deletes a property from an object using a `JSValueRef` as the
+    /// property key.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `object`: The `JSObject` whose property you want to delete.
+    /// * `propertyKey`: A `JSValueRef` containing the property key to use
+    ///   when looking up the property.
+    /// * `exception`: A pointer to a `JSValueRef` in which to store
+    ///   an exception, if any. Pass `NULL` if you do not care to
+    ///   store an exception.
+    ///
+    /// Returns `true` if the delete operation succeeds, otherwise `false`
+    /// (for example, if the property is not configurable).
+    ///
+    /// This function is the same as performing `delete object[propertyKey]`
+    /// from JavaScript.
+    pub fn JSObjectDeletePropertyForKey(
+        ctx: JSContextRef,
+        object: JSObjectRef,
+        propertyKey: JSValueRef,
+        exception: *mut JSValueRef,
+    ) -> bool;
+
     /// Gets a property from an object by numeric index.
     ///
     /// * `ctx`: The execution context to use.
@@ -2001,3 +2144,153 @@ extern "C" {
         exception: *mut JSValueRef,
     ) -> usize;
 }
+
+/// The callback invoked when the execution time limit of a context
+/// group has been reached.
+///
+/// * `ctx`: The execution context that is executing the script.
+/// * `context`: User data that was passed to
+///   [`JSContextGroupSetExecutionTimeLimit`].
+///
+/// Returns `true` if the script should be terminated. Returning
+/// `false` lets the script continue to run for another period of
+/// the time limit.
+///
+/// If you named your function `ShouldTerminate`, you would declare it like this:
+///
+/// ```ignore
+/// bool
+/// ShouldTerminate(JSContextRef ctx, void* context);
+/// ```
+///
+/// [`JSContextGroupSetExecutionTimeLimit`]: fn.JSContextGroupSetExecutionTimeLimit.html
+pub type JSShouldTerminateCallback = ::std::option::Option<
+    unsafe extern "C" fn(ctx: JSContextRef, context: *mut ::std::os::raw::c_void) -> bool,
+>;
+
+// The following functions are declared in `JSContextRefPrivate.h`. They
+// are not part of the public headers, but are exported by the framework
+// and by the GTK builds of JavaScriptCore.
+extern "C" {
+    /// Sets the script execution time limit.
+    ///
+    /// * `group`: The JavaScript context group that this time limit
+    ///   applies to.
+    /// * `limit`: The time limit of allowed script execution time in
+    ///   seconds.
+    /// * `callback`: The callback function that will be invoked when
+    ///   the time limit has been reached. This will give you a chance
+    ///   to decide if you want to terminate the script or not. If you
+    ///   pass `NULL`, the script will be terminated unconditionally
+    ///   when the time limit has been reached.
+    /// * `context`: User data that you can provide to be passed back
+    ///   to you in your callback.
+    ///
+    /// In order to guarantee that the execution time limit will take
+    /// effect, you will need to call `JSContextGroupSetExecutionTimeLimit`
+    /// before you start executing any scripts.
+    pub fn JSContextGroupSetExecutionTimeLimit(
+        group: JSContextGroupRef,
+        limit: f64,
+        callback: JSShouldTerminateCallback,
+        context: *mut ::std::os::raw::c_void,
+    );
+
+    /// Clears the script execution time limit.
+    ///
+    /// * `group`: The JavaScript context group that the time limit
+    ///   is cleared on.
+    pub fn JSContextGroupClearExecutionTimeLimit(group: JSContextGroupRef);
+
+    #[cfg(feature = "unhandled-rejections")]
+    /// Sets a function to call when a promise is rejected without a
+    /// handler.
+    ///
+    /// * `ctx`: The `JSGlobalContext` to set the callback on.
+    /// * `function`: The function to call with the promise and the
+    ///   rejection reason. It is called after the microtask queue has
+    ///   been drained, for each promise that is still unhandled then.
+    /// * `exception`: A pointer to a `JSValueRef` in which to store
+    ///   an exception, if any. Pass `NULL` if you do not care to
+    ///   store an exception.
+    ///
+    /// An exception is stored if `function` is not callable.
+    pub fn JSGlobalContextSetUnhandledRejectionCallback(
+        ctx: JSGlobalContextRef,
+        function: JSObjectRef,
+        exception: *mut JSValueRef,
+    );
+}
+
+// The following functions are declared in `JSBasePrivate.h`.
+extern "C" {
+    #[cfg(feature = "memory-usage")]
+    /// Gets statistics about the memory used by the heap of a context group.
+    ///
+    /// * `ctx`: The execution context to use.
+    ///
+    /// Returns a new object with the numeric properties `heapSize`,
+    /// `heapCapacity`, `extraMemorySize`, `objectCount`,
+    /// `protectedObjectCount`, `globalObjectCount` and
+    /// `protectedGlobalObjectCount`.
+    pub fn JSGetMemoryUsageStatistics(ctx: JSContextRef) -> JSObjectRef;
+
+    /// Performs a synchronous, full garbage collection.
+    ///
+    /// Unlike [`JSGarbageCollect`], this does not return until the
+    /// collection has finished.
+    ///
+    /// * `ctx`: The execution context to use.
+    ///
+    /// [`JSGarbageCollect`]: fn.JSGarbageCollect.html
+    pub fn JSSynchronousGarbageCollectForDebugging(ctx: JSContextRef);
+}
+
+// The following functions are declared in `JSCOptions.h`, which is part
+// of the GLib API of the GTK and WPE ports, since version 2.24. Each of
+// them returns `false` if there is no option named `option`, or if its
+// value is not of the type of the function.
+#[cfg(all(target_os = "linux", feature = "runtime-options"))]
+extern "C" {
+    /// Sets the boolean option `option`.
+    pub fn jsc_options_set_boolean(
+        option: *const ::std::os::raw::c_char,
+        value: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+
+    /// Sets the integer option `option`.
+    pub fn jsc_options_set_int(
+        option: *const ::std::os::raw::c_char,
+        value: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+
+    /// Sets the unsigned integer option `option`.
+    pub fn jsc_options_set_uint(
+        option: *const ::std::os::raw::c_char,
+        value: ::std::os::raw::c_uint,
+    ) -> ::std::os::raw::c_int;
+
+    /// Sets the size option `option`.
+    pub fn jsc_options_set_size(
+        option: *const ::std::os::raw::c_char,
+        value: usize,
+    ) -> ::std::os::raw::c_int;
+
+    /// Sets the floating point option `option`.
+    pub fn jsc_options_set_double(
+        option: *const ::std::os::raw::c_char,
+        value: f64,
+    ) -> ::std::os::raw::c_int;
+
+    /// Sets the string option `option`.
+    pub fn jsc_options_set_string(
+        option: *const ::std::os::raw::c_char,
+        value: *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+
+    /// Sets the range option `option`, such as `1:100` or `!50:60`.
+    pub fn jsc_options_set_range_string(
+        option: *const ::std::os::raw::c_char,
+        value: *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+}
@@ -0,0 +1,571 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::base::evaluate_script;
+use super::function::new_function;
+use super::{DeferredQueue, JSContext, JSException, JSObject, JSValue};
+use std::fmt;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+/// Defines `AbortController` and `AbortSignal`.
+///
+/// JavaScriptCore has no `EventTarget`, so a signal implements just
+/// enough of it for `abort` events: `addEventListener` with the `once`
+/// option, `removeEventListener` and `onabort`.
+const GLUE: &str = r#"(function (global) {
+    'use strict';
+
+    const KEY = Symbol('key');
+    const signals = new WeakMap();
+    const stateOf = (self) => {
+        const state = signals.get(self);
+        if (state === undefined) {
+            throw new TypeError('Illegal invocation');
+        }
+        return state;
+    };
+
+    const newError = (name, message) => {
+        const error = new Error(message);
+        error.name = name;
+        return error;
+    };
+
+    const signalAbort = (signal, reason) => {
+        const state = stateOf(signal);
+        if (state.aborted) {
+            return;
+        }
+        state.aborted = true;
+        state.reason = reason === undefined
+            ? newError('AbortError', 'This operation was aborted')
+            : reason;
+        const listeners = state.listeners;
+        state.listeners = [];
+        const event = { type: 'abort', target: signal, currentTarget: signal };
+        let failure;
+        const dispatch = (listener) => {
+            try {
+                if (typeof listener === 'function') {
+                    listener.call(signal, event);
+                } else if (listener !== null && typeof listener === 'object') {
+                    listener.handleEvent(event);
+                }
+            } catch (e) {
+                failure = failure || { error: e };
+            }
+        };
+        dispatch(signal.onabort);
+        for (const { listener } of listeners) {
+            dispatch(listener);
+        }
+        // Every listener runs, and then the first error is thrown.
+        if (failure !== undefined) {
+            throw failure.error;
+        }
+    };
+
+    class AbortSignal {
+        constructor(key) {
+            if (key !== KEY) {
+                throw new TypeError('Illegal constructor');
+            }
+            signals.set(this, { aborted: false, reason: undefined, listeners: [] });
+            this.onabort = null;
+        }
+
+        get aborted() {
+            return stateOf(this).aborted;
+        }
+
+        get reason() {
+            return stateOf(this).reason;
+        }
+
+        throwIfAborted() {
+            const state = stateOf(this);
+            if (state.aborted) {
+                throw state.reason;
+            }
+        }
+
+        addEventListener(type, listener, options) {
+            const state = stateOf(this);
+            if (String(type) !== 'abort' || listener === null || listener === undefined ||
+                state.aborted || state.listeners.some((l) => l.listener === listener)) {
+                return;
+            }
+            const once = Boolean(options !== null && typeof options === 'object' && options.once);
+            state.listeners.push({ listener, once });
+        }
+
+        removeEventListener(type, listener) {
+            const state = stateOf(this);
+            if (String(type) === 'abort') {
+                state.listeners = state.listeners.filter((l) => l.listener !== listener);
+            }
+        }
+
+        static abort(reason) {
+            const signal = new AbortSignal(KEY);
+            signalAbort(signal, reason);
+            return signal;
+        }
+
+        static timeout(milliseconds) {
+            if (typeof global.setTimeout !== 'function') {
+                throw new TypeError('AbortSignal.timeout requires setTimeout');
+            }
+            const signal = new AbortSignal(KEY);
+            global.setTimeout(() => {
+                signalAbort(signal, newError('TimeoutError', 'The operation timed out'));
+            }, milliseconds);
+            return signal;
+        }
+
+        static any(signals) {
+            const signal = new AbortSignal(KEY);
+            for (const source of signals) {
+                if (source.aborted) {
+                    signalAbort(signal, source.reason);
+                    return signal;
+                }
+            }
+            for (const source of signals) {
+                source.addEventListener('abort', () => signalAbort(signal, source.reason));
+            }
+            return signal;
+        }
+    }
+
+    class AbortController {
+        constructor() {
+            this.signal = new AbortSignal(KEY);
+            Object.defineProperty(this, 'signal', { writable: false, configurable: false });
+        }
+
+        abort(reason) {
+            signalAbort(this.signal, reason);
+        }
+    }
+
+    for (const [name, value] of [['AbortController', AbortController], ['AbortSignal', AbortSignal]]) {
+        Object.defineProperty(global, name, { value, writable: true, configurable: true });
+    }
+})"#;
+
+/// Aborts `controller` once `promise` is fulfilled.
+const LINK: &str = r#"(function (promise, controller) {
+    'use strict';
+    promise.then(() => controller.abort(), () => {});
+})"#;
+
+/// Installs the `AbortController` and `AbortSignal` classes into a
+/// context.
+///
+/// * `ctx`: The execution context to install the classes into.
+///
+/// The classes follow the DOM standard, except that signals are not
+/// full `EventTarget`s: they support `onabort`, and `addEventListener`
+/// and `removeEventListener` for `abort` events. An `AbortError` or
+/// `TimeoutError` is an `Error` with that name, as there is no
+/// `DOMException`. `AbortSignal.timeout` needs `setTimeout`, such as
+/// from [`install_timers`].
+///
+/// Host functions can take a signal and turn it into a
+/// [`CancellationToken`] with [`CancellationToken::from_signal`].
+///
+/// ```
+/// # use javascriptcore::*;
+/// let ctx = JSContext::default();
+/// install_abort_controller(&ctx).expect("classes installed");
+///
+/// let script = "const controller = new AbortController();
+///               controller.signal.onabort = () => globalThis.heard = true;
+///               controller.abort();
+///               [heard, controller.signal.reason.name].join()";
+/// let r = evaluate_script(&ctx, script, None, "test.js", 1).expect("aborted");
+/// assert_eq!(r.as_string().expect("string"), "true,AbortError");
+/// ```
+///
+/// [`install_timers`]: fn.install_timers.html
+/// [`CancellationToken`]: struct.CancellationToken.html
+/// [`CancellationToken::from_signal`]: struct.CancellationToken.html#method.from_signal
+pub fn install_abort_controller(ctx: &JSContext) -> Result<(), JSException> {
+    let glue = evaluate_script(ctx, GLUE, None, "abort.js", 1)?.as_object()?;
+    glue.call_as_function(None, &[ctx.global_object().into()])?;
+    Ok(())
+}
+
+/// The state shared by the clones of a `CancellationToken`.
+#[derive(Default)]
+struct Shared {
+    cancelled: AtomicBool,
+    /// The callbacks to run once the token is cancelled. This is only
+    /// changed while it is locked, and so is `cancelled`.
+    callbacks: Mutex<Vec<Box<dyn FnOnce() + Send>>>,
+}
+
+/// A flag that tells long-running work to stop, which can be set from
+/// Rust or from a script.
+///
+/// Clones share the same flag, and can be sent to other threads. Once a
+/// token is cancelled, it stays cancelled.
+///
+/// A token bridges with the `AbortSignal` of [`install_abort_controller`]
+/// both ways: [`from_signal`] makes a token that a script cancels by
+/// aborting a signal, and [`to_signal`] makes a signal that aborts when
+/// Rust code cancels the token.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let ctx = JSContext::default();
+/// install_abort_controller(&ctx).expect("classes installed");
+/// let controller = evaluate_script(&ctx, "new AbortController()", None, "test.js", 1)
+///     .expect("controller");
+/// let controller = controller.as_object().expect("object");
+///
+/// let token = CancellationToken::from_signal(&controller.get_property("signal").expect("signal"))
+///     .expect("token");
+/// let worker = token.clone();
+/// assert!(!worker.is_cancelled());
+///
+/// let abort = controller.get_property("abort").expect("abort").as_object().expect("object");
+/// abort.call_as_function(Some(&controller), &[]).expect("aborted");
+/// assert!(worker.is_cancelled());
+/// ```
+///
+/// [`install_abort_controller`]: fn.install_abort_controller.html
+/// [`from_signal`]: #method.from_signal
+/// [`to_signal`]: #method.to_signal
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    shared: Arc<Shared>,
+}
+
+impl CancellationToken {
+    /// Creates a token that has not been cancelled.
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// Cancels the token, and runs the callbacks that wait for that.
+    ///
+    /// Cancelling a token again does nothing.
+    pub fn cancel(&self) {
+        let callbacks = {
+            let mut callbacks = self.lock();
+            if self.shared.cancelled.swap(true, Ordering::SeqCst) {
+                return;
+            }
+            mem::take(&mut *callbacks)
+        };
+        for callback in callbacks {
+            callback();
+        }
+    }
+
+    /// Tests whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Runs `callback` once the token is cancelled, on the thread that
+    /// cancels it, or right away if it has been cancelled already.
+    pub fn on_cancel<F>(&self, callback: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        {
+            let mut callbacks = self.lock();
+            if !self.is_cancelled() {
+                callbacks.push(Box::new(callback));
+                return;
+            }
+        }
+        callback();
+    }
+
+    /// Returns a future that completes once the token is cancelled.
+    pub fn cancelled(&self) -> Cancelled {
+        Cancelled {
+            token: self.clone(),
+            waker: None,
+        }
+    }
+
+    /// Makes a token that is cancelled when `signal` is aborted.
+    ///
+    /// * `signal`: An `AbortSignal`, or another object with an `aborted`
+    ///   property and an `addEventListener` method.
+    ///
+    /// Fails with a `TypeError` if `signal` is not an object.
+    pub fn from_signal(signal: &JSValue) -> Result<CancellationToken, JSException> {
+        let signal = signal.as_object()?;
+        let token = CancellationToken::new();
+        if signal.get_property("aborted")?.as_boolean() {
+            token.cancel();
+            return Ok(token);
+        }
+        let ctx = unsafe { JSContext::from_context_ref(signal.value.ctx) };
+        let cancel = token.clone();
+        let listener = new_function(&ctx, move |ctx, _this, _arguments| {
+            cancel.cancel();
+            Ok(JSValue::new_undefined(ctx))
+        });
+        let add = signal.get_property("addEventListener")?.as_object()?;
+        add.call_as_function(
+            Some(&signal),
+            &[JSValue::new_string(&ctx, "abort"), listener.into()],
+        )?;
+        Ok(token)
+    }
+
+    /// Makes an `AbortSignal` that is aborted when the token is
+    /// cancelled.
+    ///
+    /// * `queue`: The queue of the context to make the signal in. As the
+    ///   token may be cancelled on any thread, the signal is aborted the
+    ///   next time that the queue is pumped.
+    ///
+    /// Requires [`install_abort_controller`].
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// install_abort_controller(&ctx).expect("classes installed");
+    /// let queue = DeferredQueue::new(&ctx);
+    ///
+    /// let token = CancellationToken::new();
+    /// let signal = token.to_signal(&queue).expect("signal");
+    /// token.cancel();
+    /// assert!(!signal.get_property("aborted").expect("aborted").as_boolean());
+    /// queue.pump();
+    /// assert!(signal.get_property("aborted").expect("aborted").as_boolean());
+    /// ```
+    ///
+    /// [`install_abort_controller`]: fn.install_abort_controller.html
+    pub fn to_signal(&self, queue: &DeferredQueue) -> Result<JSObject, JSException> {
+        let ctx = queue.context();
+        let controller = ctx
+            .global_object()
+            .get_property("AbortController")?
+            .as_object()?
+            .call_as_constructor(&[])?;
+        let signal = controller.get_property("signal")?.as_object()?;
+        let (promise, deferred) = queue.deferred()?;
+        let link = evaluate_script(ctx, LINK, None, "abort.js", 1)?.as_object()?;
+        link.call_as_function(None, &[promise.into(), controller.into()])?;
+        self.on_cancel(move || deferred.resolve(()));
+        Ok(signal)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Box<dyn FnOnce() + Send>>> {
+        self.shared
+            .callbacks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// A future that completes once a [`CancellationToken`] is cancelled.
+///
+/// This is returned by [`CancellationToken::cancelled`].
+///
+/// [`CancellationToken`]: struct.CancellationToken.html
+/// [`CancellationToken::cancelled`]: struct.CancellationToken.html#method.cancelled
+pub struct Cancelled {
+    token: CancellationToken,
+    /// Where the callback of the token finds the task to wake, once it
+    /// has been registered.
+    waker: Option<Arc<Mutex<Option<Waker>>>>,
+}
+
+impl Future for Cancelled {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.token.is_cancelled() {
+            return Poll::Ready(());
+        }
+        match &self.waker {
+            Some(waker) => {
+                *waker.lock().unwrap_or_else(|e| e.into_inner()) = Some(cx.waker().clone());
+            }
+            None => {
+                let waker = Arc::new(Mutex::new(Some(cx.waker().clone())));
+                let slot = waker.clone();
+                self.token.on_cancel(move || {
+                    if let Some(waker) = slot.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                        waker.wake();
+                    }
+                });
+                self.waker = Some(waker);
+            }
+        }
+        // The token may have been cancelled before the waker was stored.
+        if self.token.is_cancelled() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl fmt::Debug for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cancelled")
+            .field("token", &self.token)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, DeferredQueue, JSContext, JSObject, JSValue};
+    use super::{install_abort_controller, CancellationToken};
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Waker};
+    use std::thread;
+
+    fn eval(ctx: &JSContext, script: &str) -> String {
+        let r = evaluate_script(ctx, script, None, "test.js", 1).unwrap();
+        r.to_json_string(0).unwrap().to_string()
+    }
+
+    #[test]
+    fn abort_controller() {
+        let ctx = JSContext::default();
+        install_abort_controller(&ctx).unwrap();
+
+        assert_eq!(
+            eval(
+                &ctx,
+                "const controller = new AbortController();
+                 const signal = controller.signal;
+                 const heard = [];
+                 const listener = (e) => heard.push(e.type);
+                 signal.addEventListener('abort', listener);
+                 signal.addEventListener('abort', listener);
+                 signal.addEventListener('abort', { handleEvent: () => heard.push('object') });
+                 const removed = () => heard.push('removed');
+                 signal.addEventListener('abort', removed);
+                 signal.removeEventListener('abort', removed);
+                 signal.onabort = function () { heard.push(this === signal) };
+                 const before = signal.aborted;
+                 controller.abort('why');
+                 controller.abort('again');
+                 [before, signal.aborted, signal.reason, heard]"
+            ),
+            r#"[false,true,"why",[true,"abort","object"]]"#
+        );
+        assert_eq!(
+            eval(
+                &ctx,
+                "const results = [];
+                 try { AbortSignal.abort().throwIfAborted() } catch (e) { results.push(e.name) }
+                 results.push(new AbortController().signal.throwIfAborted());
+                 const a = new AbortController();
+                 const any = AbortSignal.any([new AbortController().signal, a.signal]);
+                 a.abort(1);
+                 results.push(any.reason);
+                 try { new AbortSignal() } catch (e) { results.push(e.name) }
+                 try { AbortSignal.timeout(1) } catch (e) { results.push(e.name) }
+                 results"
+            ),
+            r#"["AbortError",null,1,"TypeError","TypeError"]"#
+        );
+    }
+
+    #[test]
+    fn cancellation_token() {
+        let token = CancellationToken::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        token.on_cancel(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut cancelled = pin!(token.cancelled());
+        assert!(cancelled.as_mut().poll(&mut cx).is_pending());
+        assert!(cancelled.as_mut().poll(&mut cx).is_pending());
+
+        let other = token.clone();
+        thread::spawn(move || other.cancel()).join().unwrap();
+        token.cancel();
+        assert!(token.is_cancelled());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(cancelled.as_mut().poll(&mut cx).is_ready());
+
+        let counter = calls.clone();
+        token.on_cancel(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn signals() {
+        let ctx = JSContext::default();
+        install_abort_controller(&ctx).unwrap();
+        let queue = DeferredQueue::new(&ctx);
+
+        // A host function that stops when its signal is aborted.
+        let watch = JSObject::new_function(&ctx, |ctx, _this, arguments| {
+            let token = CancellationToken::from_signal(&arguments[0])?;
+            Ok(JSValue::new_boolean(ctx, token.is_cancelled()))
+        });
+        ctx.global_object()
+            .set_property("watch", watch.into())
+            .unwrap();
+        assert_eq!(eval(&ctx, "watch(AbortSignal.abort())"), "true");
+        assert_eq!(eval(&ctx, "watch(new AbortController().signal)"), "false");
+        assert!(CancellationToken::from_signal(&JSValue::new_null(&ctx)).is_err());
+
+        let controller = evaluate_script(&ctx, "new AbortController()", None, "test.js", 1)
+            .unwrap()
+            .as_object()
+            .unwrap();
+        let token =
+            CancellationToken::from_signal(&controller.get_property("signal").unwrap()).unwrap();
+        ctx.global_object()
+            .set_property("controller", controller.into())
+            .unwrap();
+        eval(&ctx, "controller.abort(); 0");
+        assert!(token.is_cancelled());
+
+        let token = CancellationToken::new();
+        let signal = token.to_signal(&queue).unwrap();
+        ctx.global_object()
+            .set_property("signal", signal.into())
+            .unwrap();
+        eval(&ctx, "signal.onabort = () => globalThis.aborted = true; 0");
+        let other = token.clone();
+        thread::spawn(move || other.cancel()).join().unwrap();
+        assert_eq!(eval(&ctx, "signal.aborted"), "false");
+        assert_eq!(queue.pump(), 1);
+        assert_eq!(eval(&ctx, "[signal.aborted, aborted]"), "[true,true]");
+
+        let signal = CancellationToken::new().to_signal(&queue).unwrap();
+        assert!(!signal.get_property("aborted").unwrap().as_boolean());
+    }
+}
@@ -0,0 +1,713 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::base::evaluate_script;
+use super::function::new_function;
+#[cfg(feature = "async")]
+use super::PromiseFuture;
+use super::{IntoJsResult, JSContext, JSDeferred, JSException, JSObject, JSPromise, JSValue};
+use crate::promise::Spawner;
+#[cfg(feature = "async")]
+use crate::sys;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+#[cfg(feature = "async")]
+use std::future::IntoFuture;
+use std::future::{self, Future};
+use std::mem;
+use std::pin::Pin;
+#[cfg(feature = "async")]
+use std::ptr;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+/// Makes an async iterator out of the `next` and `return` functions of
+/// a Rust stream.
+const MAKE_ITERATOR: &str = r#"(function (next, close) {
+    'use strict';
+    return {
+        next() {
+            return next();
+        },
+        return(value) {
+            close();
+            return Promise.resolve({ value, done: true });
+        },
+        [Symbol.asyncIterator]() {
+            return this;
+        },
+    };
+})"#;
+
+/// Gets an async iterator of an async iterable, as `for await` does.
+#[cfg(feature = "async")]
+const GET_ITERATOR: &str = r#"(function (iterable) {
+    'use strict';
+    const method = iterable == null ? undefined : iterable[Symbol.asyncIterator];
+    if (typeof method !== 'function') {
+        throw new TypeError('Value is not async iterable');
+    }
+    const iterator = method.call(iterable);
+    if (iterator === null || (typeof iterator !== 'object' && typeof iterator !== 'function')) {
+        throw new TypeError('Iterator is not an object');
+    }
+    return iterator;
+})"#;
+
+/// A Rust stream that a script iterates over.
+struct Source<F> {
+    /// Polls the stream for its next item, unless the stream is being
+    /// polled or has finished.
+    poll_next: Option<F>,
+    /// Whether the stream has ended or the iterator has been closed.
+    finished: bool,
+    /// The promises of the calls to `next` that wait for an item.
+    waiting: VecDeque<JSDeferred>,
+    /// Whether a future that polls the stream has been spawned.
+    driving: bool,
+}
+
+/// Stops driving a source, and rejects the calls to `next` that are
+/// left if the future is dropped before they get an item.
+struct Driver<F>(Rc<RefCell<Source<F>>>);
+
+impl<F> Drop for Driver<F> {
+    fn drop(&mut self) {
+        let mut source = self.0.borrow_mut();
+        source.driving = false;
+        let waiting = mem::take(&mut source.waiting);
+        drop(source);
+        drop(waiting);
+    }
+}
+
+/// Creates the result of an iteration.
+fn iterator_result(ctx: &JSContext, value: JSValue, done: bool) -> Result<JSValue, JSException> {
+    let result = JSObject::new(ctx);
+    result.set_property("value", value)?;
+    result.set_property("done", JSValue::new_boolean(ctx, done))?;
+    Ok(result.into())
+}
+
+/// Settles the calls to `next` that wait on a source that has finished.
+fn finish<T>(ctx: &JSContext, waiting: T)
+where
+    T: IntoIterator<Item = JSDeferred>,
+{
+    for deferred in waiting {
+        deferred.settle(iterator_result(ctx, JSValue::new_undefined(ctx), true));
+    }
+}
+
+/// Polls the stream of `source` until each call to `next` that waits
+/// for an item has one, or the stream ends.
+async fn drive<F, T>(ctx: JSContext, driver: Driver<F>)
+where
+    F: FnMut(&mut Context<'_>) -> Poll<Option<T>>,
+    T: IntoJsResult,
+{
+    let source = driver.0.clone();
+    future::poll_fn(move |cx| loop {
+        let mut state = source.borrow_mut();
+        if state.waiting.is_empty() {
+            return Poll::Ready(());
+        }
+        let Some(mut poll_next) = state.poll_next.take() else {
+            let waiting = mem::take(&mut state.waiting);
+            drop(state);
+            finish(&ctx, waiting);
+            continue;
+        };
+        // The stream is polled without holding on to the source, so that
+        // it can call into the iterator.
+        drop(state);
+        let item = poll_next(cx);
+        let mut state = source.borrow_mut();
+        if !state.finished {
+            state.poll_next = Some(poll_next);
+        }
+        match item {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Some(item)) => {
+                let deferred = state.waiting.pop_front();
+                drop(state);
+                let result = item
+                    .into_js_result(&ctx)
+                    .and_then(|value| iterator_result(&ctx, value, false));
+                if let Some(deferred) = deferred {
+                    deferred.settle(result);
+                }
+            }
+            Poll::Ready(None) => {
+                state.finished = true;
+                state.poll_next = None;
+            }
+        }
+    })
+    .await;
+    drop(driver);
+}
+
+impl JSObject {
+    /// Creates an async iterator whose items a Rust stream produces.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `spawner`: Runs the future that polls the stream.
+    /// * `poll_next`: Polls the stream for its next item, in the way of
+    ///   `poll_next` of the `Stream` trait of the `futures` crate, and
+    ///   returns `None` once the stream has ended.
+    ///
+    /// The iterator is also async iterable, so a script can consume it
+    /// with `for await`. The stream is only polled while a call to
+    /// `next` waits for an item, so items are produced as the script
+    /// asks for them rather than buffered. Each item is converted with
+    /// [`IntoJsResult`], and an error rejects the call to `next` that
+    /// it was for, without ending the stream. Leaving a `for await` loop
+    /// early calls `return`, which drops the stream.
+    ///
+    /// A pinned stream can be adapted with
+    /// `move |cx| stream.as_mut().poll_next(cx)`.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// # use std::cell::RefCell;
+    /// # use std::future::Future;
+    /// # use std::pin::Pin;
+    /// # use std::rc::Rc;
+    /// # use std::task::{Context, Poll, Waker};
+    /// #[derive(Clone, Default)]
+    /// struct Queue(Rc<RefCell<Vec<Pin<Box<dyn Future<Output = ()>>>>>>);
+    ///
+    /// impl Spawner for Queue {
+    ///     fn spawn(&self, future: Pin<Box<dyn Future<Output = ()>>>) {
+    ///         self.0.borrow_mut().push(future);
+    ///     }
+    /// }
+    ///
+    /// let ctx = JSContext::default();
+    /// let queue = Queue::default();
+    /// let mut lines = vec!["first", "second"].into_iter();
+    /// let iterator = JSObject::new_async_iterator(&ctx, queue.clone(), move |_cx| {
+    ///     Poll::Ready(lines.next())
+    /// })
+    /// .expect("iterator");
+    /// ctx.global_object().set_property("lines", iterator.into()).expect("set");
+    ///
+    /// let script = "(async () => { globalThis.seen = []; for await (const l of lines) seen.push(l) })()";
+    /// evaluate_script(&ctx, script, None, "test.js", 1).expect("started");
+    /// loop {
+    ///     let future = queue.0.borrow_mut().pop();
+    ///     let Some(mut future) = future else { break };
+    ///     assert!(future.as_mut().poll(&mut Context::from_waker(Waker::noop())).is_ready());
+    /// }
+    ///
+    /// let seen = evaluate_script(&ctx, "seen.join()", None, "test.js", 1).expect("seen");
+    /// assert_eq!(seen.as_string().expect("string"), "first,second");
+    /// ```
+    ///
+    /// [`IntoJsResult`]: trait.IntoJsResult.html
+    pub fn new_async_iterator<S, F, T>(
+        ctx: &JSContext,
+        spawner: S,
+        poll_next: F,
+    ) -> Result<JSObject, JSException>
+    where
+        S: Spawner + 'static,
+        F: FnMut(&mut Context<'_>) -> Poll<Option<T>> + 'static,
+        T: IntoJsResult + 'static,
+    {
+        let source = Rc::new(RefCell::new(Source {
+            poll_next: Some(poll_next),
+            finished: false,
+            waiting: VecDeque::new(),
+            driving: false,
+        }));
+
+        let next = {
+            let source = source.clone();
+            new_function(ctx, move |ctx, _this, _arguments| {
+                let (promise, deferred) = JSPromise::deferred(ctx)?;
+                let mut state = source.borrow_mut();
+                if state.finished {
+                    drop(state);
+                    finish(ctx, [deferred]);
+                    return Ok(promise.into());
+                }
+                state.waiting.push_back(deferred);
+                if !state.driving {
+                    state.driving = true;
+                    drop(state);
+                    let driver = Driver(source.clone());
+                    let future: Pin<Box<dyn Future<Output = ()>>> =
+                        Box::pin(drive(ctx.clone(), driver));
+                    spawner.spawn(future);
+                }
+                Ok(promise.into())
+            })
+        };
+        let close = new_function(ctx, move |ctx, _this, _arguments| {
+            let mut state = source.borrow_mut();
+            state.finished = true;
+            let poll_next = state.poll_next.take();
+            let waiting = mem::take(&mut state.waiting);
+            drop(state);
+            drop(poll_next);
+            finish(ctx, waiting);
+            Ok(JSValue::new_undefined(ctx))
+        });
+
+        let make = evaluate_script(ctx, MAKE_ITERATOR, None, "async_iterator.js", 1)?;
+        make.as_object()?
+            .call_as_function(None, &[next.into(), close.into()])?
+            .as_object()
+    }
+}
+
+/// The items of a JavaScript async iterable, as Rust futures.
+///
+/// This is made by [`JSValue::async_iter`], and follows the protocol
+/// that `for await` does. With the `futures-core` feature, it
+/// implements the `Stream` trait, so that the combinators of the
+/// `futures` crate, such as those of `StreamExt`, work with it.
+/// Otherwise, its [`poll_next`] has the same shape. Dropping it before
+/// the end calls `return` on the iterator, as leaving a `for await`
+/// loop early does, so that an async generator can clean up.
+///
+/// Like the values that it yields, it must stay on the thread of its
+/// context.
+///
+/// [`JSValue::async_iter`]: struct.JSValue.html#method.async_iter
+/// [`poll_next`]: #method.poll_next
+#[cfg(feature = "async")]
+pub struct AsyncIteratorStream {
+    ctx: JSContext,
+    /// The iterator, which is protected from garbage collection until
+    /// the iteration is over, and then null.
+    iterator: sys::JSObjectRef,
+    /// The methods of the iterator that get the next value and that end
+    /// the iteration early.
+    methods: (&'static str, &'static str),
+    /// The result of the call to `next` that is waited for.
+    next: Option<PromiseFuture>,
+}
+
+#[cfg(feature = "async")]
+impl AsyncIteratorStream {
+    /// Iterates with `iterator`, calling the method `next` for each
+    /// value and the method `close`, if there is one, to end early.
+    pub(crate) fn new(
+        ctx: &JSContext,
+        iterator: &JSObject,
+        next: &'static str,
+        close: &'static str,
+    ) -> Self {
+        unsafe { sys::JSValueProtect(ctx.raw, iterator.raw) };
+        AsyncIteratorStream {
+            ctx: ctx.clone(),
+            iterator: iterator.raw,
+            methods: (next, close),
+            next: None,
+        }
+    }
+
+    /// Polls for the next value of the iterator.
+    ///
+    /// Returns `None` once the iterator is done. An exception that a
+    /// call to `next` throws, or a rejection of its result, ends the
+    /// iteration after it is returned.
+    pub fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<JSValue, JSException>>> {
+        loop {
+            if let Some(next) = self.next.as_mut() {
+                let result = match Pin::new(next).poll(cx) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => return Poll::Pending,
+                };
+                self.next = None;
+                return Poll::Ready(match result.and_then(|r| self.read_result(r)) {
+                    Ok(Some(value)) => Some(Ok(value)),
+                    Ok(None) => {
+                        self.finish(false);
+                        None
+                    }
+                    Err(e) => {
+                        self.finish(false);
+                        Some(Err(e))
+                    }
+                });
+            }
+            if self.iterator.is_null() {
+                return Poll::Ready(None);
+            }
+            match self.call_next() {
+                Ok(next) => self.next = Some(next),
+                Err(e) => {
+                    self.finish(false);
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+        }
+    }
+
+    /// Waits for the next value of the iterator.
+    ///
+    /// See [`poll_next`] for how the iteration ends.
+    ///
+    /// [`poll_next`]: #method.poll_next
+    pub async fn next(&mut self) -> Option<Result<JSValue, JSException>> {
+        future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Calls `next` on the iterator, and returns its result as a future.
+    fn call_next(&self) -> Result<PromiseFuture, JSException> {
+        let iterator = JSObject::from_raw(self.ctx.raw, self.iterator);
+        let next = iterator.get_property(self.methods.0)?.as_object()?;
+        let result = next.call_as_function(Some(&iterator), &[])?;
+        let promise = match result.as_promise() {
+            Ok(promise) => promise,
+            Err(_) => {
+                // Resolving a promise with a thenable adopts its state,
+                // and any other value fulfills it.
+                let (promise, deferred) = JSPromise::deferred(&self.ctx)?;
+                deferred.resolve(result);
+                promise
+            }
+        };
+        Ok(promise.into_future())
+    }
+
+    /// Gets the value of the result of an iteration, or `None` if the
+    /// iterator is done.
+    fn read_result(&self, result: JSValue) -> Result<Option<JSValue>, JSException> {
+        if !result.is_object() {
+            return Err(JSException::type_error(
+                &self.ctx,
+                "Iterator result is not an object",
+            ));
+        }
+        let result = result.as_object()?;
+        if result.get_property("done")?.as_boolean() {
+            return Ok(None);
+        }
+        result.get_property("value").map(Some)
+    }
+
+    /// Ends the iteration, first calling `return` on the iterator if
+    /// `close` is set.
+    fn finish(&mut self, close: bool) {
+        let raw = mem::replace(&mut self.iterator, ptr::null_mut());
+        if raw.is_null() {
+            return;
+        }
+        if close {
+            let iterator = JSObject::from_raw(self.ctx.raw, raw);
+            if let Ok(function) = iterator
+                .get_property(self.methods.1)
+                .and_then(|r| r.as_object())
+            {
+                if function.is_function() {
+                    // Like `for await`, this does not wait for `return`.
+                    let _ = function.call_as_function(Some(&iterator), &[]);
+                }
+            }
+        }
+        unsafe { sys::JSValueUnprotect(self.ctx.raw, raw) };
+    }
+}
+
+/// Requires the `futures-core` feature.
+#[cfg(feature = "futures-core")]
+impl futures_core::Stream for AsyncIteratorStream {
+    type Item = Result<JSValue, JSException>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        AsyncIteratorStream::poll_next(self, cx)
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for AsyncIteratorStream {
+    fn drop(&mut self) {
+        self.next = None;
+        self.finish(true);
+    }
+}
+
+#[cfg(feature = "async")]
+impl JSValue {
+    /// Starts iterating over this async iterable, such as an async
+    /// generator, from Rust.
+    ///
+    /// Fails with a `TypeError` if the value is not async iterable.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// # use std::future::Future;
+    /// # use std::pin::pin;
+    /// # use std::task::{Context, Poll, Waker};
+    /// let ctx = JSContext::default();
+    /// let script = "(async function* () { yield 'a'; yield 'b'; })()";
+    /// let generator = evaluate_script(&ctx, script, None, "test.js", 1).expect("generator");
+    /// let mut stream = generator.async_iter().expect("async iterable");
+    ///
+    /// let mut items = vec![];
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// while let Poll::Ready(Some(item)) = pin!(stream.next()).poll(&mut cx) {
+    ///     items.push(item.expect("item").as_string().expect("string").to_string());
+    /// }
+    /// assert_eq!(items, ["a", "b"]);
+    /// ```
+    pub fn async_iter(&self) -> Result<AsyncIteratorStream, JSException> {
+        let ctx = unsafe { JSContext::from_context_ref(self.ctx) };
+        let get = evaluate_script(&ctx, GET_ITERATOR, None, "async_iterator.js", 1)?;
+        let iterator = get
+            .as_object()?
+            .call_as_function(
+                None,
+                &[JSValue {
+                    raw: self.raw,
+                    ctx: self.ctx,
+                }],
+            )?
+            .as_object()?;
+        Ok(AsyncIteratorStream::new(&ctx, &iterator, "next", "return"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSContext, JSObject, Spawner};
+    use std::cell::RefCell;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::task::{Context, Poll, Waker};
+
+    type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+    #[derive(Clone, Default)]
+    struct Queue(Rc<RefCell<Vec<Task>>>);
+
+    impl Spawner for Queue {
+        fn spawn(&self, future: Task) {
+            self.0.borrow_mut().push(future);
+        }
+    }
+
+    impl Queue {
+        /// Polls every future once, and keeps those that are pending.
+        fn run(&self) {
+            let mut pending = vec![];
+            for mut future in self.0.take() {
+                let mut cx = Context::from_waker(Waker::noop());
+                if future.as_mut().poll(&mut cx).is_pending() {
+                    pending.push(future);
+                }
+            }
+            self.0.borrow_mut().extend(pending);
+        }
+    }
+
+    fn eval(ctx: &JSContext, script: &str) -> String {
+        let r = evaluate_script(ctx, script, None, "test.js", 1).unwrap();
+        r.to_json_string(0).unwrap().to_string()
+    }
+
+    #[test]
+    fn new_async_iterator() {
+        let ctx = JSContext::default();
+        let queue = Queue::default();
+        let items = Rc::new(RefCell::new(Vec::<Option<f64>>::new()));
+        let source = items.clone();
+        let iterator = JSObject::new_async_iterator(&ctx, queue.clone(), move |_cx| {
+            let mut items = source.borrow_mut();
+            if items.is_empty() {
+                Poll::Pending
+            } else {
+                Poll::Ready(items.remove(0))
+            }
+        })
+        .unwrap();
+        ctx.global_object()
+            .set_property("numbers", iterator.into())
+            .unwrap();
+
+        eval(
+            &ctx,
+            "globalThis.seen = [];
+             (async () => {
+                 for await (const n of numbers) seen.push(n);
+                 seen.push('end');
+             })(); 0",
+        );
+        queue.run();
+        assert_eq!(eval(&ctx, "seen"), "[]");
+
+        items.borrow_mut().extend([Some(1.0), Some(2.0)]);
+        queue.run();
+        queue.run();
+        assert_eq!(eval(&ctx, "seen"), "[1,2]");
+
+        items.borrow_mut().push(None);
+        queue.run();
+        assert_eq!(eval(&ctx, "seen"), r#"[1,2,"end"]"#);
+        assert!(queue.0.borrow().is_empty());
+
+        // Once done, the iterator stays done.
+        eval(&ctx, "numbers.next().then(r => globalThis.after = r); 0");
+        assert_eq!(eval(&ctx, "after"), r#"{"done":true}"#);
+    }
+
+    #[test]
+    fn closed_async_iterator() {
+        let ctx = JSContext::default();
+        let queue = Queue::default();
+        let dropped = Rc::new(RefCell::new(false));
+        let guard = Guard(dropped.clone());
+        let mut n = 0.0;
+        let iterator = JSObject::new_async_iterator(&ctx, queue.clone(), move |_cx| {
+            let _ = &guard;
+            n += 1.0;
+            Poll::Ready(Some(n))
+        })
+        .unwrap();
+        ctx.global_object()
+            .set_property("counter", iterator.into())
+            .unwrap();
+
+        eval(
+            &ctx,
+            "(async () => {
+                 for await (const n of counter) {
+                     if (n == 2) break;
+                 }
+             })(); 0",
+        );
+        while !queue.0.borrow().is_empty() {
+            queue.run();
+        }
+        assert!(*dropped.borrow());
+        eval(
+            &ctx,
+            "counter.next().then(r => globalThis.after = r.done); 0",
+        );
+        assert_eq!(eval(&ctx, "after"), "true");
+
+        struct Guard(Rc<RefCell<bool>>);
+
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                *self.0.borrow_mut() = true;
+            }
+        }
+    }
+
+    #[test]
+    fn dropped_driver() {
+        let ctx = JSContext::default();
+        let queue = Queue::default();
+        let iterator =
+            JSObject::new_async_iterator(&ctx, queue.clone(), |_cx| Poll::<Option<f64>>::Pending)
+                .unwrap();
+        ctx.global_object()
+            .set_property("never", iterator.into())
+            .unwrap();
+
+        eval(
+            &ctx,
+            "never.next().catch(e => globalThis.message = e.message); 0",
+        );
+        queue.run();
+        queue.0.borrow_mut().clear();
+        assert_eq!(
+            eval(&ctx, "message"),
+            r#""The operation was dropped before it completed""#
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_iter() {
+        use super::super::JSValue;
+        use std::pin::pin;
+
+        let ctx = JSContext::default();
+        let mut cx = Context::from_waker(Waker::noop());
+        let generator = evaluate_script(
+            &ctx,
+            "globalThis.log = [];
+             (async function* () {
+                 try {
+                     yield 1;
+                     yield await Promise.resolve(2);
+                     yield 3;
+                 } finally {
+                     log.push('cleaned up');
+                 }
+             })()",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        let mut stream = generator.async_iter().unwrap();
+        for expected in [1.0, 2.0] {
+            match pin!(stream.next()).poll(&mut cx) {
+                Poll::Ready(Some(Ok(v))) => assert_eq!(v.as_number().unwrap(), expected),
+                _ => panic!("the generator should have yielded {}", expected),
+            }
+        }
+        drop(stream);
+        assert_eq!(eval(&ctx, "log"), r#"["cleaned up"]"#);
+
+        let failing = evaluate_script(
+            &ctx,
+            "({ [Symbol.asyncIterator]() { return { next: () => Promise.reject(new RangeError('no')) } } })",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        let mut stream = failing.async_iter().unwrap();
+        match pin!(stream.next()).poll(&mut cx) {
+            Poll::Ready(Some(Err(e))) => assert_eq!(e.name(), Some("RangeError")),
+            _ => panic!("the iteration should have failed"),
+        }
+        assert!(matches!(
+            pin!(stream.next()).poll(&mut cx),
+            Poll::Ready(None)
+        ));
+
+        let e = JSValue::new_number(&ctx, 1.0).async_iter().err().unwrap();
+        assert_eq!(e.name(), Some("TypeError"));
+    }
+
+    #[cfg(feature = "futures-core")]
+    #[test]
+    fn stream() {
+        use futures_core::Stream;
+
+        let ctx = JSContext::default();
+        let mut cx = Context::from_waker(Waker::noop());
+        let generator = evaluate_script(
+            &ctx,
+            "(async function* () { yield 'a'; yield 'b'; })()",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        let mut stream = generator.async_iter().unwrap();
+        let mut items = vec![];
+        while let Poll::Ready(Some(item)) = Stream::poll_next(Pin::new(&mut stream), &mut cx) {
+            items.push(item.unwrap().as_string().unwrap().to_string());
+        }
+        assert_eq!(items, ["a", "b"]);
+        assert_eq!(stream.size_hint(), (0, None));
+    }
+}
@@ -4,8 +4,12 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use super::{JSContext, JSException, JSObject, JSString, JSValue};
+use super::exception::error;
+#[cfg(feature = "async")]
+use super::JSPromise;
+use super::{EvaluateOptions, JSContext, JSException, JSObject, JSString, JSValue, SourceType};
 use crate::sys;
+use std::os::raw::c_uint;
 use std::ptr;
 
 /// Evaluates a string of JavaScript.
@@ -43,30 +47,340 @@ pub fn evaluate_script<S: Into<JSString>, U: Into<JSString>>(
     source_url: U,
     starting_line_number: i32,
 ) -> Result<JSValue, JSException> {
-    unsafe {
+    let (script, source_url) = (script.into(), source_url.into());
+    ctx.hooks.run(|| unsafe {
         let mut e: sys::JSValueRef = ptr::null_mut();
         let r = sys::JSEvaluateScript(
             ctx.raw,
-            script.into().raw,
+            script.raw,
             this_object.map(|t| t.raw).unwrap_or(ptr::null_mut()),
-            source_url.into().raw,
+            source_url.raw,
             starting_line_number,
             &mut e,
         );
         if r.is_null() {
-            Err(JSException {
-                value: JSValue {
-                    raw: e,
-                    ctx: ctx.raw,
-                },
-            })
+            Err(JSException::from_raw(ctx.raw, e))
         } else {
             Ok(JSValue {
                 raw: r,
                 ctx: ctx.raw,
             })
         }
+    })
+}
+
+/// What strict scripts that start on the first line are prefixed with.
+const STRICT_PREFIX: &str = "'use strict'; ";
+
+/// Evaluates a string of JavaScript with the given options.
+///
+/// * `ctx`: The execution context to use.
+/// * `script`: A string containing the script to evaluate.
+/// * `this_object`: The optional object to use as `this`, or `None` to
+///   use the global object as `this`.
+/// * `source_url`: An optional string containing a URL for the script's
+///   source file. This is used by debuggers and when reporting
+///   exceptions.
+/// * `starting_line_number`: An integer value specifying the script's
+///   starting line number in the file located at `source_url`.
+/// * `options`: How to evaluate the script.
+///
+/// Strict mode lets embedders hold third-party code to stricter
+/// semantics than it asks for, for example making assignments to
+/// undeclared variables throw rather than create globals. Line and
+/// column numbers in exceptions are the same as without strict mode.
+///
+/// Returns either the [`JSValue`] that results from evaluating the script or
+/// the exception that occurred. See [`SourceType::Module`] for the
+/// limits on evaluating modules.
+///
+/// ```
+/// use javascriptcore::*;
+///
+/// let ctx = JSContext::default();
+/// let options = EvaluateOptions {
+///     strict: true,
+///     ..Default::default()
+/// };
+/// let r = evaluate_script_with_options(&ctx, "leak = 1", None, "test.js", 1, options);
+/// assert!(r.is_err());
+/// ```
+///
+/// [`JSValue`]: struct.JSValue.html
+/// [`SourceType::Module`]: enum.SourceType.html#variant.Module
+pub fn evaluate_script_with_options<S: Into<JSString>, U: Into<JSString>>(
+    ctx: &JSContext,
+    script: S,
+    this_object: Option<&JSObject>,
+    source_url: U,
+    starting_line_number: i32,
+    options: EvaluateOptions,
+) -> Result<JSValue, JSException> {
+    ctx.hooks.run(|| {
+        if options.source_type == SourceType::Module {
+            return Err(error(ctx, "Evaluating modules is not supported"));
+        }
+        if !options.strict {
+            return evaluate_script(ctx, script, this_object, source_url, starting_line_number);
+        }
+        let script = script.into();
+        if starting_line_number > 1 {
+            // The directive goes on a line of its own, just above the
+            // script, so that lines and columns stay the same.
+            let mut units = "'use strict';\n".encode_utf16().collect::<Vec<u16>>();
+            units.extend_from_slice(script.utf16());
+            return evaluate_script(
+                ctx,
+                JSString::from_utf16(&units),
+                this_object,
+                source_url,
+                starting_line_number - 1,
+            );
+        }
+        // There is no line above the first one, so the directive goes on
+        // the first line, and the columns on that line are corrected.
+        let source_url = source_url.into();
+        let url = source_url.to_string();
+        let mut units = STRICT_PREFIX.encode_utf16().collect::<Vec<u16>>();
+        units.extend_from_slice(script.utf16());
+        evaluate_script(
+            ctx,
+            JSString::from_utf16(&units),
+            this_object,
+            source_url,
+            starting_line_number,
+        )
+        .map_err(|e| e.without_prefix(&url, STRICT_PREFIX.len() as u32))
+    })
+}
+
+/// Evaluates a string of JavaScript, and waits for its result if it is
+/// a promise or another thenable.
+///
+/// * `ctx`: The execution context to use.
+/// * `script`: A string containing the script to evaluate.
+/// * `this_object`: The optional object to use as `this`, or `None` to
+///   use the global object as `this`.
+/// * `source_url`: An optional string containing a URL for the script's
+///   source file. This is used by debuggers and when reporting
+///   exceptions.
+/// * `starting_line_number`: An integer value specifying the script's
+///   starting line number in the file located at `source_url`.
+///
+/// This is what users of a REPL expect from top-level async code: the
+/// result of `fetchData().then(parse)` is the parsed data rather than a
+/// promise of it. A value that is not thenable is returned as it is.
+/// JavaScriptCore runs the jobs of the context whenever the host calls
+/// into it, so the future completes once whatever settles the promise,
+/// such as timers or spawned futures, has run.
+///
+/// Returns either the settled value, or the exception that occurred or
+/// the reason that the promise was rejected with.
+///
+/// ```
+/// # use javascriptcore::*;
+/// # use std::future::Future;
+/// # use std::task::{Context, Poll, Waker};
+/// let ctx = JSContext::default();
+/// let script = "Promise.resolve(20).then(n => n * 2 + 2)";
+/// let mut future = Box::pin(evaluate_script_async(&ctx, script, None, "test.js", 1));
+/// let mut cx = Context::from_waker(Waker::noop());
+/// match future.as_mut().poll(&mut cx) {
+///     Poll::Ready(r) => assert_eq!(r.expect("fulfilled").as_number().expect("number"), 42.0),
+///     Poll::Pending => unreachable!(),
+/// }
+/// ```
+#[cfg(feature = "async")]
+pub async fn evaluate_script_async<S: Into<JSString>, U: Into<JSString>>(
+    ctx: &JSContext,
+    script: S,
+    this_object: Option<&JSObject>,
+    source_url: U,
+    starting_line_number: i32,
+) -> Result<JSValue, JSException> {
+    let value = evaluate_script(ctx, script, this_object, source_url, starting_line_number)?;
+    if let Ok(promise) = value.as_promise() {
+        return promise.await;
     }
+    if !value.is_object() {
+        return Ok(value);
+    }
+    let then = value.as_object()?.get_property("then")?;
+    if !then.as_object().is_ok_and(|then| then.is_function()) {
+        return Ok(value);
+    }
+    // Resolving a promise with a thenable adopts its state.
+    let (promise, deferred) = JSPromise::deferred(ctx)?;
+    deferred.resolve(value);
+    promise.await
+}
+
+/// Evaluates a string of JavaScript with the properties of an object
+/// visible as variables.
+///
+/// * `ctx`: The execution context to use.
+/// * `script`: A string containing the script to evaluate.
+/// * `scope`: The object whose enumerable properties become variables.
+/// * `source_url`: A URL for the script's source file, which is used
+///   when reporting exceptions, as with [`evaluate_script`].
+/// * `starting_line_number`: The script's starting line number in the
+///   file located at `source_url`.
+///
+/// The script is evaluated by a generated function that takes the
+/// properties of `scope` as parameters, so the variables hold the values
+/// that the properties had when evaluation started, and assigning to
+/// them does not change `scope`. Variables and functions declared by
+/// the script stay local to the evaluation rather than becoming globals.
+///
+/// Properties whose names can not be used as variable names, such as
+/// `"a-b"`, `"if"` or `"eval"`, are skipped.
+///
+/// Returns either the [`JSValue`] that results from evaluating the script or
+/// the exception that occurred.
+///
+/// ```
+/// use javascriptcore::*;
+///
+/// let ctx = JSContext::default();
+/// let scope = JSObject::new(&ctx);
+/// scope.set_property("name", JSValue::new_string(&ctx, "World")).expect("set");
+///
+/// let r = evaluate_with_scope(&ctx, "`Hello, ${name}!`", &scope, "template.js", 1)
+///     .expect("evaluated");
+/// assert_eq!(r.as_string().expect("string"), "Hello, World!");
+/// ```
+///
+/// [`evaluate_script`]: fn.evaluate_script.html
+/// [`JSValue`]: struct.JSValue.html
+pub fn evaluate_with_scope<S: Into<JSString>, U: Into<JSString>>(
+    ctx: &JSContext,
+    script: S,
+    scope: &JSObject,
+    source_url: U,
+    starting_line_number: i32,
+) -> Result<JSValue, JSException> {
+    let source_url = source_url.into();
+    ctx.hooks.run(|| {
+        let mut names = Vec::new();
+        let mut arguments = Vec::new();
+        for name in scope.property_names() {
+            let s = name.to_string();
+            if is_binding_name(&s) {
+                arguments.push(scope.get_property(s.as_str())?);
+                names.push(name);
+            }
+        }
+        // The script is passed as an argument so that its source is not
+        // parsed as part of the function. Code run by `eval` has no
+        // source URL and counts lines from its own start, so the script
+        // is moved down to its starting line, and errors that it throws
+        // are given the source URL.
+        let n = names.len();
+        let body = JSString::from(
+            format!(
+                "try {{
+                    return eval(arguments[{}]);
+                }} catch (e) {{
+                    if (e !== null && typeof e === 'object' && 'line' in e && !('sourceURL' in e)) {{
+                        e.sourceURL = arguments[{}];
+                    }}
+                    throw e;
+                }}",
+                n,
+                n + 1
+            )
+            .as_str(),
+        );
+        let mut units = vec![u16::from(b'\n'); starting_line_number.max(1) as usize - 1];
+        units.extend_from_slice(script.into().utf16());
+        arguments.push(JSValue::new_string(ctx, JSString::from_utf16(&units)));
+        arguments.push(JSValue {
+            raw: unsafe { sys::JSValueMakeString(ctx.raw, source_url.raw) },
+            ctx: ctx.raw,
+        });
+
+        let names: Vec<sys::JSStringRef> = names.iter().map(|n| n.raw).collect();
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        let function = unsafe {
+            sys::JSObjectMakeFunction(
+                ctx.raw,
+                ptr::null_mut(),
+                names.len() as c_uint,
+                names.as_ptr(),
+                body.raw,
+                source_url.raw,
+                starting_line_number,
+                &mut e,
+            )
+        };
+        if function.is_null() {
+            return Err(JSException::from_raw(ctx.raw, e));
+        }
+        JSObject::from_raw(ctx.raw, function).call_as_function(None, &arguments)
+    })
+}
+
+/// Words that can not be used as parameter names.
+const RESERVED_WORDS: &[&str] = &[
+    "arguments",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "eval",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "let",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+/// Tests whether `name` can be used as a parameter name.
+fn is_binding_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_well = chars
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$');
+    starts_well
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+        && !RESERVED_WORDS.contains(&name)
 }
 
 /// Checks for syntax errors in a string of JavaScript.
@@ -112,12 +426,7 @@ pub fn check_script_syntax<S: Into<JSString>, U: Into<JSString>>(
         if r {
             Ok(())
         } else {
-            Err(JSException {
-                value: JSValue {
-                    raw: e,
-                    ctx: ctx.raw,
-                },
-            })
+            Err(JSException::from_raw(ctx.raw, e))
         }
     }
 }
@@ -153,7 +462,10 @@ pub fn garbage_collect(ctx: &JSContext) {
 
 #[cfg(test)]
 mod tests {
-    use super::{check_script_syntax, evaluate_script, garbage_collect, JSContext};
+    use super::{
+        check_script_syntax, evaluate_script, evaluate_script_with_options, evaluate_with_scope,
+        garbage_collect, EvaluateOptions, JSContext, JSObject, JSValue, SourceType,
+    };
 
     #[test]
     fn can_check_script_syntax() {
@@ -174,6 +486,153 @@ mod tests {
         assert_eq!(r.unwrap().as_number().unwrap(), 4.0);
     }
 
+    #[test]
+    fn can_evaluate_script_in_strict_mode() {
+        let ctx = JSContext::default();
+        let strict = EvaluateOptions {
+            strict: true,
+            ..Default::default()
+        };
+
+        let r = evaluate_script_with_options(
+            &ctx,
+            "(function () { return this; })()",
+            None,
+            "test.js",
+            1,
+            strict,
+        );
+        assert!(r.unwrap().is_undefined());
+        let r = evaluate_script_with_options(&ctx, "2 + 2", None, "test.js", 1, strict);
+        assert_eq!(r.unwrap().as_number().unwrap(), 4.0);
+
+        let e = evaluate_script_with_options(&ctx, "\n\nleak = 1", None, "test.js", 10, strict)
+            .unwrap_err();
+        assert_eq!(e.name(), Some("ReferenceError"));
+        assert_eq!(e.line(), Some(12));
+        let column = e.column();
+        // Lines and columns do not depend on whether the script starts on
+        // the first line.
+        let e = evaluate_script_with_options(&ctx, "\n\nleak = 1", None, "test.js", 1, strict)
+            .unwrap_err();
+        assert_eq!(e.line(), Some(3));
+        assert_eq!(e.column(), column);
+        let first = evaluate_script_with_options(&ctx, "  leak = 1", None, "test.js", 1, strict)
+            .unwrap_err();
+        let second = evaluate_script_with_options(&ctx, "  leak = 1", None, "test.js", 2, strict)
+            .unwrap_err();
+        assert_eq!(first.line(), Some(1));
+        assert_eq!(second.line(), Some(2));
+        assert_eq!(first.column(), second.column());
+        let r = evaluate_script(&ctx, "typeof leak", None, "test.js", 1);
+        assert_eq!(r.unwrap().as_string().unwrap(), "undefined");
+
+        // Without the option, the same script is sloppy.
+        let r = evaluate_script_with_options(
+            &ctx,
+            "leak = 1",
+            None,
+            "test.js",
+            1,
+            EvaluateOptions::default(),
+        );
+        assert!(r.is_ok());
+    }
+
+    #[test]
+    fn can_not_evaluate_modules() {
+        let ctx = JSContext::default();
+        let module = EvaluateOptions {
+            source_type: SourceType::Module,
+            ..Default::default()
+        };
+        let r = evaluate_script_with_options(&ctx, "export default 1;", None, "test.js", 1, module);
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn can_evaluate_with_scope() {
+        let ctx = JSContext::default();
+        let scope = JSObject::new(&ctx);
+        scope
+            .set_property("a", JSValue::new_number(&ctx, 1.0))
+            .unwrap();
+        scope
+            .set_property("b", JSValue::new_number(&ctx, 2.0))
+            .unwrap();
+        scope
+            .set_property("not-a-name", JSValue::new_number(&ctx, 3.0))
+            .unwrap();
+        scope
+            .set_property("eval", JSValue::new_number(&ctx, 4.0))
+            .unwrap();
+
+        let r = evaluate_with_scope(&ctx, "var c = a + b; c * 10", &scope, "test.js", 1);
+        assert_eq!(r.unwrap().as_number().unwrap(), 30.0);
+
+        // Declarations do not leak into the global object, and
+        // assignments do not change the scope.
+        let r = evaluate_with_scope(&ctx, "a = 5; typeof c", &scope, "test.js", 1);
+        assert_eq!(r.unwrap().as_string().unwrap(), "undefined");
+        assert_eq!(scope.get_property("a").unwrap().as_number().unwrap(), 1.0);
+        let r = evaluate_script(&ctx, "typeof c", None, "test.js", 1);
+        assert_eq!(r.unwrap().as_string().unwrap(), "undefined");
+
+        // Exceptions tell where in the source file they were thrown.
+        let e =
+            evaluate_with_scope(&ctx, "a;\nthrow new Error()", &scope, "view.js", 10).unwrap_err();
+        assert_eq!(e.source_url(), Some("view.js"));
+        assert_eq!(e.line(), Some(11));
+        let e = evaluate_with_scope(&ctx, "syntax error", &scope, "view.js", 3).unwrap_err();
+        assert_eq!(e.source_url(), Some("view.js"));
+        assert_eq!(e.line(), Some(3));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn can_evaluate_script_async() {
+        use super::super::{evaluate_script_async, JSPromise};
+        use std::future::Future;
+        use std::task::{Context, Poll, Waker};
+
+        let ctx = JSContext::default();
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut poll = |script: &str| {
+            let mut future = Box::pin(evaluate_script_async(&ctx, script, None, "test.js", 1));
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(r) => r,
+                Poll::Pending => panic!("{} should have settled", script),
+            }
+        };
+
+        assert_eq!(poll("1 + 1").unwrap().as_number().unwrap(), 2.0);
+        let r = poll("({ then(resolve) { resolve('thenable') } })");
+        assert_eq!(r.unwrap().as_string().unwrap(), "thenable");
+        let r = poll("({ then: 'not a function' })");
+        assert!(r.unwrap().is_object());
+        let e = poll("Promise.reject(new RangeError('rejected'))").unwrap_err();
+        assert_eq!(e.name(), Some("RangeError"));
+        assert!(poll("throw new Error()").is_err());
+
+        let (promise, deferred) = JSPromise::deferred(&ctx).unwrap();
+        ctx.global_object()
+            .set_property("pending", promise.into())
+            .unwrap();
+        let mut future = Box::pin(evaluate_script_async(
+            &ctx,
+            "pending.then(s => s + '!')",
+            None,
+            "test.js",
+            1,
+        ));
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        deferred.resolve(JSValue::new_string(&ctx, "late"));
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(r) => assert_eq!(r.unwrap().as_string().unwrap(), "late!"),
+            Poll::Pending => panic!("the promise should have settled"),
+        }
+    }
+
     #[test]
     fn can_garbage_collect() {
         let ctx = JSContext::default();
@@ -0,0 +1,124 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::value::{is_bigint, new_bigint};
+use super::{FromJs, JSContext, JSException, JSValue, ToJs};
+use num_bigint::{BigInt, BigUint};
+
+/// Reads the digits of a `BigInt`.
+///
+/// Converting a `BigInt` to a string does not call its `toString`
+/// method, which scripts could replace, so the digits are read that way.
+fn from_bigint(ctx: &JSContext, value: &JSValue) -> Result<BigInt, JSException> {
+    if !is_bigint(value) {
+        return Err(JSException::type_error(ctx, "Expected a BigInt"));
+    }
+    let digits = value.as_string()?.to_string();
+    BigInt::parse_bytes(digits.as_bytes(), 10)
+        .ok_or_else(|| JSException::type_error(ctx, "Expected a BigInt"))
+}
+
+/// `BigInt`s convert to and from JavaScript `BigInt`s, of any size.
+///
+/// Numbers are not converted, even when they are whole, so that values
+/// which may have lost precision are not taken for exact ones.
+///
+/// Requires the `num-bigint` feature.
+///
+/// ```
+/// # use javascriptcore::*;
+/// # use num_bigint::BigInt;
+/// let ctx = JSContext::default();
+/// let v = evaluate_script(&ctx, "2n ** 100n", None, "test.js", 1).expect("BigInt");
+/// let n = BigInt::from_js(&ctx, &v).expect("converted");
+/// assert_eq!(n, BigInt::from(2).pow(100));
+///
+/// let v = (-n).to_js(&ctx).expect("BigInt");
+/// ctx.global_object().set_property("n", v).expect("set");
+/// let r = evaluate_script(&ctx, "n === -(2n ** 100n)", None, "test.js", 1).expect("compared");
+/// assert!(r.as_boolean());
+/// ```
+impl FromJs for BigInt {
+    fn from_js(ctx: &JSContext, value: &JSValue) -> Result<Self, JSException> {
+        from_bigint(ctx, value)
+    }
+}
+
+impl ToJs for BigInt {
+    fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        new_bigint(ctx, &self.to_string())
+    }
+}
+
+/// `BigUint`s convert to and from JavaScript `BigInt`s that are not
+/// negative. Negative ones give a `RangeError`.
+///
+/// Requires the `num-bigint` feature.
+impl FromJs for BigUint {
+    fn from_js(ctx: &JSContext, value: &JSValue) -> Result<Self, JSException> {
+        from_bigint(ctx, value)?
+            .to_biguint()
+            .ok_or_else(|| JSException::range_error(ctx, "Expected a BigInt that is not negative"))
+    }
+}
+
+impl ToJs for BigUint {
+    fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        new_bigint(ctx, &self.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, FromJs, JSContext, ToJs};
+    use num_bigint::{BigInt, BigUint};
+
+    #[test]
+    fn bigints() {
+        let ctx = JSContext::default();
+        let global = ctx.global_object();
+        for text in [
+            "0",
+            "-1",
+            "255",
+            "-18446744073709551617",
+            "340282366920938463463374607431768211456",
+        ] {
+            let n: BigInt = text.parse().unwrap();
+            global.set_property("n", n.to_js(&ctx).unwrap()).unwrap();
+            let script = format!("typeof n === 'bigint' && n === {}n", text);
+            let r = evaluate_script(&ctx, &script, None, "test.js", 1).unwrap();
+            assert!(r.as_boolean(), "{}", text);
+            let v = global.get_property("n").unwrap();
+            assert_eq!(BigInt::from_js(&ctx, &v).unwrap(), n);
+        }
+
+        let v = evaluate_script(&ctx, "-5n", None, "test.js", 1).unwrap();
+        let e = BigUint::from_js(&ctx, &v).unwrap_err();
+        assert_eq!(e.name(), Some("RangeError"));
+        let v = evaluate_script(&ctx, "5", None, "test.js", 1).unwrap();
+        let e = BigInt::from_js(&ctx, &v).unwrap_err();
+        assert_eq!(e.message(), Some("Expected a BigInt"));
+        let v = BigUint::from(7u32).to_js(&ctx).unwrap();
+        assert_eq!(BigUint::from_js(&ctx, &v).unwrap(), BigUint::from(7u32));
+    }
+
+    #[test]
+    fn replaced_builtins() {
+        let ctx = JSContext::default();
+        evaluate_script(
+            &ctx,
+            "BigInt.prototype.toString = () => '1'; globalThis.BigInt = () => 1n;",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        let n = BigInt::from(-12345678901234567890i128);
+        let v = n.to_js(&ctx).unwrap();
+        assert_eq!(BigInt::from_js(&ctx, &v).unwrap(), n);
+    }
+}
@@ -0,0 +1,251 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::class::init_global;
+use super::{ContextBuilder, ContextBuilderError, JSClass, JSContext, JSContextGroup};
+use crate::sys;
+use std::error::Error;
+#[cfg(all(target_os = "linux", feature = "runtime-options"))]
+use std::ffi::CString;
+use std::fmt;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Whether the engine has been started, which makes it read its
+/// options. Creating a string, a class, a context or a context group
+/// starts it.
+static ENGINE_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Serializes the setting of options with the starting of the engine.
+static OPTIONS_LOCK: Mutex<()> = Mutex::new(());
+
+/// Records that the engine is being started. This must be called
+/// before each call into the engine that may start it, and waits for a
+/// builder that is setting options.
+pub(crate) fn start_engine() {
+    if !ENGINE_STARTED.load(Ordering::Acquire) {
+        let _lock = OPTIONS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        ENGINE_STARTED.store(true, Ordering::Release);
+    }
+}
+
+impl<'a> ContextBuilder<'a> {
+    /// Creates a builder for a context with the default options.
+    pub fn new() -> Self {
+        ContextBuilder::default()
+    }
+
+    /// Sets the name of the context, as used by debuggers and by
+    /// [`JSContext::set_name`].
+    ///
+    /// [`JSContext::set_name`]: struct.JSContext.html#method.set_name
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Creates the context in `group` rather than in a group of its own.
+    pub fn group(mut self, group: &'a JSContextGroup) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    /// Sets the class of the global object.
+    pub fn global_class(mut self, class: &'a JSClass) -> Self {
+        self.global_class = Some(class);
+        self
+    }
+
+    /// Enables or disables the JIT compilers.
+    ///
+    /// Disabling the JIT is needed on platforms that do not allow
+    /// executable memory to be allocated. Scripts are interpreted then.
+    ///
+    /// This sets the `useJIT` runtime option. See [`option`] for when
+    /// runtime options take effect.
+    ///
+    /// [`option`]: #method.option
+    pub fn jit(self, enabled: bool) -> Self {
+        self.option("useJIT", if enabled { "true" } else { "false" })
+    }
+
+    /// Enables or disables compiling on background threads.
+    ///
+    /// This sets the `useConcurrentJIT` runtime option. See [`option`]
+    /// for when runtime options take effect.
+    ///
+    /// [`option`]: #method.option
+    pub fn concurrent_jit(self, enabled: bool) -> Self {
+        self.option("useConcurrentJIT", if enabled { "true" } else { "false" })
+    }
+
+    /// Sets a runtime option of JavaScriptCore.
+    ///
+    /// * `name`: The name of the option, such as `useWebAssembly`.
+    /// * `value`: The value of the option, such as `false`.
+    ///
+    /// Runtime options also control experimental language features. The
+    /// options that exist differ between versions of JavaScriptCore.
+    ///
+    /// The engine reads its runtime options once, when it starts, which
+    /// it does as soon as the first string, class, context or context
+    /// group of the process is created. Runtime options can therefore
+    /// only be used to build the first context, and they apply to all
+    /// contexts created afterwards.
+    ///
+    /// Setting runtime options requires the `runtime-options` feature,
+    /// which needs the GTK or WPE port of JavaScriptCore. Other builds
+    /// can only take them from `JSC_`-prefixed environment variables,
+    /// such as `JSC_useJIT=false`, which must be set before the process
+    /// starts.
+    pub fn option(mut self, name: &str, value: &str) -> Self {
+        self.options.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Creates the context.
+    ///
+    /// Fails if runtime options have been set but can no longer take
+    /// effect, are not valid or are not known to the engine, or can not
+    /// be set at all.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = ContextBuilder::new().name("worker").build().expect("context built");
+    /// assert_eq!(ctx.name().expect("name"), "worker");
+    /// ```
+    pub fn build(self) -> Result<JSContext, ContextBuilderError> {
+        {
+            let _lock = OPTIONS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            for (name, value) in &self.options {
+                let valid_name =
+                    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric());
+                if !valid_name || value.contains('\0') {
+                    return Err(ContextBuilderError::InvalidOption(name.clone()));
+                }
+            }
+            if !self.options.is_empty() {
+                if ENGINE_STARTED.load(Ordering::Acquire) {
+                    return Err(ContextBuilderError::EngineStarted);
+                }
+                for (name, value) in &self.options {
+                    set_option(name, value)?;
+                }
+            }
+            ENGINE_STARTED.store(true, Ordering::Release);
+        }
+        let raw = unsafe {
+            sys::JSGlobalContextCreateInGroup(
+                self.group.map_or(ptr::null(), |g| g.raw),
+                self.global_class.map_or(ptr::null_mut(), |c| c.raw),
+            )
+        };
+        let ctx = JSContext::from_raw(raw);
+        if let Some(class) = self.global_class {
+            init_global(&ctx, class);
+        }
+        if let Some(name) = self.name {
+            ctx.set_name(name.as_str());
+        }
+        Ok(ctx)
+    }
+}
+
+/// Sets the runtime option `name` to `value`, trying the types of
+/// options that `value` can be, from the most to the least specific.
+#[cfg(all(target_os = "linux", feature = "runtime-options"))]
+fn set_option(name: &str, value: &str) -> Result<(), ContextBuilderError> {
+    let c_name = CString::new(name).unwrap();
+    let option = c_name.as_ptr();
+    let set = unsafe {
+        match value {
+            "true" => sys::jsc_options_set_boolean(option, 1) != 0,
+            "false" => sys::jsc_options_set_boolean(option, 0) != 0,
+            _ => {
+                let integer = value.parse::<i64>().ok();
+                let int = integer.and_then(|n| i32::try_from(n).ok());
+                let uint = integer.and_then(|n| u32::try_from(n).ok());
+                let size = integer.and_then(|n| usize::try_from(n).ok());
+                let c_value = CString::new(value).unwrap();
+                int.is_some_and(|n| sys::jsc_options_set_int(option, n) != 0)
+                    || uint.is_some_and(|n| sys::jsc_options_set_uint(option, n) != 0)
+                    || size.is_some_and(|n| sys::jsc_options_set_size(option, n) != 0)
+                    || value
+                        .parse::<f64>()
+                        .is_ok_and(|n| sys::jsc_options_set_double(option, n) != 0)
+                    || sys::jsc_options_set_string(option, c_value.as_ptr()) != 0
+                    || sys::jsc_options_set_range_string(option, c_value.as_ptr()) != 0
+            }
+        }
+    };
+    if set {
+        Ok(())
+    } else {
+        Err(ContextBuilderError::InvalidOption(name.to_owned()))
+    }
+}
+
+/// Other builds of JavaScriptCore only read runtime options from the
+/// environment, which a library can not safely change while other
+/// threads may be reading it.
+#[cfg(not(all(target_os = "linux", feature = "runtime-options")))]
+fn set_option(_name: &str, _value: &str) -> Result<(), ContextBuilderError> {
+    Err(ContextBuilderError::Unsupported)
+}
+
+impl fmt::Display for ContextBuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ContextBuilderError::EngineStarted => write!(
+                f,
+                "runtime options must be set before the first context is created"
+            ),
+            ContextBuilderError::InvalidOption(name) => {
+                write!(f, "invalid runtime option: {:?}", name)
+            }
+            ContextBuilderError::Unsupported => write!(
+                f,
+                "runtime options can only be set with the runtime-options feature"
+            ),
+        }
+    }
+}
+
+impl Error for ContextBuilderError {}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{ContextBuilder, ContextBuilderError, JSContext, JSContextGroup};
+
+    #[test]
+    fn build() {
+        let group = JSContextGroup::new();
+        let ctx = ContextBuilder::new()
+            .name("worker")
+            .group(&group)
+            .build()
+            .unwrap();
+        assert_eq!(ctx.name().unwrap(), "worker");
+        assert_eq!(ctx.group().raw, group.raw);
+    }
+
+    #[test]
+    fn options_after_start() {
+        let _ctx = JSContext::new();
+        let r = ContextBuilder::new().jit(false).build();
+        assert_eq!(r.err(), Some(ContextBuilderError::EngineStarted));
+    }
+
+    #[test]
+    fn invalid_options() {
+        let r = ContextBuilder::new().option("use JIT", "false").build();
+        assert_eq!(
+            r.err(),
+            Some(ContextBuilderError::InvalidOption("use JIT".to_owned()))
+        );
+    }
+}
@@ -0,0 +1,142 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{JSArrayBuffer, JSContext, JSException, JSTypedArray};
+use bytes::{Bytes, BytesMut};
+use std::ptr;
+use std::sync::atomic::Ordering;
+
+/// Conversions between buffers and the [`Bytes`] of the
+/// [`bytes`](https://crates.io/crates/bytes) crate.
+///
+/// Requires the `bytes` feature.
+///
+/// [`Bytes`]: https://docs.rs/bytes/1/bytes/struct.Bytes.html
+impl JSArrayBuffer {
+    /// Creates an `ArrayBuffer` whose bytes are `bytes`.
+    ///
+    /// Scripts can write to the bytes of a buffer, so the bytes are only
+    /// handed over without copying them when no other `Bytes` shares
+    /// them. Otherwise, the buffer gets a copy of them. A `BytesMut`
+    /// can be handed over with [`from_backing_store`], which never
+    /// copies it.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// use bytes::Bytes;
+    ///
+    /// let ctx = JSContext::default();
+    /// let body = Bytes::from(vec![1, 2, 3, 4]);
+    /// let buffer = JSArrayBuffer::from_bytes(&ctx, body).expect("buffer");
+    /// ctx.global_object().set_property("buffer", buffer.into()).expect("set");
+    ///
+    /// let r = evaluate_script(&ctx, "new Uint8Array(buffer)[3]", None, "test.js", 1).expect("byte");
+    /// assert_eq!(r.as_number().expect("number"), 4.0);
+    /// ```
+    ///
+    /// [`from_backing_store`]: #method.from_backing_store
+    pub fn from_bytes(ctx: &JSContext, bytes: Bytes) -> Result<JSArrayBuffer, JSException> {
+        JSArrayBuffer::from_backing_store(ctx, BytesMut::from(bytes))
+    }
+
+    /// Copies the bytes of the buffer to `Bytes`.
+    ///
+    /// The copy can be kept and sent to other threads, unlike the bytes
+    /// of the buffer, which belong to the garbage collector. Buffers
+    /// that have been detached have no bytes.
+    pub fn to_bytes(&self) -> Bytes {
+        let shared = self.is_shared();
+        // The bytes are copied before JavaScript can run.
+        let bytes = unsafe { self.as_atomic_slice() };
+        if shared {
+            // Workers may write to shared buffers at the same time.
+            return bytes.iter().map(|b| b.load(Ordering::Relaxed)).collect();
+        }
+        let mut copy = BytesMut::zeroed(bytes.len());
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr() as *const u8, copy.as_mut_ptr(), bytes.len())
+        };
+        copy.freeze()
+    }
+}
+
+/// Requires the `bytes` feature.
+impl JSTypedArray {
+    /// Copies the bytes of the elements of the array to `Bytes`.
+    ///
+    /// Only the part of the buffer that the array views is copied.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let v = evaluate_script(&ctx, "new Uint8Array([1, 2, 3, 4]).subarray(1, 3)", None, "test.js", 1)
+    ///     .expect("array");
+    /// let array = v.as_typed_array().expect("typed array");
+    /// assert_eq!(array.to_bytes().expect("bytes"), [2, 3][..]);
+    /// ```
+    pub fn to_bytes(&self) -> Result<Bytes, JSException> {
+        let mut bytes = BytesMut::zeroed(self.byte_length());
+        let n = self.copy_to(&mut bytes)?;
+        bytes.truncate(n);
+        Ok(bytes.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSArrayBuffer, JSContext};
+    use bytes::{Bytes, BytesMut};
+
+    #[test]
+    fn from_bytes() {
+        let ctx = JSContext::default();
+
+        let unique = Bytes::from(vec![1, 2, 3]);
+        let ptr = unique.as_ptr();
+        let buffer = JSArrayBuffer::from_bytes(&ctx, unique).unwrap();
+        assert_eq!(
+            unsafe { buffer.as_atomic_slice() }.as_ptr() as *const u8,
+            ptr
+        );
+
+        let shared = Bytes::from(vec![1, 2, 3]);
+        let buffer = JSArrayBuffer::from_bytes(&ctx, shared.clone()).unwrap();
+        ctx.global_object()
+            .set_property("buffer", buffer.into())
+            .unwrap();
+        let v = evaluate_script(
+            &ctx,
+            "new Uint8Array(buffer)[0] = 7; buffer",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(v.as_array_buffer().unwrap().to_bytes(), [7, 2, 3][..]);
+        assert_eq!(shared, [1, 2, 3][..]);
+
+        let store = BytesMut::from(&b"abc"[..]);
+        let buffer = JSArrayBuffer::from_backing_store(&ctx, store).unwrap();
+        assert_eq!(buffer.to_bytes(), "abc");
+    }
+
+    #[test]
+    fn to_bytes() {
+        let ctx = JSContext::default();
+        let v = evaluate_script(&ctx, "new Uint16Array([1, 2, 3])", None, "test.js", 1).unwrap();
+        let array = v.as_typed_array().unwrap();
+        let bytes = array.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 6);
+        assert_eq!(bytes, array.buffer().unwrap().to_bytes());
+
+        // The copy outlives the buffer.
+        array.buffer().unwrap().detach().ok();
+        assert_eq!(&bytes[..2], 1u16.to_ne_bytes());
+
+        let v = evaluate_script(&ctx, "new ArrayBuffer(0)", None, "test.js", 1).unwrap();
+        assert!(v.as_array_buffer().unwrap().to_bytes().is_empty());
+    }
+}
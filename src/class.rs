@@ -4,11 +4,2132 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use super::JSClass;
-use crate::sys;
+use super::builder::start_engine;
+use super::exception::{error_in, panic_error, throw};
+use super::function::{self, arguments_from_raw, Class};
+use super::{
+    JSClass, JSClassBuilder, JSContext, JSException, JSObject, JSPropertyNameAccumulator, JSString,
+    JSValue, PropertyAttributes,
+};
+use crate::sys::{self, JSType};
+use std::any::Any;
+use std::cell::OnceCell;
+use std::ffi::CString;
+use std::os::raw::{c_uint, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+/// The longest chain of parent classes that a class can have.
+///
+/// JavaScriptCore calls the callbacks of each class in the chain, and
+/// does not tell the callbacks which class they belong to. Each class
+/// therefore gets the callbacks for its depth in the chain, which find
+/// the Rust callbacks of that class from the object's private data.
+const MAX_DEPTH: usize = 16;
+
+/// Picks the instance of a callback for classes at `depth`.
+macro_rules! at_depth {
+    ($f:ident, $depth:expr) => {
+        match $depth {
+            0 => $f::<0>,
+            1 => $f::<1>,
+            2 => $f::<2>,
+            3 => $f::<3>,
+            4 => $f::<4>,
+            5 => $f::<5>,
+            6 => $f::<6>,
+            7 => $f::<7>,
+            8 => $f::<8>,
+            9 => $f::<9>,
+            10 => $f::<10>,
+            11 => $f::<11>,
+            12 => $f::<12>,
+            13 => $f::<13>,
+            14 => $f::<14>,
+            15 => $f::<15>,
+            _ => unreachable!(),
+        }
+    };
+}
+
+type Initialize = dyn Fn(&JSContext, &JSObject);
+type Finalize = dyn Fn(&mut dyn Any);
+type Call = dyn Fn(&JSContext, &JSObject, &JSObject, &[JSValue]) -> Result<JSValue, JSException>;
+type Construct = dyn Fn(&JSContext, &JSObject, &[JSValue]) -> Result<JSObject, JSException>;
+type HasInstance = dyn Fn(&JSContext, &JSObject, &JSValue) -> Result<bool, JSException>;
+type HasProperty = dyn Fn(&JSContext, &JSObject, &str) -> bool;
+type GetProperty = dyn Fn(&JSContext, &JSObject, &str) -> Result<Option<JSValue>, JSException>;
+type SetProperty = dyn Fn(&JSContext, &JSObject, &str, &JSValue) -> Result<bool, JSException>;
+type DeleteProperty = dyn Fn(&JSContext, &JSObject, &str) -> Result<bool, JSException>;
+type GetPropertyNames = dyn Fn(&JSContext, &JSObject, &mut JSPropertyNameAccumulator);
+type ConvertToType = dyn Fn(&JSContext, &JSObject, JSType) -> Result<Option<JSValue>, JSException>;
+type GetValue = dyn Fn(&JSContext, &JSObject) -> Result<JSValue, JSException>;
+type SetValue = dyn Fn(&JSContext, &JSObject, &JSValue) -> Result<(), JSException>;
+
+/// A property whose value is got and set by Rust callbacks.
+struct StaticValue {
+    name: CString,
+    get: Box<GetValue>,
+    set: Option<Box<SetValue>>,
+    attributes: PropertyAttributes,
+}
+
+/// A function on the prototype of a class.
+struct StaticFunction {
+    name: CString,
+    call: unsafe extern "C" fn(
+        sys::JSContextRef,
+        sys::JSObjectRef,
+        sys::JSObjectRef,
+        usize,
+        *const sys::JSValueRef,
+        *mut sys::JSValueRef,
+    ) -> sys::JSValueRef,
+    attributes: PropertyAttributes,
+}
+
+/// The Rust callbacks of a class.
+#[derive(Default)]
+pub(crate) struct Callbacks {
+    initialize: Option<Box<Initialize>>,
+    finalize: Option<Box<Finalize>>,
+    call: Option<Box<Call>>,
+    construct: Option<Box<Construct>>,
+    has_instance: Option<Box<HasInstance>>,
+    has_property: Option<Box<HasProperty>>,
+    get_property: Option<Box<GetProperty>>,
+    set_property: Option<Box<SetProperty>>,
+    delete_property: Option<Box<DeleteProperty>>,
+    get_property_names: Option<Box<GetPropertyNames>>,
+    convert_to_type: Option<Box<ConvertToType>>,
+    static_values: Vec<StaticValue>,
+    static_functions: Vec<StaticFunction>,
+}
+
+impl Callbacks {
+    /// Finds the static value named `name`.
+    unsafe fn static_value(&self, name: sys::JSStringRef) -> Option<&StaticValue> {
+        self.static_values
+            .iter()
+            .find(|v| sys::JSStringIsEqualToUTF8CString(name, v.name.as_ptr()))
+    }
+}
+
+/// What is kept of a class for its callbacks.
+pub(crate) struct ClassData {
+    name: String,
+    /// The number of parent classes.
+    depth: usize,
+    automatic_prototype: bool,
+    parent: Option<Rc<ClassData>>,
+    callbacks: Callbacks,
+}
+
+impl ClassData {
+    /// Gets the class at `depth` in the chain of this class.
+    fn at(&self, depth: usize) -> &ClassData {
+        let mut class = self;
+        while class.depth > depth {
+            class = class.parent.as_deref().expect("parent class");
+        }
+        class
+    }
+}
+
+/// The private data of objects of a class that was built by a
+/// `JSClassBuilder`.
+pub(crate) struct Instance {
+    class: Rc<ClassData>,
+    /// The Rust value of the object, if it has been given one. It is only
+    /// set once, so references to it live as long as the object.
+    data: OnceCell<Box<dyn Any>>,
+}
+
+impl Instance {
+    /// Gets the Rust value of the object, if it is a `T`.
+    pub(crate) fn data<T: Any>(&self) -> Option<&T> {
+        self.data.get()?.downcast_ref()
+    }
+
+    /// Gives the object a Rust value, unless it already has one.
+    pub(crate) fn set_data<T: Any>(&self, data: T) -> Result<(), T> {
+        if self.data.get().is_some() {
+            return Err(data);
+        }
+        let _ = self.data.set(Box::new(data));
+        Ok(())
+    }
+}
+
+/// The root of all classes built by a `JSClassBuilder`.
+///
+/// JavaScriptCore gives private data to other objects too, such as the
+/// prototypes that it creates for classes. Only objects of this class
+/// hold an `Instance`. It has no prototype of its own, so it does not
+/// show in the prototype chain of objects.
+static ROOT: OnceLock<Class> = OnceLock::new();
+
+fn root() -> sys::JSClassRef {
+    ROOT.get_or_init(|| {
+        let definition = sys::JSClassDefinition {
+            attributes: sys::kJSClassAttributeNoAutomaticPrototype,
+            className: c"Object".as_ptr(),
+            ..Default::default()
+        };
+        start_engine();
+        Class(unsafe { sys::JSClassCreate(&definition) })
+    })
+    .0
+}
+
+impl JSClass {
+    /// Gets the name of the class.
+    pub fn name(&self) -> &str {
+        &self.data.name
+    }
+
+    /// Gets the prototype of the objects of the class in `ctx`.
+    ///
+    /// The prototype holds the static functions of the class, and
+    /// inherits from the prototype of the parent class, if there is one.
+    /// Functions that are added to it are shared by all objects of the
+    /// class in the context.
+    ///
+    /// Returns `None` if the class has no [`automatic_prototype`].
+    ///
+    /// [`automatic_prototype`]: struct.JSClassBuilder.html#method.automatic_prototype
+    pub fn prototype(&self, ctx: &JSContext) -> Option<JSObject> {
+        if !self.data.automatic_prototype {
+            return None;
+        }
+        // JavaScriptCore only hands out the prototype of a class as the
+        // `prototype` of a constructor.
+        let constructor = JSObject::from_raw(ctx.raw, unsafe {
+            sys::JSObjectMakeConstructor(ctx.raw, self.raw, None)
+        });
+        let prototype = constructor
+            .get_property("prototype")
+            .and_then(|prototype| prototype.as_object())
+            .expect("prototype of a class");
+        Some(prototype)
+    }
+
+    /// Creates a constructor for the objects of the class in `ctx`.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `callback`: The closure that creates the objects. It is given
+    ///   the context, `new.target` and the arguments of the call, and
+    ///   usually returns an object of the class.
+    ///
+    /// The `prototype` of the constructor is the [`prototype`] of the
+    /// class, or a new object if the class has none, and objects that
+    /// the callback returns are given it. The constructor can be
+    /// extended by JavaScript classes: the object that the callback
+    /// returns for `super()` is given the prototype of the subclass, so
+    /// it has the methods of both.
+    ///
+    /// `new.target` is the constructor that `new` was applied to, which
+    /// is a subclass when the call comes from `super()`, and `None` when
+    /// the constructor is called without `new`. Such a call creates an
+    /// object too, unless the callback rejects it.
+    ///
+    /// Constructors of classes that have a parent class are not linked
+    /// to the constructor of the parent class, but their prototypes
+    /// are.
+    ///
+    /// Returning an error throws the exception's value, and a panic is
+    /// caught and thrown as an `Error`.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// fn name(ctx: &JSContext, this: &JSObject, _arguments: &[JSValue]) -> Result<JSValue, JSException> {
+    ///     let name = this.get_native::<String>().expect("name");
+    ///     Ok(JSValue::new_string(ctx, name.as_str()))
+    /// }
+    ///
+    /// let class = JSClassBuilder::new("Animal")
+    ///     .static_function("name", name, PropertyAttributes::DONT_ENUM)
+    ///     .build();
+    /// let ctx = JSContext::default();
+    /// let c = class.clone();
+    /// let animal = class.constructor(&ctx, move |ctx, new_target, arguments| {
+    ///     if new_target.is_none() {
+    ///         return Err(JSException::type_error(ctx, "Animal must be called with new"));
+    ///     }
+    ///     let name = String::from_js(ctx, &arguments[0])?;
+    ///     Ok(JSObject::new_with_class_and_data(ctx, &c, name))
+    /// });
+    /// ctx.global_object().set_property("Animal", animal.into()).expect("set");
+    ///
+    /// let script = "class Dog extends Animal { speak() { return this.name() + ' barks'; } }
+    ///               new Dog('Rex').speak()";
+    /// let r = evaluate_script(&ctx, script, None, "test.js", 1).expect("evaluated");
+    /// assert_eq!(r.as_string().expect("string"), "Rex barks");
+    /// assert!(evaluate_script(&ctx, "Animal('Rex')", None, "test.js", 1).is_err());
+    /// ```
+    ///
+    /// [`prototype`]: #method.prototype
+    pub fn constructor<F>(&self, ctx: &JSContext, callback: F) -> JSObject
+    where
+        F: Fn(&JSContext, Option<&JSObject>, &[JSValue]) -> Result<JSObject, JSException> + 'static,
+    {
+        let construct = function::new_function(ctx, move |ctx, _this, arguments| {
+            let [new_target, constructor, arguments @ ..] = arguments else {
+                unreachable!("new.target and the constructor");
+            };
+            let new_target = if new_target.is_object() {
+                Some(new_target.as_object()?)
+            } else {
+                None
+            };
+            let object = callback(ctx, new_target.as_ref(), arguments)?;
+            // Like a constructor of a built-in class, the object takes
+            // its prototype from `new.target`, which is the subclass
+            // when this is called by `super()`, and the constructor
+            // itself otherwise.
+            let target = match new_target {
+                Some(new_target) => new_target,
+                None => constructor.as_object()?,
+            };
+            let prototype = target.get_property("prototype")?;
+            if prototype.is_object() && prototype.raw != object.prototype().raw {
+                object.set_prototype(&prototype);
+            }
+            Ok(object.into())
+        });
+        // The constructor is a JavaScript function, which passes
+        // `new.target` on. The computed key gives it the name of the
+        // class.
+        let shim = make_function(
+            ctx,
+            &["construct", "name"],
+            "const C = { [name]: function (...args) { return construct(new.target, C, ...args); } }[name];
+             return C;",
+        );
+        let name = JSValue::new_string(ctx, self.name());
+        let constructor = shim
+            .call_as_function(None, &[construct.into(), name])
+            .and_then(|constructor| constructor.as_object())
+            .expect("constructor");
+        let prototype = self.prototype(ctx).unwrap_or_else(|| JSObject::new(ctx));
+        // Neither object can have a setter that throws.
+        let _ = constructor.set_property_with_attributes(
+            "prototype",
+            JSObject::from_raw(ctx.raw, prototype.raw).into(),
+            PropertyAttributes::DONT_ENUM,
+        );
+        let _ = prototype.set_property_with_attributes(
+            "constructor",
+            JSObject::from_raw(ctx.raw, constructor.raw).into(),
+            PropertyAttributes::DONT_ENUM,
+        );
+        constructor
+    }
+}
+
+impl Clone for JSClass {
+    fn clone(&self) -> Self {
+        JSClass {
+            raw: unsafe { sys::JSClassRetain(self.raw) },
+            data: self.data.clone(),
+        }
+    }
+}
 
 impl Drop for JSClass {
     fn drop(&mut self) {
         unsafe { sys::JSClassRelease(self.raw) }
     }
 }
+
+impl JSClassBuilder {
+    /// Starts the definition of a class.
+    ///
+    /// * `name`: The name of the class, which is used by
+    ///   `Object.prototype.toString` and shows in debuggers.
+    pub fn new(name: &str) -> Self {
+        JSClassBuilder {
+            name: name.to_owned(),
+            parent: None,
+            automatic_prototype: true,
+            callbacks: Callbacks::default(),
+        }
+    }
+
+    /// Sets the parent class.
+    ///
+    /// Objects of the class are also objects of the parent class, and
+    /// the callbacks of both classes are called for them. The callbacks
+    /// of the class are called first, except for `initialize`, which is
+    /// called for the parent class first. The prototype of the class
+    /// inherits from the prototype of the parent class, so objects of
+    /// the class also have the static functions of the parent class.
+    ///
+    /// A class can have at most 15 ancestors.
+    pub fn parent_class(mut self, parent: &JSClass) -> Self {
+        self.parent = Some(parent.clone());
+        self
+    }
+
+    /// Sets whether the class has a prototype that JavaScriptCore
+    /// creates for each context. The default is `true`.
+    ///
+    /// Without an automatic prototype, the prototype of objects of the
+    /// class is `Object.prototype`, and static functions are properties
+    /// of each object instead, which makes them slower to create and
+    /// larger. Classes with many objects can share their methods with
+    /// a prototype of their own, which is set on each object with
+    /// [`JSObject::set_prototype`]. The objects that a [`constructor`]
+    /// of the class creates are given the `prototype` of the
+    /// constructor.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let class = JSClassBuilder::new("Particle").automatic_prototype(false).build();
+    /// let ctx = JSContext::default();
+    /// let methods = evaluate_script(&ctx, "({ kind() { return 'particle'; } })", None, "test.js", 1)
+    ///     .expect("methods");
+    /// for _ in 0..1000 {
+    ///     let p = JSObject::new_with_class(&ctx, &class);
+    ///     p.set_prototype(&methods);
+    /// }
+    /// assert!(class.prototype(&ctx).is_none());
+    /// ```
+    ///
+    /// [`JSObject::set_prototype`]: struct.JSObject.html#method.set_prototype
+    /// [`constructor`]: struct.JSClass.html#method.constructor
+    pub fn automatic_prototype(mut self, enabled: bool) -> Self {
+        self.automatic_prototype = enabled;
+        self
+    }
+
+    /// Sets a callback that is called when an object of the class is
+    /// created, to set it up.
+    ///
+    /// The callback is given the context in which the object is created
+    /// and the new object.
+    pub fn initialize<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&JSContext, &JSObject) + 'static,
+    {
+        self.callbacks.initialize = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets a callback that is called when an object of the class is
+    /// garbage collected.
+    ///
+    /// The callback is given the Rust value that the object was created
+    /// with by [`JSObject::new_with_class_and_data`], and is not called
+    /// for objects that have none. The value is dropped after the
+    /// callbacks of all classes of the object have returned, whether or
+    /// not the class has a `finalize` callback.
+    ///
+    /// The callback can not use JavaScript values, as it may run while
+    /// the garbage collector is sweeping or when the context group is
+    /// destroyed.
+    ///
+    /// [`JSObject::new_with_class_and_data`]: struct.JSObject.html#method.new_with_class_and_data
+    pub fn finalize<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&mut dyn Any) + 'static,
+    {
+        self.callbacks.finalize = Some(Box::new(callback));
+        self
+    }
+
+    /// Adds a function to the prototype of the class.
+    ///
+    /// * `name`: The name of the function.
+    /// * `function`: The function. It is given the context, the `this`
+    ///   object and the arguments of the call.
+    /// * `attributes`: The attributes of the property that holds the
+    ///   function on the prototype.
+    ///
+    /// JavaScriptCore creates the prototype of the class in each context
+    /// that uses it, and gives static functions no data with which to
+    /// find a closure. The function must therefore be a `fn` item or a
+    /// closure that captures nothing, which is checked when the program
+    /// is compiled. Its state belongs in the Rust value of `this`.
+    ///
+    /// Returning an error throws the exception's value, and a panic is
+    /// caught and thrown as an `Error`.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// use std::cell::Cell;
+    ///
+    /// let counter = JSClassBuilder::new("Counter")
+    ///     .static_function(
+    ///         "increment",
+    ///         |ctx, this, _arguments| {
+    ///             let count = this
+    ///                 .get_native::<Cell<u32>>()
+    ///                 .ok_or_else(|| JSException::type_error(ctx, "Not a Counter"))?;
+    ///             count.set(count.get() + 1);
+    ///             Ok(JSValue::new_number(ctx, f64::from(count.get())))
+    ///         },
+    ///         PropertyAttributes::DONT_ENUM,
+    ///     )
+    ///     .build();
+    ///
+    /// let ctx = JSContext::default();
+    /// let c = JSObject::new_with_class_and_data(&ctx, &counter, Cell::new(0_u32));
+    /// ctx.global_object().set_property("c", c.into()).expect("set");
+    ///
+    /// let r = evaluate_script(&ctx, "c.increment(); c.increment()", None, "test.js", 1);
+    /// assert_eq!(r.expect("called").as_number().expect("number"), 2.0);
+    /// ```
+    pub fn static_function<F>(
+        mut self,
+        name: &str,
+        function: F,
+        attributes: PropertyAttributes,
+    ) -> Self
+    where
+        F: Fn(&JSContext, &JSObject, &[JSValue]) -> Result<JSValue, JSException> + Copy + 'static,
+    {
+        const {
+            assert!(
+                size_of::<F>() == 0,
+                "static functions must not capture any state"
+            )
+        };
+        let _ = function;
+        self.callbacks.static_functions.push(StaticFunction {
+            name: CString::new(name).expect("function name without NUL characters"),
+            call: call_static_function::<F>,
+            attributes,
+        });
+        self
+    }
+
+    /// Sets a callback that is called when an object of the class is
+    /// called as a function.
+    ///
+    /// The callback is given the context, the object that was called,
+    /// the `this` object and the arguments of the call. Returning an
+    /// error throws the exception's value, and a panic is caught and
+    /// thrown as an `Error`.
+    ///
+    /// Unlike a function made by [`JSObject::new_function`], such an
+    /// object can have static values and the other callbacks of its
+    /// class, and can hold a Rust value:
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let class = JSClassBuilder::new("Scaler")
+    ///     .call_as_function(|ctx, function, _this, arguments| {
+    ///         let factor = function.get_native::<f64>().copied().unwrap_or(1.0);
+    ///         let n = arguments.first().map_or(Ok(0.0), |a| a.as_number())?;
+    ///         Ok(JSValue::new_number(ctx, n * factor))
+    ///     })
+    ///     .static_value_read_only(
+    ///         "factor",
+    ///         |ctx, object| {
+    ///             let factor = object.get_native::<f64>().copied().unwrap_or(1.0);
+    ///             Ok(JSValue::new_number(ctx, factor))
+    ///         },
+    ///         PropertyAttributes::NONE,
+    ///     )
+    ///     .build();
+    ///
+    /// let ctx = JSContext::default();
+    /// let triple = JSObject::new_with_class_and_data(&ctx, &class, 3.0);
+    /// ctx.global_object().set_property("triple", triple.into()).expect("set");
+    ///
+    /// let r = evaluate_script(&ctx, "triple(2) + triple.factor", None, "test.js", 1);
+    /// assert_eq!(r.expect("called").as_number().expect("number"), 9.0);
+    /// ```
+    ///
+    /// [`JSObject::new_function`]: struct.JSObject.html#method.new_function
+    pub fn call_as_function<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&JSContext, &JSObject, &JSObject, &[JSValue]) -> Result<JSValue, JSException>
+            + 'static,
+    {
+        self.callbacks.call = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets a callback that is called when an object of the class is
+    /// used as a constructor, as in `new ImageFilter(options)`.
+    ///
+    /// The callback is given the context, the constructor object and
+    /// the arguments, and returns the new object. This is usually an
+    /// object of another class that holds a Rust value, created with
+    /// [`JSObject::new_with_class_and_data`]. Returning an error throws
+    /// the exception's value, and a panic is caught and thrown as an
+    /// `Error`.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// struct ImageFilter {
+    ///     radius: f64,
+    /// }
+    ///
+    /// let filter = JSClassBuilder::new("ImageFilter").build();
+    /// let constructor = JSClassBuilder::new("ImageFilterConstructor")
+    ///     .call_as_constructor(move |ctx, _constructor, arguments| {
+    ///         let radius = match arguments.first() {
+    ///             Some(options) => options.as_object()?.get_property("radius")?.as_number()?,
+    ///             None => 1.0,
+    ///         };
+    ///         Ok(JSObject::new_with_class_and_data(ctx, &filter, ImageFilter { radius }))
+    ///     })
+    ///     .build();
+    ///
+    /// let ctx = JSContext::default();
+    /// let c = JSObject::new_with_class(&ctx, &constructor);
+    /// ctx.global_object().set_property("ImageFilter", c.into()).expect("set");
+    ///
+    /// let f = evaluate_script(&ctx, "new ImageFilter({ radius: 4 })", None, "test.js", 1)
+    ///     .expect("constructed");
+    /// let f = f.as_object().expect("object");
+    /// assert_eq!(f.get_native::<ImageFilter>().expect("filter").radius, 4.0);
+    /// ```
+    ///
+    /// [`JSObject::new_with_class_and_data`]: struct.JSObject.html#method.new_with_class_and_data
+    pub fn call_as_constructor<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&JSContext, &JSObject, &[JSValue]) -> Result<JSObject, JSException> + 'static,
+    {
+        self.callbacks.construct = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets a callback that is called when an object of the class is
+    /// the right-hand side of `instanceof`.
+    ///
+    /// The callback is given the context, the constructor object and
+    /// the value on the left-hand side, and tells whether the value is
+    /// an instance. Without it, `instanceof` follows the default rules,
+    /// which know nothing of the classes of objects. Returning an error
+    /// throws the exception's value, and a panic is caught and thrown as
+    /// an `Error`.
+    ///
+    /// Testing the class of the value keeps `instanceof` working for
+    /// objects made by classes that extend the constructor in
+    /// JavaScript, as `super()` returns the object that
+    /// [`call_as_constructor`] made:
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let point = JSClassBuilder::new("Point").build();
+    /// let p = point.clone();
+    /// let constructor = JSClassBuilder::new("PointConstructor")
+    ///     .call_as_constructor(move |ctx, _constructor, _arguments| {
+    ///         Ok(JSObject::new_with_class(ctx, &p))
+    ///     })
+    ///     .has_instance(move |_ctx, _constructor, value| {
+    ///         Ok(value.as_object().is_ok_and(|o| o.is_object_of_class(&point)))
+    ///     })
+    ///     .build();
+    ///
+    /// let ctx = JSContext::default();
+    /// let c = JSObject::new_with_class(&ctx, &constructor);
+    /// c.set_property("prototype", JSObject::new(&ctx).into()).expect("set");
+    /// ctx.global_object().set_property("Point", c.into()).expect("set");
+    ///
+    /// let script = "class Point3D extends Point {}; [new Point3D() instanceof Point, {} instanceof Point]";
+    /// let r = evaluate_script(&ctx, script, None, "test.js", 1).expect("evaluated");
+    /// assert_eq!(r.to_json_string(0).expect("JSON").to_string(), "[true,false]");
+    /// ```
+    ///
+    /// [`call_as_constructor`]: #method.call_as_constructor
+    pub fn has_instance<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&JSContext, &JSObject, &JSValue) -> Result<bool, JSException> + 'static,
+    {
+        self.callbacks.has_instance = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets a callback that tells whether an object of the class has a
+    /// property, for the `in` operator.
+    ///
+    /// The callback is given the context, the object and the name of
+    /// the property. Returning `false` lets the engine look further, in
+    /// the static values, the parent classes and the prototype chain.
+    /// Without it, [`get_property`] is used to find out whether the
+    /// property exists, which may be slower if getting it is costly.
+    ///
+    /// A panic is caught and taken as `false`.
+    ///
+    /// [`get_property`]: #method.get_property
+    pub fn has_property<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&JSContext, &JSObject, &str) -> bool + 'static,
+    {
+        self.callbacks.has_property = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets a callback that is called to get the value of any property
+    /// of an object of the class.
+    ///
+    /// The callback is given the context, the object and the name of
+    /// the property. Returning `None` lets the engine look further, in
+    /// the static values, the parent classes and the prototype chain,
+    /// which is where methods such as `toString` are found. Returning
+    /// an error throws the exception's value, and a panic is caught and
+    /// thrown as an `Error`.
+    ///
+    /// This lets an object expose keys that are not known in advance,
+    /// such as those of a map:
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// use std::cell::RefCell;
+    /// use std::collections::HashMap;
+    /// use std::rc::Rc;
+    ///
+    /// let row = Rc::new(RefCell::new(HashMap::from([("name".to_owned(), 1.0)])));
+    /// let (r1, r2, r3) = (row.clone(), row.clone(), row.clone());
+    /// let class = JSClassBuilder::new("Row")
+    ///     .has_property(move |_ctx, _object, name| r1.borrow().contains_key(name))
+    ///     .get_property(move |ctx, _object, name| {
+    ///         Ok(r2.borrow().get(name).map(|&n| JSValue::new_number(ctx, n)))
+    ///     })
+    ///     .set_property(move |_ctx, _object, name, value| {
+    ///         r3.borrow_mut().insert(name.to_owned(), value.as_number()?);
+    ///         Ok(true)
+    ///     })
+    ///     .build();
+    ///
+    /// let ctx = JSContext::default();
+    /// let o = JSObject::new_with_class(&ctx, &class);
+    /// ctx.global_object().set_property("row", o.into()).expect("set");
+    ///
+    /// let script = "row.age = 42; row.name + ('age' in row) + row.missing";
+    /// let r = evaluate_script(&ctx, script, None, "test.js", 1).expect("evaluated");
+    /// assert_eq!(r.as_string().expect("string"), "1trueundefined");
+    /// assert_eq!(row.borrow()["age"], 42.0);
+    /// ```
+    pub fn get_property<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&JSContext, &JSObject, &str) -> Result<Option<JSValue>, JSException> + 'static,
+    {
+        self.callbacks.get_property = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets a callback that is called to set the value of any property
+    /// of an object of the class.
+    ///
+    /// The callback is given the context, the object, the name of the
+    /// property and the new value, and tells whether it has set the
+    /// property. Returning `false` lets the engine set the property
+    /// as usual: through a static value or a parent class, or else as a
+    /// property of the object itself. Returning an error throws the
+    /// exception's value, and a panic is caught and thrown as an
+    /// `Error`.
+    pub fn set_property<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&JSContext, &JSObject, &str, &JSValue) -> Result<bool, JSException> + 'static,
+    {
+        self.callbacks.set_property = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets a callback that is called to delete any property of an
+    /// object of the class.
+    ///
+    /// The callback is given the context, the object and the name of
+    /// the property, and tells whether it has deleted the property.
+    /// Returning `false` lets the engine delete the property as usual.
+    /// Returning an error throws the exception's value, and a panic is
+    /// caught and thrown as an `Error`.
+    pub fn delete_property<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&JSContext, &JSObject, &str) -> Result<bool, JSException> + 'static,
+    {
+        self.callbacks.delete_property = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets a callback that adds the names of the properties that an
+    /// object of the class provides, for `Object.keys` and `for...in`.
+    ///
+    /// The callback is given the context, the object and an accumulator
+    /// to add the names to. The names of static values and of properties
+    /// that were set on the object as usual are added by the engine.
+    /// A panic is caught and ends the callback early.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let class = JSClassBuilder::new("Row")
+    ///     .get_property(|ctx, _object, name| {
+    ///         Ok(["id", "name"].contains(&name).then(|| JSValue::new_string(ctx, name)))
+    ///     })
+    ///     .get_property_names(|_ctx, _object, names| {
+    ///         names.add_name("id");
+    ///         names.add_name("name");
+    ///     })
+    ///     .build();
+    ///
+    /// let ctx = JSContext::default();
+    /// let o = JSObject::new_with_class(&ctx, &class);
+    /// ctx.global_object().set_property("row", o.into()).expect("set");
+    ///
+    /// let r = evaluate_script(&ctx, "Object.keys(row).join()", None, "test.js", 1).expect("keys");
+    /// assert_eq!(r.as_string().expect("string"), "id,name");
+    /// ```
+    pub fn get_property_names<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&JSContext, &JSObject, &mut JSPropertyNameAccumulator) + 'static,
+    {
+        self.callbacks.get_property_names = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets a callback that converts an object of the class to a
+    /// primitive value, such as for arithmetic or string concatenation.
+    ///
+    /// The callback is given the context, the object and the preferred
+    /// type of the result, which is `JSType::String` for conversions to
+    /// string and `JSType::Number` otherwise. Returning `None` lets the
+    /// engine convert the object as usual, with its `valueOf` and
+    /// `toString` methods. Returning an error throws the exception's
+    /// value, and a panic is caught and thrown as an `Error`.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let money = JSClassBuilder::new("Money")
+    ///     .convert_to_type(|ctx, object, hint| {
+    ///         let cents = *object.get_native::<i64>().expect("cents") as f64;
+    ///         Ok(Some(match hint {
+    ///             JSType::String => JSValue::new_string(ctx, format!("${:.2}", cents / 100.0)),
+    ///             _ => JSValue::new_number(ctx, cents / 100.0),
+    ///         }))
+    ///     })
+    ///     .build();
+    ///
+    /// let ctx = JSContext::default();
+    /// let price = JSObject::new_with_class_and_data(&ctx, &money, 1250_i64);
+    /// ctx.global_object().set_property("price", price.into()).expect("set");
+    ///
+    /// let r = evaluate_script(&ctx, "`${price} x 2 = ${price * 2}`", None, "test.js", 1);
+    /// assert_eq!(r.expect("evaluated").as_string().expect("string"), "$12.50 x 2 = 25");
+    /// ```
+    pub fn convert_to_type<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&JSContext, &JSObject, JSType) -> Result<Option<JSValue>, JSException> + 'static,
+    {
+        self.callbacks.convert_to_type = Some(Box::new(callback));
+        self
+    }
+
+    /// Adds a property whose value is got and set by Rust callbacks.
+    ///
+    /// * `name`: The name of the property.
+    /// * `getter`: Called with the context and the object to get the
+    ///   value of the property.
+    /// * `setter`: Called with the context, the object and the new
+    ///   value when the property is assigned to.
+    /// * `attributes`: The attributes of the property.
+    ///
+    /// The callbacks run each time the property is used, so the value is
+    /// always that of the state behind the object rather than a copy.
+    /// Returning an error throws the exception's value, and a panic is
+    /// caught and thrown as an `Error`.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    ///
+    /// let width = Rc::new(Cell::new(10.0));
+    /// let (w, w2) = (width.clone(), width.clone());
+    /// let rect = JSClassBuilder::new("Rect")
+    ///     .static_value(
+    ///         "width",
+    ///         move |ctx, _object| Ok(JSValue::new_number(ctx, w.get())),
+    ///         move |_ctx, _object, value| {
+    ///             w2.set(value.as_number()?);
+    ///             Ok(())
+    ///         },
+    ///         PropertyAttributes::DONT_DELETE,
+    ///     )
+    ///     .build();
+    ///
+    /// let ctx = JSContext::default();
+    /// let r = JSObject::new_with_class(&ctx, &rect);
+    /// ctx.global_object().set_property("r", r.into()).expect("set");
+    ///
+    /// width.set(20.0);
+    /// let v = evaluate_script(&ctx, "r.width *= 2", None, "test.js", 1).expect("evaluated");
+    /// assert_eq!(v.as_number().expect("number"), 40.0);
+    /// assert_eq!(width.get(), 40.0);
+    /// ```
+    pub fn static_value<G, S>(
+        mut self,
+        name: &str,
+        getter: G,
+        setter: S,
+        attributes: PropertyAttributes,
+    ) -> Self
+    where
+        G: Fn(&JSContext, &JSObject) -> Result<JSValue, JSException> + 'static,
+        S: Fn(&JSContext, &JSObject, &JSValue) -> Result<(), JSException> + 'static,
+    {
+        self.callbacks.static_values.push(StaticValue {
+            name: CString::new(name).expect("property name without NUL characters"),
+            get: Box::new(getter),
+            set: Some(Box::new(setter)),
+            attributes,
+        });
+        self
+    }
+
+    /// Adds a read-only property whose value is got by a Rust callback.
+    ///
+    /// This is like [`static_value`] without a setter. The property has
+    /// the `READ_ONLY` attribute besides `attributes`, so assigning to
+    /// it is ignored, or throws a `TypeError` in strict mode.
+    ///
+    /// [`static_value`]: #method.static_value
+    pub fn static_value_read_only<G>(
+        mut self,
+        name: &str,
+        getter: G,
+        attributes: PropertyAttributes,
+    ) -> Self
+    where
+        G: Fn(&JSContext, &JSObject) -> Result<JSValue, JSException> + 'static,
+    {
+        self.callbacks.static_values.push(StaticValue {
+            name: CString::new(name).expect("property name without NUL characters"),
+            get: Box::new(getter),
+            set: None,
+            attributes: attributes | PropertyAttributes::READ_ONLY,
+        });
+        self
+    }
+
+    /// Creates the class.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name of the class, of a static value or of a static
+    /// function contains a NUL character, or if the class has more than 15 ancestors.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let point = JSClassBuilder::new("Point")
+    ///     .initialize(|ctx, object| {
+    ///         let _ = object.set_property("x", JSValue::new_number(ctx, 0.0));
+    ///     })
+    ///     .build();
+    ///
+    /// let ctx = JSContext::default();
+    /// let p = JSObject::new_with_class(&ctx, &point);
+    /// assert!(p.is_object_of_class(&point));
+    /// assert_eq!(p.get_property("x").expect("x").as_number().expect("number"), 0.0);
+    /// ```
+    pub fn build(self) -> JSClass {
+        let name = CString::new(self.name.as_str()).expect("class name without NUL characters");
+        let depth = self.parent.as_ref().map_or(0, |p| p.data.depth + 1);
+        assert!(
+            depth < MAX_DEPTH,
+            "too many ancestors for class {}",
+            self.name
+        );
+        let mut static_values: Vec<sys::JSStaticValue> = self
+            .callbacks
+            .static_values
+            .iter()
+            .map(|v| sys::JSStaticValue {
+                name: v.name.as_ptr(),
+                getProperty: Some(at_depth!(get_static_value, depth)),
+                setProperty: v.set.as_ref().map(|_| at_depth!(set_static_value, depth)),
+                attributes: v.attributes.raw(),
+            })
+            .collect();
+        if !static_values.is_empty() {
+            static_values.push(sys::JSStaticValue {
+                name: ptr::null(),
+                getProperty: None,
+                setProperty: None,
+                attributes: 0,
+            });
+        }
+        let mut static_functions: Vec<sys::JSStaticFunction> = self
+            .callbacks
+            .static_functions
+            .iter()
+            .map(|f| sys::JSStaticFunction {
+                name: f.name.as_ptr(),
+                callAsFunction: Some(f.call),
+                attributes: f.attributes.raw(),
+            })
+            .collect();
+        if !static_functions.is_empty() {
+            static_functions.push(sys::JSStaticFunction {
+                name: ptr::null(),
+                callAsFunction: None,
+                attributes: 0,
+            });
+        }
+        let definition = sys::JSClassDefinition {
+            attributes: if self.automatic_prototype {
+                sys::kJSClassAttributeNone
+            } else {
+                sys::kJSClassAttributeNoAutomaticPrototype
+            },
+            className: name.as_ptr(),
+            parentClass: self.parent.as_ref().map_or(root(), |p| p.raw),
+            initialize: self
+                .callbacks
+                .initialize
+                .as_ref()
+                .map(|_| at_depth!(initialize, depth)),
+            finalize: Some(at_depth!(finalize, depth)),
+            callAsFunction: self.callbacks.call.as_ref().map(|_| at_depth!(call, depth)),
+            callAsConstructor: self
+                .callbacks
+                .construct
+                .as_ref()
+                .map(|_| at_depth!(construct, depth)),
+            hasInstance: self
+                .callbacks
+                .has_instance
+                .as_ref()
+                .map(|_| at_depth!(has_instance, depth)),
+            hasProperty: self
+                .callbacks
+                .has_property
+                .as_ref()
+                .map(|_| at_depth!(has_property, depth)),
+            getProperty: self
+                .callbacks
+                .get_property
+                .as_ref()
+                .map(|_| at_depth!(get_property, depth)),
+            setProperty: self
+                .callbacks
+                .set_property
+                .as_ref()
+                .map(|_| at_depth!(set_property, depth)),
+            deleteProperty: self
+                .callbacks
+                .delete_property
+                .as_ref()
+                .map(|_| at_depth!(delete_property, depth)),
+            getPropertyNames: self
+                .callbacks
+                .get_property_names
+                .as_ref()
+                .map(|_| at_depth!(get_property_names, depth)),
+            convertToType: self
+                .callbacks
+                .convert_to_type
+                .as_ref()
+                .map(|_| at_depth!(convert_to_type, depth)),
+            staticValues: if static_values.is_empty() {
+                ptr::null()
+            } else {
+                static_values.as_ptr()
+            },
+            staticFunctions: if static_functions.is_empty() {
+                ptr::null()
+            } else {
+                static_functions.as_ptr()
+            },
+            ..Default::default()
+        };
+        // The definition and its static values and functions are copied,
+        // so they need not outlive the class.
+        start_engine();
+        let raw = unsafe { sys::JSClassCreate(&definition) };
+        JSClass {
+            raw,
+            data: Rc::new(ClassData {
+                name: self.name,
+                depth,
+                automatic_prototype: self.automatic_prototype,
+                parent: self.parent.map(|p| p.data.clone()),
+                callbacks: self.callbacks,
+            }),
+        }
+    }
+}
+
+/// Creates a function of the crate's own, from its parameters and body.
+fn make_function(ctx: &JSContext, parameters: &[&str], body: &str) -> JSObject {
+    let parameters: Vec<JSString> = parameters.iter().map(|&p| JSString::from(p)).collect();
+    let parameters: Vec<sys::JSStringRef> = parameters.iter().map(|p| p.raw).collect();
+    let body = JSString::from(body);
+    let raw = unsafe {
+        sys::JSObjectMakeFunction(
+            ctx.raw,
+            ptr::null_mut(),
+            parameters.len() as c_uint,
+            parameters.as_ptr(),
+            body.raw,
+            ptr::null_mut(),
+            1,
+            ptr::null_mut(),
+        )
+    };
+    assert!(!raw.is_null(), "function could not be made");
+    JSObject::from_raw(ctx.raw, raw)
+}
+
+/// Creates the private data for a new object of `class`, which holds
+/// `data`.
+pub(crate) fn new_instance(class: &JSClass, data: Option<Box<dyn Any>>) -> *mut c_void {
+    Box::into_raw(Box::new(Instance {
+        class: class.data.clone(),
+        data: data.map_or_else(OnceCell::new, OnceCell::from),
+    })) as *mut c_void
+}
+
+/// Gets the private data of `object`, if it is an object of a class that
+/// was built by a `JSClassBuilder` and has been given its private data.
+pub(crate) unsafe fn instance<'a>(
+    ctx: sys::JSContextRef,
+    object: sys::JSObjectRef,
+) -> Option<&'a Instance> {
+    if !sys::JSValueIsObjectOfClass(ctx, object, root()) {
+        return None;
+    }
+    (sys::JSObjectGetPrivate(object) as *const Instance).as_ref()
+}
+
+/// Sets up the global object of a context that was created with
+/// `class`.
+///
+/// JavaScriptCore creates the global object before it can be given
+/// private data, so the `initialize` callbacks are called here instead.
+pub(crate) fn init_global(ctx: &JSContext, class: &JSClass) {
+    let global = ctx.global_object();
+    let instance = new_instance(class, None);
+    unsafe {
+        if !sys::JSObjectSetPrivate(global.raw, instance) {
+            drop(Box::from_raw(instance as *mut Instance));
+            return;
+        }
+    }
+    let mut chain = vec![];
+    let mut data = Some(&*class.data);
+    while let Some(class) = data {
+        chain.push(class);
+        data = class.parent.as_deref();
+    }
+    for class in chain.into_iter().rev() {
+        if let Some(initialize) = &class.callbacks.initialize {
+            initialize(ctx, &global);
+        }
+    }
+}
+
+/// Gets the class at `depth` in the chain of the class of `object`, if
+/// the object has been given its private data.
+unsafe fn class_at<'a>(object: sys::JSObjectRef, depth: usize) -> Option<&'a ClassData> {
+    let instance = sys::JSObjectGetPrivate(object) as *const Instance;
+    if instance.is_null() {
+        None
+    } else {
+        Some((*instance).class.at(depth))
+    }
+}
+
+impl JSPropertyNameAccumulator {
+    /// Adds the name of a property.
+    ///
+    /// * `name`: A value that can be converted to a [`JSString`]
+    ///   containing the property's name.
+    ///
+    /// [`JSString`]: struct.JSString.html
+    pub fn add_name<S>(&mut self, name: S)
+    where
+        S: Into<JSString>,
+    {
+        unsafe { sys::JSPropertyNameAccumulatorAddName(self.raw, name.into().raw) }
+    }
+}
+
+/// Converts the name of a property that was given to a callback.
+unsafe fn property_name(name: sys::JSStringRef) -> String {
+    JSString {
+        raw: sys::JSStringRetain(name),
+    }
+    .to_string_lossy()
+}
+
+// Unwinding into JavaScriptCore would abort the process, so the
+// callbacks below catch panics. Those that can not report an exception
+// only keep the panic from unwinding.
+
+unsafe extern "C" fn initialize<const D: usize>(ctx: sys::JSContextRef, object: sys::JSObjectRef) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        if let Some(initialize) = class_at(object, D).and_then(|c| c.callbacks.initialize.as_ref())
+        {
+            let ctx = JSContext::from_context_ref(ctx);
+            initialize(&ctx, &JSObject::from_raw(ctx.raw, object));
+        }
+    }));
+}
+
+unsafe extern "C" fn get_static_value<const D: usize>(
+    ctx: sys::JSContextRef,
+    object: sys::JSObjectRef,
+    name: sys::JSStringRef,
+    exception: *mut sys::JSValueRef,
+) -> sys::JSValueRef {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        // Returning null lets the engine look the property up further,
+        // as it does before the global object has its private data.
+        let value = class_at(object, D).and_then(|c| c.callbacks.static_value(name))?;
+        let ctx = JSContext::from_context_ref(ctx);
+        Some((value.get)(&ctx, &JSObject::from_raw(ctx.raw, object)))
+    }));
+    match result.unwrap_or_else(|payload| Some(Err(panic_error(ctx, payload)))) {
+        Some(Ok(value)) => value.raw,
+        Some(Err(e)) => {
+            throw(exception, e);
+            ptr::null()
+        }
+        None => ptr::null(),
+    }
+}
+
+unsafe extern "C" fn set_static_value<const D: usize>(
+    ctx: sys::JSContextRef,
+    object: sys::JSObjectRef,
+    name: sys::JSStringRef,
+    value: sys::JSValueRef,
+    exception: *mut sys::JSValueRef,
+) -> bool {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let set = class_at(object, D)
+            .and_then(|c| c.callbacks.static_value(name))
+            .and_then(|v| v.set.as_ref())?;
+        let ctx = JSContext::from_context_ref(ctx);
+        let value = JSValue {
+            raw: value,
+            ctx: ctx.raw,
+        };
+        Some(set(&ctx, &JSObject::from_raw(ctx.raw, object), &value))
+    }));
+    match result.unwrap_or_else(|payload| Some(Err(panic_error(ctx, payload)))) {
+        Some(Ok(())) => true,
+        Some(Err(e)) => {
+            throw(exception, e);
+            true
+        }
+        None => false,
+    }
+}
+
+unsafe extern "C" fn call_static_function<F>(
+    ctx: sys::JSContextRef,
+    _function: sys::JSObjectRef,
+    this_object: sys::JSObjectRef,
+    argument_count: usize,
+    arguments: *const sys::JSValueRef,
+    exception: *mut sys::JSValueRef,
+) -> sys::JSValueRef
+where
+    F: Fn(&JSContext, &JSObject, &[JSValue]) -> Result<JSValue, JSException> + Copy + 'static,
+{
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        // `F` has no state, as `static_function` checks, so this makes
+        // the very function that was given to it. Reading a zero-sized
+        // value from a dangling pointer is allowed.
+        let function: F = ptr::NonNull::<F>::dangling().as_ptr().read();
+        let ctx = JSContext::from_context_ref(ctx);
+        let arguments = arguments_from_raw(ctx.raw, argument_count, arguments);
+        function(&ctx, &JSObject::from_raw(ctx.raw, this_object), &arguments)
+    }));
+    match result.unwrap_or_else(|payload| Err(panic_error(ctx, payload))) {
+        Ok(value) => value.raw,
+        Err(e) => {
+            throw(exception, e);
+            ptr::null()
+        }
+    }
+}
+
+unsafe extern "C" fn call<const D: usize>(
+    ctx: sys::JSContextRef,
+    function: sys::JSObjectRef,
+    this_object: sys::JSObjectRef,
+    argument_count: usize,
+    arguments: *const sys::JSValueRef,
+    exception: *mut sys::JSValueRef,
+) -> sys::JSValueRef {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let Some(call) = class_at(function, D).and_then(|c| c.callbacks.call.as_ref()) else {
+            return Err(error_in(ctx, "The object is not ready to be called"));
+        };
+        let ctx = JSContext::from_context_ref(ctx);
+        let arguments = arguments_from_raw(ctx.raw, argument_count, arguments);
+        call(
+            &ctx,
+            &JSObject::from_raw(ctx.raw, function),
+            &JSObject::from_raw(ctx.raw, this_object),
+            &arguments,
+        )
+    }));
+    match result.unwrap_or_else(|payload| Err(panic_error(ctx, payload))) {
+        Ok(value) => value.raw,
+        Err(e) => {
+            throw(exception, e);
+            ptr::null()
+        }
+    }
+}
+
+unsafe extern "C" fn construct<const D: usize>(
+    ctx: sys::JSContextRef,
+    constructor: sys::JSObjectRef,
+    argument_count: usize,
+    arguments: *const sys::JSValueRef,
+    exception: *mut sys::JSValueRef,
+) -> sys::JSObjectRef {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let Some(construct) = class_at(constructor, D).and_then(|c| c.callbacks.construct.as_ref())
+        else {
+            return Err(error_in(
+                ctx,
+                "The object is not ready to be used as a constructor",
+            ));
+        };
+        let ctx = JSContext::from_context_ref(ctx);
+        let arguments = arguments_from_raw(ctx.raw, argument_count, arguments);
+        construct(&ctx, &JSObject::from_raw(ctx.raw, constructor), &arguments)
+    }));
+    match result.unwrap_or_else(|payload| Err(panic_error(ctx, payload))) {
+        Ok(object) => object.raw,
+        Err(e) => {
+            throw(exception, e);
+            ptr::null_mut()
+        }
+    }
+}
+
+unsafe extern "C" fn has_instance<const D: usize>(
+    ctx: sys::JSContextRef,
+    constructor: sys::JSObjectRef,
+    possible_instance: sys::JSValueRef,
+    exception: *mut sys::JSValueRef,
+) -> bool {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let Some(has_instance) =
+            class_at(constructor, D).and_then(|c| c.callbacks.has_instance.as_ref())
+        else {
+            return Ok(false);
+        };
+        let ctx = JSContext::from_context_ref(ctx);
+        let value = JSValue {
+            raw: possible_instance,
+            ctx: ctx.raw,
+        };
+        has_instance(&ctx, &JSObject::from_raw(ctx.raw, constructor), &value)
+    }));
+    match result.unwrap_or_else(|payload| Err(panic_error(ctx, payload))) {
+        Ok(is_instance) => is_instance,
+        Err(e) => {
+            throw(exception, e);
+            false
+        }
+    }
+}
+
+unsafe extern "C" fn has_property<const D: usize>(
+    ctx: sys::JSContextRef,
+    object: sys::JSObjectRef,
+    name: sys::JSStringRef,
+) -> bool {
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        let Some(has_property) =
+            class_at(object, D).and_then(|c| c.callbacks.has_property.as_ref())
+        else {
+            return false;
+        };
+        let ctx = JSContext::from_context_ref(ctx);
+        has_property(
+            &ctx,
+            &JSObject::from_raw(ctx.raw, object),
+            &property_name(name),
+        )
+    }))
+    .unwrap_or(false)
+}
+
+unsafe extern "C" fn get_property<const D: usize>(
+    ctx: sys::JSContextRef,
+    object: sys::JSObjectRef,
+    name: sys::JSStringRef,
+    exception: *mut sys::JSValueRef,
+) -> sys::JSValueRef {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let Some(get_property) =
+            class_at(object, D).and_then(|c| c.callbacks.get_property.as_ref())
+        else {
+            return Ok(None);
+        };
+        let ctx = JSContext::from_context_ref(ctx);
+        get_property(
+            &ctx,
+            &JSObject::from_raw(ctx.raw, object),
+            &property_name(name),
+        )
+    }));
+    match result.unwrap_or_else(|payload| Err(panic_error(ctx, payload))) {
+        Ok(Some(value)) => value.raw,
+        Ok(None) => ptr::null(),
+        Err(e) => {
+            throw(exception, e);
+            ptr::null()
+        }
+    }
+}
+
+unsafe extern "C" fn set_property<const D: usize>(
+    ctx: sys::JSContextRef,
+    object: sys::JSObjectRef,
+    name: sys::JSStringRef,
+    value: sys::JSValueRef,
+    exception: *mut sys::JSValueRef,
+) -> bool {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let Some(set_property) =
+            class_at(object, D).and_then(|c| c.callbacks.set_property.as_ref())
+        else {
+            return Ok(false);
+        };
+        let ctx = JSContext::from_context_ref(ctx);
+        let value = JSValue {
+            raw: value,
+            ctx: ctx.raw,
+        };
+        set_property(
+            &ctx,
+            &JSObject::from_raw(ctx.raw, object),
+            &property_name(name),
+            &value,
+        )
+    }));
+    match result.unwrap_or_else(|payload| Err(panic_error(ctx, payload))) {
+        Ok(handled) => handled,
+        Err(e) => {
+            throw(exception, e);
+            true
+        }
+    }
+}
+
+unsafe extern "C" fn delete_property<const D: usize>(
+    ctx: sys::JSContextRef,
+    object: sys::JSObjectRef,
+    name: sys::JSStringRef,
+    exception: *mut sys::JSValueRef,
+) -> bool {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let Some(delete_property) =
+            class_at(object, D).and_then(|c| c.callbacks.delete_property.as_ref())
+        else {
+            return Ok(false);
+        };
+        let ctx = JSContext::from_context_ref(ctx);
+        delete_property(
+            &ctx,
+            &JSObject::from_raw(ctx.raw, object),
+            &property_name(name),
+        )
+    }));
+    match result.unwrap_or_else(|payload| Err(panic_error(ctx, payload))) {
+        Ok(deleted) => deleted,
+        Err(e) => {
+            throw(exception, e);
+            false
+        }
+    }
+}
+
+unsafe extern "C" fn get_property_names<const D: usize>(
+    ctx: sys::JSContextRef,
+    object: sys::JSObjectRef,
+    names: sys::JSPropertyNameAccumulatorRef,
+) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        if let Some(get_property_names) =
+            class_at(object, D).and_then(|c| c.callbacks.get_property_names.as_ref())
+        {
+            let ctx = JSContext::from_context_ref(ctx);
+            let mut names = JSPropertyNameAccumulator { raw: names };
+            get_property_names(&ctx, &JSObject::from_raw(ctx.raw, object), &mut names);
+        }
+    }));
+}
+
+unsafe extern "C" fn convert_to_type<const D: usize>(
+    ctx: sys::JSContextRef,
+    object: sys::JSObjectRef,
+    hint: JSType,
+    exception: *mut sys::JSValueRef,
+) -> sys::JSValueRef {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let Some(convert_to_type) =
+            class_at(object, D).and_then(|c| c.callbacks.convert_to_type.as_ref())
+        else {
+            return Ok(None);
+        };
+        let ctx = JSContext::from_context_ref(ctx);
+        convert_to_type(&ctx, &JSObject::from_raw(ctx.raw, object), hint)
+    }));
+    match result.unwrap_or_else(|payload| Err(panic_error(ctx, payload))) {
+        Ok(Some(value)) => value.raw,
+        Ok(None) => ptr::null(),
+        Err(e) => {
+            throw(exception, e);
+            ptr::null()
+        }
+    }
+}
+
+unsafe extern "C" fn finalize<const D: usize>(object: sys::JSObjectRef) {
+    let instance = sys::JSObjectGetPrivate(object) as *mut Instance;
+    if instance.is_null() {
+        return;
+    }
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        let Instance { class, data } = &mut *instance;
+        if let (Some(finalize), Some(data)) = (&class.at(D).callbacks.finalize, data.get_mut()) {
+            finalize(data.as_mut());
+        }
+    }));
+    // The classes of an object are finalized from the object's own class
+    // up to the root class, which frees the private data.
+    if D == 0 {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(instance))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::exception::error;
+    use super::super::{
+        evaluate_script, garbage_collect, ContextBuilder, JSClassBuilder, JSContext, JSException,
+        JSObject, JSType, JSValue, PropertyAttributes,
+    };
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    #[test]
+    fn build() {
+        let class = JSClassBuilder::new("Thing").build();
+        assert_eq!(class.name(), "Thing");
+
+        let ctx = JSContext::default();
+        let o = JSObject::new_with_class(&ctx, &class);
+        assert!(o.is_object_of_class(&class));
+        assert!(!JSObject::new(&ctx).is_object_of_class(&class));
+        ctx.global_object().set_property("o", o.into()).unwrap();
+        let r = evaluate_script(
+            &ctx,
+            "Object.prototype.toString.call(o)",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(r.as_string().unwrap(), "[object Thing]");
+    }
+
+    #[test]
+    fn parent_class() {
+        let calls = Rc::new(RefCell::new(vec![]));
+        let c = calls.clone();
+        let parent = JSClassBuilder::new("Parent")
+            .initialize(move |_ctx, _object| c.borrow_mut().push("Parent"))
+            .build();
+        let c = calls.clone();
+        let child = JSClassBuilder::new("Child")
+            .parent_class(&parent)
+            .initialize(move |_ctx, _object| c.borrow_mut().push("Child"))
+            .build();
+
+        let ctx = JSContext::default();
+        let o = JSObject::new_with_class(&ctx, &child);
+        assert!(o.is_object_of_class(&child));
+        assert!(o.is_object_of_class(&parent));
+        assert_eq!(*calls.borrow(), ["Parent", "Child"]);
+
+        let o = JSObject::new_with_class(&ctx, &parent);
+        assert!(!o.is_object_of_class(&child));
+        assert_eq!(*calls.borrow(), ["Parent", "Child", "Parent"]);
+    }
+
+    fn area(
+        ctx: &JSContext,
+        this: &JSObject,
+        _arguments: &[JSValue],
+    ) -> Result<JSValue, JSException> {
+        match this.get_native::<(f64, f64)>() {
+            Some((w, h)) => Ok(JSValue::new_number(ctx, w * h)),
+            None => Err(JSException::type_error(ctx, "Not a shape")),
+        }
+    }
+
+    fn is_square(
+        ctx: &JSContext,
+        this: &JSObject,
+        _arguments: &[JSValue],
+    ) -> Result<JSValue, JSException> {
+        let (w, h) = this.get_native::<(f64, f64)>().copied().unwrap_or_default();
+        Ok(JSValue::new_boolean(ctx, w == h))
+    }
+
+    #[test]
+    fn inheritance() {
+        let shape = JSClassBuilder::new("Shape")
+            .static_function("area", area, PropertyAttributes::DONT_ENUM)
+            .build();
+        let rectangle = JSClassBuilder::new("Rectangle")
+            .parent_class(&shape)
+            .static_function("isSquare", is_square, PropertyAttributes::DONT_ENUM)
+            .build();
+
+        let ctx = JSContext::default();
+        let class = rectangle.clone();
+        let constructor = rectangle.constructor(&ctx, move |ctx, _new_target, arguments| {
+            let w = arguments[0].as_number()?;
+            let h = arguments[1].as_number()?;
+            Ok(JSObject::new_with_class_and_data(ctx, &class, (w, h)))
+        });
+        let global = ctx.global_object();
+        global
+            .set_property("Rectangle", constructor.into())
+            .unwrap();
+        global
+            .set_property(
+                "Shape",
+                shape
+                    .constructor(&ctx, |ctx, _, _| Err(error(ctx, "abstract")))
+                    .into(),
+            )
+            .unwrap();
+
+        let r = evaluate_script(
+            &ctx,
+            "class Square extends Rectangle {
+                 constructor(side) { super(side, side); }
+                 perimeter() { return 4 * Math.sqrt(this.area()); }
+             }
+             const s = new Square(3);
+             const r = Rectangle(2, 3);
+             [s.area(), s.isSquare(), s.perimeter(), r.area(), r.isSquare(),
+              s instanceof Square, s instanceof Rectangle, s instanceof Shape,
+              r instanceof Square, Rectangle.name, r.constructor === Rectangle,
+              Object.getPrototypeOf(Rectangle.prototype) === Shape.prototype]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            r.to_json_string(0).unwrap().to_string(),
+            r#"[9,true,12,6,false,true,true,true,false,"Rectangle",true,true]"#
+        );
+
+        // Prototypes have private data of JavaScriptCore's own.
+        let prototype = rectangle.prototype(&ctx).unwrap();
+        assert!(prototype.get_native::<(f64, f64)>().is_none());
+        assert_eq!(prototype.set_native(1), Err(1));
+        let r = evaluate_script(&ctx, "Rectangle.prototype.area()", None, "test.js", 1);
+        assert_eq!(r.unwrap_err().message(), Some("Not a shape"));
+        let r = evaluate_script(&ctx, "new Shape()", None, "test.js", 1);
+        assert_eq!(r.unwrap_err().message(), Some("abstract"));
+    }
+
+    #[test]
+    fn new_target() {
+        let class = JSClassBuilder::new("Base").build();
+        let ctx = JSContext::default();
+        let c = class.clone();
+        let constructor = class.constructor(&ctx, move |ctx, new_target, _arguments| {
+            let object = JSObject::new_with_class(ctx, &c);
+            let name = match new_target {
+                Some(new_target) => new_target.get_property("name")?,
+                None => JSValue::new_null(ctx),
+            };
+            object.set_property("createdBy", name)?;
+            Ok(object)
+        });
+        ctx.global_object()
+            .set_property("Base", constructor.into())
+            .unwrap();
+
+        let r = evaluate_script(
+            &ctx,
+            "class Derived extends Base {}
+             [new Base().createdBy, new Derived().createdBy, Base().createdBy,
+              Base() instanceof Base, Reflect.construct(Base, [], Derived) instanceof Derived]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            r.to_json_string(0).unwrap().to_string(),
+            r#"["Base","Derived",null,true,true]"#
+        );
+    }
+
+    #[test]
+    fn manual_prototype() {
+        let class = JSClassBuilder::new("Rectangle")
+            .static_function("area", area, PropertyAttributes::DONT_ENUM)
+            .automatic_prototype(false)
+            .build();
+        let ctx = JSContext::default();
+        assert!(class.prototype(&ctx).is_none());
+        let o = JSObject::new_with_class_and_data(&ctx, &class, (2.0, 3.0));
+        ctx.global_object().set_property("o", o.into()).unwrap();
+
+        let c = class.clone();
+        let constructor = class.constructor(&ctx, move |ctx, _new_target, _arguments| {
+            Ok(JSObject::new_with_class_and_data(ctx, &c, (1.0, 1.0)))
+        });
+        ctx.global_object()
+            .set_property("Rectangle", constructor.into())
+            .unwrap();
+
+        let r = evaluate_script(
+            &ctx,
+            "Rectangle.prototype.double = function () { return 2 * this.area(); };
+             [o.area(), Object.getPrototypeOf(o) === Object.prototype, o instanceof Rectangle,
+              new Rectangle().double(), Rectangle().double(), 'area' in Rectangle.prototype]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            r.to_json_string(0).unwrap().to_string(),
+            "[6,true,false,2,2,false]"
+        );
+    }
+
+    #[test]
+    fn global_class() {
+        let class = JSClassBuilder::new("Global")
+            .initialize(|ctx, object| {
+                object
+                    .set_property("answer", JSValue::new_number(ctx, 42.0))
+                    .unwrap();
+            })
+            .build();
+        let ctx = JSContext::new_with_class(&class);
+        assert!(ctx.global_object().is_object_of_class(&class));
+        let r = evaluate_script(&ctx, "answer", None, "test.js", 1).unwrap();
+        assert_eq!(r.as_number().unwrap(), 42.0);
+    }
+
+    #[test]
+    fn static_values() {
+        let width = Rc::new(Cell::new(1.0));
+        let (w, w2) = (width.clone(), width.clone());
+        let parent = JSClassBuilder::new("Shape")
+            .static_value_read_only(
+                "kind",
+                |ctx, _object| Ok(JSValue::new_string(ctx, "shape")),
+                PropertyAttributes::DONT_ENUM,
+            )
+            .build();
+        let class = JSClassBuilder::new("Rect")
+            .parent_class(&parent)
+            .static_value(
+                "width",
+                move |ctx, _object| Ok(JSValue::new_number(ctx, w.get())),
+                move |_ctx, _object, value| {
+                    let n = value.as_number()?;
+                    if n < 0.0 {
+                        panic!("negative width");
+                    }
+                    w2.set(n);
+                    Ok(())
+                },
+                PropertyAttributes::NONE,
+            )
+            .build();
+
+        let ctx = JSContext::default();
+        let o = JSObject::new_with_class(&ctx, &class);
+        ctx.global_object().set_property("o", o.into()).unwrap();
+
+        width.set(3.0);
+        let r = evaluate_script(&ctx, "o.width", None, "test.js", 1).unwrap();
+        assert_eq!(r.as_number().unwrap(), 3.0);
+        evaluate_script(&ctx, "o.width = 5", None, "test.js", 1).unwrap();
+        assert_eq!(width.get(), 5.0);
+
+        let r = evaluate_script(&ctx, "o.width = -1", None, "test.js", 1);
+        assert!(r.unwrap_err().message().unwrap().contains("negative width"));
+        assert_eq!(width.get(), 5.0);
+
+        let r = evaluate_script(
+            &ctx,
+            "o.kind = 'other'; o.kind + Object.keys(o).indexOf('kind')",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(r.as_string().unwrap(), "shape-1");
+    }
+
+    #[test]
+    fn finalize() {
+        struct Counted(Rc<Cell<usize>>);
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Rc::new(Cell::new(0));
+        let finalized = Rc::new(Cell::new(0));
+        let f = finalized.clone();
+        let parent = JSClassBuilder::new("Parent").build();
+        let class = JSClassBuilder::new("Child")
+            .parent_class(&parent)
+            .finalize(move |data| {
+                assert!(data.downcast_mut::<Counted>().is_some());
+                f.set(f.get() + 1);
+            })
+            .build();
+
+        let ctx = JSContext::default();
+        let kept = JSObject::new_with_class_and_data(&ctx, &class, Counted(drops.clone()));
+        ctx.global_object()
+            .set_property("kept", kept.into())
+            .unwrap();
+        for _ in 0..1000 {
+            let o = JSObject::new_with_class_and_data(&ctx, &class, Counted(drops.clone()));
+            assert!(o.get_native::<Counted>().is_some());
+            assert!(o.get_native::<u32>().is_none());
+        }
+        let o = JSObject::new_with_class(&ctx, &class);
+        assert!(o.get_native::<Counted>().is_none());
+        garbage_collect(&ctx);
+        assert_eq!(drops.get(), finalized.get());
+        assert!(drops.get() <= 1000);
+
+        let kept = evaluate_script(&ctx, "kept", None, "test.js", 1).unwrap();
+        assert!(kept.as_object().unwrap().get_native::<Counted>().is_some());
+
+        // Every value is dropped once by the time the context is gone.
+        drop(ctx);
+        assert_eq!(drops.get(), 1001);
+        assert_eq!(finalized.get(), 1001);
+    }
+
+    #[test]
+    fn set_native() {
+        let class = JSClassBuilder::new("Host").build();
+        let ctx = ContextBuilder::new().global_class(&class).build().unwrap();
+        let global = ctx.global_object();
+        assert_eq!(global.set_native(vec![1, 2]), Ok(()));
+        assert_eq!(global.get_native::<Vec<i32>>(), Some(&vec![1, 2]));
+        assert_eq!(global.set_native(3), Err(3));
+
+        let o = JSObject::new_with_class_and_data(&ctx, &class, 'a');
+        assert_eq!(o.set_native('b'), Err('b'));
+        assert_eq!(o.get_native::<char>(), Some(&'a'));
+
+        let f = JSObject::new_function(&ctx, |ctx, _this, _arguments| {
+            Ok(JSValue::new_undefined(ctx))
+        });
+        assert_eq!(f.set_native(4), Err(4));
+        assert_eq!(f.get_native::<i32>(), None);
+    }
+
+    #[test]
+    fn call_as_constructor() {
+        let point = JSClassBuilder::new("Point").build();
+        let constructor = JSClassBuilder::new("PointConstructor")
+            .call_as_constructor(move |ctx, _constructor, arguments| {
+                if arguments.len() != 2 {
+                    panic!("Point takes 2 arguments");
+                }
+                let x = arguments[0].as_number()?;
+                let y = arguments[1].as_number()?;
+                Ok(JSObject::new_with_class_and_data(ctx, &point, (x, y)))
+            })
+            .build();
+
+        let ctx = JSContext::default();
+        let c = JSObject::new_with_class(&ctx, &constructor);
+        ctx.global_object().set_property("Point", c.into()).unwrap();
+
+        let p = evaluate_script(&ctx, "new Point(1, 2)", None, "test.js", 1).unwrap();
+        let p = p.as_object().unwrap();
+        assert_eq!(p.get_native::<(f64, f64)>(), Some(&(1.0, 2.0)));
+
+        let r = evaluate_script(&ctx, "new Point(1, Symbol())", None, "test.js", 1);
+        assert_eq!(r.unwrap_err().name(), Some("TypeError"));
+        let r = evaluate_script(&ctx, "new Point()", None, "test.js", 1);
+        assert!(r.unwrap_err().message().unwrap().contains("2 arguments"));
+    }
+
+    #[test]
+    fn has_instance() {
+        let point = JSClassBuilder::new("Point").build();
+        let p = point.clone();
+        let constructor = JSClassBuilder::new("PointConstructor")
+            .call_as_constructor(move |ctx, _constructor, _arguments| {
+                Ok(JSObject::new_with_class(ctx, &p))
+            })
+            .has_instance(move |ctx, _constructor, value| {
+                if value.is_null() {
+                    return Err(error(ctx, "null is not a point"));
+                }
+                Ok(value
+                    .as_object()
+                    .is_ok_and(|o| o.is_object_of_class(&point)))
+            })
+            .build();
+
+        let ctx = JSContext::default();
+        let c = JSObject::new_with_class(&ctx, &constructor);
+        c.set_property("prototype", JSObject::new(&ctx).into())
+            .unwrap();
+        ctx.global_object().set_property("Point", c.into()).unwrap();
+
+        let r = evaluate_script(
+            &ctx,
+            "class Point3D extends Point {}
+             [new Point() instanceof Point, new Point3D() instanceof Point, 1 instanceof Point]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            r.to_json_string(0).unwrap().to_string(),
+            "[true,true,false]"
+        );
+
+        let r = evaluate_script(&ctx, "null instanceof Point", None, "test.js", 1);
+        assert_eq!(r.unwrap_err().message(), Some("null is not a point"));
+    }
+
+    #[test]
+    fn property_callbacks() {
+        let map = Rc::new(RefCell::new(HashMap::new()));
+        let (m1, m2, m3) = (map.clone(), map.clone(), map.clone());
+        let (m4, m5) = (map.clone(), map.clone());
+        let class = JSClassBuilder::new("Map")
+            .has_property(move |_ctx, _object, name| {
+                name == "broken" || m1.borrow().contains_key(name)
+            })
+            .get_property(move |ctx, _object, name| {
+                if name == "broken" {
+                    return Err(error(ctx, "broken property"));
+                }
+                Ok(m2
+                    .borrow()
+                    .get(name)
+                    .map(|s: &String| JSValue::new_string(ctx, s.as_str())))
+            })
+            .set_property(move |_ctx, _object, name, value| {
+                if name.starts_with('_') {
+                    return Ok(false);
+                }
+                m3.borrow_mut()
+                    .insert(name.to_owned(), value.as_string()?.to_string());
+                Ok(true)
+            })
+            .delete_property(move |_ctx, _object, name| Ok(m4.borrow_mut().remove(name).is_some()))
+            .get_property_names(move |_ctx, _object, names| {
+                let mut keys: Vec<_> = m5.borrow().keys().cloned().collect();
+                keys.sort();
+                for key in keys {
+                    names.add_name(key);
+                }
+            })
+            .build();
+
+        let ctx = JSContext::default();
+        let o = JSObject::new_with_class(&ctx, &class);
+        ctx.global_object().set_property("o", o.into()).unwrap();
+        map.borrow_mut().insert("a".to_owned(), "x".to_owned());
+
+        let r = evaluate_script(
+            &ctx,
+            "o.b = 'y'; o._c = 'z'; [o.a, o.b, o._c, 'a' in o, 'd' in o, typeof o.toString]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            r.to_json_string(0).unwrap().to_string(),
+            r#"["x","y","z",true,false,"function"]"#
+        );
+        assert_eq!(map.borrow().get("b").map(String::as_str), Some("y"));
+        assert!(!map.borrow().contains_key("_c"));
+
+        let r = evaluate_script(&ctx, "o.broken", None, "test.js", 1);
+        assert_eq!(r.unwrap_err().message(), Some("broken property"));
+        // Scripts can catch the errors of callbacks.
+        let r = evaluate_script(
+            &ctx,
+            "try { o.broken; } catch (e) { e instanceof Error && e.message }",
+            None,
+            "test.js",
+            1,
+        );
+        assert_eq!(r.unwrap().as_string().unwrap(), "broken property");
+
+        let r = evaluate_script(
+            &ctx,
+            "delete o.a; delete o._c; var keys = []; for (var k in o) keys.push(k);
+             [Object.keys(o).join(), keys.join(), o.a, o._c]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            r.to_json_string(0).unwrap().to_string(),
+            r#"["b","b",null,null]"#
+        );
+        assert!(!map.borrow().contains_key("a"));
+    }
+
+    #[test]
+    fn call_as_function() {
+        let class = JSClassBuilder::new("Callable")
+            .call_as_function(|ctx, function, this, arguments| {
+                if arguments.is_empty() {
+                    panic!("No arguments");
+                }
+                let label = function.get_property("label")?.as_string()?.to_string();
+                let name = this.get_property("name")?.as_string()?.to_string();
+                let n = arguments.len() as f64;
+                Ok(JSValue::new_string(
+                    ctx,
+                    format!("{} {} {}", label, name, n),
+                ))
+            })
+            .build();
+
+        let ctx = JSContext::default();
+        let f = JSObject::new_with_class(&ctx, &class);
+        f.set_property("label", JSValue::new_string(&ctx, "call"))
+            .unwrap();
+        assert!(f.is_function());
+        ctx.global_object().set_property("f", f.into()).unwrap();
+
+        let r = evaluate_script(&ctx, "({ name: 'o', f: f }).f(1, 2)", None, "test.js", 1).unwrap();
+        assert_eq!(r.as_string().unwrap(), "call o 2");
+
+        let r = evaluate_script(&ctx, "f()", None, "test.js", 1);
+        assert!(r.unwrap_err().message().unwrap().contains("No arguments"));
+    }
+
+    #[test]
+    fn convert_to_type() {
+        let class = JSClassBuilder::new("Money")
+            .convert_to_type(|ctx, object, hint| {
+                let cents = match object.get_native::<i64>() {
+                    Some(&cents) => cents as f64,
+                    None => return Ok(None),
+                };
+                if cents < 0.0 {
+                    return Err(error(ctx, "negative amount"));
+                }
+                Ok(Some(match hint {
+                    JSType::String => JSValue::new_string(ctx, format!("{} cents", cents)),
+                    _ => JSValue::new_number(ctx, cents),
+                }))
+            })
+            .build();
+
+        let ctx = JSContext::default();
+        let global = ctx.global_object();
+        let m = JSObject::new_with_class_and_data(&ctx, &class, 150_i64);
+        global.set_property("m", m.into()).unwrap();
+        let plain = JSObject::new_with_class(&ctx, &class);
+        global.set_property("plain", plain.into()).unwrap();
+        let debt = JSObject::new_with_class_and_data(&ctx, &class, -1_i64);
+        global.set_property("debt", debt.into()).unwrap();
+
+        let r = evaluate_script(
+            &ctx,
+            "[m + 1, String(m), `${m}`, String(plain)]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            r.to_json_string(0).unwrap().to_string(),
+            r#"[151,"150 cents","150 cents","[object Money]"]"#
+        );
+
+        let r = evaluate_script(&ctx, "debt * 1", None, "test.js", 1);
+        assert_eq!(r.unwrap_err().message(), Some("negative amount"));
+    }
+
+    #[test]
+    fn static_functions() {
+        fn describe(
+            ctx: &JSContext,
+            this: &JSObject,
+            arguments: &[JSValue],
+        ) -> Result<JSValue, JSException> {
+            let name = this
+                .get_native::<&str>()
+                .ok_or_else(|| JSException::type_error(ctx, "Not a Named"))?;
+            Ok(JSValue::new_string(
+                ctx,
+                format!("{} with {} arguments", name, arguments.len()),
+            ))
+        }
+
+        let class = JSClassBuilder::new("Named")
+            .static_function("describe", describe, PropertyAttributes::DONT_ENUM)
+            .static_function(
+                "fail",
+                |_ctx, _this, _arguments| panic!("failed"),
+                PropertyAttributes::NONE,
+            )
+            .build();
+
+        let ctx = JSContext::default();
+        let a = JSObject::new_with_class_and_data(&ctx, &class, "a");
+        ctx.global_object().set_property("a", a.into()).unwrap();
+        let b = JSObject::new_with_class_and_data(&ctx, &class, "b");
+        ctx.global_object().set_property("b", b.into()).unwrap();
+
+        let r = evaluate_script(
+            &ctx,
+            "[a.describe(1), a.describe.call(b), a.describe === b.describe,
+              Object.keys(Object.getPrototypeOf(a)).join()]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            r.to_json_string(0).unwrap().to_string(),
+            r#"["a with 1 arguments","b with 0 arguments",true,"fail"]"#
+        );
+
+        let r = evaluate_script(&ctx, "a.describe.call({})", None, "test.js", 1);
+        assert_eq!(r.unwrap_err().message(), Some("Not a Named"));
+        let r = evaluate_script(&ctx, "a.fail()", None, "test.js", 1);
+        assert!(r.unwrap_err().message().unwrap().contains("failed"));
+    }
+}
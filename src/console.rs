@@ -0,0 +1,149 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::function::new_function;
+use super::{JSContext, JSException, JSObject, JSValue};
+use log::Level;
+
+/// The log target used for contexts that have no name.
+const DEFAULT_TARGET: &str = "javascript";
+
+/// The `console` methods, and the level at which each of them logs.
+const METHODS: &[(&str, Level)] = &[
+    ("log", Level::Info),
+    ("info", Level::Info),
+    ("warn", Level::Warn),
+    ("error", Level::Error),
+    ("debug", Level::Debug),
+    ("trace", Level::Trace),
+];
+
+/// Installs a `console` object that forwards messages to the `log` crate.
+///
+/// * `ctx`: The execution context to install `console` into.
+///
+/// The `console.log`, `info`, `warn`, `error`, `debug` and `trace`
+/// methods log their arguments, separated by spaces, at the matching
+/// [`log::Level`]. The name of the context (see [`JSContext::set_name`])
+/// is used as the log target, so that script output can be filtered like
+/// any other log output of the host. Contexts without a name log to the
+/// `javascript` target.
+///
+/// Strings are logged as is. Arrays and plain objects are logged as
+/// JSON, and all other values are converted to strings.
+///
+/// Requires the `log` feature.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let ctx = JSContext::default();
+/// ctx.set_name("plugin");
+/// install_console(&ctx).expect("console installed");
+///
+/// // Logs "Hello [1,2]" at the `Warn` level with the `plugin` target.
+/// evaluate_script(&ctx, "console.warn('Hello', [1, 2])", None, "test.js", 1)
+///     .expect("logged");
+/// ```
+///
+/// [`log::Level`]: https://docs.rs/log/0.4/log/enum.Level.html
+/// [`JSContext::set_name`]: struct.JSContext.html#method.set_name
+pub fn install_console(ctx: &JSContext) -> Result<(), JSException> {
+    let console = JSObject::new(ctx);
+    for &(name, level) in METHODS {
+        let method = new_function(ctx, move |ctx, _this, arguments| {
+            let target = ctx.name().map(|n| n.to_string());
+            let target = target.as_deref().unwrap_or(DEFAULT_TARGET);
+            if log::log_enabled!(target: target, level) {
+                log::log!(target: target, level, "{}", format_arguments(arguments));
+            }
+            Ok(JSValue::new_undefined(ctx))
+        });
+        console.set_property(name, method.into())?;
+    }
+    ctx.global_object().set_property("console", console.into())
+}
+
+/// Formats the arguments of a `console` call into one message.
+fn format_arguments(arguments: &[JSValue]) -> String {
+    arguments
+        .iter()
+        .map(|arg| format_argument(arg).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn format_argument(value: &JSValue) -> Option<String> {
+    let s = value.as_string().ok()?;
+    if value.is_object() && (value.is_array() || s == "[object Object]") {
+        if let Ok(json) = value.to_json_string(0) {
+            return Some(json.to_string());
+        }
+    }
+    Some(s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSContext};
+    use super::install_console;
+    use log::{Level, Log, Metadata, Record};
+    use std::sync::Mutex;
+
+    struct Capture(Mutex<Vec<(Level, String, String)>>);
+
+    impl Log for Capture {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            self.0.lock().unwrap().push((
+                record.level(),
+                record.target().to_owned(),
+                record.args().to_string(),
+            ));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: Capture = Capture(Mutex::new(Vec::new()));
+
+    #[test]
+    fn console_routes_to_log() {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let ctx = JSContext::default();
+        ctx.set_name("plugin");
+        install_console(&ctx).unwrap();
+        evaluate_script(
+            &ctx,
+            "console.log('a', 1, true, [1, 2], {x: 'y'}, null, undefined);
+             console.warn.call(console, new Error('oops'));
+             console.error('e');",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+
+        let records = LOGGER.0.lock().unwrap();
+        assert_eq!(
+            records[0],
+            (
+                Level::Info,
+                "plugin".to_owned(),
+                "a 1 true [1,2] {\"x\":\"y\"} null undefined".to_owned()
+            )
+        );
+        assert_eq!(
+            records[1],
+            (Level::Warn, "plugin".to_owned(), "Error: oops".to_owned())
+        );
+        assert_eq!(records[2].0, Level::Error);
+    }
+}
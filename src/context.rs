@@ -4,9 +4,19 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use super::{JSClass, JSContext, JSContextGroup, JSString};
+use super::base::evaluate_script;
+use super::builder::start_engine;
+use super::class::init_global;
+use super::hooks::Hooks;
+#[cfg(feature = "memory-usage")]
+use super::MemoryUsage;
+use super::{
+    JSClass, JSContext, JSContextGroup, JSException, JSObject, JSString, JSValue,
+    PropertyAttributes, TerminationHandle,
+};
 use crate::sys;
 use std::ptr;
+use std::sync::Arc;
 
 impl JSContext {
     /// Creates a global JavaScript execution context and populates it
@@ -35,11 +45,30 @@ impl JSContext {
     /// * `global_object_class`: The class to use when creating the global
     ///   object.
     pub fn new_with_class(global_object_class: &JSClass) -> Self {
+        let ctx =
+            JSContext::from_raw(unsafe { sys::JSGlobalContextCreate(global_object_class.raw) });
+        init_global(&ctx, global_object_class);
+        ctx
+    }
+
+    /// Wraps a global context, taking over the caller's reference to it.
+    pub(crate) fn from_raw(raw: sys::JSGlobalContextRef) -> Self {
         JSContext {
-            raw: unsafe { sys::JSGlobalContextCreate(global_object_class.raw) },
+            raw,
+            hooks: Hooks::for_context(raw),
         }
     }
 
+    /// Wraps the global context of `ctx`, retaining it.
+    ///
+    /// This is used to hand a `JSContext` to native callbacks, which
+    /// are only given a `JSContextRef`.
+    pub(crate) unsafe fn from_context_ref(ctx: sys::JSContextRef) -> Self {
+        JSContext::from_raw(sys::JSGlobalContextRetain(sys::JSContextGetGlobalContext(
+            ctx,
+        )))
+    }
+
     /// Gets the context group to which a JavaScript execution context belongs.
     pub fn group(&self) -> JSContextGroup {
         let g = unsafe { sys::JSContextGetGroup(self.raw) };
@@ -49,6 +78,138 @@ impl JSContext {
         JSContextGroup { raw: g }
     }
 
+    /// Gets the global object of this context.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::new();
+    ///
+    /// let global = ctx.global_object();
+    /// assert!(global.has_property("Object"));
+    /// ```
+    pub fn global_object(&self) -> JSObject {
+        JSObject::from_raw(self.raw, unsafe { sys::JSContextGetGlobalObject(self.raw) })
+    }
+
+    /// Creates a namespace of host functions on the global object.
+    ///
+    /// * `name`: The name of the global that holds the namespace.
+    /// * `members`: The names and functions of the namespace.
+    ///
+    /// The namespace is frozen, so scripts can not replace or add to its
+    /// members. Like the `Math` and `JSON` namespaces, the global itself
+    /// is not enumerable but can be replaced.
+    ///
+    /// Returns the namespace, or an exception if one was thrown, for
+    /// example because the global object does not allow the global to
+    /// be set.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let read = JSObject::new_function(&ctx, |ctx, _this, _arguments| {
+    ///     Ok(JSValue::new_string(ctx, "contents"))
+    /// });
+    /// let exists = JSObject::new_function(&ctx, |ctx, _this, _arguments| {
+    ///     Ok(JSValue::new_boolean(ctx, true))
+    /// });
+    /// ctx.register_namespace("fs", &[("readFile", read), ("exists", exists)])
+    ///     .expect("registered");
+    ///
+    /// let script = "'use strict'; fs.exists = null";
+    /// assert!(evaluate_script(&ctx, script, None, "test.js", 1).is_err());
+    /// let r = evaluate_script(&ctx, "fs.readFile('a.txt')", None, "test.js", 1).expect("read");
+    /// assert_eq!(r.as_string().expect("string"), "contents");
+    /// ```
+    pub fn register_namespace(
+        &self,
+        name: &str,
+        members: &[(&str, JSObject)],
+    ) -> Result<JSObject, JSException> {
+        let namespace = JSObject::new(self);
+        for (member, function) in members {
+            namespace.set_property(*member, JSObject::from_raw(self.raw, function.raw).into())?;
+        }
+        let freeze =
+            evaluate_script(self, "Object.freeze", None, "namespace.js", 1)?.as_object()?;
+        let namespace = freeze
+            .call_as_function(None, &[namespace.into()])?
+            .as_object()?;
+        self.global_object().set_property_with_attributes(
+            name,
+            JSObject::from_raw(self.raw, namespace.raw).into(),
+            PropertyAttributes::DONT_ENUM,
+        )?;
+        Ok(namespace)
+    }
+
+    /// Registers a class as a global of this context, with a constructor
+    /// that is created by [`JSClass::constructor`].
+    ///
+    /// * `class`: The class to register.
+    /// * `callback`: The closure that creates the objects of the class.
+    ///
+    /// Classes are registered by name. If a class with the same name is
+    /// already registered in this context, `class` and `callback` are
+    /// dropped, and the class and constructor that were registered
+    /// first are used instead. This keeps code that sets up a context
+    /// from creating a second class when it runs twice, which would
+    /// break `instanceof` for the objects of the first one. The global
+    /// is set to the constructor either way, so it is restored if a
+    /// script or [`reset`] removed it.
+    ///
+    /// Returns the registered class, which the objects of the class
+    /// should be created with, and its constructor. The registration
+    /// lasts as long as the context.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let setup = |ctx: &JSContext| {
+    ///     let class = JSClassBuilder::new("Counter").build();
+    ///     let c = class.clone();
+    ///     ctx.register_class(&class, move |ctx, _new_target, _arguments| {
+    ///         Ok(JSObject::new_with_class_and_data(ctx, &c, 0u32))
+    ///     })
+    ///     .expect("registered")
+    /// };
+    /// let (class, _) = setup(&ctx);
+    /// let counter = JSObject::new_with_class(&ctx, &class);
+    /// ctx.global_object().set_property("counter", counter.into()).expect("set");
+    ///
+    /// // Setting the context up again reuses the class.
+    /// setup(&ctx);
+    /// let r = evaluate_script(&ctx, "counter instanceof Counter", None, "test.js", 1)
+    ///     .expect("evaluated");
+    /// assert!(r.as_boolean());
+    /// ```
+    ///
+    /// [`JSClass::constructor`]: struct.JSClass.html#method.constructor
+    /// [`reset`]: #method.reset
+    pub fn register_class<F>(
+        &self,
+        class: &JSClass,
+        callback: F,
+    ) -> Result<(JSClass, JSObject), JSException>
+    where
+        F: Fn(&JSContext, Option<&JSObject>, &[JSValue]) -> Result<JSObject, JSException> + 'static,
+    {
+        let (class, constructor) = match self.hooks.class(class.name()) {
+            Some((class, constructor)) => (class, JSObject::from_raw(self.raw, constructor)),
+            None => {
+                let constructor = class.constructor(self, callback);
+                self.hooks.add_class(class.clone(), constructor.raw);
+                (class.clone(), constructor)
+            }
+        };
+        self.global_object().set_property_with_attributes(
+            class.name(),
+            JSObject::from_raw(self.raw, constructor.raw).into(),
+            PropertyAttributes::DONT_ENUM,
+        )?;
+        Ok((class, constructor))
+    }
+
     /// Gets a copy of the name of a context.
     ///
     /// A `JSContext`'s name is exposed for remote debugging
@@ -87,6 +248,63 @@ impl JSContext {
     pub fn set_name<S: Into<JSString>>(&self, name: S) {
         unsafe { sys::JSGlobalContextSetName(self.raw, name.into().raw) }
     }
+
+    /// Gets a handle that can terminate scripts running in this context
+    /// from another thread.
+    ///
+    /// Termination applies to the whole context group, so a handle stops
+    /// whichever script is running in any context of the group. All
+    /// handles for a group share the same state.
+    ///
+    /// A handle should be obtained before scripts start executing in the
+    /// group, as JavaScriptCore may not notice the request in a script
+    /// that was already running when the first handle was created.
+    ///
+    /// ```
+    /// # use javascriptcore::JSContext;
+    /// let ctx = JSContext::new();
+    /// let handle = ctx.termination_handle();
+    ///
+    /// // `handle` can now be moved to another thread.
+    /// std::thread::spawn(move || handle.terminate());
+    /// ```
+    pub fn termination_handle(&self) -> TerminationHandle {
+        self.hooks.watchdog().termination_handle()
+    }
+
+    /// Gets statistics about the memory used by the heap of this
+    /// context's group.
+    ///
+    /// Requires the `memory-usage` feature.
+    ///
+    /// ```
+    /// # use javascriptcore::JSContext;
+    /// let ctx = JSContext::new();
+    ///
+    /// let usage = ctx.memory_usage();
+    /// assert!(usage.heap_size > 0);
+    /// assert!(usage.object_count > 0);
+    /// ```
+    #[cfg(feature = "memory-usage")]
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let raw = unsafe { sys::JSGetMemoryUsageStatistics(self.raw) };
+        let stats = JSObject::from_raw(self.raw, raw);
+        let count = |name: &str| {
+            stats
+                .get_property(name)
+                .and_then(|v| v.as_number())
+                .map_or(0, |n| n as usize)
+        };
+        MemoryUsage {
+            heap_size: count("heapSize"),
+            heap_capacity: count("heapCapacity"),
+            extra_memory_size: count("extraMemorySize"),
+            object_count: count("objectCount"),
+            protected_object_count: count("protectedObjectCount"),
+            global_object_count: count("globalObjectCount"),
+            protected_global_object_count: count("protectedGlobalObjectCount"),
+        }
+    }
 }
 
 impl Default for JSContext {
@@ -100,20 +318,36 @@ impl Default for JSContext {
     /// However, you may not use values created in the context in other
     /// contexts.
     fn default() -> Self {
+        start_engine();
+        JSContext::from_raw(unsafe { sys::JSGlobalContextCreate(ptr::null_mut()) })
+    }
+}
+
+impl Clone for JSContext {
+    /// Gets another reference to the same execution context.
+    ///
+    /// The context is kept alive until all references to it are dropped.
+    fn clone(&self) -> Self {
+        unsafe { sys::JSGlobalContextRetain(self.raw) };
         JSContext {
-            raw: unsafe { sys::JSGlobalContextCreate(ptr::null_mut()) },
+            raw: self.raw,
+            hooks: self.hooks.clone(),
         }
     }
 }
 
 impl Drop for JSContext {
     fn drop(&mut self) {
+        if Arc::strong_count(&self.hooks) == 1 {
+            self.hooks.release();
+        }
         unsafe { sys::JSGlobalContextRelease(self.raw) }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::super::{evaluate_script, JSClassBuilder, JSObject, JSValue};
     use super::JSContext;
 
     #[test]
@@ -131,4 +365,92 @@ mod tests {
         ctx.set_name("test thread");
         assert_eq!(ctx.name().unwrap(), "test thread");
     }
+
+    #[test]
+    fn global_object() {
+        let ctx = JSContext::new();
+        let global = ctx.global_object();
+        assert!(global.is_object());
+        assert!(global.get_property("Array").unwrap().is_object());
+    }
+
+    #[cfg(feature = "memory-usage")]
+    #[test]
+    fn memory_usage() {
+        let ctx = JSContext::new();
+        let usage = ctx.memory_usage();
+        assert!(usage.heap_size > 0);
+        assert!(usage.heap_capacity >= usage.heap_size);
+        assert!(usage.object_count > 0);
+        assert!(usage.global_object_count >= 1);
+    }
+
+    #[test]
+    fn register_namespace() {
+        let ctx = JSContext::default();
+        let add = JSObject::new_function(&ctx, |ctx, _this, arguments| {
+            let sum = arguments[0].as_number()? + arguments[1].as_number()?;
+            Ok(JSValue::new_number(ctx, sum))
+        });
+        let namespace = ctx.register_namespace("calc", &[("add", add)]).unwrap();
+        assert!(namespace.has_property("add"));
+
+        let r = evaluate_script(
+            &ctx,
+            "calc.add = null; calc.sub = null; delete calc.add;
+             [calc.add(1, 2), Object.isFrozen(calc), Object.keys(calc).join(),
+              Object.keys(globalThis).includes('calc')]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            r.to_json_string(0).unwrap().to_string(),
+            r#"[3,true,"add",false]"#
+        );
+    }
+
+    #[test]
+    fn register_class() {
+        let ctx = JSContext::default();
+        let register = |ctx: &JSContext| {
+            let class = JSClassBuilder::new("Thing").build();
+            let c = class.clone();
+            ctx.register_class(&class, move |ctx, _new_target, _arguments| {
+                Ok(JSObject::new_with_class(ctx, &c))
+            })
+            .unwrap()
+        };
+        let (class, first) = register(&ctx);
+        evaluate_script(
+            &ctx,
+            "globalThis.a = new Thing(); delete globalThis.Thing",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        let (again, second) = register(&ctx);
+        assert_eq!(first.raw, second.raw);
+        assert_eq!(class.raw, again.raw);
+
+        let r = evaluate_script(
+            &ctx,
+            "[a instanceof Thing, new Thing() instanceof Thing, Object.keys(globalThis).includes('Thing')]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            r.to_json_string(0).unwrap().to_string(),
+            "[true,true,false]"
+        );
+
+        // Other contexts have registrations of their own.
+        let other = JSContext::default();
+        let (_, third) = register(&other);
+        assert_ne!(first.raw, third.raw);
+    }
 }
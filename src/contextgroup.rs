@@ -4,6 +4,8 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use super::builder::start_engine;
+use super::class::init_global;
 use super::{JSClass, JSContext, JSContextGroup};
 use crate::sys;
 use std::ptr;
@@ -23,9 +25,7 @@ impl JSContextGroup {
     ///
     /// The created global context retains this group.
     pub fn new_context(&self) -> JSContext {
-        JSContext {
-            raw: unsafe { sys::JSGlobalContextCreateInGroup(self.raw, ptr::null_mut()) },
-        }
+        JSContext::from_raw(unsafe { sys::JSGlobalContextCreateInGroup(self.raw, ptr::null_mut()) })
     }
 
     /// Creates a global JavaScript execution context in this context
@@ -40,18 +40,20 @@ impl JSContextGroup {
     /// * `global_object_class`: The class to use when creating the global
     ///   object.
     pub fn new_context_with_class(&self, global_object_class: &JSClass) -> JSContext {
-        JSContext {
-            raw: unsafe { sys::JSGlobalContextCreateInGroup(self.raw, global_object_class.raw) },
-        }
+        let ctx = JSContext::from_raw(unsafe {
+            sys::JSGlobalContextCreateInGroup(self.raw, global_object_class.raw)
+        });
+        init_global(&ctx, global_object_class);
+        ctx
     }
 }
 
 impl Default for JSContextGroup {
     /// Creates a JavaScript context group.
     fn default() -> Self {
-        JSContextGroup {
-            raw: unsafe { sys::JSContextGroupCreate() },
-        }
+        start_engine();
+        let raw = unsafe { sys::JSContextGroupCreate() };
+        JSContextGroup { raw }
     }
 }
 
@@ -0,0 +1,359 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{JSContext, JSError, JSException, JSVirtualMachine};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Creates a new context of a pool.
+type Create = dyn Fn() -> JSContext + Send + Sync;
+
+/// Sets up a new context of a pool.
+type Setup = dyn Fn(&JSContext) -> Result<(), JSException> + Send + Sync;
+
+/// A pool of warm contexts, which lets threads run scripts in parallel.
+///
+/// A [`JSVirtualMachine`] lets threads share one context, but only one
+/// of them can use it at a time. A pool instead keeps several virtual
+/// machines, each with a context in a group of its own, so that as many
+/// threads as there are contexts can run scripts at the same time.
+///
+/// Each context is set up once, when it is created, such as by
+/// installing host functions and evaluating a bundle of scripts, so
+/// that jobs do not pay for that. After each job, the context is
+/// [`reset`] to the state that it had once it was set up, so that
+/// state does not leak from one job to the next.
+///
+/// ```
+/// # use javascriptcore::*;
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// const BUNDLE: &str = "function render(n) { return '<li>' + n + '</li>'; }";
+///
+/// let pool = Arc::new(
+///     ContextPool::new(2, |ctx| {
+///         evaluate_script(ctx, BUNDLE, None, "bundle.js", 1)?;
+///         Ok(())
+///     })
+///     .expect("pool"),
+/// );
+/// let workers: Vec<_> = (0..4)
+///     .map(|i| {
+///         let pool = pool.clone();
+///         thread::spawn(move || {
+///             pool.enter(|ctx| {
+///                 let script = format!("render({})", i);
+///                 let r = evaluate_script(ctx, script.as_str(), None, "job.js", 1);
+///                 r.expect("rendered").as_string().expect("string").to_string()
+///             })
+///         })
+///     })
+///     .collect();
+/// let items: Vec<String> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+/// assert_eq!(items[3], "<li>3</li>");
+/// ```
+///
+/// [`JSVirtualMachine`]: struct.JSVirtualMachine.html
+/// [`reset`]: struct.JSContext.html#method.reset
+pub struct ContextPool {
+    create: Arc<Create>,
+    setup: Box<Setup>,
+    size: usize,
+    /// The contexts that no thread has checked out.
+    idle: Mutex<Vec<JSVirtualMachine>>,
+    /// Signalled when a context is returned.
+    returned: Condvar,
+}
+
+impl ContextPool {
+    /// Creates a pool of `size` contexts, which are each set up by
+    /// `setup`.
+    ///
+    /// The state of each context once `setup` has run is the state that
+    /// [`JSContext::reset`] restores after each job. Fails with the
+    /// first exception that `setup` returns, as a [`JSError`], since
+    /// `setup` runs on the threads of the virtual machines.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    ///
+    /// [`JSContext::reset`]: struct.JSContext.html#method.reset
+    /// [`JSError`]: struct.JSError.html
+    pub fn new<F>(size: usize, setup: F) -> Result<Self, JSError>
+    where
+        F: Fn(&JSContext) -> Result<(), JSException> + Send + Sync + 'static,
+    {
+        ContextPool::with_context(size, JSContext::default, setup)
+    }
+
+    /// Creates a pool of `size` contexts, which are each created by
+    /// `create` and set up by `setup`.
+    ///
+    /// This is like [`new`], but lets the host choose how contexts are
+    /// created, such as with a [`ContextBuilder`]. As with
+    /// [`JSVirtualMachine::with_context`], `create` runs on the thread of
+    /// each virtual machine. Each context should be in a group of its
+    /// own, as contexts of the same group can not run scripts at the
+    /// same time. If `create` panics, so does this.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let pool = ContextPool::with_context(
+    ///     2,
+    ///     || ContextBuilder::new().name("worker").build().expect("context"),
+    ///     |_ctx| Ok(()),
+    /// )
+    /// .expect("pool");
+    /// let name = pool.enter(|ctx| ctx.name().map(|n| n.to_string()));
+    /// assert_eq!(name.as_deref(), Some("worker"));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    ///
+    /// [`ContextBuilder`]: struct.ContextBuilder.html
+    /// [`JSVirtualMachine::with_context`]: struct.JSVirtualMachine.html#method.with_context
+    /// [`new`]: #method.new
+    pub fn with_context<C, F>(size: usize, create: C, setup: F) -> Result<Self, JSError>
+    where
+        C: Fn() -> JSContext + Send + Sync + 'static,
+        F: Fn(&JSContext) -> Result<(), JSException> + Send + Sync + 'static,
+    {
+        assert!(size > 0, "A pool needs at least one context");
+        let create: Arc<Create> = Arc::new(create);
+        let setup: Box<Setup> = Box::new(setup);
+        let idle = (0..size)
+            .map(|_| new_context(&create, &*setup))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ContextPool {
+            create,
+            setup,
+            size,
+            idle: Mutex::new(idle),
+            returned: Condvar::new(),
+        })
+    }
+
+    /// Runs `f` with a context of the pool, which is reset afterwards.
+    ///
+    /// If all of the contexts are in use, this blocks until one is
+    /// returned. `f` must not enter the same pool again, as that could
+    /// deadlock.
+    ///
+    /// As with [`JSVirtualMachine::enter`], `f` runs on the thread of
+    /// the virtual machine, and `f` and its result must be `Send`, so
+    /// that no JavaScript values can be returned.
+    ///
+    /// If the context can not be reset, for example because a script
+    /// froze a built-in object, it is replaced with a new context that
+    /// is created and set up again. If that fails or panics, the context
+    /// is kept as it is.
+    ///
+    /// [`JSVirtualMachine::enter`]: struct.JSVirtualMachine.html#method.enter
+    pub fn enter<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&JSContext) -> R + Send,
+        R: Send,
+    {
+        let mut checkout = Checkout {
+            pool: self,
+            vm: Some(self.check_out()),
+            broken: false,
+        };
+        let (r, reset) = checkout.vm().enter(|ctx| {
+            // The context is reset even if the job panics.
+            let r = panic::catch_unwind(AssertUnwindSafe(|| f(ctx)));
+            (r, ctx.reset().is_ok())
+        });
+        checkout.broken = !reset;
+        match r {
+            Ok(r) => r,
+            Err(p) => panic::resume_unwind(p),
+        }
+    }
+
+    /// Gets the number of contexts in the pool.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn check_out(&self) -> JSVirtualMachine {
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(vm) = idle.pop() {
+                return vm;
+            }
+            idle = self.returned.wait(idle).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn check_in(&self, vm: JSVirtualMachine) {
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        idle.push(vm);
+        self.returned.notify_one();
+    }
+}
+
+/// Creates a context with `create`, sets it up with `setup` and marks
+/// that as the state to reset it to.
+fn new_context(create: &Arc<Create>, setup: &Setup) -> Result<JSVirtualMachine, JSError> {
+    let create = create.clone();
+    let vm = JSVirtualMachine::with_context(move || create());
+    vm.enter(|ctx| {
+        setup(ctx)
+            .and_then(|()| ctx.mark_pristine())
+            .map_err(JSError::from)
+    })?;
+    Ok(vm)
+}
+
+/// Returns a context to its pool, even if the job panics.
+struct Checkout<'a> {
+    pool: &'a ContextPool,
+    vm: Option<JSVirtualMachine>,
+    /// Whether the context could not be reset.
+    broken: bool,
+}
+
+impl Checkout<'_> {
+    fn vm(&self) -> &JSVirtualMachine {
+        self.vm.as_ref().expect("checked out")
+    }
+}
+
+impl Drop for Checkout<'_> {
+    fn drop(&mut self) {
+        let mut vm = self.vm.take().expect("checked out");
+        if self.broken {
+            let pool = self.pool;
+            // Unwinding out of a drop could abort the process.
+            let new =
+                panic::catch_unwind(AssertUnwindSafe(|| new_context(&pool.create, &*pool.setup)));
+            if let Ok(Ok(new)) = new {
+                vm = new;
+            }
+        }
+        self.pool.check_in(vm);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, ContextBuilder, JSContext, JSValue};
+    use super::ContextPool;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    fn eval(ctx: &JSContext, script: &str) -> String {
+        evaluate_script(ctx, script, None, "test.js", 1)
+            .unwrap()
+            .as_string()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn parallel() {
+        let pool = Arc::new(
+            ContextPool::new(3, |ctx| {
+                evaluate_script(ctx, "function id() { return 'ready'; }", None, "test.js", 1)?;
+                Ok(())
+            })
+            .unwrap(),
+        );
+        assert_eq!(pool.size(), 3);
+
+        // All of the contexts can be in use at the same time.
+        let barrier = Arc::new(Barrier::new(3));
+        let workers: Vec<_> = (0..3)
+            .map(|_| {
+                let pool = pool.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    pool.enter(|ctx| {
+                        barrier.wait();
+                        eval(ctx, "globalThis.leak = 1; id()")
+                    })
+                })
+            })
+            .collect();
+        for w in workers {
+            assert_eq!(w.join().unwrap(), "ready");
+        }
+
+        for _ in 0..3 {
+            let r = pool.enter(|ctx| eval(ctx, "typeof leak + ' ' + id()"));
+            assert_eq!(r, "undefined ready");
+        }
+    }
+
+    #[test]
+    fn setup_fails() {
+        let r = ContextPool::new(2, |ctx| {
+            evaluate_script(ctx, "throw new Error('No bundle')", None, "test.js", 1)?;
+            Ok(())
+        });
+        assert_eq!(r.err().unwrap().message(), Some("No bundle"));
+    }
+
+    #[test]
+    fn replace_broken_contexts() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let counter = created.clone();
+        let pool = ContextPool::new(1, move |ctx| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            ctx.global_object()
+                .set_property("host", JSValue::new_number(ctx, 1.0))
+        })
+        .unwrap();
+
+        pool.enter(|ctx| {
+            evaluate_script(ctx, "Object.freeze(Array.prototype)", None, "test.js", 1).unwrap();
+        });
+        assert_eq!(created.load(Ordering::SeqCst), 2);
+        let r = pool.enter(|ctx| eval(ctx, "typeof host + ' ' + Object.isFrozen(Array.prototype)"));
+        assert_eq!(r, "number false");
+
+        // A job that panics still returns its context.
+        let r = panic::catch_unwind(AssertUnwindSafe(|| pool.enter(|_| panic!("job"))));
+        assert!(r.is_err());
+        assert_eq!(pool.enter(|ctx| eval(ctx, "typeof host")), "number");
+    }
+
+    #[test]
+    fn with_context() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let counter = created.clone();
+        let pool = ContextPool::with_context(
+            2,
+            move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                ContextBuilder::new().name("worker").build().unwrap()
+            },
+            |ctx| {
+                ctx.global_object()
+                    .set_property("host", JSValue::new_number(ctx, 1.0))
+            },
+        )
+        .unwrap();
+        assert_eq!(created.load(Ordering::SeqCst), 2);
+
+        let name = pool.enter(|ctx| ctx.name().map(|n| n.to_string()));
+        assert_eq!(name.as_deref(), Some("worker"));
+
+        // Broken contexts are replaced with new ones from the factory.
+        pool.enter(|ctx| {
+            evaluate_script(ctx, "Object.freeze(Array.prototype)", None, "test.js", 1).unwrap();
+        });
+        assert_eq!(created.load(Ordering::SeqCst), 3);
+        assert_eq!(pool.enter(|ctx| eval(ctx, "typeof host")), "number");
+    }
+}
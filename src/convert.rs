@@ -0,0 +1,762 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::object::new_array;
+use super::{JSContext, JSErrorKind, JSException, JSObject, JSString, JSValue};
+use std::collections::HashMap;
+use std::error::Error;
+use std::hash::BuildHasher;
+
+/// A Rust type that can be converted from a JavaScript value.
+///
+/// Conversions are strict: a value of the wrong type gives a
+/// `TypeError` rather than being coerced as JavaScript would, so that
+/// mistakes in scripts are reported where they are made.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let ctx = JSContext::default();
+/// let v = JSValue::new_number(&ctx, 42.0);
+/// assert_eq!(u8::from_js(&ctx, &v).expect("u8"), 42);
+/// assert!(String::from_js(&ctx, &v).is_err());
+/// ```
+pub trait FromJs: Sized {
+    /// Converts `value` to `Self`, or fails with the exception to throw.
+    fn from_js(ctx: &JSContext, value: &JSValue) -> Result<Self, JSException>;
+}
+
+/// A Rust type that can be converted to a JavaScript value.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let ctx = JSContext::default();
+/// let v = "Cave canem.".to_js(&ctx).expect("string");
+/// assert_eq!(v.as_string().expect("string"), "Cave canem.");
+/// ```
+pub trait ToJs {
+    /// Converts `self` to a JavaScript value in `ctx`.
+    fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException>;
+}
+
+impl FromJs for JSValue {
+    fn from_js(_ctx: &JSContext, value: &JSValue) -> Result<Self, JSException> {
+        Ok(JSValue {
+            raw: value.raw,
+            ctx: value.ctx,
+        })
+    }
+}
+
+impl ToJs for JSValue {
+    fn to_js(&self, _ctx: &JSContext) -> Result<JSValue, JSException> {
+        Ok(JSValue {
+            raw: self.raw,
+            ctx: self.ctx,
+        })
+    }
+}
+
+impl FromJs for JSObject {
+    fn from_js(ctx: &JSContext, value: &JSValue) -> Result<Self, JSException> {
+        if value.is_object() {
+            value.as_object()
+        } else {
+            Err(JSException::type_error(ctx, "Expected an object"))
+        }
+    }
+}
+
+impl ToJs for JSObject {
+    fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        self.value.to_js(ctx)
+    }
+}
+
+impl FromJs for bool {
+    fn from_js(ctx: &JSContext, value: &JSValue) -> Result<Self, JSException> {
+        if value.is_boolean() {
+            Ok(value.as_boolean())
+        } else {
+            Err(JSException::type_error(ctx, "Expected a boolean"))
+        }
+    }
+}
+
+impl ToJs for bool {
+    fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        Ok(JSValue::new_boolean(ctx, *self))
+    }
+}
+
+impl FromJs for String {
+    fn from_js(ctx: &JSContext, value: &JSValue) -> Result<Self, JSException> {
+        if value.is_string() {
+            Ok(value.as_string()?.to_string_lossy())
+        } else {
+            Err(JSException::type_error(ctx, "Expected a string"))
+        }
+    }
+}
+
+impl ToJs for str {
+    fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        Ok(JSValue::new_string(ctx, JSString::from_str_lossless(self)))
+    }
+}
+
+impl ToJs for String {
+    fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        self.as_str().to_js(ctx)
+    }
+}
+
+impl FromJs for f64 {
+    fn from_js(ctx: &JSContext, value: &JSValue) -> Result<Self, JSException> {
+        if value.is_number() {
+            value.to_number()
+        } else {
+            Err(JSException::type_error(ctx, "Expected a number"))
+        }
+    }
+}
+
+impl FromNumber for f64 {
+    fn from_number(_ctx: &JSContext, n: f64) -> Result<Self, JSException> {
+        Ok(n)
+    }
+}
+
+impl ToJs for f64 {
+    fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        Ok(JSValue::new_number(ctx, *self))
+    }
+}
+
+impl FromJs for f32 {
+    fn from_js(ctx: &JSContext, value: &JSValue) -> Result<Self, JSException> {
+        f64::from_js(ctx, value).map(|n| n as f32)
+    }
+}
+
+impl ToJs for f32 {
+    fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        Ok(JSValue::new_number(ctx, f64::from(*self)))
+    }
+}
+
+/// A Rust type that can be converted from a JavaScript number, with
+/// the same checks as its conversion from a value.
+pub(crate) trait FromNumber: Sized {
+    /// Converts `n` to `Self`, or fails with the exception to throw.
+    fn from_number(ctx: &JSContext, n: f64) -> Result<Self, JSException>;
+}
+
+/// Implements the conversions of integer types, which take numbers
+/// that are whole and within the range of the type.
+macro_rules! integer_conversions {
+    ($($t:ty),*) => {
+        $(
+            impl FromJs for $t {
+                fn from_js(ctx: &JSContext, value: &JSValue) -> Result<Self, JSException> {
+                    <$t>::from_number(ctx, f64::from_js(ctx, value)?)
+                }
+            }
+
+            impl FromNumber for $t {
+                fn from_number(ctx: &JSContext, n: f64) -> Result<Self, JSException> {
+                    if n.fract() != 0.0 || !n.is_finite() {
+                        return Err(JSException::type_error(ctx, "Expected an integer"));
+                    }
+                    if n < <$t>::MIN as f64 || n > <$t>::MAX as f64 {
+                        let message = format!(
+                            "Expected an integer from {} to {}",
+                            <$t>::MIN,
+                            <$t>::MAX
+                        );
+                        return Err(JSException::range_error(ctx, &message));
+                    }
+                    Ok(n as $t)
+                }
+            }
+
+            impl ToJs for $t {
+                fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException> {
+                    Ok(JSValue::new_number(ctx, *self as f64))
+                }
+            }
+        )*
+    };
+}
+
+integer_conversions!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+/// `null` and `undefined` convert to `None`, and `None` converts to
+/// `undefined`.
+impl<T: FromJs> FromJs for Option<T> {
+    fn from_js(ctx: &JSContext, value: &JSValue) -> Result<Self, JSException> {
+        if value.is_null() || value.is_undefined() {
+            Ok(None)
+        } else {
+            T::from_js(ctx, value).map(Some)
+        }
+    }
+}
+
+impl<T: ToJs> ToJs for Option<T> {
+    fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        match self {
+            Some(value) => value.to_js(ctx),
+            None => Ok(JSValue::new_undefined(ctx)),
+        }
+    }
+}
+
+/// Arrays convert to `Vec`s, element by element, and `Vec`s and slices
+/// convert to arrays.
+///
+/// Other values, including typed arrays, give a `TypeError`. An error
+/// of an element is given a message that names its index.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let ctx = JSContext::default();
+/// let v = evaluate_script(&ctx, "[1, 2, 3]", None, "test.js", 1).expect("array");
+/// assert_eq!(Vec::<u8>::from_js(&ctx, &v).expect("converted"), [1, 2, 3]);
+///
+/// let v = evaluate_script(&ctx, "[1, 'b']", None, "test.js", 1).expect("array");
+/// let e = Vec::<u8>::from_js(&ctx, &v).unwrap_err();
+/// assert_eq!(e.message(), Some("Invalid element 1: Expected a number"));
+/// ```
+impl<T: FromJs> FromJs for Vec<T> {
+    fn from_js(ctx: &JSContext, value: &JSValue) -> Result<Self, JSException> {
+        if !value.is_array() {
+            return Err(JSException::type_error(ctx, "Expected an array"));
+        }
+        let array = value.as_object()?;
+        let length = array.get_property("length")?.as_number()? as u32;
+        (0..length)
+            .map(|index| {
+                let element = array.get_property_at_index(index)?;
+                T::from_js(ctx, &element)
+                    .map_err(|e| invalid(ctx, e, &format!("element {}", index)))
+            })
+            .collect()
+    }
+}
+
+impl<T: ToJs> ToJs for [T] {
+    fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        let elements = self
+            .iter()
+            .map(|element| element.to_js(ctx))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(new_array(ctx, &elements)?.into())
+    }
+}
+
+impl<T: ToJs> ToJs for Vec<T> {
+    fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        self.as_slice().to_js(ctx)
+    }
+}
+
+/// Objects convert to `HashMap`s, with an entry for each of their
+/// enumerable properties, and `HashMap`s convert to objects.
+///
+/// Arrays, functions and other values give a `TypeError`. An error of a
+/// property is given a message that names it.
+///
+/// ```
+/// # use javascriptcore::*;
+/// # use std::collections::HashMap;
+/// let ctx = JSContext::default();
+/// let v = evaluate_script(&ctx, "({ a: 1, b: 2 })", None, "test.js", 1).expect("object");
+/// let map = HashMap::<String, f64>::from_js(&ctx, &v).expect("converted");
+/// assert_eq!(map["b"], 2.0);
+/// ```
+impl<T: FromJs, S: BuildHasher + Default> FromJs for HashMap<String, T, S> {
+    fn from_js(ctx: &JSContext, value: &JSValue) -> Result<Self, JSException> {
+        if !value.is_object() || value.is_array() {
+            return Err(JSException::type_error(ctx, "Expected an object"));
+        }
+        let object = value.as_object()?;
+        if object.is_function() {
+            return Err(JSException::type_error(ctx, "Expected an object"));
+        }
+        object
+            .property_names()
+            .collect::<Vec<JSString>>()
+            .into_iter()
+            .map(|name| {
+                let key = name.to_string_lossy();
+                let property = object.get_property(name)?;
+                let value = T::from_js(ctx, &property)
+                    .map_err(|e| invalid(ctx, e, &format!("property {}", key)))?;
+                Ok((key, value))
+            })
+            .collect()
+    }
+}
+
+impl<T: ToJs, S> ToJs for HashMap<String, T, S> {
+    fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        let object = JSObject::new(ctx);
+        for (key, value) in self {
+            object.set_property(JSString::from_str_lossless(key), value.to_js(ctx)?)?;
+        }
+        Ok(object.into())
+    }
+}
+
+/// How an `Option` is converted, for APIs that tell `null` and
+/// `undefined` apart.
+///
+/// The default policy converts as the [`FromJs`] and [`ToJs`]
+/// implementations of `Option` do: `None` converts to `undefined`, and
+/// `null` and `undefined` convert to `None`. Fields of structs that
+/// derive `ToJs` and `FromJs` take a policy with `#[js(none_as_null)]`
+/// and `#[js(reject_undefined)]`.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let ctx = JSContext::default();
+/// let nullable = OptionPolicy {
+///     none_as_null: true,
+///     reject_undefined: true,
+/// };
+/// assert!(nullable.to_js(&ctx, &None::<u8>).expect("null").is_null());
+///
+/// let v = JSValue::new_null(&ctx);
+/// assert_eq!(nullable.from_js::<u8>(&ctx, &v).expect("None"), None);
+/// let v = JSValue::new_undefined(&ctx);
+/// let e = nullable.from_js::<u8>(&ctx, &v).unwrap_err();
+/// assert_eq!(e.message(), Some("Expected a value or null"));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OptionPolicy {
+    /// Whether `None` converts to `null` rather than `undefined`.
+    pub none_as_null: bool,
+    /// Whether `undefined` gives a `TypeError` rather than converting to
+    /// `None`. `null` always converts to `None`.
+    pub reject_undefined: bool,
+}
+
+impl OptionPolicy {
+    /// Converts `value` to a JavaScript value with this policy.
+    pub fn to_js<T: ToJs>(
+        self,
+        ctx: &JSContext,
+        value: &Option<T>,
+    ) -> Result<JSValue, JSException> {
+        match value {
+            Some(value) => value.to_js(ctx),
+            None if self.none_as_null => Ok(JSValue::new_null(ctx)),
+            None => Ok(JSValue::new_undefined(ctx)),
+        }
+    }
+
+    /// Converts `value` to an `Option` with this policy.
+    pub fn from_js<T: FromJs>(
+        self,
+        ctx: &JSContext,
+        value: &JSValue,
+    ) -> Result<Option<T>, JSException> {
+        if value.is_undefined() && self.reject_undefined {
+            Err(JSException::type_error(ctx, "Expected a value or null"))
+        } else {
+            Option::<T>::from_js(ctx, value)
+        }
+    }
+}
+
+/// `()` converts to `undefined`, as returned by functions that return
+/// nothing.
+impl ToJs for () {
+    fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        Ok(JSValue::new_undefined(ctx))
+    }
+}
+
+impl<T: ToJs + ?Sized> ToJs for &T {
+    fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        (**self).to_js(ctx)
+    }
+}
+
+/// The arguments of a native function, converted to Rust types.
+///
+/// This is implemented for tuples of [`FromJsArgument`] types, which
+/// take the arguments in order. A missing argument is converted from
+/// `undefined`, so trailing `Option` arguments may be left out, and
+/// other missing arguments give a `TypeError`. A [`Rest`] at the end of
+/// the tuple takes the remaining arguments. Other arguments beyond those
+/// of the tuple are ignored, as in JavaScript.
+///
+/// A `TypeError` or `RangeError` of a conversion is given a message
+/// that names the argument.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let ctx = JSContext::default();
+/// let f = JSObject::new_function(&ctx, |ctx, _this, arguments| {
+///     let (text, count) = <(String, Option<u32>)>::from_js_args(ctx, arguments)?;
+///     text.repeat(count.unwrap_or(1) as usize).into_js_result(ctx)
+/// });
+/// ctx.global_object().set_property("repeat", f.into()).expect("set");
+///
+/// let r = evaluate_script(&ctx, "repeat('ab', 2) + repeat('c')", None, "test.js", 1)
+///     .expect("evaluated");
+/// assert_eq!(r.as_string().expect("string"), "ababc");
+///
+/// let e = evaluate_script(&ctx, "repeat('ab', 'x')", None, "test.js", 1).unwrap_err();
+/// assert_eq!(e.message(), Some("Invalid argument 2: Expected a number"));
+/// let e = evaluate_script(&ctx, "repeat()", None, "test.js", 1).unwrap_err();
+/// assert_eq!(e.message(), Some("Missing argument 1"));
+/// ```
+///
+/// [`FromJsArgument`]: trait.FromJsArgument.html
+/// [`Rest`]: struct.Rest.html
+pub trait FromJsArgs: Sized {
+    /// Converts `arguments`, naming them by their position in errors.
+    fn from_js_args(ctx: &JSContext, arguments: &[JSValue]) -> Result<Self, JSException> {
+        Self::from_js_named_args(ctx, arguments, &[])
+    }
+
+    /// Converts `arguments`, naming them with `names` in errors.
+    ///
+    /// Arguments without a name are named by their position.
+    fn from_js_named_args(
+        ctx: &JSContext,
+        arguments: &[JSValue],
+        names: &[&str],
+    ) -> Result<Self, JSException>;
+}
+
+/// A Rust value that can be returned from a native function.
+///
+/// This is implemented for [`ToJs`] types, and for `Result`s of them
+/// whose error is thrown after being converted with
+/// [`JSException::from_error`].
+///
+/// [`ToJs`]: trait.ToJs.html
+/// [`JSException::from_error`]: struct.JSException.html#method.from_error
+pub trait IntoJsResult {
+    /// Converts `self` to the result of a native function.
+    fn into_js_result(self, ctx: &JSContext) -> Result<JSValue, JSException>;
+}
+
+impl<T: ToJs> IntoJsResult for T {
+    fn into_js_result(self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        self.to_js(ctx)
+    }
+}
+
+impl<T: ToJs, E: Error + 'static> IntoJsResult for Result<T, E> {
+    fn into_js_result(self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        match self {
+            Ok(value) => value.to_js(ctx),
+            Err(e) => Err(JSException::from_error(ctx, e)),
+        }
+    }
+}
+
+/// An argument of a native function, converted to a Rust type.
+///
+/// This is implemented for [`FromJs`] types, which take a single
+/// argument, and for [`Rest`], which takes the remaining arguments.
+///
+/// [`FromJs`]: trait.FromJs.html
+/// [`Rest`]: struct.Rest.html
+pub trait FromJsArgument: Sized {
+    /// Converts the argument at `index` of `arguments`.
+    ///
+    /// * `names`: The names of the arguments by position, for errors.
+    ///   Arguments without a name are named by their position.
+    fn from_js_argument(
+        ctx: &JSContext,
+        arguments: &[JSValue],
+        index: usize,
+        names: &[&str],
+    ) -> Result<Self, JSException>;
+}
+
+impl<T: FromJs> FromJsArgument for T {
+    fn from_js_argument(
+        ctx: &JSContext,
+        arguments: &[JSValue],
+        index: usize,
+        names: &[&str],
+    ) -> Result<Self, JSException> {
+        let name = || match names.get(index) {
+            Some(name) => (*name).to_owned(),
+            None => (index + 1).to_string(),
+        };
+        let Some(value) = arguments.get(index) else {
+            return T::from_js(ctx, &JSValue::new_undefined(ctx)).map_err(|_| {
+                JSException::type_error(ctx, &format!("Missing argument {}", name()))
+            });
+        };
+        T::from_js(ctx, value).map_err(|e| invalid(ctx, e, &format!("argument {}", name())))
+    }
+}
+
+/// Names what a conversion error is about, such as `argument 2`, in the
+/// message of a `TypeError` or `RangeError`.
+pub(crate) fn invalid(ctx: &JSContext, e: JSException, what: &str) -> JSException {
+    let message = match e.message() {
+        Some(message) => format!("Invalid {}: {}", what, message),
+        None => return e,
+    };
+    match e.kind() {
+        JSErrorKind::TypeError => JSException::type_error(ctx, &message),
+        JSErrorKind::RangeError => JSException::range_error(ctx, &message),
+        _ => e,
+    }
+}
+
+/// The remaining arguments of a call, for functions that take any
+/// number of arguments, like `Math.max`.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let ctx = JSContext::default();
+/// let f = JSObject::new_function(&ctx, |ctx, _this, arguments| {
+///     let (separator, Rest(parts)) = <(String, Rest<String>)>::from_js_args(ctx, arguments)?;
+///     parts.join(&separator).into_js_result(ctx)
+/// });
+/// ctx.global_object().set_property("join", f.into()).expect("set");
+///
+/// let r = evaluate_script(&ctx, "join('-', 'a', 'b', 'c')", None, "test.js", 1)
+///     .expect("evaluated");
+/// assert_eq!(r.as_string().expect("string"), "a-b-c");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rest<T>(pub Vec<T>);
+
+impl<T: FromJs> FromJsArgument for Rest<T> {
+    fn from_js_argument(
+        ctx: &JSContext,
+        arguments: &[JSValue],
+        index: usize,
+        names: &[&str],
+    ) -> Result<Self, JSException> {
+        (index..arguments.len())
+            .map(|i| T::from_js_argument(ctx, arguments, i, names))
+            .collect::<Result<_, _>>()
+            .map(Rest)
+    }
+}
+
+impl FromJsArgs for () {
+    fn from_js_named_args(
+        _ctx: &JSContext,
+        _arguments: &[JSValue],
+        _names: &[&str],
+    ) -> Result<Self, JSException> {
+        Ok(())
+    }
+}
+
+/// Implements `FromJsArgs` for a tuple of the given types and indices.
+macro_rules! tuple_arguments {
+    ($($t:ident $i:tt),*) => {
+        impl<$($t: FromJsArgument),*> FromJsArgs for ($($t,)*) {
+            fn from_js_named_args(
+                ctx: &JSContext,
+                arguments: &[JSValue],
+                names: &[&str],
+            ) -> Result<Self, JSException> {
+                Ok(($($t::from_js_argument(ctx, arguments, $i, names)?,)*))
+            }
+        }
+    };
+}
+
+tuple_arguments!(A 0);
+tuple_arguments!(A 0, B 1);
+tuple_arguments!(A 0, B 1, C 2);
+tuple_arguments!(A 0, B 1, C 2, D 3);
+tuple_arguments!(A 0, B 1, C 2, D 3, E 4);
+tuple_arguments!(A 0, B 1, C 2, D 3, E 4, F 5);
+tuple_arguments!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple_arguments!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+tuple_arguments!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8);
+tuple_arguments!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9);
+tuple_arguments!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10);
+tuple_arguments!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11);
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSContext, JSErrorKind, JSException, JSValue};
+    use super::{FromJs, FromJsArgs, IntoJsResult, OptionPolicy, Rest, ToJs};
+    use std::collections::HashMap;
+    use std::fmt;
+
+    #[test]
+    fn numbers() {
+        let ctx = JSContext::default();
+        let v = JSValue::new_number(&ctx, -3.0);
+        assert_eq!(i32::from_js(&ctx, &v).unwrap(), -3);
+        assert_eq!(f64::from_js(&ctx, &v).unwrap(), -3.0);
+        let e = u32::from_js(&ctx, &v).unwrap_err();
+        assert!(matches!(e.kind(), JSErrorKind::RangeError));
+
+        let v = JSValue::new_number(&ctx, 1.5);
+        let e = i64::from_js(&ctx, &v).unwrap_err();
+        assert!(matches!(e.kind(), JSErrorKind::TypeError));
+
+        let v = evaluate_script(&ctx, "NaN", None, "test.js", 1).unwrap();
+        assert!(f64::from_js(&ctx, &v).unwrap().is_nan());
+        assert!(u8::from_js(&ctx, &v).is_err());
+
+        let v = JSValue::new_string(&ctx, "1");
+        assert!(f64::from_js(&ctx, &v).is_err());
+
+        let v = 255_u8.to_js(&ctx).unwrap();
+        assert_eq!(v.as_number().unwrap(), 255.0);
+    }
+
+    #[test]
+    fn strings_and_options() {
+        let ctx = JSContext::default();
+        let v = "a\0b".to_js(&ctx).unwrap();
+        assert_eq!(String::from_js(&ctx, &v).unwrap(), "a\0b");
+
+        let v = JSValue::new_null(&ctx);
+        assert_eq!(Option::<String>::from_js(&ctx, &v).unwrap(), None);
+        assert!(String::from_js(&ctx, &v).is_err());
+        assert!(None::<bool>.to_js(&ctx).unwrap().is_undefined());
+        assert!(Some(true).to_js(&ctx).unwrap().as_boolean());
+
+        let policy = OptionPolicy::default();
+        assert!(policy.to_js(&ctx, &None::<u8>).unwrap().is_undefined());
+        let undefined = JSValue::new_undefined(&ctx);
+        assert_eq!(policy.from_js::<u8>(&ctx, &undefined).unwrap(), None);
+        let policy = OptionPolicy {
+            reject_undefined: true,
+            ..policy
+        };
+        assert!(policy.to_js(&ctx, &None::<u8>).unwrap().is_undefined());
+        assert!(policy.from_js::<u8>(&ctx, &undefined).is_err());
+        assert_eq!(policy.from_js::<u8>(&ctx, &v).unwrap(), None);
+        let one = JSValue::new_number(&ctx, 1.0);
+        assert_eq!(policy.from_js::<u8>(&ctx, &one).unwrap(), Some(1));
+        let policy = OptionPolicy {
+            none_as_null: true,
+            reject_undefined: false,
+        };
+        assert!(policy.to_js(&ctx, &None::<u8>).unwrap().is_null());
+        assert_eq!(
+            policy.to_js(&ctx, &Some(2)).unwrap().as_number().unwrap(),
+            2.0
+        );
+        assert_eq!(policy.from_js::<u8>(&ctx, &undefined).unwrap(), None);
+    }
+
+    #[test]
+    fn containers() {
+        let ctx = JSContext::default();
+        let v = evaluate_script(&ctx, "[[1, null], [], [2.5]]", None, "test.js", 1).unwrap();
+        let nested = Vec::<Vec<Option<f64>>>::from_js(&ctx, &v).unwrap();
+        assert_eq!(nested, [vec![Some(1.0), None], vec![], vec![Some(2.5)]]);
+        let v = nested.to_js(&ctx).unwrap();
+        assert_eq!(v.to_json_string(0).unwrap(), "[[1,null],[],[2.5]]");
+        let v = evaluate_script(&ctx, "[1, [2, 'x']]", None, "test.js", 1).unwrap();
+        let e = Vec::<Vec<u8>>::from_js(&ctx, &v).unwrap_err();
+        assert_eq!(e.message(), Some("Invalid element 0: Expected an array"));
+        let e = Vec::<u8>::from_js(&ctx, &"abc".to_js(&ctx).unwrap()).unwrap_err();
+        assert!(matches!(e.kind(), JSErrorKind::TypeError));
+
+        let v = evaluate_script(&ctx, "({ a: [1], b: [] })", None, "test.js", 1).unwrap();
+        let map = HashMap::<String, Vec<u32>>::from_js(&ctx, &v).unwrap();
+        assert_eq!(
+            map,
+            HashMap::from([("a".to_owned(), vec![1]), ("b".to_owned(), vec![])])
+        );
+        let v = HashMap::from([("c".to_owned(), true)]).to_js(&ctx).unwrap();
+        assert_eq!(v.to_json_string(0).unwrap(), r#"{"c":true}"#);
+        let v = evaluate_script(&ctx, "({ a: 1, b: -1 })", None, "test.js", 1).unwrap();
+        let e = HashMap::<String, u8>::from_js(&ctx, &v).unwrap_err();
+        assert!(matches!(e.kind(), JSErrorKind::RangeError));
+        assert_eq!(
+            e.message(),
+            Some("Invalid property b: Expected an integer from 0 to 255")
+        );
+        for script in ["[]", "(function () {})", "1"] {
+            let v = evaluate_script(&ctx, script, None, "test.js", 1).unwrap();
+            let e = HashMap::<String, u8>::from_js(&ctx, &v).unwrap_err();
+            assert_eq!(e.message(), Some("Expected an object"), "{}", script);
+        }
+    }
+
+    #[test]
+    fn arguments() {
+        let ctx = JSContext::default();
+        let arguments = [
+            JSValue::new_number(&ctx, 1.0),
+            JSValue::new_string(&ctx, "a"),
+            JSValue::new_number(&ctx, 2.0),
+        ];
+        let (n, s) = <(u8, String)>::from_js_args(&ctx, &arguments).unwrap();
+        assert_eq!((n, s.as_str()), (1, "a"));
+        let (n, s, b) = <(f64, String, Option<bool>)>::from_js_args(&ctx, &arguments[..2]).unwrap();
+        assert_eq!((n, s.as_str(), b), (1.0, "a", None));
+
+        let e = <(u8, u8)>::from_js_named_args(&ctx, &arguments, &["x", "y"]).unwrap_err();
+        assert!(matches!(e.kind(), JSErrorKind::TypeError));
+        assert_eq!(e.message(), Some("Invalid argument y: Expected a number"));
+        let e = <(f64, String, i8, u8)>::from_js_named_args(&ctx, &arguments, &["x"]).unwrap_err();
+        assert_eq!(e.message(), Some("Missing argument 4"));
+
+        let arguments = [JSValue::new_number(&ctx, -1.0)];
+        let e = <(u32,)>::from_js_named_args(&ctx, &arguments, &["count"]).unwrap_err();
+        assert!(matches!(e.kind(), JSErrorKind::RangeError));
+        assert_eq!(
+            e.message(),
+            Some("Invalid argument count: Expected an integer from 0 to 4294967295")
+        );
+
+        let arguments = [
+            JSValue::new_string(&ctx, "a"),
+            JSValue::new_number(&ctx, 1.0),
+            JSValue::new_number(&ctx, 2.0),
+        ];
+        let (s, Rest(rest)) = <(String, Rest<u8>)>::from_js_args(&ctx, &arguments).unwrap();
+        assert_eq!((s.as_str(), rest), ("a", vec![1, 2]));
+        let (_, _, _, Rest(rest)) =
+            <(String, u8, u8, Rest<u8>)>::from_js_args(&ctx, &arguments).unwrap();
+        assert!(rest.is_empty());
+        let e = <(Rest<u8>,)>::from_js_args(&ctx, &arguments).unwrap_err();
+        assert_eq!(e.message(), Some("Invalid argument 1: Expected a number"));
+    }
+
+    #[derive(Debug)]
+    struct Overdrawn;
+
+    impl fmt::Display for Overdrawn {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "The account is overdrawn")
+        }
+    }
+
+    impl std::error::Error for Overdrawn {}
+
+    #[test]
+    fn results() {
+        let ctx = JSContext::default();
+        assert!(().into_js_result(&ctx).unwrap().is_undefined());
+        let r: Result<i32, Overdrawn> = Ok(3);
+        assert_eq!(r.into_js_result(&ctx).unwrap().as_number().unwrap(), 3.0);
+        let r: Result<i32, Overdrawn> = Err(Overdrawn);
+        let e = r.into_js_result(&ctx).unwrap_err();
+        assert_eq!(e.message(), Some("The account is overdrawn"));
+
+        let thrown = JSException::range_error(&ctx, "Too far");
+        let r: Result<(), JSException> = Err(thrown);
+        let e = r.into_js_result(&ctx).unwrap_err();
+        assert!(matches!(e.kind(), JSErrorKind::RangeError));
+    }
+}
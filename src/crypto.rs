@@ -0,0 +1,169 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::exception::error;
+use super::function::new_function;
+use super::typedarray::{typed_array_bytes, typed_array_type};
+use super::{JSContext, JSException, JSObject, JSTypedArrayType, JSValue};
+
+/// The most bytes that `getRandomValues` fills in one call.
+const MAX_BYTES: usize = 65536;
+
+/// Installs a `crypto` object with `getRandomValues` and `randomUUID`
+/// into a context.
+///
+/// * `ctx`: The execution context to install `crypto` into.
+///
+/// The random values come from the operating system's secure random
+/// number generator, by way of the `getrandom` crate. Like in browsers,
+/// `getRandomValues` only accepts integer typed arrays of up to 65536
+/// bytes and throws an error named `TypeMismatchError` or
+/// `QuotaExceededError` otherwise.
+///
+/// Requires the `getrandom` feature.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let ctx = JSContext::default();
+/// install_crypto(&ctx).expect("crypto installed");
+///
+/// let uuid = evaluate_script(&ctx, "crypto.randomUUID()", None, "test.js", 1)
+///     .expect("uuid");
+/// assert_eq!(uuid.as_string().expect("string").to_string().len(), 36);
+/// ```
+pub fn install_crypto(ctx: &JSContext) -> Result<(), JSException> {
+    let crypto = JSObject::new(ctx);
+    let get_random_values = new_function(ctx, |ctx, _this, arguments| {
+        let array = match arguments.first() {
+            Some(array) if is_integer_array(array) => array.as_object()?,
+            _ => {
+                return Err(named_error(
+                    ctx,
+                    "TypeMismatchError",
+                    "The argument must be an integer typed array",
+                ))
+            }
+        };
+        let bytes = unsafe { typed_array_bytes(&array)? };
+        if bytes.len() > MAX_BYTES {
+            return Err(named_error(
+                ctx,
+                "QuotaExceededError",
+                "The array is longer than 65536 bytes",
+            ));
+        }
+        fill(ctx, bytes)?;
+        Ok(array.into())
+    });
+    crypto.set_property("getRandomValues", get_random_values.into())?;
+    let random_uuid = new_function(ctx, |ctx, _this, _arguments| {
+        let mut bytes = [0; 16];
+        fill(ctx, &mut bytes)?;
+        Ok(JSValue::new_string(ctx, format_uuid(bytes).as_str()))
+    });
+    crypto.set_property("randomUUID", random_uuid.into())?;
+    ctx.global_object().set_property("crypto", crypto.into())
+}
+
+fn is_integer_array(value: &JSValue) -> bool {
+    !matches!(
+        typed_array_type(value),
+        JSTypedArrayType::Float32Array
+            | JSTypedArrayType::Float64Array
+            | JSTypedArrayType::ArrayBuffer
+            | JSTypedArrayType::None
+    )
+}
+
+/// Creates an `Error` whose `name` is `name`, in place of the
+/// `DOMException` that browsers throw.
+fn named_error(ctx: &JSContext, name: &str, message: &str) -> JSException {
+    let e = error(ctx, message);
+    match e.value.as_object() {
+        Ok(object)
+            if object
+                .set_property("name", JSValue::new_string(ctx, name))
+                .is_ok() =>
+        {
+            JSException::from_raw(ctx.raw, object.raw)
+        }
+        _ => e,
+    }
+}
+
+fn fill(ctx: &JSContext, bytes: &mut [u8]) -> Result<(), JSException> {
+    getrandom::getrandom(bytes)
+        .map_err(|e| error(ctx, &format!("Unable to get random values: {}", e)))
+}
+
+/// Formats random bytes as a version 4 UUID.
+fn format_uuid(mut bytes: [u8; 16]) -> String {
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSContext};
+    use super::{format_uuid, install_crypto};
+
+    fn eval(ctx: &JSContext, script: &str) -> String {
+        evaluate_script(ctx, script, None, "test.js", 1)
+            .unwrap()
+            .as_string()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn get_random_values() {
+        let ctx = JSContext::default();
+        install_crypto(&ctx).unwrap();
+        assert_eq!(
+            eval(
+                &ctx,
+                "var a = new Uint32Array(64);
+                 String(crypto.getRandomValues(a) === a && a.some(x => x != 0))"
+            ),
+            "true"
+        );
+        assert_eq!(
+            eval(
+                &ctx,
+                "var errors = [];
+                 for (const a of [new Float64Array(1), [1, 2], new Uint8Array(65537)]) {
+                     try { crypto.getRandomValues(a); } catch (e) { errors.push(e.name); }
+                 }
+                 errors.join()"
+            ),
+            "TypeMismatchError,TypeMismatchError,QuotaExceededError"
+        );
+    }
+
+    #[test]
+    fn random_uuid() {
+        assert_eq!(
+            format_uuid([0xff; 16]),
+            "ffffffff-ffff-4fff-bfff-ffffffffffff"
+        );
+
+        let ctx = JSContext::default();
+        install_crypto(&ctx).unwrap();
+        let uuid = eval(&ctx, "crypto.randomUUID()");
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        assert_ne!(uuid, eval(&ctx, "crypto.randomUUID()"));
+    }
+}
@@ -0,0 +1,293 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{JSArrayBuffer, JSContext, JSDataView, JSException, JSObject, JSValue};
+use crate::sys;
+use std::mem;
+use std::ops::Deref;
+use std::ptr;
+use std::slice;
+
+/// Defines the methods that read and write numbers of a type, in either
+/// byte order.
+macro_rules! accessors {
+    ($($t:ty, $get:ident, $set:ident;)*) => {
+        $(
+            #[doc = concat!("Reads the `", stringify!($t), "` at `byte_offset` in the view.")]
+            ///
+            /// The number is read as little endian if `little_endian` is
+            /// set, and as big endian otherwise. Fails with a `RangeError`
+            /// if the number is not inside the view.
+            pub fn $get(&self, byte_offset: usize, little_endian: bool) -> Result<$t, JSException> {
+                let mut bytes = [0; mem::size_of::<$t>()];
+                self.with_bytes(byte_offset, bytes.len(), |b| bytes.copy_from_slice(b))?;
+                Ok(if little_endian {
+                    <$t>::from_le_bytes(bytes)
+                } else {
+                    <$t>::from_be_bytes(bytes)
+                })
+            }
+
+            #[doc = concat!("Writes `value` as the `", stringify!($t), "` at `byte_offset` in the view.")]
+            ///
+            /// The number is written as little endian if `little_endian`
+            /// is set, and as big endian otherwise. Fails with a
+            /// `RangeError` if the number is not inside the view.
+            pub fn $set(
+                &self,
+                byte_offset: usize,
+                value: $t,
+                little_endian: bool,
+            ) -> Result<(), JSException> {
+                let bytes = if little_endian {
+                    value.to_le_bytes()
+                } else {
+                    value.to_be_bytes()
+                };
+                self.with_bytes(byte_offset, bytes.len(), |b| b.copy_from_slice(&bytes))
+            }
+        )*
+    };
+}
+
+impl JSDataView {
+    /// Creates a `DataView` of the bytes of `buffer`.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `buffer`: The buffer to view.
+    /// * `byte_offset`: The offset of the view from the start of the
+    ///   buffer.
+    /// * `byte_length`: The number of bytes of the view, or `None` for
+    ///   the rest of the buffer.
+    ///
+    /// Fails with a `RangeError` if the view does not fit in the buffer.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let buffer = JSArrayBuffer::from_slice(&ctx, &[0; 8]).expect("buffer");
+    /// let view = JSDataView::new(&ctx, &buffer, 2, Some(4)).expect("view");
+    /// view.set_u32(0, 0xcafe_f00d, false).expect("set");
+    ///
+    /// assert_eq!(view.get_u16(0, false).expect("get"), 0xcafe);
+    /// assert_eq!(view.get_u16(2, true).expect("get"), 0x0df0);
+    /// assert!(view.get_u32(1, false).is_err());
+    /// ```
+    pub fn new(
+        ctx: &JSContext,
+        buffer: &JSArrayBuffer,
+        byte_offset: usize,
+        byte_length: Option<usize>,
+    ) -> Result<JSDataView, JSException> {
+        let constructor = ctx.global_object().get_property("DataView")?.as_object()?;
+        let mut arguments = vec![
+            JSObject::from_raw(ctx.raw, buffer.raw).into(),
+            JSValue::new_number(ctx, byte_offset as f64),
+        ];
+        if let Some(byte_length) = byte_length {
+            arguments.push(JSValue::new_number(ctx, byte_length as f64));
+        }
+        constructor.call_as_constructor(&arguments)?.as_data_view()
+    }
+
+    /// Gets the `ArrayBuffer` that the view is of.
+    pub fn buffer(&self) -> Result<JSArrayBuffer, JSException> {
+        self.get_property("buffer")?.as_array_buffer()
+    }
+
+    /// Gets the offset in bytes of the view from the start of its
+    /// buffer.
+    pub fn byte_offset(&self) -> Result<usize, JSException> {
+        Ok(self.get_property("byteOffset")?.as_number()? as usize)
+    }
+
+    /// Gets the number of bytes of the view.
+    pub fn byte_length(&self) -> Result<usize, JSException> {
+        Ok(self.get_property("byteLength")?.as_number()? as usize)
+    }
+
+    /// Reads the `i8` at `byte_offset` in the view.
+    ///
+    /// Fails with a `RangeError` if the number is not inside the view.
+    pub fn get_i8(&self, byte_offset: usize) -> Result<i8, JSException> {
+        Ok(self.get_u8(byte_offset)? as i8)
+    }
+
+    /// Writes `value` as the `i8` at `byte_offset` in the view.
+    ///
+    /// Fails with a `RangeError` if the number is not inside the view.
+    pub fn set_i8(&self, byte_offset: usize, value: i8) -> Result<(), JSException> {
+        self.set_u8(byte_offset, value as u8)
+    }
+
+    /// Reads the `u8` at `byte_offset` in the view.
+    ///
+    /// Fails with a `RangeError` if the number is not inside the view.
+    pub fn get_u8(&self, byte_offset: usize) -> Result<u8, JSException> {
+        self.with_bytes(byte_offset, 1, |b| b[0])
+    }
+
+    /// Writes `value` as the `u8` at `byte_offset` in the view.
+    ///
+    /// Fails with a `RangeError` if the number is not inside the view.
+    pub fn set_u8(&self, byte_offset: usize, value: u8) -> Result<(), JSException> {
+        self.with_bytes(byte_offset, 1, |b| b[0] = value)
+    }
+
+    accessors! {
+        i16, get_i16, set_i16;
+        u16, get_u16, set_u16;
+        i32, get_i32, set_i32;
+        u32, get_u32, set_u32;
+        i64, get_i64, set_i64;
+        u64, get_u64, set_u64;
+        f32, get_f32, set_f32;
+        f64, get_f64, set_f64;
+    }
+
+    /// Calls `f` with the `size` bytes at `byte_offset` in the view.
+    ///
+    /// The bounds of the view are checked against its buffer, so that a
+    /// script that changes what the view reports can not make this
+    /// reach outside of the buffer.
+    fn with_bytes<R>(
+        &self,
+        byte_offset: usize,
+        size: usize,
+        f: impl FnOnce(&mut [u8]) -> R,
+    ) -> Result<R, JSException> {
+        let ctx = unsafe { JSContext::from_context_ref(self.value.ctx) };
+        let buffer = self.buffer()?;
+        let view_offset = self.byte_offset()?;
+        let view_length = self.byte_length()?;
+        if byte_offset
+            .checked_add(size)
+            .is_none_or(|end| end > view_length)
+        {
+            return Err(JSException::range_error(
+                &ctx,
+                "Offset is outside the bounds of the DataView",
+            ));
+        }
+        // Nothing runs JavaScript from here on, so the buffer can not be
+        // changed while its bytes are used.
+        let start = view_offset.saturating_add(byte_offset);
+        if start.saturating_add(size) > buffer.byte_length() {
+            return Err(JSException::range_error(
+                &ctx,
+                "Offset is outside the bounds of the buffer",
+            ));
+        }
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        let bytes = unsafe { sys::JSObjectGetArrayBufferBytesPtr(ctx.raw, buffer.raw, &mut e) };
+        if !e.is_null() {
+            return Err(JSException::from_raw(ctx.raw, e));
+        }
+        if bytes.is_null() {
+            return Err(JSException::type_error(&ctx, "The buffer is detached"));
+        }
+        let bytes = unsafe { slice::from_raw_parts_mut((bytes as *mut u8).add(start), size) };
+        Ok(f(bytes))
+    }
+}
+
+impl JSValue {
+    /// Gets the `DataView` that this value is.
+    ///
+    /// Fails with a `TypeError` if the value is not a `DataView`.
+    pub fn as_data_view(&self) -> Result<JSDataView, JSException> {
+        if !self.is_instance_of("DataView")? {
+            let ctx = unsafe { JSContext::from_context_ref(self.ctx) };
+            return Err(JSException::type_error(&ctx, "Value is not a DataView"));
+        }
+        Ok(JSDataView {
+            object: JSObject::from_raw(self.ctx, self.raw as sys::JSObjectRef),
+        })
+    }
+}
+
+/// A `JSDataView` can be dereferenced to return the underlying
+/// `JSObject`.
+impl Deref for JSDataView {
+    type Target = JSObject;
+
+    fn deref(&self) -> &JSObject {
+        &self.object
+    }
+}
+
+impl From<JSDataView> for JSObject {
+    fn from(view: JSDataView) -> Self {
+        view.object
+    }
+}
+
+impl From<JSDataView> for JSValue {
+    fn from(view: JSDataView) -> Self {
+        view.object.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSArrayBuffer, JSContext, JSDataView, JSValue};
+
+    #[test]
+    fn read_and_write() {
+        let ctx = JSContext::default();
+        let v = evaluate_script(
+            &ctx,
+            "globalThis.view = new DataView(new ArrayBuffer(16), 4);
+             view.setFloat64(0, 1.5);
+             view.setInt16(8, -2, true);
+             view",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        let view = v.as_data_view().unwrap();
+        assert_eq!(view.byte_offset().unwrap(), 4);
+        assert_eq!(view.byte_length().unwrap(), 12);
+        assert_eq!(view.buffer().unwrap().byte_length(), 16);
+        assert_eq!(view.get_f64(0, false).unwrap(), 1.5);
+        assert_eq!(view.get_i16(8, true).unwrap(), -2);
+        assert_eq!(view.get_u16(8, false).unwrap(), 0xfeff);
+
+        view.set_u64(0, 1 << 40, true).unwrap();
+        view.set_f32(8, -0.25, false).unwrap();
+        let r = evaluate_script(
+            &ctx,
+            "[view.getBigUint64(0, true) === 2n ** 40n, view.getFloat32(8)]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(r.to_json_string(0).unwrap().to_string(), "[true,-0.25]");
+
+        let e = view.get_u32(9, false).unwrap_err();
+        assert_eq!(e.name(), Some("RangeError"));
+        view.set_i8(11, -1).unwrap();
+        assert_eq!(view.get_u8(11).unwrap(), 255);
+        assert!(view.set_u8(usize::MAX, 0).is_err());
+    }
+
+    #[test]
+    fn new() {
+        let ctx = JSContext::default();
+        let buffer = JSArrayBuffer::from_slice(&ctx, &[1, 2, 3, 4]).unwrap();
+        let view = JSDataView::new(&ctx, &buffer, 1, None).unwrap();
+        assert_eq!(view.byte_length().unwrap(), 3);
+        assert_eq!(view.get_u16(0, true).unwrap(), 0x0302);
+
+        let e = JSDataView::new(&ctx, &buffer, 2, Some(8)).unwrap_err();
+        assert_eq!(e.name(), Some("RangeError"));
+
+        let e = JSValue::from(buffer).as_data_view().unwrap_err();
+        assert_eq!(e.name(), Some("TypeError"));
+    }
+}
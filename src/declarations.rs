@@ -0,0 +1,394 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{JSObject, JSValue, Rest};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A Rust type that scripts see as a TypeScript type.
+///
+/// This is used to describe the arguments and results of native
+/// functions in [`Declarations`].
+///
+/// ```
+/// # use javascriptcore::*;
+/// assert_eq!(u32::ts_type(), "number");
+/// assert_eq!(<Option<String>>::ts_type(), "string | undefined");
+/// assert_eq!(<Option<String>>::ts_parameter("name"), "name?: string | null");
+/// ```
+///
+/// [`Declarations`]: struct.Declarations.html
+pub trait TypeScript {
+    /// Gets the TypeScript type of the values of this type.
+    fn ts_type() -> String;
+
+    /// Declares a parameter called `name` that takes this type.
+    ///
+    /// Parameters of types that may be left out, such as `Option`, are
+    /// declared as optional.
+    fn ts_parameter(name: &str) -> String {
+        format!("{}: {}", name, Self::ts_type())
+    }
+}
+
+macro_rules! ts_types {
+    ($ts:expr => $($t:ty),*) => {
+        $(
+            impl TypeScript for $t {
+                fn ts_type() -> String {
+                    $ts.to_owned()
+                }
+            }
+        )*
+    };
+}
+
+ts_types!("unknown" => JSValue);
+ts_types!("object" => JSObject);
+ts_types!("boolean" => bool);
+ts_types!("string" => str, String);
+ts_types!("number" => f32, f64, i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+ts_types!("void" => ());
+#[cfg(feature = "num-bigint")]
+ts_types!("bigint" => num_bigint::BigInt, num_bigint::BigUint);
+
+impl<T: TypeScript> TypeScript for Option<T> {
+    fn ts_type() -> String {
+        format!("{} | undefined", T::ts_type())
+    }
+
+    /// `null` and `undefined` are taken for `None`, as is a missing
+    /// argument.
+    fn ts_parameter(name: &str) -> String {
+        format!("{}?: {} | null", name, T::ts_type())
+    }
+}
+
+impl<T: TypeScript + ?Sized> TypeScript for &T {
+    fn ts_type() -> String {
+        T::ts_type()
+    }
+
+    fn ts_parameter(name: &str) -> String {
+        T::ts_parameter(name)
+    }
+}
+
+/// A result is declared as the type of its value, as its error is
+/// thrown.
+impl<T: TypeScript, E> TypeScript for Result<T, E> {
+    fn ts_type() -> String {
+        T::ts_type()
+    }
+}
+
+impl<T: TypeScript> TypeScript for Rest<T> {
+    fn ts_type() -> String {
+        array_of(&T::ts_type())
+    }
+
+    fn ts_parameter(name: &str) -> String {
+        format!("...{}: {}", name, Self::ts_type())
+    }
+}
+
+impl<T: TypeScript> TypeScript for [T] {
+    fn ts_type() -> String {
+        array_of(&T::ts_type())
+    }
+}
+
+impl<T: TypeScript> TypeScript for Vec<T> {
+    fn ts_type() -> String {
+        array_of(&T::ts_type())
+    }
+}
+
+impl<T: TypeScript, S> TypeScript for HashMap<String, T, S> {
+    fn ts_type() -> String {
+        format!("Record<string, {}>", T::ts_type())
+    }
+}
+
+/// Gets the type of arrays of `ts_type`.
+fn array_of(ts_type: &str) -> String {
+    if ts_type.contains(' ') {
+        format!("({})[]", ts_type)
+    } else {
+        format!("{}[]", ts_type)
+    }
+}
+
+/// The declaration of a function, for [`Declarations`].
+///
+/// ```
+/// # use javascriptcore::*;
+/// let f = FunctionDeclaration::new("parseInt")
+///     .parameter("text", "string")
+///     .optional_parameter("radix", "number")
+///     .returns("number");
+/// assert_eq!(f.to_string(), "parseInt(text: string, radix?: number): number");
+/// ```
+///
+/// [`Declarations`]: struct.Declarations.html
+#[derive(Clone, Debug)]
+pub struct FunctionDeclaration {
+    pub(crate) name: String,
+    pub(crate) parameters: Vec<String>,
+    pub(crate) result: String,
+}
+
+impl FunctionDeclaration {
+    /// Starts the declaration of a function called `name`, which takes
+    /// no arguments and returns `void`.
+    pub fn new(name: &str) -> Self {
+        FunctionDeclaration {
+            name: name.to_owned(),
+            parameters: vec![],
+            result: "void".to_owned(),
+        }
+    }
+
+    /// Adds a parameter called `name` of the TypeScript type `ts_type`.
+    pub fn parameter(mut self, name: &str, ts_type: &str) -> Self {
+        self.parameters.push(format!("{}: {}", name, ts_type));
+        self
+    }
+
+    /// Adds a parameter that may be left out.
+    pub fn optional_parameter(mut self, name: &str, ts_type: &str) -> Self {
+        self.parameters.push(format!("{}?: {}", name, ts_type));
+        self
+    }
+
+    /// Adds a parameter that takes the remaining arguments, each of the
+    /// TypeScript type `ts_type`.
+    pub fn rest_parameter(mut self, name: &str, ts_type: &str) -> Self {
+        self.parameters
+            .push(format!("...{}: {}", name, array_of(ts_type)));
+        self
+    }
+
+    /// Sets the TypeScript type of the result.
+    pub fn returns(mut self, ts_type: &str) -> Self {
+        self.result = ts_type.to_owned();
+        self
+    }
+}
+
+impl fmt::Display for FunctionDeclaration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}({}): {}",
+            self.name,
+            self.parameters.join(", "),
+            self.result
+        )
+    }
+}
+
+/// The declaration of the objects of a class, for [`Declarations`].
+///
+/// The class is declared as an interface, which describes the
+/// properties and methods of its objects.
+///
+/// [`Declarations`]: struct.Declarations.html
+#[derive(Clone, Debug)]
+pub struct ClassDeclaration {
+    name: String,
+    members: Vec<String>,
+}
+
+impl ClassDeclaration {
+    /// Starts the declaration of a class called `name`.
+    pub fn new(name: &str) -> Self {
+        ClassDeclaration {
+            name: name.to_owned(),
+            members: vec![],
+        }
+    }
+
+    /// Adds a property called `name` of the TypeScript type `ts_type`.
+    pub fn property(mut self, name: &str, ts_type: &str) -> Self {
+        self.members.push(format!("{}: {};", name, ts_type));
+        self
+    }
+
+    /// Adds a property that scripts can not set.
+    pub fn readonly_property(mut self, name: &str, ts_type: &str) -> Self {
+        self.members
+            .push(format!("readonly {}: {};", name, ts_type));
+        self
+    }
+
+    /// Adds a method.
+    pub fn method(mut self, method: FunctionDeclaration) -> Self {
+        self.members.push(format!("{};", method));
+        self
+    }
+}
+
+/// A generator of TypeScript declarations for the classes, functions
+/// and namespaces that a host installs.
+///
+/// The declarations can be written to a `.d.ts` file, so that editors
+/// can complete and type check scripts written for the host. Classes
+/// that derive [`JsClass`] and functions marked with [`js_function`]
+/// can be declared from their Rust types, and anything else can be
+/// declared by hand.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let read = FunctionDeclaration::new("readFile")
+///     .parameter("path", "string")
+///     .returns("string");
+/// let d = Declarations::new()
+///     .namespace("fs", vec![read])
+///     .function(FunctionDeclaration::new("print").parameter("text", "string"))
+///     .global("VERSION", "string");
+///
+/// assert_eq!(
+///     d.to_string(),
+///     "declare namespace fs {
+///     function readFile(path: string): string;
+/// }
+///
+/// declare function print(text: string): void;
+///
+/// declare var VERSION: string;
+/// "
+/// );
+/// ```
+///
+/// [`JsClass`]: trait.JsClass.html
+/// [`js_function`]: attr.js_function.html
+#[derive(Clone, Debug, Default)]
+pub struct Declarations {
+    declarations: Vec<String>,
+}
+
+impl Declarations {
+    /// Starts a set of declarations.
+    pub fn new() -> Self {
+        Declarations::default()
+    }
+
+    /// Declares the objects of a class that derives [`JsClass`], with
+    /// its properties and methods.
+    ///
+    /// The types of the properties and methods are found with
+    /// [`TypeScript`], and those that do not implement it are declared
+    /// as `unknown`. Classes that are registered with a constructor can
+    /// declare it with [`global`].
+    ///
+    /// Requires the `macros` feature.
+    ///
+    /// [`JsClass`]: trait.JsClass.html
+    /// [`TypeScript`]: trait.TypeScript.html
+    /// [`global`]: #method.global
+    #[cfg(feature = "macros")]
+    pub fn class<T: super::JsClass>(self) -> Self {
+        self.class_declaration(T::declare(ClassDeclaration::new(T::NAME)))
+    }
+
+    /// Declares the objects of a class.
+    pub fn class_declaration(mut self, class: ClassDeclaration) -> Self {
+        let mut s = format!("interface {} {{\n", class.name);
+        for member in &class.members {
+            s.push_str(&format!("    {}\n", member));
+        }
+        s.push_str("}\n");
+        self.declarations.push(s);
+        self
+    }
+
+    /// Declares a global function.
+    pub fn function(mut self, function: FunctionDeclaration) -> Self {
+        self.declarations
+            .push(format!("declare function {};\n", function));
+        self
+    }
+
+    /// Declares a namespace of functions, such as one registered with
+    /// [`JSContext::register_namespace`].
+    ///
+    /// [`JSContext::register_namespace`]: struct.JSContext.html#method.register_namespace
+    pub fn namespace(mut self, name: &str, functions: Vec<FunctionDeclaration>) -> Self {
+        let mut s = format!("declare namespace {} {{\n", name);
+        for function in &functions {
+            s.push_str(&format!("    function {};\n", function));
+        }
+        s.push_str("}\n");
+        self.declarations.push(s);
+        self
+    }
+
+    /// Declares a global variable called `name` of the TypeScript type
+    /// `ts_type`.
+    pub fn global(mut self, name: &str, ts_type: &str) -> Self {
+        self.declarations
+            .push(format!("declare var {}: {};\n", name, ts_type));
+        self
+    }
+}
+
+impl fmt::Display for Declarations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.declarations.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{JSObject, Rest};
+    use super::{ClassDeclaration, Declarations, FunctionDeclaration, TypeScript};
+    use std::collections::HashMap;
+
+    #[test]
+    fn ts_types() {
+        assert_eq!(<&str>::ts_type(), "string");
+        assert_eq!(
+            <Result<Option<JSObject>, ()>>::ts_type(),
+            "object | undefined"
+        );
+        assert_eq!(<Rest<f64>>::ts_parameter("values"), "...values: number[]");
+        assert_eq!(<Rest<Option<bool>>>::ts_type(), "(boolean | undefined)[]");
+        assert_eq!(<()>::ts_parameter("nothing"), "nothing: void");
+        assert_eq!(<Vec<Option<u8>>>::ts_type(), "(number | undefined)[]");
+        assert_eq!(
+            <HashMap<String, Vec<String>>>::ts_type(),
+            "Record<string, string[]>"
+        );
+    }
+
+    #[test]
+    fn classes() {
+        let class = ClassDeclaration::new("Point")
+            .property("x", "number")
+            .readonly_property("id", "string")
+            .method(
+                FunctionDeclaration::new("moveBy")
+                    .parameter("dx", "number")
+                    .rest_parameter("more", "number | string"),
+            );
+        let d = Declarations::new()
+            .class_declaration(class)
+            .global("Point", "new (x: number) => Point");
+        assert_eq!(
+            d.to_string(),
+            "interface Point {
+    x: number;
+    readonly id: string;
+    moveBy(dx: number, ...more: (number | string)[]): void;
+}
+
+declare var Point: new (x: number) => Point;
+"
+        );
+        assert_eq!(Declarations::new().to_string(), "");
+    }
+}
@@ -0,0 +1,875 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{ClassDeclaration, JSClass, JSClassBuilder, JSContext, JSException, JSObject, JSValue};
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// A Rust type that is exposed to scripts as a class.
+///
+/// This is usually derived with `#[derive(JsClass)]`, which makes each
+/// field of a struct a property of the objects of the class. Functions
+/// in an `impl` block that is marked with `#[js_methods]` become methods
+/// of the class when they are marked with `#[js_method]`. Arguments and
+/// results are converted with [`FromJs`] and [`ToJs`], and methods may
+/// take `&JSContext` and `&str` arguments and return a `Result`, whose
+/// error is thrown. Parameters take `#[js(default)]` and [`Rest`] as
+/// with [`js_function`].
+///
+/// ```
+/// # use javascriptcore::*;
+/// #[derive(JsClass)]
+/// struct Point {
+///     x: f64,
+///     y: f64,
+///     #[js(readonly)]
+///     id: u32,
+///     #[js(skip)]
+///     moves: usize,
+/// }
+///
+/// #[js_methods]
+/// impl Point {
+///     #[js_method]
+///     fn norm(&self) -> f64 {
+///         self.x.hypot(self.y)
+///     }
+///
+///     #[js_method(name = "moveBy")]
+///     fn move_by(&mut self, dx: f64, dy: f64) {
+///         self.x += dx;
+///         self.y += dy;
+///         self.moves += 1;
+///     }
+/// }
+///
+/// let ctx = JSContext::default();
+/// let p = Point { x: 0.0, y: 0.0, id: 7, moves: 0 }.into_object(&ctx);
+/// ctx.global_object().set_property("p", p.into()).expect("set");
+///
+/// let script = "p.moveBy(3, 4); p.x = 6; p.id = 0; [p.norm(), p.id]";
+/// let r = evaluate_script(&ctx, script, None, "test.js", 1).expect("evaluated");
+/// assert_eq!(r.to_json_string(0).expect("JSON").to_string(), "[7.211102550927978,7]");
+///
+/// let p = ctx.global_object().get_property("p").expect("p").as_object().expect("object");
+/// let p = Point::from_object(&p).expect("point").borrow();
+/// assert_eq!((p.x, p.y, p.moves), (6.0, 4.0, 1));
+/// ```
+///
+/// Fields are accessed through the Rust value, so scripts always see its
+/// current state. The value is kept in a `RefCell` while it belongs to
+/// an object, and a method or property that is used while the value is
+/// borrowed by another throws an `Error`.
+///
+/// [`FromJs`]: trait.FromJs.html
+/// [`ToJs`]: trait.ToJs.html
+/// [`Rest`]: struct.Rest.html
+/// [`js_function`]: attr.js_function.html
+pub trait JsClass: Sized + 'static {
+    /// The name of the class.
+    const NAME: &'static str;
+
+    /// Adds the properties and methods of the class to `builder`.
+    fn define(builder: JSClassBuilder) -> JSClassBuilder;
+
+    /// Gets the class.
+    ///
+    /// The class is built the first time it is needed on each thread, so
+    /// that all objects of the type on a thread share it.
+    fn class() -> JSClass {
+        thread_local! {
+            static CLASSES: RefCell<HashMap<TypeId, JSClass>> = RefCell::new(HashMap::new());
+        }
+        if let Some(class) =
+            CLASSES.with(|classes| classes.borrow().get(&TypeId::of::<Self>()).cloned())
+        {
+            return class;
+        }
+        let class = Self::define(JSClassBuilder::new(Self::NAME)).build();
+        CLASSES.with(|classes| {
+            classes
+                .borrow_mut()
+                .insert(TypeId::of::<Self>(), class.clone())
+        });
+        class
+    }
+
+    /// Creates an object of the class that holds `self`.
+    fn into_object(self, ctx: &JSContext) -> JSObject {
+        JSObject::new_with_class_and_data(ctx, &Self::class(), RefCell::new(self))
+    }
+
+    /// Registers the class as a global of `ctx`, with a constructor that
+    /// creates objects of the class from the values that `callback`
+    /// returns.
+    ///
+    /// The class is registered with [`JSContext::register_class`], so
+    /// registering it again returns the constructor that was registered
+    /// first.
+    ///
+    /// [`JSContext::register_class`]: struct.JSContext.html#method.register_class
+    fn register<F>(ctx: &JSContext, callback: F) -> Result<JSObject, JSException>
+    where
+        F: Fn(&JSContext, &[JSValue]) -> Result<Self, JSException> + 'static,
+    {
+        let (_, constructor) = ctx.register_class(&Self::class(), move |ctx, _, arguments| {
+            Ok(callback(ctx, arguments)?.into_object(ctx))
+        })?;
+        Ok(constructor)
+    }
+
+    /// Adds the properties and methods of the class to `declaration`,
+    /// for [`Declarations::class`].
+    ///
+    /// The derived implementation declares the fields and the methods
+    /// marked with `#[js_method]`.
+    ///
+    /// [`Declarations::class`]: struct.Declarations.html#method.class
+    fn declare(declaration: ClassDeclaration) -> ClassDeclaration {
+        declaration
+    }
+
+    /// Gets the Rust value of an object of the class.
+    ///
+    /// Returns `None` if the object was not created by [`into_object`].
+    ///
+    /// [`into_object`]: #method.into_object
+    fn from_object(object: &JSObject) -> Option<&RefCell<Self>> {
+        object.get_native()
+    }
+}
+
+/// Support for the code generated by the macros.
+#[doc(hidden)]
+pub mod __private {
+    use super::super::convert::invalid;
+    use super::super::exception::error;
+    use super::super::{
+        ClassDeclaration, FromJs, FunctionDeclaration, JSClassBuilder, JSContext, JSException,
+        JSObject, JSValue, ToJs, TypeScript,
+    };
+    use super::JsClass;
+    use std::cell::{Ref, RefCell, RefMut};
+    use std::marker::PhantomData;
+
+    /// Gives types without `#[js_methods]` an empty set of methods.
+    pub trait NoMethods {
+        fn __js_methods(builder: JSClassBuilder) -> JSClassBuilder {
+            builder
+        }
+
+        fn __js_method_declarations(declaration: ClassDeclaration) -> ClassDeclaration {
+            declaration
+        }
+    }
+
+    /// Stands for the type `T` when declaring it.
+    ///
+    /// `(&TypeOf::<T>::new()).ts_type()` uses `KnownType` if `T`
+    /// implements `TypeScript`, and falls back to `UnknownType`
+    /// otherwise, so the macros can declare any type.
+    pub struct TypeOf<T: ?Sized>(PhantomData<T>);
+
+    impl<T: ?Sized> TypeOf<T> {
+        #[allow(clippy::new_without_default)]
+        pub fn new() -> Self {
+            TypeOf(PhantomData)
+        }
+    }
+
+    pub trait KnownType {
+        fn ts_type(&self) -> String;
+        fn ts_parameter(&self, name: &str) -> String;
+    }
+
+    impl<T: TypeScript + ?Sized> KnownType for TypeOf<T> {
+        fn ts_type(&self) -> String {
+            T::ts_type()
+        }
+
+        fn ts_parameter(&self, name: &str) -> String {
+            T::ts_parameter(name)
+        }
+    }
+
+    pub trait UnknownType {
+        fn ts_type(&self) -> String;
+        fn ts_parameter(&self, name: &str) -> String;
+    }
+
+    impl<T: ?Sized> UnknownType for &TypeOf<T> {
+        fn ts_type(&self) -> String {
+            "unknown".to_owned()
+        }
+
+        fn ts_parameter(&self, name: &str) -> String {
+            format!("{}: unknown", name)
+        }
+    }
+
+    pub fn function_declaration(
+        name: &str,
+        parameters: Vec<String>,
+        result: String,
+    ) -> FunctionDeclaration {
+        FunctionDeclaration {
+            name: name.to_owned(),
+            parameters,
+            result,
+        }
+    }
+
+    impl<T> NoMethods for T {}
+
+    pub fn this<'a, T: JsClass>(
+        ctx: &JSContext,
+        object: &'a JSObject,
+    ) -> Result<&'a RefCell<T>, JSException> {
+        T::from_object(object).ok_or_else(|| {
+            JSException::type_error(ctx, &format!("Expected an object of class {}", T::NAME))
+        })
+    }
+
+    pub fn borrow<'a, T: JsClass>(
+        ctx: &JSContext,
+        this: &'a RefCell<T>,
+    ) -> Result<Ref<'a, T>, JSException> {
+        this.try_borrow()
+            .map_err(|_| error(ctx, &format!("The {} is in use", T::NAME)))
+    }
+
+    pub fn borrow_mut<'a, T: JsClass>(
+        ctx: &JSContext,
+        this: &'a RefCell<T>,
+    ) -> Result<RefMut<'a, T>, JSException> {
+        this.try_borrow_mut()
+            .map_err(|_| error(ctx, &format!("The {} is in use", T::NAME)))
+    }
+
+    pub fn get_field<T: JsClass, V: ToJs + ?Sized>(
+        ctx: &JSContext,
+        object: &JSObject,
+        field: impl Fn(&T) -> &V,
+    ) -> Result<JSValue, JSException> {
+        let this = borrow(ctx, this::<T>(ctx, object)?)?;
+        field(&this).to_js(ctx)
+    }
+
+    pub fn set_field<T: JsClass, V: FromJs>(
+        ctx: &JSContext,
+        object: &JSObject,
+        value: &JSValue,
+        field: impl Fn(&mut T) -> &mut V,
+    ) -> Result<(), JSException> {
+        let value = V::from_js(ctx, value)?;
+        let mut this = borrow_mut(ctx, this::<T>(ctx, object)?)?;
+        *field(&mut this) = value;
+        Ok(())
+    }
+
+    pub fn new_array(ctx: &JSContext, elements: &[JSValue]) -> Result<JSValue, JSException> {
+        super::super::object::new_array(ctx, elements).map(JSValue::from)
+    }
+
+    pub fn object(ctx: &JSContext, value: &JSValue) -> Result<JSObject, JSException> {
+        JSObject::from_js(ctx, value)
+    }
+
+    pub fn set_property<T: ToJs + ?Sized>(
+        ctx: &JSContext,
+        object: &JSObject,
+        name: &str,
+        value: &T,
+    ) -> Result<(), JSException> {
+        object.set_property(name, value.to_js(ctx)?)
+    }
+
+    pub fn get_property<T: FromJs>(
+        ctx: &JSContext,
+        object: &JSObject,
+        name: &str,
+    ) -> Result<T, JSException> {
+        get_property_with(ctx, object, name, T::from_js)
+    }
+
+    pub fn get_property_with<T>(
+        ctx: &JSContext,
+        object: &JSObject,
+        name: &str,
+        from_js: impl FnOnce(&JSContext, &JSValue) -> Result<T, JSException>,
+    ) -> Result<T, JSException> {
+        let value = object.get_property(name)?;
+        from_js(ctx, &value).map_err(|e| {
+            if value.is_undefined() {
+                JSException::type_error(ctx, &format!("Missing property {}", name))
+            } else {
+                invalid(ctx, e, &format!("property {}", name))
+            }
+        })
+    }
+
+    pub fn get_property_or<T: FromJs>(
+        ctx: &JSContext,
+        object: &JSObject,
+        name: &str,
+        default: impl FnOnce() -> T,
+    ) -> Result<T, JSException> {
+        let value = object.get_property(name)?;
+        from_js_or(ctx, &value, default).map_err(|e| invalid(ctx, e, &format!("property {}", name)))
+    }
+
+    /// Wraps the value of an externally tagged variant in an object
+    /// with a single property, named for the variant.
+    pub fn variant_object(
+        ctx: &JSContext,
+        variant: &str,
+        value: JSValue,
+    ) -> Result<JSValue, JSException> {
+        let object = JSObject::new(ctx);
+        object.set_property(variant, value)?;
+        Ok(object.into())
+    }
+
+    /// Adds the tag of an internally tagged newtype variant to its
+    /// value, which must be an object.
+    pub fn tagged(
+        ctx: &JSContext,
+        value: JSValue,
+        tag: &str,
+        variant: &str,
+    ) -> Result<JSValue, JSException> {
+        let object = if value.is_object() && !value.is_array() {
+            value.as_object()?
+        } else {
+            let message = format!("Expected an object for variant {}", variant);
+            return Err(JSException::type_error(ctx, &message));
+        };
+        set_property(ctx, &object, tag, variant)?;
+        Ok(object.into())
+    }
+
+    /// Gets the name and the value of an externally tagged variant,
+    /// which is either a string or an object with a single property.
+    pub fn external_variant(
+        ctx: &JSContext,
+        value: &JSValue,
+        units_only: bool,
+    ) -> Result<(String, Option<JSValue>), JSException> {
+        if value.is_string() {
+            return Ok((String::from_js(ctx, value)?, None));
+        }
+        if !units_only && value.is_object() {
+            let object = value.as_object()?;
+            let mut names = object.property_names();
+            if let (Some(name), None) = (names.next(), names.next()) {
+                let variant = name.to_string_lossy();
+                return Ok((variant, Some(object.get_property(name)?)));
+            }
+        }
+        Err(JSException::type_error(
+            ctx,
+            if units_only {
+                "Expected a string"
+            } else {
+                "Expected a string or an object with a single property"
+            },
+        ))
+    }
+
+    /// Makes the error of a variant that is unknown, or that is given
+    /// as a string when it has fields, or the other way round.
+    pub fn unknown_variant(
+        ctx: &JSContext,
+        variant: &str,
+        units: &[&str],
+        others: &[&str],
+    ) -> JSException {
+        let message = if units.contains(&variant) {
+            format!("Expected variant {} as a string", variant)
+        } else if others.contains(&variant) {
+            format!(
+                "Expected variant {} as an object with a single property",
+                variant
+            )
+        } else {
+            let names: Vec<&str> = units.iter().chain(others).copied().collect();
+            format!(
+                "Unknown variant {}, expected one of {}",
+                variant,
+                names.join(", ")
+            )
+        };
+        JSException::type_error(ctx, &message)
+    }
+
+    pub fn from_js_or<T: FromJs>(
+        ctx: &JSContext,
+        value: &JSValue,
+        default: impl FnOnce() -> T,
+    ) -> Result<T, JSException> {
+        Ok(Option::<T>::from_js(ctx, value)?.unwrap_or_else(default))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, js_declaration, Declarations, JSClassBuilder, JSContext};
+    use super::super::{
+        js_function, js_methods, FromJs, JSObject, JSValue, PropertyAttributes, Rest, ToJs,
+    };
+    use super::super::{JSException, JsClass};
+    use std::num::ParseIntError;
+
+    #[derive(JsClass)]
+    #[js(name = "Account")]
+    struct BankAccount {
+        #[js(rename = "owner")]
+        name: String,
+        #[js(readonly)]
+        balance: i64,
+    }
+
+    #[js_methods]
+    impl BankAccount {
+        #[js_method]
+        fn deposit(&mut self, ctx: &JSContext, amount: i64) -> Result<i64, JSException> {
+            if amount <= 0 {
+                return Err(JSException::range_error(ctx, "Deposits must be positive"));
+            }
+            self.balance += amount;
+            Ok(self.balance)
+        }
+
+        #[js_method]
+        fn describe(&self, other: Option<JSObject>) -> String {
+            match other.as_ref().and_then(BankAccount::from_object) {
+                Some(other) => format!("{} and {}", self.name, other.borrow().name),
+                None => self.name.clone(),
+            }
+        }
+    }
+
+    #[derive(JsClass)]
+    struct Empty {}
+
+    #[test]
+    fn derive() {
+        let ctx = JSContext::default();
+        let global = ctx.global_object();
+        let a = BankAccount {
+            name: "Ann".to_owned(),
+            balance: 0,
+        };
+        global
+            .set_property("a", a.into_object(&ctx).into())
+            .unwrap();
+        let b = BankAccount {
+            name: "Bob".to_owned(),
+            balance: 0,
+        };
+        global
+            .set_property("b", b.into_object(&ctx).into())
+            .unwrap();
+        global
+            .set_property("e", Empty {}.into_object(&ctx).into())
+            .unwrap();
+
+        let r = evaluate_script(
+            &ctx,
+            "a.deposit(5); a.owner = 'Amy';
+             [a.deposit(10), a.balance, a.describe(b), a.describe(),
+              String(a), Object.keys(Object.getPrototypeOf(a)).length]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            r.to_json_string(0).unwrap().to_string(),
+            r#"[15,15,"Amy and Bob","Amy","[object Account]",0]"#
+        );
+
+        let r = evaluate_script(&ctx, "a.deposit(-1)", None, "test.js", 1);
+        assert_eq!(r.unwrap_err().message(), Some("Deposits must be positive"));
+        let r = evaluate_script(&ctx, "a.deposit('1')", None, "test.js", 1);
+        assert_eq!(r.unwrap_err().name(), Some("TypeError"));
+        let r = evaluate_script(&ctx, "a.owner = 1", None, "test.js", 1);
+        assert_eq!(r.unwrap_err().name(), Some("TypeError"));
+        let r = evaluate_script(&ctx, "a.deposit.call(e, 1)", None, "test.js", 1);
+        assert_eq!(
+            r.unwrap_err().message(),
+            Some("Expected an object of class Account")
+        );
+    }
+
+    #[test]
+    fn class_is_shared() {
+        let ctx = JSContext::default();
+        let a = Empty {}.into_object(&ctx);
+        assert!(a.is_object_of_class(&Empty::class()));
+        assert!(!a.is_object_of_class(&BankAccount::class()));
+    }
+
+    #[test]
+    fn declarations() {
+        let d = Declarations::new()
+            .class::<BankAccount>()
+            .function(js_declaration!(join))
+            .function(js_declaration!(parse));
+        assert_eq!(
+            d.to_string(),
+            "interface Account {
+    owner: string;
+    readonly balance: number;
+    deposit(amount: number): number;
+    describe(other?: object | null): string;
+}
+
+declare function join(separator?: string | null, prefix?: string | null, ...parts: number[]): string;
+
+declare function parse(text: string, radix?: number | null): number;
+"
+        );
+    }
+
+    #[test]
+    fn register() {
+        let ctx = JSContext::default();
+        let open = |ctx: &JSContext, arguments: &[JSValue]| {
+            Ok(BankAccount {
+                name: String::from_js(ctx, &arguments[0])?,
+                balance: 0,
+            })
+        };
+        let first = BankAccount::register(&ctx, open).unwrap();
+        let a = BankAccount {
+            name: "Ann".to_owned(),
+            balance: 0,
+        };
+        ctx.global_object()
+            .set_property("a", a.into_object(&ctx).into())
+            .unwrap();
+        evaluate_script(
+            &ctx,
+            "globalThis.b = new Account('Bob')",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+
+        let second = BankAccount::register(&ctx, open).unwrap();
+        assert_eq!(first.raw, second.raw);
+        let r = evaluate_script(
+            &ctx,
+            "[a instanceof Account, b instanceof Account, b.owner, Account.name]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            r.to_json_string(0).unwrap().to_string(),
+            r#"[true,true,"Bob","Account"]"#
+        );
+    }
+
+    #[js_function]
+    fn parse(text: &str, radix: Option<u32>) -> Result<i64, ParseIntError> {
+        i64::from_str_radix(text, radix.unwrap_or(10))
+    }
+
+    #[js_function]
+    fn join(
+        #[js(default = ", ")] separator: &str,
+        #[js(default)] prefix: String,
+        parts: Rest<f64>,
+    ) -> String {
+        let parts: Vec<String> = parts.0.iter().map(|p| format!("{}{}", prefix, p)).collect();
+        parts.join(separator)
+    }
+
+    #[js_function]
+    fn factorial(n: u32) -> f64 {
+        if n == 0 {
+            1.0
+        } else {
+            f64::from(n) * factorial(n - 1)
+        }
+    }
+
+    #[test]
+    fn function() {
+        let ctx = JSContext::default();
+        let global = ctx.global_object();
+        global
+            .set_property("parse", JSObject::new_function(&ctx, parse).into())
+            .unwrap();
+        global
+            .set_property("join", JSObject::new_function(&ctx, join).into())
+            .unwrap();
+        let class = JSClassBuilder::new("Math")
+            .static_function("factorial", factorial, PropertyAttributes::NONE)
+            .build();
+        global
+            .set_property("m", JSObject::new_with_class(&ctx, &class).into())
+            .unwrap();
+
+        let r = evaluate_script(
+            &ctx,
+            "[parse('42'), parse('ff', 16), m.factorial(5)]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(r.to_json_string(0).unwrap().to_string(), "[42,255,120]");
+
+        let r = evaluate_script(
+            &ctx,
+            "[join(), join(null, '#', 1, 2), join('/', undefined, 3)]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            r.to_json_string(0).unwrap().to_string(),
+            r##"["","#1, #2","3"]"##
+        );
+        let r = evaluate_script(&ctx, "join('', '', 1, 'two')", None, "test.js", 1);
+        assert_eq!(
+            r.unwrap_err().message(),
+            Some("Invalid argument 4: Expected a number")
+        );
+
+        let r = evaluate_script(&ctx, "parse('x')", None, "test.js", 1);
+        assert_eq!(
+            r.unwrap_err().message(),
+            Some("invalid digit found in string")
+        );
+        let r = evaluate_script(&ctx, "parse(42)", None, "test.js", 1);
+        assert_eq!(
+            r.unwrap_err().message(),
+            Some("Invalid argument text: Expected a string")
+        );
+    }
+
+    #[derive(ToJs, FromJs, Debug, PartialEq)]
+    struct Entry<T> {
+        key: String,
+        #[js(rename = "value")]
+        item: T,
+        #[js(default)]
+        tags: Option<Tags>,
+    }
+
+    #[derive(ToJs, FromJs, Debug, PartialEq)]
+    struct Tags(String);
+
+    #[derive(ToJs, FromJs, Debug, PartialEq)]
+    struct Range(f64, #[js(default = 1.0)] f64);
+
+    #[test]
+    fn conversions() {
+        let ctx = JSContext::default();
+        let entry = Entry {
+            key: "a".to_owned(),
+            item: Range(0.0, 2.5),
+            tags: Some(Tags("x".to_owned())),
+        };
+        let v = entry.to_js(&ctx).unwrap();
+        assert_eq!(
+            v.to_json_string(0).unwrap().to_string(),
+            r#"{"key":"a","value":[0,2.5],"tags":"x"}"#
+        );
+        assert_eq!(Entry::<Range>::from_js(&ctx, &v).unwrap(), entry);
+
+        let v = evaluate_script(
+            &ctx,
+            "({ key: 'b', value: [3], tags: null })",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            Entry::<Range>::from_js(&ctx, &v).unwrap(),
+            Entry {
+                key: "b".to_owned(),
+                item: Range(3.0, 1.0),
+                tags: None,
+            }
+        );
+
+        let v = evaluate_script(&ctx, "({ value: 1 })", None, "test.js", 1).unwrap();
+        let e = Entry::<u8>::from_js(&ctx, &v).unwrap_err();
+        assert_eq!(e.message(), Some("Missing property key"));
+        let v = evaluate_script(&ctx, "({ key: 'c', value: 256 })", None, "test.js", 1).unwrap();
+        let e = Entry::<u8>::from_js(&ctx, &v).unwrap_err();
+        assert_eq!(e.name(), Some("RangeError"));
+        assert_eq!(
+            e.message(),
+            Some("Invalid property value: Expected an integer from 0 to 255")
+        );
+        let e = Tags::from_js(&ctx, &JSValue::new_number(&ctx, 1.0)).unwrap_err();
+        assert_eq!(e.name(), Some("TypeError"));
+    }
+
+    #[derive(ToJs, FromJs, Debug, PartialEq)]
+    enum Shape {
+        Empty,
+        #[js(rename = "dot")]
+        Point(f64),
+        Line(f64, f64),
+        Circle {
+            radius: f64,
+            #[js(rename = "fill")]
+            fill_color: Option<String>,
+        },
+    }
+
+    #[derive(ToJs, FromJs, Debug, PartialEq)]
+    #[js(tag = "type")]
+    enum Event {
+        #[js(rename = "close")]
+        Close,
+        #[js(rename = "message")]
+        Message { data: String },
+        #[js(rename = "resize")]
+        Resize(Size),
+    }
+
+    #[derive(ToJs, FromJs, Debug, PartialEq)]
+    struct Size {
+        width: u32,
+        height: u32,
+    }
+
+    #[derive(ToJs, FromJs, Debug, PartialEq)]
+    enum Mode {
+        #[js(rename = "read")]
+        Read,
+        #[js(rename = "write")]
+        Write,
+    }
+
+    #[test]
+    fn enums() {
+        let ctx = JSContext::default();
+        let json = |value: &dyn ToJs| {
+            let v = value.to_js(&ctx).unwrap();
+            v.to_json_string(0).unwrap().to_string()
+        };
+        let shapes = [
+            (Shape::Empty, r#""Empty""#),
+            (Shape::Point(1.5), r#"{"dot":1.5}"#),
+            (Shape::Line(0.0, 2.0), r#"{"Line":[0,2]}"#),
+            (
+                Shape::Circle {
+                    radius: 1.0,
+                    fill_color: Some("red".to_owned()),
+                },
+                r#"{"Circle":{"radius":1,"fill":"red"}}"#,
+            ),
+        ];
+        for (shape, expected) in shapes {
+            assert_eq!(json(&shape), expected);
+            let v = JSValue::new_from_json(&ctx, expected).unwrap();
+            assert_eq!(Shape::from_js(&ctx, &v).unwrap(), shape);
+        }
+
+        let events = [
+            (Event::Close, r#"{"type":"close"}"#),
+            (
+                Event::Message {
+                    data: "hi".to_owned(),
+                },
+                r#"{"type":"message","data":"hi"}"#,
+            ),
+            (
+                Event::Resize(Size {
+                    width: 2,
+                    height: 3,
+                }),
+                r#"{"width":2,"height":3,"type":"resize"}"#,
+            ),
+        ];
+        for (event, expected) in events {
+            assert_eq!(json(&event), expected);
+            let v = JSValue::new_from_json(&ctx, expected).unwrap();
+            assert_eq!(Event::from_js(&ctx, &v).unwrap(), event);
+        }
+
+        assert_eq!(json(&Mode::Write), r#""write""#);
+        let v = "read".to_js(&ctx).unwrap();
+        assert_eq!(Mode::from_js(&ctx, &v).unwrap(), Mode::Read);
+
+        let eval = |script| evaluate_script(&ctx, script, None, "test.js", 1).unwrap();
+        let e = Mode::from_js(&ctx, &eval("'append'")).unwrap_err();
+        assert_eq!(
+            e.message(),
+            Some("Unknown variant append, expected one of read, write")
+        );
+        let e = Mode::from_js(&ctx, &eval("({ read: 1 })")).unwrap_err();
+        assert_eq!(e.message(), Some("Expected a string"));
+        let e = Shape::from_js(&ctx, &eval("'dot'")).unwrap_err();
+        assert_eq!(
+            e.message(),
+            Some("Expected variant dot as an object with a single property")
+        );
+        let e = Shape::from_js(&ctx, &eval("({ Empty: null })")).unwrap_err();
+        assert_eq!(e.message(), Some("Expected variant Empty as a string"));
+        let e = Shape::from_js(&ctx, &eval("({ a: 1, b: 2 })")).unwrap_err();
+        assert_eq!(
+            e.message(),
+            Some("Expected a string or an object with a single property")
+        );
+        let e = Event::from_js(&ctx, &eval("({ type: 'open' })")).unwrap_err();
+        assert_eq!(
+            e.message(),
+            Some("Unknown variant open, expected one of close, message, resize")
+        );
+        let e = Event::from_js(&ctx, &eval("({ data: 'x' })")).unwrap_err();
+        assert_eq!(e.message(), Some("Missing property type"));
+    }
+
+    #[derive(ToJs, FromJs, Debug, PartialEq)]
+    struct Patch {
+        #[js(none_as_null)]
+        name: Option<String>,
+        #[js(reject_undefined)]
+        parent: Option<u32>,
+        size: Option<u32>,
+    }
+
+    #[derive(ToJs, FromJs, Debug, PartialEq)]
+    struct Nullable(#[js(none_as_null, reject_undefined)] Option<u8>);
+
+    #[test]
+    fn option_policies() {
+        let ctx = JSContext::default();
+        let patch = Patch {
+            name: None,
+            parent: None,
+            size: None,
+        };
+        let v = patch.to_js(&ctx).unwrap();
+        assert_eq!(v.to_json_string(0).unwrap().to_string(), r#"{"name":null}"#);
+
+        let v = evaluate_script(&ctx, "({ parent: null })", None, "test.js", 1).unwrap();
+        assert_eq!(Patch::from_js(&ctx, &v).unwrap(), patch);
+        let v = evaluate_script(&ctx, "({ name: 'a' })", None, "test.js", 1).unwrap();
+        let e = Patch::from_js(&ctx, &v).unwrap_err();
+        assert_eq!(e.message(), Some("Missing property parent"));
+
+        assert!(Nullable(None).to_js(&ctx).unwrap().is_null());
+        let v = JSValue::new_number(&ctx, 3.0);
+        assert_eq!(Nullable::from_js(&ctx, &v).unwrap(), Nullable(Some(3)));
+        assert!(Nullable::from_js(&ctx, &JSValue::new_undefined(&ctx)).is_err());
+    }
+}
@@ -0,0 +1,513 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::base::evaluate_script;
+use super::function::new_function;
+use super::typedarray::{new_uint8_array, typed_array_bytes};
+use super::{JSContext, JSException, JSObject, JSString, JSValue};
+use std::cell::RefCell;
+use std::char::{decode_utf16, REPLACEMENT_CHARACTER};
+
+/// Defines `TextEncoder` and `TextDecoder` on top of the native
+/// functions that it is called with.
+///
+/// Argument checking and the class boilerplate are much simpler to get
+/// right in JavaScript. The actual encoding and decoding is done in Rust.
+const GLUE: &str = r#"(function (global, encode, encodeInto, newDecoder) {
+    'use strict';
+
+    const toBytes = (input) => {
+        if (input === undefined) {
+            return new Uint8Array(0);
+        }
+        if (ArrayBuffer.isView(input)) {
+            return new Uint8Array(input.buffer, input.byteOffset, input.byteLength);
+        }
+        if (input instanceof ArrayBuffer ||
+            (typeof SharedArrayBuffer !== 'undefined' && input instanceof SharedArrayBuffer)) {
+            return new Uint8Array(input);
+        }
+        throw new TypeError('The input must be an ArrayBuffer or an ArrayBufferView');
+    };
+
+    const decoders = new WeakMap();
+    const decoderOf = (self) => {
+        const state = decoders.get(self);
+        if (state === undefined) {
+            throw new TypeError('Illegal invocation');
+        }
+        return state;
+    };
+
+    class TextEncoder {
+        get encoding() {
+            return 'utf-8';
+        }
+
+        encode(input = '') {
+            return encode(String(input));
+        }
+
+        encodeInto(source, destination) {
+            if (!(destination instanceof Uint8Array)) {
+                throw new TypeError('The destination must be a Uint8Array');
+            }
+            return encodeInto(String(source), destination);
+        }
+    }
+
+    class TextDecoder {
+        constructor(label = 'utf-8', options = {}) {
+            const fatal = Boolean(options && options.fatal);
+            const ignoreBOM = Boolean(options && options.ignoreBOM);
+            const decode = newDecoder(String(label), fatal, ignoreBOM);
+            if (decode === null) {
+                throw new RangeError(`The encoding label '${label}' is not supported`);
+            }
+            decoders.set(this, { decode, fatal, ignoreBOM });
+        }
+
+        get encoding() {
+            return decoderOf(this).decode.encoding;
+        }
+
+        get fatal() {
+            return decoderOf(this).fatal;
+        }
+
+        get ignoreBOM() {
+            return decoderOf(this).ignoreBOM;
+        }
+
+        decode(input, options = {}) {
+            const stream = Boolean(options && options.stream);
+            const text = decoderOf(this).decode(toBytes(input), stream);
+            if (text === null) {
+                throw new TypeError(`The encoded data is not valid ${this.encoding}`);
+            }
+            return text;
+        }
+    }
+
+    for (const [name, value] of [['TextEncoder', TextEncoder], ['TextDecoder', TextDecoder]]) {
+        Object.defineProperty(global, name, { value, writable: true, configurable: true });
+    }
+})"#;
+
+/// Installs the `TextEncoder` and `TextDecoder` classes into a context.
+///
+/// * `ctx`: The execution context to install the classes into.
+///
+/// `TextEncoder` always encodes to UTF-8. `TextDecoder` decodes UTF-8,
+/// and with the `encoding_rs` feature, every other encoding of the
+/// [Encoding Standard]. Both follow the standard, including streaming
+/// decodes and the `fatal` and `ignoreBOM` options.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let ctx = JSContext::default();
+/// install_text_encoding(&ctx).expect("classes installed");
+///
+/// let s = evaluate_script(
+///     &ctx,
+///     "new TextDecoder().decode(new TextEncoder().encode('h\u{e9}llo'))",
+///     None,
+///     "test.js",
+///     1,
+/// )
+/// .expect("round trip");
+/// assert_eq!(s.as_string().expect("string"), "h\u{e9}llo");
+/// ```
+///
+/// [Encoding Standard]: https://encoding.spec.whatwg.org/
+pub fn install_text_encoding(ctx: &JSContext) -> Result<(), JSException> {
+    let encode = new_function(ctx, |ctx, _this, arguments| {
+        let text = arguments[0].as_string()?.to_string_lossy();
+        Ok(new_uint8_array(ctx, text.as_bytes())?.into())
+    });
+    let encode_into = new_function(ctx, |ctx, _this, arguments| {
+        let source = arguments[0].as_string()?;
+        let destination = arguments[1].as_object()?;
+        let (read, written) =
+            encode_utf8_into(source.utf16(), unsafe { typed_array_bytes(&destination)? });
+        let result = JSObject::new(ctx);
+        result.set_property("read", JSValue::new_number(ctx, read as f64))?;
+        result.set_property("written", JSValue::new_number(ctx, written as f64))?;
+        Ok(result.into())
+    });
+    let new_decoder = new_function(ctx, |ctx, _this, arguments| {
+        let label = arguments[0].as_string()?.to_string_lossy();
+        let fatal = arguments[1].as_boolean();
+        let ignore_bom = arguments[2].as_boolean();
+        let decoder = match Decoder::for_label(&label, fatal, ignore_bom) {
+            Some(decoder) => decoder,
+            None => return Ok(JSValue::new_null(ctx)),
+        };
+        let encoding = decoder.encoding();
+        let decoder = RefCell::new(decoder);
+        let decode = new_function(ctx, move |ctx, _this, arguments| {
+            let array = arguments[0].as_object()?;
+            let stream = arguments[1].as_boolean();
+            let text = decoder
+                .borrow_mut()
+                .decode(unsafe { typed_array_bytes(&array)? }, stream);
+            Ok(match text {
+                Some(text) => JSValue::new_string(ctx, JSString::from_str_lossless(&text)),
+                None => JSValue::new_null(ctx),
+            })
+        });
+        decode.set_property("encoding", JSValue::new_string(ctx, encoding.as_str()))?;
+        Ok(decode.into())
+    });
+
+    let glue = evaluate_script(ctx, GLUE, None, "text-encoding.js", 1)?.as_object()?;
+    glue.call_as_function(
+        None,
+        &[
+            ctx.global_object().into(),
+            encode.into(),
+            encode_into.into(),
+            new_decoder.into(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Encodes as much of `source` as fits into `destination` as UTF-8,
+/// never splitting a character.
+///
+/// Returns the number of UTF-16 code units read and bytes written.
+fn encode_utf8_into(source: &[u16], destination: &mut [u8]) -> (usize, usize) {
+    let (mut read, mut written) = (0, 0);
+    for c in decode_utf16(source.iter().copied()) {
+        let c = c.unwrap_or(REPLACEMENT_CHARACTER);
+        let len = c.len_utf8();
+        if written + len > destination.len() {
+            break;
+        }
+        c.encode_utf8(&mut destination[written..]);
+        written += len;
+        // An unpaired surrogate is one code unit, like its replacement.
+        read += c.len_utf16();
+    }
+    (read, written)
+}
+
+/// The state of a UTF-8 `TextDecoder`.
+#[cfg(not(feature = "encoding_rs"))]
+struct Decoder {
+    fatal: bool,
+    ignore_bom: bool,
+    /// Whether the start of the stream, which may hold a BOM, has been
+    /// decoded already.
+    bom_seen: bool,
+    /// The bytes of an incomplete character at the end of the last chunk
+    /// of a streaming decode.
+    pending: Vec<u8>,
+}
+
+#[cfg(not(feature = "encoding_rs"))]
+impl Decoder {
+    const BOM: &'static [u8] = b"\xEF\xBB\xBF";
+
+    /// The labels of UTF-8 in the Encoding Standard.
+    const LABELS: &'static [&'static str] = &[
+        "unicode-1-1-utf-8",
+        "unicode11utf8",
+        "unicode20utf8",
+        "utf-8",
+        "utf8",
+        "x-unicode20utf8",
+    ];
+
+    fn for_label(label: &str, fatal: bool, ignore_bom: bool) -> Option<Self> {
+        let label = label.trim_matches(|c: char| c.is_ascii_whitespace());
+        if !Decoder::LABELS
+            .iter()
+            .any(|l| l.eq_ignore_ascii_case(label))
+        {
+            return None;
+        }
+        Some(Decoder {
+            fatal,
+            ignore_bom,
+            bom_seen: false,
+            pending: Vec::new(),
+        })
+    }
+
+    fn encoding(&self) -> String {
+        "utf-8".to_owned()
+    }
+
+    /// Decodes the next chunk of input, or returns `None` if it is
+    /// malformed and the decoder is fatal.
+    fn decode(&mut self, bytes: &[u8], stream: bool) -> Option<String> {
+        let mut input = std::mem::take(&mut self.pending);
+        input.extend_from_slice(bytes);
+
+        let mut start = 0;
+        if !self.bom_seen && !self.ignore_bom {
+            // Wait for enough input to tell whether it starts with a BOM.
+            if stream && input.len() < Decoder::BOM.len() && Decoder::BOM.starts_with(&input) {
+                self.pending = input;
+                return Some(String::new());
+            }
+            if input.starts_with(Decoder::BOM) {
+                start = Decoder::BOM.len();
+            }
+        }
+        self.bom_seen = true;
+
+        let end = if stream {
+            start + complete_utf8_len(&input[start..])
+        } else {
+            input.len()
+        };
+        let text = if self.fatal {
+            std::str::from_utf8(&input[start..end])
+                .ok()
+                .map(str::to_owned)
+        } else {
+            Some(String::from_utf8_lossy(&input[start..end]).into_owned())
+        };
+        if stream && text.is_some() {
+            self.pending = input[end..].to_vec();
+        } else {
+            self.bom_seen = false;
+        }
+        text
+    }
+}
+
+/// Gets the length of `bytes` without an incomplete UTF-8 sequence at
+/// its end.
+#[cfg(not(feature = "encoding_rs"))]
+fn complete_utf8_len(bytes: &[u8]) -> usize {
+    let mut start = 0;
+    loop {
+        match std::str::from_utf8(&bytes[start..]) {
+            Ok(_) => return bytes.len(),
+            Err(e) => match e.error_len() {
+                Some(len) => start += e.valid_up_to() + len,
+                None => return start + e.valid_up_to(),
+            },
+        }
+    }
+}
+
+/// The state of a `TextDecoder`.
+#[cfg(feature = "encoding_rs")]
+struct Decoder {
+    fatal: bool,
+    ignore_bom: bool,
+    encoding: &'static encoding_rs::Encoding,
+    decoder: encoding_rs::Decoder,
+}
+
+#[cfg(feature = "encoding_rs")]
+impl Decoder {
+    fn for_label(label: &str, fatal: bool, ignore_bom: bool) -> Option<Self> {
+        let encoding = encoding_rs::Encoding::for_label(label.as_bytes())?;
+        // The replacement encoding only exists to keep certain labels
+        // from being misinterpreted, and can not be used to decode.
+        if encoding == encoding_rs::REPLACEMENT {
+            return None;
+        }
+        Some(Decoder {
+            fatal,
+            ignore_bom,
+            encoding,
+            decoder: Decoder::new_decoder(encoding, ignore_bom),
+        })
+    }
+
+    fn new_decoder(
+        encoding: &'static encoding_rs::Encoding,
+        ignore_bom: bool,
+    ) -> encoding_rs::Decoder {
+        if ignore_bom {
+            encoding.new_decoder_without_bom_handling()
+        } else {
+            encoding.new_decoder_with_bom_removal()
+        }
+    }
+
+    fn encoding(&self) -> String {
+        self.encoding.name().to_ascii_lowercase()
+    }
+
+    /// Decodes the next chunk of input, or returns `None` if it is
+    /// malformed and the decoder is fatal.
+    fn decode(&mut self, bytes: &[u8], stream: bool) -> Option<String> {
+        let last = !stream;
+        let text = if self.fatal {
+            let len = self
+                .decoder
+                .max_utf8_buffer_length_without_replacement(bytes.len())?;
+            let mut text = String::with_capacity(len);
+            let (result, _) = self
+                .decoder
+                .decode_to_string_without_replacement(bytes, &mut text, last);
+            match result {
+                encoding_rs::DecoderResult::InputEmpty => Some(text),
+                _ => None,
+            }
+        } else {
+            let len = self.decoder.max_utf8_buffer_length(bytes.len())?;
+            let mut text = String::with_capacity(len);
+            let _ = self.decoder.decode_to_string(bytes, &mut text, last);
+            Some(text)
+        };
+        if last || text.is_none() {
+            self.decoder = Decoder::new_decoder(self.encoding, self.ignore_bom);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSContext};
+    use super::install_text_encoding;
+
+    fn eval(ctx: &JSContext, script: &str) -> String {
+        evaluate_script(ctx, script, None, "test.js", 1)
+            .unwrap()
+            .as_string()
+            .unwrap()
+            .to_string()
+    }
+
+    fn context() -> JSContext {
+        let ctx = JSContext::default();
+        install_text_encoding(&ctx).unwrap();
+        ctx
+    }
+
+    #[test]
+    fn encode() {
+        let ctx = context();
+        assert_eq!(
+            eval(&ctx, "new TextEncoder().encode('a\u{e9}\u{1f600}').join()"),
+            "97,195,169,240,159,152,128"
+        );
+        assert_eq!(eval(&ctx, "new TextEncoder().encode().length + ''"), "0");
+        // Unpaired surrogates are replaced.
+        assert_eq!(
+            eval(&ctx, "new TextEncoder().encode('\\ud800').join()"),
+            "239,191,189"
+        );
+    }
+
+    #[test]
+    fn encode_into() {
+        let ctx = context();
+        assert_eq!(
+            eval(
+                &ctx,
+                "var a = new Uint8Array(5);
+                 var r = new TextEncoder().encodeInto('ab\u{1f600}', a);
+                 [r.read, r.written, a.join()].join(' ')"
+            ),
+            "2 2 97,98,0,0,0"
+        );
+    }
+
+    #[test]
+    fn decode() {
+        let ctx = context();
+        assert_eq!(
+            eval(
+                &ctx,
+                "new TextDecoder().decode(new Uint8Array([0xef, 0xbb, 0xbf, 104, 0, 105]))"
+            ),
+            "h\0i"
+        );
+        assert_eq!(
+            eval(
+                &ctx,
+                "new TextDecoder('utf-8', {ignoreBOM: true})
+                     .decode(new Uint8Array([0xef, 0xbb, 0xbf])).length + ''"
+            ),
+            "1"
+        );
+        assert_eq!(
+            eval(
+                &ctx,
+                "new TextDecoder().decode(new Uint8Array([104, 0xff]))"
+            ),
+            "h\u{fffd}"
+        );
+        // Views only decode their own part of the buffer.
+        assert_eq!(
+            eval(
+                &ctx,
+                "new TextDecoder().decode(new DataView(new Uint8Array([97, 98, 99]).buffer, 1, 1))"
+            ),
+            "b"
+        );
+        assert_eq!(eval(&ctx, "new TextDecoder(' UTF8 ').encoding"), "utf-8");
+    }
+
+    #[test]
+    fn decode_stream() {
+        let ctx = context();
+        assert_eq!(
+            eval(
+                &ctx,
+                "var d = new TextDecoder();
+                 var s = d.decode(new Uint8Array([0xef, 0xbb]), {stream: true});
+                 s += d.decode(new Uint8Array([0xbf, 0xf0, 0x9f]), {stream: true});
+                 s += d.decode(new Uint8Array([0x98, 0x80]));
+                 s"
+            ),
+            "\u{1f600}"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let ctx = context();
+        assert_eq!(
+            eval(
+                &ctx,
+                "var errors = [];
+                 for (const f of [
+                     () => new TextDecoder('utf-8', {fatal: true}).decode(new Uint8Array([0xff])),
+                     () => new TextDecoder('no-such-encoding'),
+                     () => new TextDecoder().decode('not bytes'),
+                     () => TextDecoder.prototype.decode.call({}),
+                 ]) {
+                     try { f(); } catch (e) { errors.push(e.name); }
+                 }
+                 errors.join()"
+            ),
+            "TypeError,RangeError,TypeError,TypeError"
+        );
+    }
+
+    #[cfg(feature = "encoding_rs")]
+    #[test]
+    fn other_encodings() {
+        let ctx = context();
+        assert_eq!(
+            eval(
+                &ctx,
+                "var d = new TextDecoder('latin1');
+                 d.encoding + ' ' + d.decode(new Uint8Array([0x63, 0x61, 0x66, 0xe9]))"
+            ),
+            "windows-1252 caf\u{e9}"
+        );
+        assert_eq!(
+            eval(
+                &ctx,
+                "new TextDecoder('utf-16le').decode(new Uint8Array([0x68, 0, 0x69, 0]))"
+            ),
+            "hi"
+        );
+    }
+}
@@ -0,0 +1,414 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{JSException, Spawner, TimerId, TimerScheduler, Timers};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+/// A future that an event loop runs.
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+/// The event loop that drives the timers, promises and async functions
+/// of a context.
+///
+/// JavaScriptCore has no event loop of its own, so the host provides
+/// one, such as that of Tokio, glib, winit or a game. An event loop that
+/// implements this trait can be given to [`install_timers`] and to the
+/// functions that take a [`Spawner`], by way of an `Rc`, so that one
+/// loop drives them all. The loop must call into the context from the
+/// thread that owns it.
+///
+/// There is nothing to do for microtasks, such as the reactions to
+/// promises: JavaScriptCore runs them whenever the host returns from
+/// calling into the context, so firing a timer or polling a future also
+/// runs the jobs that it queues.
+///
+/// [`LocalEventLoop`] is an implementation that runs on the current
+/// thread.
+///
+/// [`install_timers`]: fn.install_timers.html
+/// [`Spawner`]: trait.Spawner.html
+/// [`LocalEventLoop`]: struct.LocalEventLoop.html
+pub trait EventLoop {
+    /// Requests that `timer` fires once `delay` has passed, by way of
+    /// [`Timers::fire`].
+    ///
+    /// This is called again for each repetition of an interval.
+    ///
+    /// [`Timers::fire`]: struct.Timers.html#method.fire
+    fn schedule_timer(&self, timer: TimerId, delay: Duration);
+
+    /// Tells the loop that `timer` has been cleared and no longer needs
+    /// to fire.
+    fn cancel_timer(&self, timer: TimerId);
+
+    /// Starts running `future` on the thread of the context.
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()>>>);
+
+    /// Asks the loop to run again soon, for example because it is
+    /// waiting for work that has just become ready.
+    fn wake(&self);
+}
+
+impl<L: EventLoop + ?Sized> TimerScheduler for Rc<L> {
+    fn schedule(&mut self, timer: TimerId, delay: Duration) {
+        self.schedule_timer(timer, delay);
+    }
+
+    fn cancel(&mut self, timer: TimerId) {
+        self.cancel_timer(timer);
+    }
+}
+
+impl<L: EventLoop + ?Sized> Spawner for Rc<L> {
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()>>>) {
+        EventLoop::spawn(&**self, future);
+    }
+}
+
+/// What may wake a `LocalEventLoop` from another thread.
+struct Signal {
+    /// The tasks that have been woken since they were last polled.
+    ready: Mutex<Vec<usize>>,
+    /// Whether the loop has been asked to run again.
+    woken: AtomicBool,
+    /// The thread that the loop runs on.
+    thread: Thread,
+}
+
+/// Wakes a task of a `LocalEventLoop`.
+struct TaskWaker {
+    task: usize,
+    signal: Arc<Signal>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.signal
+            .ready
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(self.task);
+        self.signal.thread.unpark();
+    }
+}
+
+/// A single-threaded [`EventLoop`], which runs on the thread that
+/// creates it.
+///
+/// The host creates it in an `Rc`, gives clones of that to
+/// [`install_timers`] and as a [`Spawner`], and then calls [`run`] to
+/// run the timers and futures until there are none left. A host with an
+/// event loop of its own can call [`run_ready`] from it instead.
+///
+/// ```
+/// # use javascriptcore::*;
+/// # use std::rc::Rc;
+/// let ctx = JSContext::default();
+/// let event_loop = Rc::new(LocalEventLoop::new());
+/// let timers = install_timers(&ctx, event_loop.clone()).expect("timers installed");
+///
+/// let script = "var log = []; setTimeout(() => log.push('timer'), 5);
+///               Promise.resolve().then(() => log.push('promise'))";
+/// evaluate_script(&ctx, script, None, "test.js", 1).expect("evaluated");
+/// event_loop.run(Some(&timers)).expect("ran");
+///
+/// let log = evaluate_script(&ctx, "log.join()", None, "test.js", 1).expect("log");
+/// assert_eq!(log.as_string().expect("string"), "promise,timer");
+/// ```
+///
+/// [`EventLoop`]: trait.EventLoop.html
+/// [`install_timers`]: fn.install_timers.html
+/// [`Spawner`]: trait.Spawner.html
+/// [`run`]: #method.run
+/// [`run_ready`]: #method.run_ready
+pub struct LocalEventLoop {
+    signal: Arc<Signal>,
+    /// The futures that have not completed, by their number.
+    tasks: RefCell<HashMap<usize, Task>>,
+    next_task: Cell<usize>,
+    /// The pending timers, in the order that they are due. The number
+    /// keeps timers that are due at the same time in the order that they
+    /// were scheduled.
+    timers: RefCell<BTreeMap<(Instant, u64), TimerId>>,
+    deadlines: RefCell<HashMap<TimerId, (Instant, u64)>>,
+    next_timer: Cell<u64>,
+}
+
+impl LocalEventLoop {
+    /// Creates an event loop for the current thread.
+    pub fn new() -> Self {
+        LocalEventLoop {
+            signal: Arc::new(Signal {
+                ready: Mutex::new(Vec::new()),
+                woken: AtomicBool::new(false),
+                thread: thread::current(),
+            }),
+            tasks: RefCell::new(HashMap::new()),
+            next_task: Cell::new(0),
+            timers: RefCell::new(BTreeMap::new()),
+            deadlines: RefCell::new(HashMap::new()),
+            next_timer: Cell::new(0),
+        }
+    }
+
+    /// Tests whether there are no futures or timers left to run.
+    pub fn is_idle(&self) -> bool {
+        self.tasks.borrow().is_empty() && self.timers.borrow().is_empty()
+    }
+
+    /// Runs the futures that are ready to make progress and the timers
+    /// that are due, without waiting for any others.
+    ///
+    /// * `timers`: The timers to fire, as returned by
+    ///   [`install_timers`], if any were installed with this loop.
+    ///
+    /// Returns whether anything ran, or the exception thrown by the
+    /// callback of a timer. The timers after that one run the next time.
+    ///
+    /// [`install_timers`]: fn.install_timers.html
+    pub fn run_ready(&self, timers: Option<&Timers>) -> Result<bool, JSException> {
+        self.signal.woken.store(false, Ordering::SeqCst);
+        let ready = mem::take(&mut *self.signal.ready.lock().unwrap_or_else(|e| e.into_inner()));
+        let mut ran = false;
+        for id in ready {
+            // The task is taken out while it is polled, so that it can
+            // spawn others.
+            let task = self.tasks.borrow_mut().remove(&id);
+            if let Some(mut task) = task {
+                ran = true;
+                let waker = Waker::from(Arc::new(TaskWaker {
+                    task: id,
+                    signal: self.signal.clone(),
+                }));
+                if task
+                    .as_mut()
+                    .poll(&mut Context::from_waker(&waker))
+                    .is_pending()
+                {
+                    self.tasks.borrow_mut().insert(id, task);
+                }
+            }
+        }
+
+        let now = Instant::now();
+        loop {
+            let due = {
+                let mut pending = self.timers.borrow_mut();
+                match pending.first_key_value() {
+                    Some((&(deadline, _), _)) if deadline <= now => pending.pop_first(),
+                    _ => None,
+                }
+            };
+            let Some((_, timer)) = due else { break };
+            self.deadlines.borrow_mut().remove(&timer);
+            ran = true;
+            if let Some(timers) = timers {
+                timers.fire(timer)?;
+            }
+        }
+        Ok(ran)
+    }
+
+    /// Blocks the thread until a future may be ready to make progress,
+    /// the next timer is due or the loop is woken, or until `timeout`
+    /// has passed.
+    pub fn wait(&self, timeout: Option<Duration>) {
+        let start = Instant::now();
+        let mut deadline = timeout.map(|timeout| start + timeout);
+        if let Some((&(due, _), _)) = self.timers.borrow().first_key_value() {
+            deadline = Some(deadline.map_or(due, |deadline| deadline.min(due)));
+        }
+        loop {
+            if self.signal.woken.load(Ordering::SeqCst)
+                || !self
+                    .signal
+                    .ready
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .is_empty()
+            {
+                return;
+            }
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return;
+                    }
+                    thread::park_timeout(deadline - now);
+                }
+                None => thread::park(),
+            }
+        }
+    }
+
+    /// Runs futures and timers until there are none left.
+    ///
+    /// * `timers`: The timers to fire, as returned by
+    ///   [`install_timers`], if any were installed with this loop.
+    ///
+    /// Returns the exception thrown by the callback of a timer, which
+    /// stops the loop. Calling this again carries on from there.
+    ///
+    /// [`install_timers`]: fn.install_timers.html
+    pub fn run(&self, timers: Option<&Timers>) -> Result<(), JSException> {
+        loop {
+            self.run_ready(timers)?;
+            if self.is_idle() {
+                return Ok(());
+            }
+            self.wait(None);
+        }
+    }
+}
+
+impl Default for LocalEventLoop {
+    fn default() -> Self {
+        LocalEventLoop::new()
+    }
+}
+
+impl EventLoop for LocalEventLoop {
+    fn schedule_timer(&self, timer: TimerId, delay: Duration) {
+        self.cancel_timer(timer);
+        let key = (Instant::now() + delay, self.next_timer.get());
+        self.next_timer.set(key.1 + 1);
+        self.timers.borrow_mut().insert(key, timer);
+        self.deadlines.borrow_mut().insert(timer, key);
+    }
+
+    fn cancel_timer(&self, timer: TimerId) {
+        if let Some(key) = self.deadlines.borrow_mut().remove(&timer) {
+            self.timers.borrow_mut().remove(&key);
+        }
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()>>>) {
+        let id = self.next_task.get();
+        self.next_task.set(id.wrapping_add(1));
+        self.tasks.borrow_mut().insert(id, future);
+        self.signal
+            .ready
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(id);
+    }
+
+    fn wake(&self) {
+        self.signal.woken.store(true, Ordering::SeqCst);
+        self.signal.thread.unpark();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, install_timers, JSContext, JSObject};
+    use super::{EventLoop, LocalEventLoop};
+    use std::cell::Cell;
+    use std::future::{self, Future};
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::task::{Poll, Waker};
+    use std::thread;
+    use std::time::Duration;
+
+    fn eval(ctx: &JSContext, script: &str) -> String {
+        let r = evaluate_script(ctx, script, None, "test.js", 1).unwrap();
+        r.to_json_string(0).unwrap().to_string()
+    }
+
+    /// Completes once `delay` has passed on another thread.
+    fn sleep(delay: Duration) -> impl Future<Output = ()> {
+        let state = Arc::new((AtomicBool::new(false), Mutex::new(None::<Waker>)));
+        let sleeper = state.clone();
+        thread::spawn(move || {
+            thread::sleep(delay);
+            sleeper.0.store(true, Ordering::SeqCst);
+            if let Some(waker) = sleeper.1.lock().unwrap().take() {
+                waker.wake();
+            }
+        });
+        future::poll_fn(move |cx| {
+            *state.1.lock().unwrap() = Some(cx.waker().clone());
+            if state.0.load(Ordering::SeqCst) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+    }
+
+    #[test]
+    fn local_event_loop() {
+        let ctx = JSContext::default();
+        let event_loop = Rc::new(LocalEventLoop::new());
+        let timers = install_timers(&ctx, event_loop.clone()).unwrap();
+        let slept = JSObject::new_async_function(&ctx, event_loop.clone(), |_ctx, _this, _args| {
+            Ok(async {
+                sleep(Duration::from_millis(10)).await;
+                "slept"
+            })
+        });
+        ctx.global_object()
+            .set_property("sleep", slept.into())
+            .unwrap();
+
+        eval(
+            &ctx,
+            "globalThis.log = [];
+             setTimeout(() => log.push('second'), 20);
+             setTimeout(() => log.push('first'), 0);
+             clearTimeout(setTimeout(() => log.push('cleared'), 0));
+             sleep().then(s => log.push(s));
+             0",
+        );
+        assert!(!event_loop.is_idle());
+        event_loop.run(Some(&timers)).unwrap();
+        assert!(event_loop.is_idle());
+        assert_eq!(eval(&ctx, "log"), r#"["first","slept","second"]"#);
+
+        eval(
+            &ctx,
+            "setTimeout(() => { throw new Error('oops') }, 0);
+             setTimeout(() => log.push('after'), 0); 0",
+        );
+        let e = event_loop.run(Some(&timers)).unwrap_err();
+        assert_eq!(e.message(), Some("oops"));
+        event_loop.run(Some(&timers)).unwrap();
+        assert_eq!(eval(&ctx, "log.length"), "4");
+    }
+
+    #[test]
+    fn wake() {
+        let event_loop = LocalEventLoop::new();
+        event_loop.wake();
+        event_loop.wait(None);
+        assert!(!event_loop.run_ready(None).unwrap());
+        event_loop.wait(Some(Duration::from_millis(1)));
+
+        let ran = Rc::new(Cell::new(false));
+        let task = ran.clone();
+        event_loop.spawn(Box::pin(async move { task.set(true) }));
+        assert!(event_loop.run_ready(None).unwrap());
+        assert!(ran.get());
+        assert!(event_loop.is_idle());
+    }
+}
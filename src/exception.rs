@@ -4,6 +4,1163 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use super::JSException;
+use super::{JSContext, JSError, JSErrorKind, JSException, JSObject, JSString, JSValue};
+use crate::sys;
+use std::any::Any;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::error::Error;
+use std::fmt;
+use std::ptr;
 
-impl JSException {}
+/// What was read from a thrown value. This is boxed to keep results
+/// with a `JSException` small.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Details {
+    name: Option<String>,
+    message: Option<String>,
+    stack: Option<String>,
+    line: Option<u32>,
+    column: Option<u32>,
+    source_url: Option<String>,
+    rust_backtrace: Option<String>,
+}
+
+/// The property that holds the Rust backtrace of an error that was
+/// created for a Rust error or panic.
+const RUST_BACKTRACE: &str = "rustBacktrace";
+
+impl JSException {
+    /// Wraps a value that was thrown in `ctx`, and reads the details of
+    /// the error from it.
+    ///
+    /// A null `raw` stands for an exception without a value, which is
+    /// turned into `undefined`.
+    pub(crate) fn from_raw(ctx: sys::JSContextRef, raw: sys::JSValueRef) -> Self {
+        let raw = if raw.is_null() {
+            unsafe { sys::JSValueMakeUndefined(ctx) }
+        } else {
+            raw
+        };
+        let mut details = Details::default();
+        if unsafe { sys::JSValueIsObject(ctx, raw) } {
+            let object = raw as sys::JSObjectRef;
+            details.name = string_property(ctx, object, "name");
+            details.message = string_property(ctx, object, "message");
+            details.stack = string_property(ctx, object, "stack");
+            details.line = number_property(ctx, object, "line");
+            details.column = number_property(ctx, object, "column");
+            details.source_url = string_property(ctx, object, "sourceURL");
+            details.rust_backtrace = string_property(ctx, object, RUST_BACKTRACE);
+        } else {
+            // A thrown primitive, such as a string, is its own message.
+            details.message = to_string(ctx, raw);
+        }
+        JSException {
+            value: JSValue { raw, ctx },
+            details: Box::new(details),
+        }
+    }
+
+    /// Moves the column of an error that was thrown on the first line
+    /// of `source_url` back by `prefix` columns, for scripts whose first
+    /// line starts with `prefix` columns of code added by the host.
+    pub(crate) fn without_prefix(mut self, source_url: &str, prefix: u32) -> Self {
+        let details = &mut self.details;
+        if details.line == Some(1) && details.source_url.as_deref() == Some(source_url) {
+            details.column = details.column.map(|c| c.saturating_sub(prefix).max(1));
+        }
+        self
+    }
+
+    /// Creates an exception that throws `value`.
+    ///
+    /// Returning the exception from a native callback throws `value`
+    /// into the calling script.
+    pub fn new(value: JSValue) -> Self {
+        JSException::from_raw(value.ctx, value.raw)
+    }
+
+    /// Creates an exception that throws a new `Error`.
+    ///
+    /// * `ctx`: The execution context to create the `Error` in.
+    /// * `message`: The message of the `Error`.
+    pub fn error(ctx: &JSContext, message: &str) -> Self {
+        error_in(ctx.raw, message)
+    }
+
+    /// Creates an exception that throws a new `TypeError`, as is done
+    /// for arguments of the wrong type.
+    ///
+    /// * `ctx`: The execution context to create the `TypeError` in.
+    /// * `message`: The message of the `TypeError`.
+    ///
+    /// Like the other constructors for standard errors, this calls the
+    /// constructor of the class, as found on the global object.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let e = JSException::type_error(&ctx, "Expected a string");
+    /// assert!(matches!(e.kind(), JSErrorKind::TypeError));
+    /// assert_eq!(e.to_string(), "TypeError: Expected a string");
+    /// ```
+    pub fn type_error(ctx: &JSContext, message: &str) -> Self {
+        native_error(ctx, "TypeError", message)
+    }
+
+    /// Creates an exception that throws a new `RangeError`, as is done
+    /// for arguments outside of the allowed range.
+    ///
+    /// * `ctx`: The execution context to create the `RangeError` in.
+    /// * `message`: The message of the `RangeError`.
+    pub fn range_error(ctx: &JSContext, message: &str) -> Self {
+        native_error(ctx, "RangeError", message)
+    }
+
+    /// Creates an exception that throws a new `SyntaxError`, as is done
+    /// for source text that can not be parsed.
+    ///
+    /// * `ctx`: The execution context to create the `SyntaxError` in.
+    /// * `message`: The message of the `SyntaxError`.
+    pub fn syntax_error(ctx: &JSContext, message: &str) -> Self {
+        native_error(ctx, "SyntaxError", message)
+    }
+
+    /// Creates an exception that throws a new `ReferenceError`, as is
+    /// done for references to names that do not exist.
+    ///
+    /// * `ctx`: The execution context to create the `ReferenceError` in.
+    /// * `message`: The message of the `ReferenceError`.
+    pub fn reference_error(ctx: &JSContext, message: &str) -> Self {
+        native_error(ctx, "ReferenceError", message)
+    }
+
+    /// Creates an exception that throws a new `EvalError`.
+    ///
+    /// * `ctx`: The execution context to create the `EvalError` in.
+    /// * `message`: The message of the `EvalError`.
+    pub fn eval_error(ctx: &JSContext, message: &str) -> Self {
+        native_error(ctx, "EvalError", message)
+    }
+
+    /// Creates an exception that throws a JavaScript `Error` for a Rust
+    /// error.
+    ///
+    /// * `ctx`: The execution context to create the `Error` in.
+    /// * `error`: The Rust error.
+    ///
+    /// The message of the `Error` is the `Display` output of `error`.
+    /// If `error` has a [`source`], the source becomes the `cause` of the
+    /// `Error`, and so on down the chain of sources. A `JSException`
+    /// found in the chain is used as it is, so the original JavaScript
+    /// value is kept.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let e = "x".parse::<u32>().unwrap_err();
+    /// let e = JSException::from_error(&ctx, e);
+    /// assert_eq!(e.name(), Some("Error"));
+    /// assert_eq!(e.message(), Some("invalid digit found in string"));
+    /// ```
+    ///
+    /// Like for panics of native functions, a Rust backtrace is captured
+    /// if backtraces are enabled. See [`report`].
+    ///
+    /// [`source`]: https://doc.rust-lang.org/std/error/trait.Error.html#method.source
+    /// [`report`]: #method.report
+    pub fn from_error<E: Error + 'static>(ctx: &JSContext, error: E) -> Self {
+        JSException::from_dyn_error(ctx.raw, &error)
+    }
+
+    /// Converts an `anyhow::Error` into a JavaScript `Error`, like
+    /// [`from_error`] does for other errors.
+    ///
+    /// A [`JSError`] in the chain of causes is turned back into the
+    /// value that was thrown, if it comes from the same context group.
+    ///
+    /// Requires the `anyhow` feature.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let e = evaluate_script(&ctx, "throw new RangeError('Too far')", None, "test.js", 1)
+    ///     .unwrap_err();
+    /// let e = anyhow::Error::new(JSError::from(e)).context("while running the job");
+    /// let e = JSException::from_anyhow(&ctx, &e);
+    /// assert_eq!(e.name(), Some("RangeError"));
+    /// ```
+    ///
+    /// [`from_error`]: #method.from_error
+    /// [`JSError`]: struct.JSError.html
+    #[cfg(feature = "anyhow")]
+    pub fn from_anyhow(ctx: &JSContext, error: &anyhow::Error) -> Self {
+        match JSError::find(error).and_then(|e| e.to_exception(ctx)) {
+            Some(e) => e,
+            None => JSException::from_dyn_error(ctx.raw, error.as_ref()),
+        }
+    }
+
+    fn from_dyn_error(ctx: sys::JSContextRef, error: &(dyn Error + 'static)) -> Self {
+        let value = error_value(ctx, error);
+        if original_value(ctx, error).is_none() {
+            attach_backtrace(ctx, value);
+        }
+        JSException::from_raw(ctx, value)
+    }
+
+    /// Gets the value that was thrown.
+    ///
+    /// This is usually an `Error`, but scripts can throw any value.
+    pub fn value(&self) -> &JSValue {
+        &self.value
+    }
+
+    /// Converts the exception into the value that was thrown.
+    pub fn into_value(self) -> JSValue {
+        self.value
+    }
+
+    /// Throws the value that was thrown again, from a native function
+    /// that runs in `ctx`.
+    ///
+    /// The value itself is thrown, rather than a new error with the
+    /// same message, so scripts that catch it get the very object that
+    /// was thrown first. This lets native functions look at the errors
+    /// of the scripts they call and pass on the ones they do not handle.
+    ///
+    /// `ctx` must be in the same context group as the context that the
+    /// exception was caught in.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// fn call_ignoring_range_errors(
+    ///     ctx: &JSContext,
+    ///     f: &JSObject,
+    /// ) -> Result<JSValue, JSException> {
+    ///     match f.call_as_function(None, &[]) {
+    ///         Err(e) if e.name() == Some("RangeError") => Ok(JSValue::new_undefined(ctx)),
+    ///         Err(e) => e.rethrow(ctx),
+    ///         r => r,
+    ///     }
+    /// }
+    ///
+    /// let ctx = JSContext::default();
+    /// let script = "globalThis.e = new TypeError(); () => { throw e; }";
+    /// let f = evaluate_script(&ctx, script, None, "test.js", 1)
+    ///     .expect("evaluated")
+    ///     .as_object()
+    ///     .expect("function");
+    /// let e = call_ignoring_range_errors(&ctx, &f).unwrap_err();
+    /// let original = evaluate_script(&ctx, "e", None, "test.js", 1).expect("evaluated");
+    /// assert_eq!(e.value(), &original);
+    /// ```
+    pub fn rethrow<T>(self, ctx: &JSContext) -> Result<T, JSException> {
+        Err(JSException {
+            value: JSValue {
+                raw: self.value.raw,
+                ctx: ctx.raw,
+            },
+            details: self.details,
+        })
+    }
+
+    /// Classifies the value that was thrown by its error class.
+    ///
+    /// The classes are looked up on the global object of the context,
+    /// once for each call.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let e = evaluate_script(&ctx, "undefinedVariable", None, "test.js", 1).unwrap_err();
+    /// assert!(matches!(e.kind(), JSErrorKind::ReferenceError));
+    ///
+    /// let e = evaluate_script(&ctx, "throw 'oops'", None, "test.js", 1).unwrap_err();
+    /// assert!(matches!(e.kind(), JSErrorKind::NonError(_)));
+    /// ```
+    pub fn kind(&self) -> JSErrorKind {
+        let ctx = self.value.ctx;
+        let raw = self.value.raw;
+        let is_instance = |class: &str| unsafe {
+            let global = sys::JSContextGetGlobalObject(ctx);
+            let constructor = property(ctx, global, class);
+            !constructor.is_null()
+                && sys::JSValueIsObject(ctx, constructor)
+                && sys::JSValueIsInstanceOfConstructor(
+                    ctx,
+                    raw,
+                    constructor as sys::JSObjectRef,
+                    ptr::null_mut(),
+                )
+        };
+        if !unsafe { sys::JSValueIsObject(ctx, raw) } {
+            JSErrorKind::NonError(JSValue { raw, ctx })
+        } else if is_instance("TypeError") {
+            JSErrorKind::TypeError
+        } else if is_instance("RangeError") {
+            JSErrorKind::RangeError
+        } else if is_instance("SyntaxError") {
+            JSErrorKind::SyntaxError
+        } else if is_instance("ReferenceError") {
+            JSErrorKind::ReferenceError
+        } else if is_instance("EvalError") {
+            JSErrorKind::EvalError
+        } else if is_instance("Error") {
+            JSErrorKind::Custom(JSObject::from_raw(ctx, raw as sys::JSObjectRef))
+        } else {
+            JSErrorKind::NonError(JSValue { raw, ctx })
+        }
+    }
+
+    /// Gets the `name` of the error, such as `TypeError`.
+    ///
+    /// Returns `None` if the thrown value has no `name` string.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let e = evaluate_script(&ctx, "null.x", None, "test.js", 1).unwrap_err();
+    /// assert_eq!(e.name(), Some("TypeError"));
+    /// ```
+    pub fn name(&self) -> Option<&str> {
+        self.details.name.as_deref()
+    }
+
+    /// Gets the `message` of the error.
+    ///
+    /// If a primitive value was thrown, such as with `throw "oops"`, the
+    /// message is that value converted to a string.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let e = evaluate_script(&ctx, "throw new RangeError('Too far')", None, "test.js", 1)
+    ///     .unwrap_err();
+    /// assert_eq!(e.message(), Some("Too far"));
+    ///
+    /// let e = evaluate_script(&ctx, "throw 'oops'", None, "test.js", 1).unwrap_err();
+    /// assert_eq!(e.message(), Some("oops"));
+    /// ```
+    pub fn message(&self) -> Option<&str> {
+        self.details.message.as_deref()
+    }
+
+    /// Gets the stack trace that was recorded when the error was created.
+    ///
+    /// The trace has one line per frame, in the form
+    /// `function@sourceURL:line:column`.
+    pub fn stack(&self) -> Option<&str> {
+        self.details.stack.as_deref()
+    }
+
+    /// Gets the line, starting at 1, where the error was thrown.
+    pub fn line(&self) -> Option<u32> {
+        self.details.line
+    }
+
+    /// Gets the column, starting at 1, where the error was thrown.
+    pub fn column(&self) -> Option<u32> {
+        self.details.column
+    }
+
+    /// Gets the URL of the script in which the error was thrown, as
+    /// passed to [`evaluate_script`].
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let e = evaluate_script(&ctx, "\n\nundefinedFunction()", None, "app.js", 1)
+    ///     .unwrap_err();
+    /// assert_eq!(e.source_url(), Some("app.js"));
+    /// assert_eq!(e.line(), Some(3));
+    /// ```
+    ///
+    /// [`evaluate_script`]: fn.evaluate_script.html
+    pub fn source_url(&self) -> Option<&str> {
+        self.details.source_url.as_deref()
+    }
+
+    /// Gets the Rust backtrace of the point where a Rust error or a panic
+    /// was turned into this error.
+    ///
+    /// Backtraces are captured by [`from_error`] and when a native
+    /// function panics, if they are enabled with the `RUST_BACKTRACE` or
+    /// `RUST_LIB_BACKTRACE` environment variables. They are kept in the
+    /// `rustBacktrace` property of the error, so they are still there
+    /// when the error has passed through scripts and is caught again.
+    ///
+    /// [`from_error`]: #method.from_error
+    pub fn rust_backtrace(&self) -> Option<&str> {
+        self.details.rust_backtrace.as_deref()
+    }
+
+    /// Formats a report of the exception for logs, with the JavaScript
+    /// stack and the Rust backtrace, if there are any.
+    ///
+    /// The first line is the same as the `Display` output.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let e = evaluate_script(&ctx, "function f() { null.x; }\nf()", None, "app.js", 1)
+    ///     .unwrap_err();
+    /// let report = e.report();
+    /// assert!(report.starts_with(&e.to_string()));
+    /// assert!(report.contains("JavaScript stack:\nf@app.js:1:"));
+    /// ```
+    pub fn report(&self) -> String {
+        self.details.report()
+    }
+}
+
+impl Details {
+    fn report(&self) -> String {
+        let mut report = self.to_string();
+        if let Some(stack) = self.stack.as_deref().filter(|stack| !stack.is_empty()) {
+            report.push_str("\n\nJavaScript stack:\n");
+            report.push_str(stack);
+        }
+        if let Some(backtrace) = &self.rust_backtrace {
+            report.push_str("\n\nRust backtrace:\n");
+            report.push_str(backtrace);
+        }
+        report
+    }
+}
+
+impl fmt::Display for Details {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.name.as_deref(), self.message.as_deref()) {
+            (Some(name), Some("")) | (Some(name), None) => f.write_str(name)?,
+            (Some(name), Some(message)) => write!(f, "{}: {}", name, message)?,
+            (None, Some(message)) => f.write_str(message)?,
+            (None, None) => f.write_str("Uncaught exception")?,
+        }
+        // The first frame is where the error was created, which points
+        // at the culprit more precisely than the throwing line does.
+        let frame = self
+            .stack
+            .as_deref()
+            .and_then(|stack| stack.lines().next())
+            .filter(|frame| !frame.is_empty());
+        if let Some(frame) = frame {
+            write!(f, " at {}", frame)
+        } else if let (Some(url), Some(line)) = (&self.source_url, self.line) {
+            write!(f, " at {}:{}", url, line)?;
+            match self.column {
+                Some(column) => write!(f, ":{}", column),
+                None => Ok(()),
+            }
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Converts the error of a `Result` into a `JSException`.
+///
+/// This lets native callbacks use `?` on fallible Rust calls, throwing
+/// an `Error` into the calling script when they fail, as described for
+/// [`JSException::from_error`].
+///
+/// ```
+/// # use javascriptcore::*;
+/// fn parse_port(ctx: &JSContext, s: &str) -> Result<JSValue, JSException> {
+///     let port: u16 = s.parse().or_throw(ctx)?;
+///     Ok(JSValue::new_number(ctx, f64::from(port)))
+/// }
+///
+/// let ctx = JSContext::default();
+/// assert!(parse_port(&ctx, "8080").is_ok());
+/// let e = parse_port(&ctx, "http").unwrap_err();
+/// assert_eq!(e.message(), Some("invalid digit found in string"));
+/// ```
+///
+/// [`JSException::from_error`]: struct.JSException.html#method.from_error
+pub trait OrThrow<T> {
+    /// Converts the error, if any, into an exception in `ctx`.
+    fn or_throw(self, ctx: &JSContext) -> Result<T, JSException>;
+}
+
+impl<T, E: Error + 'static> OrThrow<T> for Result<T, E> {
+    fn or_throw(self, ctx: &JSContext) -> Result<T, JSException> {
+        self.map_err(|e| JSException::from_error(ctx, e))
+    }
+}
+
+/// Creates an exception that throws a new instance of the error class
+/// named `class`.
+///
+/// If the class can not be constructed, because a script has replaced
+/// it, the exception is the one that constructing it threw.
+fn native_error(ctx: &JSContext, class: &str, message: &str) -> JSException {
+    let mut e: sys::JSValueRef = ptr::null_mut();
+    let raw = unsafe {
+        let global = sys::JSContextGetGlobalObject(ctx.raw);
+        let constructor = property(ctx.raw, global, class);
+        if constructor.is_null() || !sys::JSValueIsObject(ctx.raw, constructor) {
+            return error_in(ctx.raw, &format!("{} is not a constructor", class));
+        }
+        let message = sys::JSValueMakeString(ctx.raw, JSString::from(message).raw);
+        sys::JSObjectCallAsConstructor(
+            ctx.raw,
+            constructor as sys::JSObjectRef,
+            1,
+            &message,
+            &mut e,
+        )
+    };
+    JSException::from_raw(ctx.raw, if raw.is_null() { e } else { raw })
+}
+
+/// Creates an exception for a panic of a native callback, with the
+/// panic message if there is one.
+pub(crate) fn panic_error(ctx: sys::JSContextRef, payload: Box<dyn Any + Send>) -> JSException {
+    let message = if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.as_str()
+    } else {
+        "Box<dyn Any>"
+    };
+    let e = error_in(ctx, &format!("A native function panicked: {}", message));
+    attach_backtrace(ctx, e.value.raw);
+    JSException::from_raw(ctx, e.value.raw)
+}
+
+/// Throws `e` from a native callback by storing its value in the
+/// exception out-parameter that JavaScriptCore passed, if any.
+///
+/// The callback must then return its failure value, such as `NULL`.
+pub(crate) unsafe fn throw(exception: *mut sys::JSValueRef, e: JSException) {
+    if !exception.is_null() {
+        *exception = e.value.raw;
+    }
+}
+
+/// Records the current Rust backtrace on an error, if backtraces are
+/// enabled.
+fn attach_backtrace(ctx: sys::JSContextRef, error: sys::JSValueRef) {
+    let backtrace = Backtrace::capture();
+    if backtrace.status() != BacktraceStatus::Captured {
+        return;
+    }
+    let backtrace = JSString::from(backtrace.to_string().trim_end());
+    unsafe {
+        let backtrace = sys::JSValueMakeString(ctx, backtrace.raw);
+        set_hidden(ctx, error, RUST_BACKTRACE, backtrace);
+    }
+}
+
+/// Sets a property that is not enumerable on a new error, which can not
+/// throw.
+unsafe fn set_hidden(
+    ctx: sys::JSContextRef,
+    error: sys::JSValueRef,
+    name: &str,
+    value: sys::JSValueRef,
+) {
+    sys::JSObjectSetProperty(
+        ctx,
+        error as sys::JSObjectRef,
+        JSString::from(name).raw,
+        value,
+        sys::kJSPropertyAttributeDontEnum,
+        ptr::null_mut(),
+    );
+}
+
+/// Creates the value to throw for `error`, with its sources as causes.
+fn error_value(ctx: sys::JSContextRef, error: &(dyn Error + 'static)) -> sys::JSValueRef {
+    if let Some(value) = original_value(ctx, error) {
+        return value;
+    }
+    let value = error_in(ctx, &error.to_string()).value.raw;
+    if let Some(source) = error.source() {
+        // Like `cause` set by the `Error` constructor, this is not
+        // enumerable.
+        unsafe { set_hidden(ctx, value, "cause", error_value(ctx, source)) };
+    }
+    value
+}
+
+/// Gets the thrown value that `error` was created from, if it is a
+/// `JSException` or a `JSError` that can be used in `ctx`.
+fn original_value(
+    ctx: sys::JSContextRef,
+    error: &(dyn Error + 'static),
+) -> Option<sys::JSValueRef> {
+    if let Some(e) = error.downcast_ref::<JSException>() {
+        Some(e.value.raw)
+    } else {
+        error
+            .downcast_ref::<JSError>()
+            .filter(|e| e.usable_in(ctx))
+            .map(|e| e.value)
+    }
+}
+
+/// Formats the exception like JavaScript formats an error, followed by
+/// where it was thrown.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let ctx = JSContext::default();
+/// let e = evaluate_script(&ctx, "\n\nnull.x", None, "app.js", 1).unwrap_err();
+/// assert!(e.to_string().starts_with("TypeError: "));
+/// assert!(e.to_string().contains(" at global code@app.js:3:"));
+/// ```
+impl fmt::Display for JSException {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.details.fmt(f)
+    }
+}
+
+impl Error for JSException {}
+
+// The thrown value is only handed out by `to_exception`, which requires
+// a context of the same group, and so the thread that uses it. Dropping
+// a `JSError` on another thread only unprotects the value, for which
+// JavaScriptCore takes the API lock.
+unsafe impl Send for JSError {}
+unsafe impl Sync for JSError {}
+
+impl JSError {
+    /// Gets the exception back, with the value that was thrown, so that
+    /// it can be inspected or thrown again.
+    ///
+    /// Returns `None` if `ctx` is not in the context group of the
+    /// exception, as the value can not be used there.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let e = evaluate_script(&ctx, "throw 42", None, "test.js", 1).unwrap_err();
+    /// let e = JSError::from(e);
+    ///
+    /// let value = e.to_exception(&ctx).expect("same context").into_value();
+    /// assert_eq!(value.as_number().expect("number"), 42.0);
+    /// assert!(e.to_exception(&JSContext::default()).is_none());
+    /// ```
+    pub fn to_exception(&self, ctx: &JSContext) -> Option<JSException> {
+        if self.usable_in(ctx.raw) {
+            Some(JSException {
+                value: JSValue {
+                    raw: self.value,
+                    ctx: ctx.raw,
+                },
+                details: self.details.clone(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Finds the first `JSError` in the chain of causes of an
+    /// `anyhow::Error`.
+    ///
+    /// Requires the `anyhow` feature.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// fn run(ctx: &JSContext) -> anyhow::Result<()> {
+    ///     evaluate_script(ctx, "null.x", None, "app.js", 1).map_err(JSError::from)?;
+    ///     Ok(())
+    /// }
+    ///
+    /// let ctx = JSContext::default();
+    /// let e = run(&ctx).map_err(|e| e.context("job failed")).unwrap_err();
+    /// assert_eq!(JSError::find(&e).and_then(|e| e.name()), Some("TypeError"));
+    /// ```
+    #[cfg(feature = "anyhow")]
+    pub fn find(error: &anyhow::Error) -> Option<&JSError> {
+        error.chain().find_map(|e| e.downcast_ref::<JSError>())
+    }
+
+    /// Gets the `name` of the error. See [`JSException::name`].
+    ///
+    /// [`JSException::name`]: struct.JSException.html#method.name
+    pub fn name(&self) -> Option<&str> {
+        self.details.name.as_deref()
+    }
+
+    /// Gets the `message` of the error. See [`JSException::message`].
+    ///
+    /// [`JSException::message`]: struct.JSException.html#method.message
+    pub fn message(&self) -> Option<&str> {
+        self.details.message.as_deref()
+    }
+
+    /// Gets the stack trace that was recorded when the error was created.
+    pub fn stack(&self) -> Option<&str> {
+        self.details.stack.as_deref()
+    }
+
+    /// Gets the line, starting at 1, where the error was thrown.
+    pub fn line(&self) -> Option<u32> {
+        self.details.line
+    }
+
+    /// Gets the column, starting at 1, where the error was thrown.
+    pub fn column(&self) -> Option<u32> {
+        self.details.column
+    }
+
+    /// Gets the URL of the script in which the error was thrown.
+    pub fn source_url(&self) -> Option<&str> {
+        self.details.source_url.as_deref()
+    }
+
+    /// Gets the Rust backtrace of the point where a Rust error or a panic
+    /// was turned into this error. See [`JSException::rust_backtrace`].
+    ///
+    /// [`JSException::rust_backtrace`]: struct.JSException.html#method.rust_backtrace
+    pub fn rust_backtrace(&self) -> Option<&str> {
+        self.details.rust_backtrace.as_deref()
+    }
+
+    /// Formats a report of the error for logs. See [`JSException::report`].
+    ///
+    /// [`JSException::report`]: struct.JSException.html#method.report
+    pub fn report(&self) -> String {
+        self.details.report()
+    }
+
+    fn usable_in(&self, ctx: sys::JSContextRef) -> bool {
+        unsafe { sys::JSContextGetGroup(ctx) == sys::JSContextGetGroup(self.ctx) }
+    }
+}
+
+impl From<JSException> for JSError {
+    fn from(e: JSException) -> Self {
+        unsafe {
+            let ctx = sys::JSGlobalContextRetain(sys::JSContextGetGlobalContext(e.value.ctx));
+            sys::JSValueProtect(ctx, e.value.raw);
+            JSError {
+                ctx,
+                value: e.value.raw,
+                details: e.details,
+            }
+        }
+    }
+}
+
+impl Drop for JSError {
+    fn drop(&mut self) {
+        unsafe {
+            sys::JSValueUnprotect(self.ctx, self.value);
+            sys::JSGlobalContextRelease(self.ctx);
+        }
+    }
+}
+
+impl fmt::Debug for JSError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JSError")
+            .field("details", &self.details)
+            .finish()
+    }
+}
+
+impl fmt::Display for JSError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.details.fmt(f)
+    }
+}
+
+impl Error for JSError {}
+
+/// Reads a property of a thrown object without running into another
+/// exception.
+fn property(ctx: sys::JSContextRef, object: sys::JSObjectRef, name: &str) -> sys::JSValueRef {
+    // An exception thrown by a getter is dropped, as the caller is
+    // already handling one.
+    let mut e: sys::JSValueRef = ptr::null_mut();
+    let value = unsafe { sys::JSObjectGetProperty(ctx, object, JSString::from(name).raw, &mut e) };
+    if e.is_null() {
+        value
+    } else {
+        ptr::null()
+    }
+}
+
+fn string_property(ctx: sys::JSContextRef, object: sys::JSObjectRef, name: &str) -> Option<String> {
+    let value = property(ctx, object, name);
+    if !value.is_null() && unsafe { sys::JSValueIsString(ctx, value) } {
+        to_string(ctx, value)
+    } else {
+        None
+    }
+}
+
+fn number_property(ctx: sys::JSContextRef, object: sys::JSObjectRef, name: &str) -> Option<u32> {
+    let value = property(ctx, object, name);
+    if !value.is_null() && unsafe { sys::JSValueIsNumber(ctx, value) } {
+        let n = unsafe { sys::JSValueToNumber(ctx, value, ptr::null_mut()) };
+        if n >= 0.0 && n <= u32::MAX as f64 {
+            return Some(n as u32);
+        }
+    }
+    None
+}
+
+fn to_string(ctx: sys::JSContextRef, value: sys::JSValueRef) -> Option<String> {
+    let mut e: sys::JSValueRef = ptr::null_mut();
+    let raw = unsafe { sys::JSValueToStringCopy(ctx, value, &mut e) };
+    if raw.is_null() {
+        None
+    } else {
+        Some(JSString { raw }.to_string_lossy())
+    }
+}
+
+/// Creates an exception whose value is a new `Error` with `message`.
+pub(crate) fn error(ctx: &JSContext, message: &str) -> JSException {
+    error_in(ctx.raw, message)
+}
+
+/// Creates an exception whose value is a new `Error` with `message`,
+/// in the context that `ctx` refers to.
+pub(crate) fn error_in(ctx: sys::JSContextRef, message: &str) -> JSException {
+    let mut e: sys::JSValueRef = ptr::null_mut();
+    let raw = unsafe {
+        let message = sys::JSValueMakeString(ctx, JSString::from(message).raw);
+        sys::JSObjectMakeError(ctx, 1, &message, &mut e)
+    };
+    // Creating the error fails only if the engine runs out of memory,
+    // which in turn throws an error.
+    JSException::from_raw(ctx, if raw.is_null() { e } else { raw })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        evaluate_script, garbage_collect, JSContext, JSError, JSErrorKind, JSException, JSValue,
+    };
+    use crate::function::new_function;
+    use std::error::Error;
+    use std::fmt;
+    use std::num::ParseIntError;
+
+    #[test]
+    fn error_details() {
+        let ctx = JSContext::default();
+        let e = evaluate_script(
+            &ctx,
+            "function f() {\n  throw new TypeError('Bad input');\n}\nf();",
+            None,
+            "input.js",
+            1,
+        )
+        .unwrap_err();
+        assert!(e.value().is_object());
+        assert_eq!(e.name(), Some("TypeError"));
+        assert_eq!(e.message(), Some("Bad input"));
+        assert_eq!(e.source_url(), Some("input.js"));
+        assert_eq!(e.line(), Some(2));
+        assert!(e.column().is_some());
+        assert!(e.stack().unwrap().starts_with("f@input.js:2"));
+    }
+
+    #[test]
+    fn thrown_primitives() {
+        let ctx = JSContext::default();
+        let e = evaluate_script(&ctx, "throw 42", None, "test.js", 1).unwrap_err();
+        assert_eq!(e.into_value().as_number().unwrap(), 42.0);
+
+        let e = evaluate_script(&ctx, "throw 42", None, "test.js", 1).unwrap_err();
+        assert_eq!(e.name(), None);
+        assert_eq!(e.message(), Some("42"));
+        assert_eq!(e.stack(), None);
+
+        let e = evaluate_script(&ctx, "throw Symbol()", None, "test.js", 1).unwrap_err();
+        assert_eq!(e.message(), None);
+    }
+
+    #[test]
+    fn throwing_getters() {
+        let ctx = JSContext::default();
+        let e = evaluate_script(
+            &ctx,
+            "throw { get name() { throw 1; }, message: 'ok' }",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap_err();
+        assert_eq!(e.name(), None);
+        assert_eq!(e.message(), Some("ok"));
+    }
+
+    #[test]
+    fn display() {
+        let ctx = JSContext::default();
+        let e = evaluate_script(
+            &ctx,
+            "function f() {\n  throw new TypeError('Bad input');\n}\nf();",
+            None,
+            "input.js",
+            1,
+        )
+        .unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with("TypeError: Bad input at f@input.js:2:"));
+
+        let e = evaluate_script(&ctx, "throw 'oops'", None, "test.js", 1).unwrap_err();
+        assert_eq!(e.to_string(), "oops");
+
+        let e = evaluate_script(&ctx, "throw new Error()", None, "test.js", 1).unwrap_err();
+        assert!(e.to_string().starts_with("Error at "));
+
+        let e = evaluate_script(&ctx, "throw Symbol()", None, "test.js", 1).unwrap_err();
+        assert_eq!(e.to_string(), "Uncaught exception");
+    }
+
+    #[test]
+    fn from_error() {
+        #[derive(Debug)]
+        struct Outer(ParseIntError);
+
+        impl fmt::Display for Outer {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("Invalid configuration")
+            }
+        }
+
+        impl Error for Outer {
+            fn source(&self) -> Option<&(dyn Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let ctx = JSContext::default();
+        let e = Outer("x".parse::<u32>().unwrap_err());
+        let e = JSException::from_error(&ctx, e);
+        assert_eq!(e.message(), Some("Invalid configuration"));
+        let error = e.value().as_object().unwrap();
+        let cause = error.get_property("cause").unwrap();
+        let cause = JSException::new(cause);
+        assert_eq!(cause.message(), Some("invalid digit found in string"));
+        assert!(!error.property_names().any(|name| name == "cause"));
+
+        // Exceptions keep their identity.
+        let thrown = evaluate_script(
+            &ctx,
+            "globalThis.e = new Error(); throw e",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap_err();
+        let e = JSException::from_error(&ctx, thrown);
+        let original = evaluate_script(&ctx, "e", None, "test.js", 1).unwrap();
+        assert_eq!(e.value(), &original);
+    }
+
+    #[test]
+    fn kinds() {
+        let ctx = JSContext::default();
+        let kind = |script: &str| {
+            evaluate_script(&ctx, script, None, "test.js", 1)
+                .unwrap_err()
+                .kind()
+        };
+        assert!(matches!(kind("null.x"), JSErrorKind::TypeError));
+        assert!(matches!(kind("new Array(-1)"), JSErrorKind::RangeError));
+        assert!(matches!(kind("("), JSErrorKind::SyntaxError));
+        assert!(matches!(kind("x"), JSErrorKind::ReferenceError));
+        assert!(matches!(
+            kind("throw new EvalError()"),
+            JSErrorKind::EvalError
+        ));
+        assert!(matches!(
+            kind("class E extends TypeError {}; throw new E()"),
+            JSErrorKind::TypeError
+        ));
+        match kind("class AppError extends Error {}; throw new AppError('x')") {
+            JSErrorKind::Custom(e) => {
+                let name = e.get_property("constructor").unwrap();
+                let name = name.as_object().unwrap().get_property("name").unwrap();
+                assert_eq!(name.as_string().unwrap(), "AppError");
+            }
+            k => panic!("unexpected kind: {:?}", k),
+        }
+        assert!(matches!(
+            kind("throw { message: 'x' }"),
+            JSErrorKind::NonError(_)
+        ));
+        match kind("throw 42") {
+            JSErrorKind::NonError(v) => assert_eq!(v.as_number().unwrap(), 42.0),
+            k => panic!("unexpected kind: {:?}", k),
+        }
+    }
+
+    #[test]
+    fn standard_errors() {
+        let ctx = JSContext::default();
+        let check = |e: JSException, name: &str| {
+            assert_eq!(e.name(), Some(name));
+            assert_eq!(e.message(), Some("Bad"));
+            ctx.global_object()
+                .set_property("e", e.into_value())
+                .unwrap();
+            let script = format!("e instanceof {}", name);
+            let r = evaluate_script(&ctx, script.as_str(), None, "test.js", 1).unwrap();
+            assert!(r.as_boolean());
+        };
+        check(JSException::error(&ctx, "Bad"), "Error");
+        check(JSException::type_error(&ctx, "Bad"), "TypeError");
+        check(JSException::range_error(&ctx, "Bad"), "RangeError");
+        check(JSException::syntax_error(&ctx, "Bad"), "SyntaxError");
+        check(JSException::reference_error(&ctx, "Bad"), "ReferenceError");
+        check(JSException::eval_error(&ctx, "Bad"), "EvalError");
+
+        evaluate_script(&ctx, "RangeError = 1", None, "test.js", 1).unwrap();
+        let e = JSException::range_error(&ctx, "Bad");
+        assert!(matches!(e.kind(), JSErrorKind::Custom(_)));
+    }
+
+    #[test]
+    fn report() {
+        let ctx = JSContext::default();
+        let e = evaluate_script(&ctx, "throw 'oops'", None, "test.js", 1).unwrap_err();
+        assert_eq!(e.report(), "oops");
+
+        ctx.global_object()
+            .set_property("rustBacktrace", JSValue::new_string(&ctx, "0: main"))
+            .unwrap();
+        let e = evaluate_script(
+            &ctx,
+            "var e = new Error('Failed'); e.rustBacktrace = rustBacktrace; throw e",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap_err();
+        assert_eq!(e.rust_backtrace(), Some("0: main"));
+        let report = e.report();
+        assert!(report.starts_with("Error: Failed at global code@test.js:1:"));
+        assert!(report.contains("\n\nJavaScript stack:\nglobal code@test.js:1:"));
+        assert!(report.ends_with("\n\nRust backtrace:\n0: main"));
+    }
+
+    #[test]
+    fn rethrow() {
+        let ctx = JSContext::default();
+        let filter = new_function(&ctx, |ctx, _this, args| {
+            let f = args[0].as_object()?;
+            match f.call_as_function(None, &[]) {
+                Err(e) if e.message() == Some("ignored") => Ok(JSValue::new_undefined(ctx)),
+                Err(e) => e.rethrow(ctx),
+                r => r,
+            }
+        });
+        ctx.global_object()
+            .set_property("filter", filter.into())
+            .unwrap();
+        let r = evaluate_script(
+            &ctx,
+            "const original = new Error('passed on');
+             let caught;
+             try { filter(() => { throw original; }); } catch (e) { caught = e; }
+             filter(() => { throw new Error('ignored'); });
+             caught === original",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert!(r.as_boolean());
+    }
+
+    #[test]
+    fn js_error() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<JSError>();
+
+        #[derive(Debug)]
+        enum AppError {
+            Script(JSError),
+        }
+
+        impl fmt::Display for AppError {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("The script failed")
+            }
+        }
+
+        impl Error for AppError {
+            fn source(&self) -> Option<&(dyn Error + 'static)> {
+                match self {
+                    AppError::Script(e) => Some(e),
+                }
+            }
+        }
+
+        let ctx = JSContext::default();
+        let thrown = evaluate_script(
+            &ctx,
+            "globalThis.e = new TypeError('Bad input'); throw e",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap_err();
+        let text = thrown.to_string();
+        let e = JSError::from(thrown);
+        assert_eq!(e.name(), Some("TypeError"));
+        assert_eq!(e.message(), Some("Bad input"));
+        assert_eq!(e.source_url(), Some("test.js"));
+        assert_eq!(e.to_string(), text);
+
+        // The value survives a garbage collection and keeps its identity.
+        let e = std::thread::spawn(move || e).join().unwrap();
+        garbage_collect(&ctx);
+        let original = evaluate_script(&ctx, "e", None, "test.js", 1).unwrap();
+        assert_eq!(e.to_exception(&ctx).unwrap().value(), &original);
+
+        // Rust errors that wrap it are thrown as the original value.
+        let e = JSException::from_error(&ctx, AppError::Script(e));
+        let cause = e
+            .value()
+            .as_object()
+            .unwrap()
+            .get_property("cause")
+            .unwrap();
+        assert_eq!(cause, original);
+
+        let other = JSContext::default();
+        let e = JSError::from(e);
+        assert!(e.to_exception(&other).is_none());
+    }
+
+    #[cfg(feature = "anyhow")]
+    #[test]
+    fn anyhow() {
+        let ctx = JSContext::default();
+        let thrown = evaluate_script(
+            &ctx,
+            "globalThis.e = new Error(); throw e",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap_err();
+        let e = anyhow::Error::new(JSError::from(thrown))
+            .context("Loading the plugin")
+            .context("Starting up");
+        assert!(JSError::find(&e).is_some());
+        let original = evaluate_script(&ctx, "e", None, "test.js", 1).unwrap();
+        assert_eq!(JSException::from_anyhow(&ctx, &e).value(), &original);
+
+        // Errors from another group are converted instead.
+        let other = JSContext::default();
+        let e = JSException::from_anyhow(&other, &e);
+        assert_eq!(e.message(), Some("Starting up"));
+
+        let e = JSException::from_anyhow(&ctx, &anyhow::anyhow!("Disk full"));
+        assert_eq!(e.message(), Some("Disk full"));
+    }
+}
@@ -0,0 +1,551 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::base::evaluate_script;
+use super::function::new_function;
+use super::object::new_array;
+use super::typedarray::{new_uint8_array, typed_array_bytes};
+use super::{IntoJsResult, JSContext, JSException, JSPromise, JSString, JSValue, Spawner};
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+
+/// Defines `fetch`, `Headers` and `Response` on top of the native
+/// functions that it is called with.
+const GLUE: &str = r#"(function (global, send, encode, decode) {
+    'use strict';
+
+    const LIST = Symbol('list');
+    const BODY = Symbol('body');
+
+    class Headers {
+        constructor(init) {
+            this[LIST] = new Map();
+            if (init === undefined || init === null) {
+                return;
+            }
+            if (init instanceof Headers) {
+                init.forEach((value, name) => this.append(name, value));
+            } else if (typeof init[Symbol.iterator] === 'function') {
+                for (const [name, value] of init) {
+                    this.append(name, value);
+                }
+            } else {
+                for (const name of Object.keys(init)) {
+                    this.append(name, init[name]);
+                }
+            }
+        }
+
+        append(name, value) {
+            name = String(name).toLowerCase();
+            value = String(value);
+            const old = this[LIST].get(name);
+            this[LIST].set(name, old === undefined ? value : `${old}, ${value}`);
+        }
+
+        delete(name) {
+            this[LIST].delete(String(name).toLowerCase());
+        }
+
+        get(name) {
+            const value = this[LIST].get(String(name).toLowerCase());
+            return value === undefined ? null : value;
+        }
+
+        has(name) {
+            return this[LIST].has(String(name).toLowerCase());
+        }
+
+        set(name, value) {
+            this[LIST].set(String(name).toLowerCase(), String(value));
+        }
+
+        forEach(callback, thisArg) {
+            for (const [name, value] of this) {
+                callback.call(thisArg, value, name, this);
+            }
+        }
+
+        *entries() {
+            yield* [...this[LIST]].sort(([a], [b]) => (a < b ? -1 : a > b ? 1 : 0));
+        }
+
+        *keys() {
+            for (const [name] of this) {
+                yield name;
+            }
+        }
+
+        *values() {
+            for (const [, value] of this) {
+                yield value;
+            }
+        }
+
+        [Symbol.iterator]() {
+            return this.entries();
+        }
+    }
+
+    class Response {
+        constructor(body = null, init = {}) {
+            this.status = init.status === undefined ? 200 : Number(init.status);
+            this.statusText = init.statusText === undefined ? '' : String(init.statusText);
+            this.headers = new Headers(init.headers);
+            this.url = '';
+            this.bodyUsed = false;
+            this[BODY] = body === null || body instanceof Uint8Array ? body : toBytes(body);
+        }
+
+        get ok() {
+            return this.status >= 200 && this.status < 300;
+        }
+
+        arrayBuffer() {
+            if (this.bodyUsed) {
+                return Promise.reject(new TypeError('The body has already been read'));
+            }
+            this.bodyUsed = true;
+            const body = this[BODY] === null ? new Uint8Array(0) : this[BODY];
+            return Promise.resolve(body.slice().buffer);
+        }
+
+        text() {
+            return this.arrayBuffer().then((buffer) => decode(new Uint8Array(buffer)));
+        }
+
+        json() {
+            return this.text().then(JSON.parse);
+        }
+    }
+
+    const toBytes = (body) => {
+        if (body === undefined || body === null) {
+            return null;
+        }
+        if (ArrayBuffer.isView(body)) {
+            return new Uint8Array(body.buffer, body.byteOffset, body.byteLength);
+        }
+        if (body instanceof ArrayBuffer) {
+            return new Uint8Array(body);
+        }
+        return encode(String(body));
+    };
+
+    const fetch = (input, init = {}) => new Promise((resolve) => {
+        const isRequest = typeof input === 'object' && input !== null && 'url' in input;
+        const url = String(isRequest ? input.url : input);
+        const options = Object.assign({}, isRequest ? input : {}, init);
+        const method = String(options.method === undefined ? 'GET' : options.method).toUpperCase();
+        const headers = [];
+        new Headers(options.headers).forEach((value, name) => headers.push(name, value));
+        resolve(send(url, method, headers, toBytes(options.body))
+            .then(([status, statusText, headerList, body]) => {
+                const response = new Response(body, { status, statusText });
+                for (let i = 0; i < headerList.length; i += 2) {
+                    response.headers.append(headerList[i], headerList[i + 1]);
+                }
+                response.url = url;
+                return response;
+            }));
+    });
+
+    for (const [name, value] of [['fetch', fetch], ['Headers', Headers], ['Response', Response]]) {
+        Object.defineProperty(global, name, { value, writable: true, configurable: true });
+    }
+})"#;
+
+/// A request made by a script through `fetch`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FetchRequest {
+    /// The URL to fetch, exactly as given by the script.
+    ///
+    /// Relative URLs are not resolved, as a context has no base URL.
+    pub url: String,
+    /// The request method in upper case, such as `GET` or `POST`.
+    pub method: String,
+    /// The request headers as lower-case name and value pairs, sorted
+    /// by name.
+    pub headers: Vec<(String, String)>,
+    /// The request body, if any. String bodies are encoded as UTF-8.
+    pub body: Option<Vec<u8>>,
+}
+
+/// The response to a [`FetchRequest`].
+///
+/// [`FetchRequest`]: struct.FetchRequest.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FetchResponse {
+    /// The HTTP status code. Defaults to `200`.
+    pub status: u16,
+    /// The HTTP status message, such as `OK`.
+    pub status_text: String,
+    /// The response headers as name and value pairs.
+    pub headers: Vec<(String, String)>,
+    /// The response body.
+    pub body: Vec<u8>,
+}
+
+impl Default for FetchResponse {
+    fn default() -> Self {
+        FetchResponse {
+            status: 200,
+            status_text: String::new(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+}
+
+/// The response that a [`FetchHandler`] gives to a request, once it is
+/// ready.
+///
+/// An error rejects the promise that `fetch` returned with a
+/// `TypeError`, like `fetch` does for network errors, whose message is
+/// that of the error.
+///
+/// [`FetchHandler`]: trait.FetchHandler.html
+pub type FetchFuture = Pin<Box<dyn Future<Output = Result<FetchResponse, Box<dyn Error>>>>>;
+
+/// Performs the requests that scripts make through `fetch`.
+///
+/// The handler is given each request, and returns a future of its
+/// response, so that it can wait for an HTTP client such as `reqwest`
+/// or `hyper`, or answer from a sandboxed resolver right away. The
+/// future is run by the [`Spawner`] that `fetch` was installed with, on
+/// the thread that owns the context.
+///
+/// [`Spawner`]: trait.Spawner.html
+pub trait FetchHandler {
+    /// Starts performing `request`, and returns the future of its
+    /// response.
+    ///
+    /// The promise that `fetch` returned to the script settles when the
+    /// future completes, and is rejected if the future is dropped before
+    /// then.
+    fn fetch(&self, request: FetchRequest) -> FetchFuture;
+}
+
+/// The outcome of a request, which settles the promise of `send` in the
+/// glue code.
+struct Reply(Result<FetchResponse, Box<dyn Error>>);
+
+impl IntoJsResult for Reply {
+    fn into_js_result(self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        let response = match self.0 {
+            Ok(response) => response,
+            Err(e) => return Err(JSException::type_error(ctx, &e.to_string())),
+        };
+        let headers: Vec<JSValue> = response
+            .headers
+            .iter()
+            .flat_map(|(name, value)| [name, value])
+            .map(|s| JSValue::new_string(ctx, JSString::from_str_lossless(s)))
+            .collect();
+        let reply = [
+            JSValue::new_number(ctx, f64::from(response.status)),
+            JSValue::new_string(ctx, JSString::from_str_lossless(&response.status_text)),
+            new_array(ctx, &headers)?.into(),
+            new_uint8_array(ctx, &response.body)?.into(),
+        ];
+        Ok(new_array(ctx, &reply)?.into())
+    }
+}
+
+/// Converts the arguments of the glue code's `send` into a request.
+fn request_from_arguments(arguments: &[JSValue]) -> Result<FetchRequest, JSException> {
+    let headers = arguments[2].as_object()?;
+    let count = headers.get_property("length")?.as_number()? as u32;
+    let headers = (0..count)
+        .step_by(2)
+        .map(|i| {
+            Ok((
+                headers
+                    .get_property_at_index(i)?
+                    .as_string()?
+                    .to_string_lossy(),
+                headers
+                    .get_property_at_index(i + 1)?
+                    .as_string()?
+                    .to_string_lossy(),
+            ))
+        })
+        .collect::<Result<Vec<_>, JSException>>()?;
+    let body = &arguments[3];
+    let body = if body.is_null() {
+        None
+    } else {
+        Some(unsafe { typed_array_bytes(&body.as_object()?)? }.to_vec())
+    };
+    Ok(FetchRequest {
+        url: arguments[0].as_string()?.to_string_lossy(),
+        method: arguments[1].as_string()?.to_string_lossy(),
+        headers,
+        body,
+    })
+}
+
+/// Installs `fetch`, along with the `Headers` and `Response` classes that
+/// it uses, into a context.
+///
+/// * `ctx`: The execution context to install `fetch` into.
+/// * `handler`: Performs the requests.
+/// * `spawner`: Runs the futures of the responses.
+///
+/// `fetch` accepts a URL, or a request-like object with a `url`
+/// property, and the `method`, `headers` and `body` options. The
+/// `Response` that it resolves to has the `status`, `statusText`, `ok`,
+/// `headers` and `url` properties and can be read with `arrayBuffer()`,
+/// `text()` and `json()`. Other options, such as `signal` or `redirect`,
+/// are ignored and left to the handler's own policy.
+///
+/// ```
+/// # use javascriptcore::*;
+/// # use std::cell::RefCell;
+/// # use std::future::Future;
+/// # use std::pin::Pin;
+/// # use std::rc::Rc;
+/// # use std::task::{Context, Waker};
+/// #[derive(Clone, Default)]
+/// struct Queue(Rc<RefCell<Vec<Pin<Box<dyn Future<Output = ()>>>>>>);
+///
+/// impl Spawner for Queue {
+///     fn spawn(&self, future: Pin<Box<dyn Future<Output = ()>>>) {
+///         self.0.borrow_mut().push(future);
+///     }
+/// }
+///
+/// struct Hello;
+///
+/// impl FetchHandler for Hello {
+///     fn fetch(&self, request: FetchRequest) -> FetchFuture {
+///         Box::pin(async move {
+///             if request.url != "https://example.com/hello" {
+///                 return Err("Not found".into());
+///             }
+///             Ok(FetchResponse {
+///                 body: b"Hello!".to_vec(),
+///                 ..Default::default()
+///             })
+///         })
+///     }
+/// }
+///
+/// let ctx = JSContext::default();
+/// let queue = Queue::default();
+/// install_fetch(&ctx, Hello, queue.clone()).expect("fetch installed");
+/// evaluate_script(
+///     &ctx,
+///     "fetch('https://example.com/hello').then(r => r.text()).then(t => globalThis.text = t)",
+///     None,
+///     "test.js",
+///     1,
+/// )
+/// .expect("fetch started");
+///
+/// for mut future in queue.0.take() {
+///     assert!(future.as_mut().poll(&mut Context::from_waker(Waker::noop())).is_ready());
+/// }
+/// let text = evaluate_script(&ctx, "text", None, "test.js", 1).expect("text");
+/// assert_eq!(text.as_string().expect("string"), "Hello!");
+/// ```
+pub fn install_fetch<H, S>(ctx: &JSContext, handler: H, spawner: S) -> Result<(), JSException>
+where
+    H: FetchHandler + 'static,
+    S: Spawner + 'static,
+{
+    let send = new_function(ctx, move |ctx, _this, arguments| {
+        let request = request_from_arguments(arguments)?;
+        let response = handler.fetch(request);
+        let promise = JSPromise::from_future(ctx, async move { Reply(response.await) }, &spawner)?;
+        Ok(promise.into())
+    });
+    let encode = new_function(ctx, |ctx, _this, arguments| {
+        let text = arguments[0].as_string()?.to_string_lossy();
+        Ok(new_uint8_array(ctx, text.as_bytes())?.into())
+    });
+    let decode = new_function(ctx, |ctx, _this, arguments| {
+        let array = arguments[0].as_object()?;
+        let text = String::from_utf8_lossy(unsafe { typed_array_bytes(&array)? });
+        Ok(JSValue::new_string(ctx, JSString::from_str_lossless(&text)))
+    });
+
+    let glue = evaluate_script(ctx, GLUE, None, "fetch.js", 1)?.as_object()?;
+    glue.call_as_function(
+        None,
+        &[
+            ctx.global_object().into(),
+            send.into(),
+            encode.into(),
+            decode.into(),
+        ],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSContext, Spawner};
+    use super::{install_fetch, FetchFuture, FetchHandler, FetchRequest, FetchResponse};
+    use std::cell::RefCell;
+    use std::error::Error;
+    use std::future::{self, Future};
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::task::{Context, Poll, Waker};
+
+    type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+    #[derive(Clone, Default)]
+    struct Queue(Rc<RefCell<Vec<Task>>>);
+
+    impl Spawner for Queue {
+        fn spawn(&self, future: Task) {
+            self.0.borrow_mut().push(future);
+        }
+    }
+
+    impl Queue {
+        /// Polls every future once, and keeps those that are pending.
+        fn run(&self) {
+            let mut pending = vec![];
+            for mut future in self.0.take() {
+                let mut cx = Context::from_waker(Waker::noop());
+                if future.as_mut().poll(&mut cx).is_pending() {
+                    pending.push(future);
+                }
+            }
+            self.0.borrow_mut().extend(pending);
+        }
+    }
+
+    type Reply = Rc<RefCell<Option<Result<FetchResponse, Box<dyn Error>>>>>;
+
+    /// Records requests, leaving it to the test to reply.
+    #[derive(Clone, Default)]
+    struct Pending(Rc<RefCell<Vec<(FetchRequest, Reply)>>>);
+
+    impl FetchHandler for Pending {
+        fn fetch(&self, request: FetchRequest) -> FetchFuture {
+            let reply = Reply::default();
+            self.0.borrow_mut().push((request, reply.clone()));
+            Box::pin(future::poll_fn(move |_cx| {
+                match reply.borrow_mut().take() {
+                    Some(reply) => Poll::Ready(reply),
+                    None => Poll::Pending,
+                }
+            }))
+        }
+    }
+
+    fn eval(ctx: &JSContext, script: &str) -> String {
+        evaluate_script(ctx, script, None, "test.js", 1)
+            .unwrap()
+            .as_string()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn request() {
+        let ctx = JSContext::default();
+        let pending = Pending::default();
+        install_fetch(&ctx, pending.clone(), Queue::default()).unwrap();
+
+        eval(
+            &ctx,
+            "fetch({url: 'https://example.com/'}, {
+                 method: 'post',
+                 headers: {'X-B': '1', 'x-a': '2'},
+                 body: new Uint8Array([1, 2, 3]),
+             });
+             fetch('/text', {body: 'h\u{e9}'});
+             ''",
+        );
+        let requests = pending.0.borrow();
+        assert_eq!(
+            requests[0].0,
+            FetchRequest {
+                url: "https://example.com/".to_owned(),
+                method: "POST".to_owned(),
+                headers: vec![
+                    ("x-a".to_owned(), "2".to_owned()),
+                    ("x-b".to_owned(), "1".to_owned()),
+                ],
+                body: Some(vec![1, 2, 3]),
+            }
+        );
+        assert_eq!(requests[1].0.method, "GET");
+        assert_eq!(requests[1].0.body, Some("h\u{e9}".as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn response() {
+        let ctx = JSContext::default();
+        let pending = Pending::default();
+        let queue = Queue::default();
+        install_fetch(&ctx, pending.clone(), queue.clone()).unwrap();
+
+        eval(
+            &ctx,
+            "var result;
+             fetch('/data').then(async r => {
+                 result = [r.status, r.ok, r.statusText, r.headers.get('Content-Type'),
+                           r.url, (await r.json()).x, r.bodyUsed].join();
+             });
+             ''",
+        );
+        queue.run();
+        assert_eq!(eval(&ctx, "typeof result"), "undefined");
+        let (_, reply) = pending.0.borrow_mut().remove(0);
+        *reply.borrow_mut() = Some(Ok(FetchResponse {
+            status: 201,
+            status_text: "Created".to_owned(),
+            headers: vec![("content-type".to_owned(), "application/json".to_owned())],
+            body: b"{\"x\": 1}".to_vec(),
+        }));
+        queue.run();
+        assert_eq!(
+            eval(&ctx, "result"),
+            "201,true,Created,application/json,/data,1,true"
+        );
+
+        // Scripts can make responses of their own, such as for mocks.
+        eval(
+            &ctx,
+            "new Response('h\u{e9}', {status: 404}).text().then(t => result = [t, t.length].join()); ''",
+        );
+        assert_eq!(eval(&ctx, "result"), "h\u{e9},2");
+    }
+
+    #[test]
+    fn failures() {
+        let ctx = JSContext::default();
+        let pending = Pending::default();
+        let queue = Queue::default();
+        install_fetch(&ctx, pending.clone(), queue.clone()).unwrap();
+
+        eval(
+            &ctx,
+            "var errors = [];
+             fetch('/a').catch(e => errors.push(e.name + ': ' + e.message));
+             fetch('/b').catch(e => errors.push(e.name + ': ' + e.message));
+             ''",
+        );
+        let (_, a) = pending.0.borrow_mut().remove(0);
+        *a.borrow_mut() = Some(Err("Connection refused".into()));
+        queue.run();
+        assert_eq!(
+            eval(&ctx, "errors.join('; ')"),
+            "TypeError: Connection refused"
+        );
+
+        // The promise of a request whose future is dropped is rejected.
+        queue.0.borrow_mut().clear();
+        assert_eq!(eval(&ctx, "errors.length.toString()"), "2");
+    }
+}
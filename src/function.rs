@@ -0,0 +1,466 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::builder::start_engine;
+use super::exception::{error, panic_error, throw};
+use super::{JSContext, JSException, JSObject, JSValue};
+use crate::sys;
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::rc::Rc;
+use std::slice;
+use std::sync::OnceLock;
+
+/// The Rust side of a native function.
+///
+/// Callbacks are given the context, the `this` object and the arguments
+/// of the call. Returning an error throws the exception's value, and a
+/// panic is caught and thrown as an `Error`.
+pub(crate) type Callback =
+    dyn Fn(&JSContext, &JSObject, &[JSValue]) -> Result<JSValue, JSException>;
+
+/// A class that is shared by all threads.
+pub(crate) struct Class(pub(crate) sys::JSClassRef);
+
+// A class is immutable once created and may be used from any thread.
+unsafe impl Send for Class {}
+unsafe impl Sync for Class {}
+
+/// The class of all native functions.
+///
+/// Instances hold their `Callback` as private data.
+static CLASS: OnceLock<Class> = OnceLock::new();
+
+pub(crate) fn class() -> sys::JSClassRef {
+    CLASS
+        .get_or_init(|| {
+            let definition = sys::JSClassDefinition {
+                attributes: sys::kJSClassAttributeNoAutomaticPrototype,
+                className: c"Function".as_ptr(),
+                finalize: Some(finalize),
+                callAsFunction: Some(call),
+                ..Default::default()
+            };
+            start_engine();
+            Class(unsafe { sys::JSClassCreate(&definition) })
+        })
+        .0
+}
+
+/// Creates a function that invokes `callback` when called from JavaScript.
+///
+/// The callback is dropped when the function is garbage collected. The
+/// function's prototype is `Function.prototype`, so `call`, `apply` and
+/// `bind` can be used on it like on any other function.
+pub(crate) fn new_function<F>(ctx: &JSContext, callback: F) -> JSObject
+where
+    F: Fn(&JSContext, &JSObject, &[JSValue]) -> Result<JSValue, JSException> + 'static,
+{
+    let data: Box<Box<Callback>> = Box::new(Box::new(callback));
+    unsafe {
+        let raw = sys::JSObjectMake(ctx.raw, class(), Box::into_raw(data) as *mut c_void);
+        // Any function will do to find the `Function.prototype` of this
+        // context, even if a script has replaced the global `Function`.
+        let intrinsic = sys::JSObjectMakeFunctionWithCallback(ctx.raw, ptr::null_mut(), None);
+        sys::JSObjectSetPrototype(ctx.raw, raw, sys::JSObjectGetPrototype(ctx.raw, intrinsic));
+        JSObject::from_raw(ctx.raw, raw)
+    }
+}
+
+impl JSObject {
+    /// Creates a function that calls a Rust closure.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `callback`: The closure to call. It is given the context, the
+    ///   `this` object and the arguments of the call.
+    ///
+    /// The closure can keep state of its own, so host functions need not
+    /// rely on global variables. It is dropped when the function is
+    /// garbage collected.
+    ///
+    /// Returning an error throws the exception's value into the calling
+    /// script, and a panic is caught and thrown as an `Error`. If the
+    /// closure calls back into a script that calls the function again
+    /// while the closure is still running, that inner call throws an
+    /// `Error`, as the closure can not be borrowed twice.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let mut count = 0;
+    /// let counter = JSObject::new_function(&ctx, move |ctx, _this, _arguments| {
+    ///     count += 1;
+    ///     Ok(JSValue::new_number(ctx, f64::from(count)))
+    /// });
+    /// ctx.global_object().set_property("next", counter.into()).expect("set");
+    ///
+    /// let r = evaluate_script(&ctx, "next(); next()", None, "test.js", 1).expect("evaluated");
+    /// assert_eq!(r.as_number().expect("number"), 2.0);
+    /// ```
+    pub fn new_function<F>(ctx: &JSContext, callback: F) -> JSObject
+    where
+        F: FnMut(&JSContext, &JSObject, &[JSValue]) -> Result<JSValue, JSException> + 'static,
+    {
+        let callback = RefCell::new(callback);
+        new_function(ctx, move |ctx, this, arguments| {
+            match callback.try_borrow_mut() {
+                Ok(mut callback) => callback(ctx, this, arguments),
+                Err(_) => Err(error(
+                    ctx,
+                    "The function can not be called while it is running",
+                )),
+            }
+        })
+    }
+}
+
+/// Saves the globals called `names`, and returns a function that
+/// restores them, deleting those that did not exist.
+const SAVE_GLOBALS: &str = r#"(function (global, ...names) {
+    'use strict';
+
+    const getOwnPropertyDescriptor = Object.getOwnPropertyDescriptor;
+    const defineProperty = Object.defineProperty;
+    const deleteProperty = Reflect.deleteProperty;
+    const saved = [];
+    for (const name of names) {
+        saved.push([name, getOwnPropertyDescriptor(global, name)]);
+    }
+    return () => {
+        for (let i = saved.length - 1; i >= 0; i--) {
+            const [name, descriptor] = saved[i];
+            if (descriptor === undefined) {
+                deleteProperty(global, name);
+            } else {
+                defineProperty(global, name, descriptor);
+            }
+        }
+    };
+})"#;
+
+/// A function that [`JSContext::with_functions`] installs for the
+/// duration of a closure.
+///
+/// It is given the context, the state of the scope and the arguments of
+/// the call.
+///
+/// [`JSContext::with_functions`]: struct.JSContext.html#method.with_functions
+pub type ScopedFunction<S> = fn(&JSContext, &mut S, &[JSValue]) -> Result<JSValue, JSException>;
+
+/// Calls a `ScopedFunction<S>` that was erased to `function`, with the
+/// state that was erased to `state`.
+type ScopedCall =
+    unsafe fn(*const (), &JSContext, *mut (), &[JSValue]) -> Result<JSValue, JSException>;
+
+unsafe fn call_scoped<S>(
+    function: *const (),
+    ctx: &JSContext,
+    state: *mut (),
+    arguments: &[JSValue],
+) -> Result<JSValue, JSException> {
+    let function = mem::transmute::<*const (), ScopedFunction<S>>(function);
+    function(ctx, &mut *(state as *mut S), arguments)
+}
+
+/// Puts the state back into its slot once a scoped function returns.
+struct Lend<'a>(&'a Cell<*mut ()>, *mut ());
+
+impl Drop for Lend<'_> {
+    fn drop(&mut self) {
+        self.0.set(self.1);
+    }
+}
+
+/// Takes the functions of a scope away again, and restores the globals
+/// that they replaced, even if its closure panics.
+struct Scope<'a> {
+    ctx: &'a JSContext,
+    slot: Rc<Cell<*mut ()>>,
+    /// The function made by `SAVE_GLOBALS`, which is protected until the
+    /// scope ends.
+    restore: JSObject,
+}
+
+impl Drop for Scope<'_> {
+    fn drop(&mut self) {
+        self.slot.set(ptr::null_mut());
+        // Restoring can only fail if a script made a global that it
+        // replaces unconfigurable, in which case the global is left as
+        // it is.
+        let _ = self.restore.call_as_function(None, &[]);
+        unsafe { sys::JSValueUnprotect(self.ctx.raw, self.restore.raw) };
+    }
+}
+
+impl JSContext {
+    /// Installs functions that use borrowed state as globals for the
+    /// duration of a closure.
+    ///
+    /// * `state`: The state that the functions are given.
+    /// * `functions`: The names of the globals and their functions.
+    /// * `body`: The closure to run, usually evaluating scripts.
+    ///
+    /// Unlike the closures of [`JSObject::new_function`], the state need
+    /// not be `'static`, so it can borrow local variables without
+    /// wrapping them in an `Rc` or `Arc`. When `body` returns or panics,
+    /// the globals that the functions replaced are restored, those that
+    /// did not exist before are deleted, and functions that scripts kept a
+    /// reference to throw an `Error` from then on. So does a function
+    /// that is called while another function of the scope is running,
+    /// as the state can not be borrowed twice.
+    ///
+    /// Returns the result of `body`, or an exception if a global could
+    /// not be set, in which case `body` is not run.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let mut lines: Vec<String> = Vec::new();
+    /// let print: ScopedFunction<Vec<String>> = |ctx, lines, arguments| {
+    ///     lines.push(String::from_js(ctx, &arguments[0])?);
+    ///     Ok(JSValue::new_undefined(ctx))
+    /// };
+    /// ctx.with_functions(&mut lines, &[("print", print)], |ctx| {
+    ///     evaluate_script(ctx, "print('a'); print('b')", None, "test.js", 1)
+    /// })
+    /// .expect("installed")
+    /// .expect("evaluated");
+    /// assert_eq!(lines, ["a", "b"]);
+    /// ```
+    ///
+    /// [`JSObject::new_function`]: struct.JSObject.html#method.new_function
+    pub fn with_functions<S, F, R>(
+        &self,
+        state: &mut S,
+        functions: &[(&str, ScopedFunction<S>)],
+        body: F,
+    ) -> Result<R, JSException>
+    where
+        F: FnOnce(&JSContext) -> R,
+    {
+        let global = self.global_object();
+        let save = self.hooks.function(self, SAVE_GLOBALS, "function.js")?;
+        let mut arguments = vec![JSValue {
+            raw: global.raw,
+            ctx: self.raw,
+        }];
+        arguments.extend(
+            functions
+                .iter()
+                .map(|&(name, _)| JSValue::new_string(self, name)),
+        );
+        let restore = save.call_as_function(None, &arguments)?.as_object()?;
+        unsafe { sys::JSValueProtect(self.raw, restore.raw) };
+        let scope = Scope {
+            ctx: self,
+            slot: Rc::new(Cell::new(state as *mut S as *mut ())),
+            restore,
+        };
+        for &(name, function) in functions {
+            let slot = scope.slot.clone();
+            let function = function as *const ();
+            let call: ScopedCall = call_scoped::<S>;
+            let f = new_function(self, move |ctx, _this, arguments| {
+                // The slot is empty while a function of the scope is
+                // running, and once the scope has ended.
+                let state = slot.replace(ptr::null_mut());
+                if state.is_null() {
+                    return Err(error(ctx, "The function is not available here"));
+                }
+                let _lend = Lend(&slot, state);
+                unsafe { call(function, ctx, state, arguments) }
+            });
+            global.set_property(name, f.into())?;
+        }
+        Ok(body(self))
+    }
+}
+
+/// Wraps the arguments passed to a callback.
+pub(crate) unsafe fn arguments_from_raw(
+    ctx: sys::JSContextRef,
+    argument_count: usize,
+    arguments: *const sys::JSValueRef,
+) -> Vec<JSValue> {
+    if argument_count == 0 {
+        return Vec::new();
+    }
+    slice::from_raw_parts(arguments, argument_count)
+        .iter()
+        .map(|&raw| JSValue { raw, ctx })
+        .collect()
+}
+
+unsafe extern "C" fn call(
+    ctx: sys::JSContextRef,
+    function: sys::JSObjectRef,
+    this_object: sys::JSObjectRef,
+    argument_count: usize,
+    arguments: *const sys::JSValueRef,
+    exception: *mut sys::JSValueRef,
+) -> sys::JSValueRef {
+    // Unwinding into JavaScriptCore would abort the process, so panics
+    // are turned into exceptions here.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let callback = &*(sys::JSObjectGetPrivate(function) as *const Box<Callback>);
+        let ctx = JSContext::from_context_ref(ctx);
+        let this = JSObject::from_raw(ctx.raw, this_object);
+        let arguments = arguments_from_raw(ctx.raw, argument_count, arguments);
+        callback(&ctx, &this, &arguments)
+    }));
+    match result.unwrap_or_else(|payload| Err(panic_error(ctx, payload))) {
+        Ok(value) => value.raw,
+        Err(e) => {
+            throw(exception, e);
+            ptr::null()
+        }
+    }
+}
+
+unsafe extern "C" fn finalize(object: sys::JSObjectRef) {
+    let data = sys::JSObjectGetPrivate(object) as *mut Box<Callback>;
+    if !data.is_null() {
+        // A panic while dropping the callback can not be reported from a
+        // finalizer, so it is only kept from unwinding.
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(data))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSContext, JSObject, JSValue};
+    use super::{new_function, ScopedFunction};
+    use std::rc::Rc;
+
+    #[test]
+    fn panics_are_thrown() {
+        let ctx = JSContext::default();
+        let f = new_function(&ctx, |ctx, _this, arguments| {
+            if arguments.is_empty() {
+                panic!("No arguments");
+            }
+            Ok(JSValue::new_undefined(ctx))
+        });
+        ctx.global_object().set_property("f", f.into()).unwrap();
+
+        let r = evaluate_script(
+            &ctx,
+            "try { f(); } catch (e) { e instanceof Error && e.message }",
+            None,
+            "test.js",
+            1,
+        );
+        let message = r.unwrap().as_string().unwrap().to_string();
+        assert!(message.contains("No arguments"));
+
+        // The function still works after a panic.
+        let r = evaluate_script(&ctx, "f(1)", None, "test.js", 1);
+        assert!(r.unwrap().is_undefined());
+    }
+
+    #[test]
+    fn closures() {
+        let ctx = JSContext::default();
+        let state = Rc::new(());
+        let captured = state.clone();
+        let mut calls = vec![];
+        let f = JSObject::new_function(&ctx, move |ctx, this, arguments| {
+            let _ = &captured;
+            calls.push(arguments.len());
+            if calls.len() == 2 {
+                // Calling the function from inside itself fails.
+                let inner = this.get_property("f")?.as_object()?;
+                assert!(inner.call_as_function(None, &[]).is_err());
+            }
+            Ok(JSValue::new_number(ctx, calls.iter().sum::<usize>() as f64))
+        });
+        ctx.global_object().set_property("f", f.into()).unwrap();
+
+        let r = evaluate_script(&ctx, "f(1, 2); f.call(globalThis)", None, "test.js", 1);
+        assert_eq!(r.unwrap().as_number().unwrap(), 2.0);
+        assert_eq!(Rc::strong_count(&state), 2);
+
+        // The closure is dropped with the function.
+        drop(ctx);
+        assert_eq!(Rc::strong_count(&state), 1);
+    }
+
+    #[test]
+    fn with_functions() {
+        let ctx = JSContext::default();
+        let mut total = 0.0;
+        let add: ScopedFunction<f64> = |ctx, total, arguments| {
+            *total += arguments[0].as_number()?;
+            Ok(JSValue::new_number(ctx, *total))
+        };
+        let call: ScopedFunction<f64> = |_ctx, _total, arguments| {
+            // The state is still borrowed by this call.
+            arguments[0].as_object()?.call_as_function(None, &[])
+        };
+        let r = ctx
+            .with_functions(&mut total, &[("add", add), ("call", call)], |ctx| {
+                evaluate_script(
+                    ctx,
+                    "globalThis.kept = add; add(1); add(2);
+                     try { call(() => add(3)); } catch (e) { e.message }",
+                    None,
+                    "test.js",
+                    1,
+                )
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(r.as_string().unwrap(), "The function is not available here");
+        assert_eq!(total, 3.0);
+
+        let r = evaluate_script(&ctx, "typeof add", None, "test.js", 1).unwrap();
+        assert_eq!(r.as_string().unwrap(), "undefined");
+        let r = evaluate_script(&ctx, "kept(1)", None, "test.js", 1);
+        assert_eq!(
+            r.unwrap_err().message(),
+            Some("The function is not available here")
+        );
+    }
+
+    #[test]
+    fn with_functions_restores_globals() {
+        let ctx = JSContext::default();
+        let mut calls = Vec::new();
+        let name: ScopedFunction<Vec<&str>> = |ctx, calls, _arguments| {
+            calls.push("outer");
+            Ok(JSValue::new_string(ctx, "outer"))
+        };
+        let inner: ScopedFunction<Vec<&str>> = |ctx, calls, _arguments| {
+            calls.push("inner");
+            Ok(JSValue::new_string(ctx, "inner"))
+        };
+        evaluate_script(&ctx, "var name = 'host';", None, "test.js", 1).unwrap();
+        let r = ctx
+            .with_functions(&mut calls, &[("name", name)], |ctx| {
+                let mut inner_calls = Vec::new();
+                let r = ctx
+                    .with_functions(&mut inner_calls, &[("name", inner)], |ctx| {
+                        evaluate_script(ctx, "name()", None, "test.js", 1)
+                    })
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(r.as_string().unwrap(), "inner");
+                // The function of the outer scope is back.
+                evaluate_script(ctx, "name()", None, "test.js", 1)
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(r.as_string().unwrap(), "outer");
+        assert_eq!(calls, ["outer"]);
+
+        // So is the binding of the host.
+        let r = evaluate_script(&ctx, "name", None, "test.js", 1).unwrap();
+        assert_eq!(r.as_string().unwrap(), "host");
+    }
+}
@@ -0,0 +1,502 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::base::evaluate_script;
+use super::function::new_function;
+use super::watchdog::Watchdog;
+use super::{JSClass, JSContext, JSException, JSObject, JSValue};
+use crate::sys;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::ptr;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, Weak};
+
+/// The hooks of all contexts for which a `JSContext` exists, keyed by
+/// global context address.
+static HOOKS: Mutex<BTreeMap<usize, Weak<Hooks>>> = Mutex::new(BTreeMap::new());
+
+/// Wraps `queueMicrotask` so that exceptions thrown by microtasks are
+/// reported instead of being dropped.
+const WRAP_QUEUE_MICROTASK: &str = r#"(function (global, report) {
+    'use strict';
+
+    const queueMicrotask = global.queueMicrotask;
+    if (typeof queueMicrotask !== 'function') {
+        return;
+    }
+    const apply = Reflect.apply;
+    Object.defineProperty(global, 'queueMicrotask', {
+        value: {
+            queueMicrotask(callback) {
+                if (typeof callback !== 'function') {
+                    return apply(queueMicrotask, global, [callback]);
+                }
+                return apply(queueMicrotask, global, [() => {
+                    try {
+                        apply(callback, undefined, []);
+                    } catch (e) {
+                        report(e);
+                    }
+                }]);
+            },
+        }.queueMicrotask,
+        writable: true,
+        configurable: true,
+    });
+})"#;
+
+/// A handler for exceptions that no script caught.
+type ExceptionHandler = dyn Fn(JSException);
+
+/// A handler for rejected promises that have no rejection handler.
+#[cfg(feature = "unhandled-rejections")]
+type RejectionHandler = dyn Fn(JSObject, JSException);
+
+/// The state of a context that is shared by all `JSContext`s for it.
+///
+/// Hooks exist for as long as any `JSContext` for the context does. The
+/// context can not be garbage collected before then, so its address
+/// identifies it.
+///
+/// The hooks also hold the watchdog of the context's group, so that
+/// wrapping a context, as each native callback does, takes only one
+/// lookup in a registry.
+pub(crate) struct Hooks {
+    ctx: usize,
+    watchdog: Arc<Watchdog>,
+    /// How many evaluations of the host are running in the context.
+    depth: Cell<usize>,
+    exception_handler: RefCell<Option<Rc<ExceptionHandler>>>,
+    microtasks_wrapped: Cell<bool>,
+    #[cfg(feature = "unhandled-rejections")]
+    rejection_handler: RefCell<Option<Rc<RejectionHandler>>>,
+    #[cfg(feature = "unhandled-rejections")]
+    rejections_tracked: Cell<bool>,
+    /// The classes registered with `register_class` and their
+    /// constructors, which are protected from garbage collection, by
+    /// class name.
+    classes: RefCell<HashMap<String, (JSClass, sys::JSObjectRef)>>,
+    /// The objects, mostly functions, that scripts of the host evaluate
+    /// to, which are protected from garbage collection, by source.
+    functions: RefCell<HashMap<&'static str, sys::JSObjectRef>>,
+    /// The function that restores the pristine state of the context, or
+    /// null. It is protected from garbage collection.
+    pristine: Cell<sys::JSObjectRef>,
+}
+
+// Hooks are only used by code that uses the context, and so must not
+// run on more than one thread at a time. Keeping them in the registry
+// only touches the reference counts, which are atomic.
+unsafe impl Send for Hooks {}
+unsafe impl Sync for Hooks {}
+
+impl Hooks {
+    /// Gets the hooks of the global context `ctx`, creating them if
+    /// needed.
+    pub(crate) fn for_context(ctx: sys::JSGlobalContextRef) -> Arc<Hooks> {
+        let mut hooks = HOOKS.lock().unwrap();
+        if let Some(h) = hooks.get(&(ctx as usize)).and_then(Weak::upgrade) {
+            return h;
+        }
+        let h = Arc::new(Hooks {
+            ctx: ctx as usize,
+            watchdog: Watchdog::for_group(unsafe { sys::JSContextGetGroup(ctx) }),
+            depth: Cell::new(0),
+            exception_handler: RefCell::new(None),
+            microtasks_wrapped: Cell::new(false),
+            #[cfg(feature = "unhandled-rejections")]
+            rejection_handler: RefCell::new(None),
+            #[cfg(feature = "unhandled-rejections")]
+            rejections_tracked: Cell::new(false),
+            classes: RefCell::new(HashMap::new()),
+            functions: RefCell::new(HashMap::new()),
+            pristine: Cell::new(ptr::null_mut()),
+        });
+        hooks.insert(ctx as usize, Arc::downgrade(&h));
+        h
+    }
+
+    /// Gets the watchdog of the context's group.
+    pub(crate) fn watchdog(&self) -> &Arc<Watchdog> {
+        &self.watchdog
+    }
+
+    /// Runs an evaluation of the host, and reports the exception that it
+    /// fails with, unless it is nested inside of another evaluation.
+    pub(crate) fn run<T>(
+        &self,
+        f: impl FnOnce() -> Result<T, JSException>,
+    ) -> Result<T, JSException> {
+        let outermost = self.depth.get() == 0;
+        let r = {
+            let _running = self.watchdog.enter();
+            let _nested = Nested::new(self);
+            f()
+        };
+        if outermost {
+            if let Err(e) = &r {
+                self.report(JSException {
+                    value: JSValue {
+                        raw: e.value.raw,
+                        ctx: e.value.ctx,
+                    },
+                    details: e.details.clone(),
+                });
+            }
+        }
+        r
+    }
+
+    /// Gets the class registered under `name` and its constructor.
+    pub(crate) fn class(&self, name: &str) -> Option<(JSClass, sys::JSObjectRef)> {
+        self.classes.borrow().get(name).cloned()
+    }
+
+    /// Registers `class` and its constructor, protecting the
+    /// constructor until the context is released.
+    pub(crate) fn add_class(&self, class: JSClass, constructor: sys::JSObjectRef) {
+        unsafe { sys::JSValueProtect(self.ctx as sys::JSContextRef, constructor) };
+        self.classes
+            .borrow_mut()
+            .insert(class.name().to_owned(), (class, constructor));
+    }
+
+    /// Gets the object, usually a function, that the script `source`
+    /// evaluates to, evaluating it only the first time, and protecting
+    /// the object until the context is released.
+    pub(crate) fn function(
+        &self,
+        ctx: &JSContext,
+        source: &'static str,
+        url: &str,
+    ) -> Result<JSObject, JSException> {
+        let cached = self.functions.borrow().get(source).copied();
+        if let Some(raw) = cached {
+            return Ok(JSObject::from_raw(ctx.raw, raw));
+        }
+        let function = evaluate_script(ctx, source, None, url, 1)?.as_object()?;
+        unsafe { sys::JSValueProtect(ctx.raw, function.raw) };
+        self.functions.borrow_mut().insert(source, function.raw);
+        Ok(function)
+    }
+
+    /// Gets the function that restores the pristine state of the
+    /// context, or null if there is none.
+    pub(crate) fn pristine(&self) -> sys::JSObjectRef {
+        self.pristine.get()
+    }
+
+    /// Sets the function that restores the pristine state of the
+    /// context, protecting it until it is replaced or the context is
+    /// released.
+    pub(crate) fn set_pristine(&self, restore: sys::JSObjectRef) {
+        let ctx = self.ctx as sys::JSContextRef;
+        unsafe { sys::JSValueProtect(ctx, restore) };
+        let old = self.pristine.replace(restore);
+        if !old.is_null() {
+            unsafe { sys::JSValueUnprotect(ctx, old) };
+        }
+    }
+
+    /// Releases the state that keeps values of the context alive. This
+    /// is called before the last `JSContext` releases the context.
+    pub(crate) fn release(&self) {
+        for (_, (_, constructor)) in self.classes.borrow_mut().drain() {
+            unsafe { sys::JSValueUnprotect(self.ctx as sys::JSContextRef, constructor) };
+        }
+        for (_, function) in self.functions.borrow_mut().drain() {
+            unsafe { sys::JSValueUnprotect(self.ctx as sys::JSContextRef, function) };
+        }
+        let pristine = self.pristine.replace(ptr::null_mut());
+        if !pristine.is_null() {
+            unsafe { sys::JSValueUnprotect(self.ctx as sys::JSContextRef, pristine) };
+        }
+    }
+
+    /// Reports an exception that no script can catch anymore.
+    fn report(&self, e: JSException) {
+        let handler = self.exception_handler.borrow().clone();
+        if let Some(handler) = handler {
+            // Exceptions of evaluations made by the handler are for the
+            // handler to deal with.
+            let _nested = Nested::new(self);
+            handler(e);
+        }
+    }
+
+    /// Reports a promise that was rejected and has no rejection handler.
+    #[cfg(feature = "unhandled-rejections")]
+    fn report_rejection(&self, promise: JSObject, reason: JSException) {
+        let handler = self.rejection_handler.borrow().clone();
+        if let Some(handler) = handler {
+            let _nested = Nested::new(self);
+            handler(promise, reason);
+        }
+    }
+}
+
+impl Drop for Hooks {
+    fn drop(&mut self) {
+        let mut hooks = HOOKS.lock().unwrap();
+        // New hooks may already have replaced these ones.
+        if hooks.get(&self.ctx).is_some_and(|h| h.strong_count() == 0) {
+            hooks.remove(&self.ctx);
+        }
+    }
+}
+
+/// Marks an evaluation as running until dropped.
+struct Nested<'a>(&'a Hooks);
+
+impl<'a> Nested<'a> {
+    fn new(hooks: &'a Hooks) -> Self {
+        hooks.depth.set(hooks.depth.get() + 1);
+        Nested(hooks)
+    }
+}
+
+impl Drop for Nested<'_> {
+    fn drop(&mut self) {
+        self.0.depth.set(self.0.depth.get() - 1);
+    }
+}
+
+impl JSContext {
+    /// Sets a handler for exceptions that escape all scripts.
+    ///
+    /// * `handler`: Called with each exception that is not caught.
+    ///
+    /// The handler is called for exceptions that [`evaluate_script`] and
+    /// the other evaluation functions fail with, for exceptions thrown
+    /// by timer callbacks run with [`Timers::fire`], and for exceptions
+    /// thrown by callbacks passed to `queueMicrotask`. The evaluation
+    /// functions and `Timers::fire` still return their exceptions, so
+    /// the handler is a central place to log failures rather than a
+    /// replacement for handling errors.
+    ///
+    /// Exceptions of evaluations that are started by native functions,
+    /// while a script is running, are not reported, as the script may
+    /// still catch them. Neither are exceptions of evaluations that the
+    /// handler starts itself.
+    ///
+    /// The handler applies to all `JSContext`s for this context, and
+    /// replaces any handler set before. It is kept as long as any of
+    /// them exists.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    ///
+    /// let ctx = JSContext::default();
+    /// let log = Rc::new(RefCell::new(Vec::new()));
+    /// let l = log.clone();
+    /// ctx.set_unhandled_exception_handler(move |e| l.borrow_mut().push(e.to_string()))
+    ///     .expect("handler set");
+    ///
+    /// let _ = evaluate_script(&ctx, "null.x", None, "app.js", 1);
+    /// assert_eq!(log.borrow().len(), 1);
+    /// ```
+    ///
+    /// [`evaluate_script`]: fn.evaluate_script.html
+    /// [`Timers::fire`]: struct.Timers.html#method.fire
+    pub fn set_unhandled_exception_handler<F>(&self, handler: F) -> Result<(), JSException>
+    where
+        F: Fn(JSException) + 'static,
+    {
+        if !self.hooks.microtasks_wrapped.get() {
+            let wrap =
+                evaluate_script(self, WRAP_QUEUE_MICROTASK, None, "hooks.js", 1)?.as_object()?;
+            let report = new_function(self, |ctx, _this, arguments| {
+                if let Some(e) = arguments.first() {
+                    ctx.hooks.report(JSException::from_raw(e.ctx, e.raw));
+                }
+                Ok(JSValue::new_undefined(ctx))
+            });
+            wrap.call_as_function(None, &[self.global_object().into(), report.into()])?;
+            self.hooks.microtasks_wrapped.set(true);
+        }
+        *self.hooks.exception_handler.borrow_mut() = Some(Rc::new(handler));
+        Ok(())
+    }
+
+    /// Removes the handler set by [`set_unhandled_exception_handler`].
+    ///
+    /// [`set_unhandled_exception_handler`]: #method.set_unhandled_exception_handler
+    pub fn clear_unhandled_exception_handler(&self) {
+        self.hooks.exception_handler.borrow_mut().take();
+    }
+
+    /// Sets a handler for promises that are rejected and never handled.
+    ///
+    /// * `handler`: Called with the promise and its rejection reason.
+    ///
+    /// Rejections are checked once the microtask queue has been drained
+    /// after a script has run, so a promise that gets a rejection handler
+    /// in the same turn, such as with `await` or `.catch()`, is not
+    /// reported. The reason is given as a `JSException`, so that the
+    /// `stack` of an `Error` shows where it was created. Where the
+    /// promise itself was created is not recorded by JavaScriptCore.
+    ///
+    /// Without a handler, these rejections go unnoticed by the host.
+    ///
+    /// The handler applies to all `JSContext`s for this context, and
+    /// replaces any handler set before.
+    ///
+    /// Requires the `unhandled-rejections` feature.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    ///
+    /// let ctx = JSContext::default();
+    /// let reasons = Rc::new(RefCell::new(Vec::new()));
+    /// let r = reasons.clone();
+    /// ctx.set_unhandled_rejection_handler(move |_promise, reason| {
+    ///     r.borrow_mut().push(reason.to_string())
+    /// })
+    /// .expect("handler set");
+    ///
+    /// let script = "Promise.reject(new Error('lost')); Promise.reject(1).catch(() => {})";
+    /// evaluate_script(&ctx, script, None, "app.js", 1).expect("evaluated");
+    /// assert_eq!(reasons.borrow().len(), 1);
+    /// assert!(reasons.borrow()[0].starts_with("Error: lost"));
+    /// ```
+    #[cfg(feature = "unhandled-rejections")]
+    pub fn set_unhandled_rejection_handler<F>(&self, handler: F) -> Result<(), JSException>
+    where
+        F: Fn(JSObject, JSException) + 'static,
+    {
+        if !self.hooks.rejections_tracked.get() {
+            let callback = new_function(self, |ctx, _this, arguments| {
+                if let (Some(promise), Some(reason)) = (arguments.first(), arguments.get(1)) {
+                    let promise = promise.as_object()?;
+                    let reason = JSException::from_raw(reason.ctx, reason.raw);
+                    ctx.hooks.report_rejection(promise, reason);
+                }
+                Ok(JSValue::new_undefined(ctx))
+            });
+            let mut e: sys::JSValueRef = ptr::null_mut();
+            unsafe {
+                sys::JSGlobalContextSetUnhandledRejectionCallback(self.raw, callback.raw, &mut e);
+            }
+            if !e.is_null() {
+                return Err(JSException::from_raw(self.raw, e));
+            }
+            self.hooks.rejections_tracked.set(true);
+        }
+        *self.hooks.rejection_handler.borrow_mut() = Some(Rc::new(handler));
+        Ok(())
+    }
+
+    /// Removes the handler set by [`set_unhandled_rejection_handler`].
+    ///
+    /// [`set_unhandled_rejection_handler`]: #method.set_unhandled_rejection_handler
+    #[cfg(feature = "unhandled-rejections")]
+    pub fn clear_unhandled_rejection_handler(&self) {
+        self.hooks.rejection_handler.borrow_mut().take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::function::new_function;
+    use super::super::{evaluate_script, JSContext};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn log(ctx: &JSContext) -> Rc<RefCell<Vec<String>>> {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let l = log.clone();
+        ctx.set_unhandled_exception_handler(move |e| {
+            l.borrow_mut()
+                .push(e.message().unwrap_or_default().to_owned())
+        })
+        .unwrap();
+        log
+    }
+
+    #[test]
+    fn evaluation_exceptions() {
+        let ctx = JSContext::default();
+        let log = log(&ctx);
+
+        assert!(evaluate_script(&ctx, "throw new Error('a')", None, "test.js", 1).is_err());
+        assert!(evaluate_script(&ctx, "1", None, "test.js", 1).is_ok());
+        assert_eq!(*log.borrow(), ["a"]);
+
+        // Handlers are shared by all `JSContext`s for the context.
+        let f = new_function(&ctx, |ctx, _this, _arguments| {
+            // The calling script catches this one.
+            evaluate_script(ctx, "throw new Error('nested')", None, "test.js", 1)
+        });
+        ctx.global_object().set_property("f", f.into()).unwrap();
+        evaluate_script(&ctx, "try { f(); } catch (e) {}", None, "test.js", 1).unwrap();
+        assert!(evaluate_script(&ctx, "f()", None, "test.js", 1).is_err());
+        assert_eq!(*log.borrow(), ["a", "nested"]);
+
+        ctx.clear_unhandled_exception_handler();
+        assert!(evaluate_script(&ctx, "throw new Error('b')", None, "test.js", 1).is_err());
+        assert_eq!(log.borrow().len(), 2);
+    }
+
+    #[test]
+    fn microtask_exceptions() {
+        let ctx = JSContext::default();
+        let log = log(&ctx);
+        evaluate_script(
+            &ctx,
+            "queueMicrotask(() => { throw new Error('microtask'); }); 1",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(*log.borrow(), ["microtask"]);
+    }
+
+    #[cfg(feature = "unhandled-rejections")]
+    #[test]
+    fn unhandled_rejections() {
+        let ctx = JSContext::default();
+        let reasons = Rc::new(RefCell::new(Vec::new()));
+        let r = reasons.clone();
+        ctx.set_unhandled_rejection_handler(move |promise, reason| {
+            assert!(promise.is_object());
+            r.borrow_mut()
+                .push(reason.message().unwrap_or_default().to_owned());
+        })
+        .unwrap();
+
+        evaluate_script(
+            &ctx,
+            "Promise.reject(new Error('a'));
+             Promise.reject(new Error('b')).catch(() => {});
+             (async () => { throw new Error('c'); })();
+             (async () => { try { await Promise.reject(1); } catch (e) {} })();
+             new Promise((resolve, reject) => reject('d'));
+             0",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(*reasons.borrow(), ["a", "c", "d"]);
+
+        ctx.clear_unhandled_rejection_handler();
+        evaluate_script(
+            &ctx,
+            "Promise.reject(new Error('e')); 0",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(reasons.borrow().len(), 3);
+    }
+}
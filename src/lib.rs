@@ -19,26 +19,352 @@
 
 extern crate javascriptcore_sys as sys;
 
+// Lets the code generated by the macros name this crate from inside it.
+#[cfg(feature = "macros")]
+extern crate self as javascriptcore;
+
+use std::rc::Rc;
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+mod abort;
+mod asynciterator;
 mod base;
+#[cfg(feature = "num-bigint")]
+mod bigint;
+mod builder;
+#[cfg(feature = "bytes")]
+mod bytes;
 mod class;
+#[cfg(feature = "log")]
+mod console;
 mod context;
 mod contextgroup;
+mod contextpool;
+mod convert;
+#[cfg(feature = "getrandom")]
+mod crypto;
+mod dataview;
+mod declarations;
+#[cfg(feature = "macros")]
+mod derive;
+mod encoding;
+mod eventloop;
 mod exception;
+mod fetch;
+mod function;
+mod hooks;
+#[cfg(feature = "ndarray")]
+mod ndarray;
 mod object;
+mod pristine;
+mod promise;
+mod remote;
+#[cfg(feature = "tokio")]
+mod runtime;
+#[cfg(feature = "serde")]
+mod serde;
+mod stream;
 mod string;
+mod timers;
+mod typedarray;
 mod value;
+mod valuescope;
+mod vm;
+mod watchdog;
 
-pub use crate::base::{check_script_syntax, evaluate_script, garbage_collect};
+pub use crate::abort::{install_abort_controller, CancellationToken, Cancelled};
+#[cfg(feature = "async")]
+pub use crate::asynciterator::AsyncIteratorStream;
+#[cfg(feature = "async")]
+pub use crate::base::evaluate_script_async;
+pub use crate::base::{
+    check_script_syntax, evaluate_script, evaluate_script_with_options, evaluate_with_scope,
+    garbage_collect,
+};
+#[cfg(feature = "log")]
+pub use crate::console::install_console;
+pub use crate::contextpool::ContextPool;
+pub use crate::convert::{
+    FromJs, FromJsArgs, FromJsArgument, IntoJsResult, OptionPolicy, Rest, ToJs,
+};
+#[cfg(feature = "getrandom")]
+pub use crate::crypto::install_crypto;
+pub use crate::declarations::{ClassDeclaration, Declarations, FunctionDeclaration, TypeScript};
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub use crate::derive::__private;
+#[cfg(feature = "macros")]
+pub use crate::derive::JsClass;
+pub use crate::encoding::install_text_encoding;
+pub use crate::eventloop::{EventLoop, LocalEventLoop};
+pub use crate::exception::OrThrow;
+pub use crate::fetch::{install_fetch, FetchFuture, FetchHandler, FetchRequest, FetchResponse};
+pub use crate::function::ScopedFunction;
+#[cfg(feature = "async")]
+pub use crate::promise::PromiseFuture;
+pub use crate::promise::Spawner;
+pub use crate::remote::{DeferredQueue, RemoteDeferred};
+#[cfg(feature = "tokio")]
+pub use crate::runtime::{JsRuntime, RuntimeError, RuntimeFuture};
+#[cfg(feature = "serde")]
+pub use crate::serde::{
+    from_value, from_value_with_options, to_value, to_value_into, to_value_with_options,
+    LargeIntegers, SerdeOptions,
+};
+#[cfg(feature = "async")]
+pub use crate::stream::ReadableStreamReader;
 pub use crate::sys::{JSType, JSTypedArrayType};
+pub use crate::timers::{install_timers, TimerId, TimerScheduler, Timers};
+pub use crate::typedarray::{BackingStore, TypedArrayElement};
+pub use crate::valuescope::ValueScope;
+#[cfg(feature = "macros")]
+pub use javascriptcore_macros::{js_methods, JsClass};
+
+/// Derives [`ToJs`] for a struct or an enum.
+///
+/// A struct with named fields becomes an object with a property for
+/// each field, a struct with a single unnamed field becomes the value of
+/// the field, and other tuple structs become arrays. The fields are
+/// converted with [`ToJs`]. A field that is marked with
+/// `#[js(rename = "...")]` is given another name, and one that is marked
+/// with `#[js(skip)]` is left out. An `Option` field that is marked with
+/// `#[js(none_as_null)]` converts `None` to `null` rather than
+/// `undefined`, as with [`OptionPolicy`].
+///
+/// ```
+/// # use javascriptcore::*;
+/// #[derive(ToJs, FromJs, Debug, PartialEq)]
+/// struct Options {
+///     #[js(rename = "maxItems")]
+///     max_items: u32,
+///     #[js(default = 0.5)]
+///     ratio: f64,
+///     label: Option<String>,
+///     #[js(skip)]
+///     cache: Vec<u8>,
+/// }
+///
+/// let ctx = JSContext::default();
+/// let v = evaluate_script(&ctx, "({ maxItems: 10 })", None, "test.js", 1).expect("options");
+/// let options = Options::from_js(&ctx, &v).expect("options");
+/// assert_eq!(options, Options { max_items: 10, ratio: 0.5, label: None, cache: vec![] });
+///
+/// let v = options.to_js(&ctx).expect("object");
+/// assert_eq!(v.to_json_string(0).expect("JSON").to_string(), r#"{"maxItems":10,"ratio":0.5}"#);
+/// ```
+///
+/// Enums are represented like with `serde`. By default they are
+/// externally tagged: a unit variant becomes the string of its name, so
+/// an enum of unit variants is a union of strings, and other variants
+/// become an object with a single property, named for the variant,
+/// whose value is converted like a struct with the fields of the
+/// variant. An enum that is marked with `#[js(tag = "...")]` is
+/// internally tagged instead: its variants become objects with the name
+/// of the variant in the given property, and the fields of the variant
+/// in the others. The only field of a newtype variant must then convert
+/// to an object, and other tuple variants can not be used. A variant
+/// that is marked with `#[js(rename = "...")]` is given another name.
+///
+/// ```
+/// # use javascriptcore::*;
+/// #[derive(ToJs, FromJs, Debug, PartialEq)]
+/// #[js(tag = "kind")]
+/// enum Shape {
+///     #[js(rename = "circle")]
+///     Circle { radius: f64 },
+///     #[js(rename = "empty")]
+///     Empty,
+/// }
+///
+/// let ctx = JSContext::default();
+/// let v = Shape::Circle { radius: 2.0 }.to_js(&ctx).expect("object");
+/// assert_eq!(v.to_json_string(0).expect("JSON").to_string(), r#"{"kind":"circle","radius":2}"#);
+///
+/// let v = evaluate_script(&ctx, "({ kind: 'empty' })", None, "test.js", 1).expect("shape");
+/// assert_eq!(Shape::from_js(&ctx, &v).expect("shape"), Shape::Empty);
+/// ```
+///
+/// [`OptionPolicy`]: struct.OptionPolicy.html
+/// [`ToJs`]: trait.ToJs.html
+#[cfg(feature = "macros")]
+pub use javascriptcore_macros::ToJs;
+
+/// Derives [`FromJs`] for a struct or an enum.
+///
+/// This is the reverse of [`ToJs`](derive.ToJs.html), and takes the
+/// same representations of enums. The fields are
+/// converted with [`FromJs`] from the properties of an object, from the
+/// value itself for a struct with a single unnamed field, or from the
+/// elements of an array for other tuple structs. A property that is
+/// missing gives a `TypeError` that names it, unless its field can be
+/// converted from `undefined`, like an `Option`. A field that is marked
+/// with `#[js(default)]` or `#[js(default = ...)]` is given a default
+/// value if its property is missing, `undefined` or `null`, and one that
+/// is marked with `#[js(skip)]` is given `Default::default()`. An
+/// `Option` field that is marked with `#[js(reject_undefined)]` is only
+/// `None` if its property is `null`, as with [`OptionPolicy`]. A variant
+/// that is not one of the enum gives a `TypeError` that names it.
+///
+/// [`FromJs`]: trait.FromJs.html
+/// [`OptionPolicy`]: struct.OptionPolicy.html
+#[cfg(feature = "macros")]
+pub use javascriptcore_macros::FromJs;
+
+/// The callback is a function that can be passed to
+/// [`JSObject::new_function`] and [`JSClassBuilder::static_function`].
+/// Its arguments are converted with [`FromJs`], and its result with
+/// [`ToJs`]. A `&JSContext` parameter is given the context of the call,
+/// and a `&str` parameter takes a string. A parameter that is marked
+/// with `#[js(default)]` or `#[js(default = ...)]` takes a default value
+/// if its argument is missing, `undefined` or `null`, and a [`Rest`]
+/// parameter takes the remaining arguments. A function that returns a
+/// `Result` throws its error, which is converted with
+/// [`JSException::from_error`].
+///
+/// ```
+/// # use javascriptcore::*;
+/// #[js_function]
+/// fn repeat(
+///     ctx: &JSContext,
+///     #[js(default = 2.0)] count: f64,
+///     text: &str,
+/// ) -> Result<String, JSException> {
+///     if count < 0.0 || count.fract() != 0.0 {
+///         return Err(JSException::range_error(ctx, "Invalid count"));
+///     }
+///     Ok(text.repeat(count as usize))
+/// }
+///
+/// let ctx = JSContext::default();
+/// let f = JSObject::new_function(&ctx, repeat);
+/// ctx.global_object().set_property("repeat", f.into()).expect("set");
+///
+/// let r = evaluate_script(&ctx, "repeat(3, 'ab')", None, "test.js", 1).expect("evaluated");
+/// assert_eq!(r.as_string().expect("string"), "ababab");
+/// let e = evaluate_script(&ctx, "repeat(-1, 'ab')", None, "test.js", 1).unwrap_err();
+/// assert_eq!(e.name(), Some("RangeError"));
+/// let r = evaluate_script(&ctx, "repeat(undefined, 'c')", None, "test.js", 1).expect("evaluated");
+/// assert_eq!(r.as_string().expect("string"), "cc");
+/// ```
+///
+/// [`JSObject::new_function`]: struct.JSObject.html#method.new_function
+/// [`JSClassBuilder::static_function`]: struct.JSClassBuilder.html#method.static_function
+/// [`FromJs`]: trait.FromJs.html
+/// [`ToJs`]: trait.ToJs.html
+/// [`Rest`]: struct.Rest.html
+/// [`JSException::from_error`]: struct.JSException.html#method.from_error
+#[cfg(feature = "macros")]
+pub use javascriptcore_macros::js_function;
+
+/// Gets the [`FunctionDeclaration`] of a function that is marked with
+/// [`js_function`], for [`Declarations`].
+///
+/// The types of the parameters and the result are found with
+/// [`TypeScript`], and those that do not implement it are declared as
+/// `unknown`.
+///
+/// ```
+/// # use javascriptcore::*;
+/// #[js_function]
+/// fn greet(name: &str, times: Option<u32>) -> String {
+///     format!("Hello, {}!", name).repeat(times.unwrap_or(1) as usize)
+/// }
+///
+/// let d = Declarations::new().function(js_declaration!(greet));
+/// assert_eq!(
+///     d.to_string(),
+///     "declare function greet(name: string, times?: number | null): string;\n"
+/// );
+/// ```
+///
+/// [`FunctionDeclaration`]: struct.FunctionDeclaration.html
+/// [`js_function`]: attr.js_function.html
+/// [`Declarations`]: struct.Declarations.html
+/// [`TypeScript`]: trait.TypeScript.html
+#[cfg(feature = "macros")]
+pub use javascriptcore_macros::js_declaration;
 
 /// A JavaScript class.
 ///
-/// Used with `JSObjectMake` to construct objects with custom
-/// behavior.
+/// Classes are defined with a [`JSClassBuilder`]. They are used with
+/// [`JSObject::new_with_class`] to construct objects with custom
+/// behavior, and with [`JSContext::new_with_class`] to give the global
+/// object custom behavior.
 ///
-/// TODO: Fix `JSObjectMake` reference once it has been wrapped.
+/// [`JSClassBuilder`]: struct.JSClassBuilder.html
+/// [`JSObject::new_with_class`]: struct.JSObject.html#method.new_with_class
+/// [`JSContext::new_with_class`]: struct.JSContext.html#method.new_with_class
 pub struct JSClass {
     raw: sys::JSClassRef,
+    data: Rc<class::ClassData>,
+}
+
+/// Defines a [`JSClass`].
+///
+/// The builder takes the name of the class and the Rust callbacks that
+/// give its objects their behavior. Callbacks are closures, which are
+/// kept as long as the class or any of its objects exist.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let base = JSClassBuilder::new("Base").build();
+/// let derived = JSClassBuilder::new("Derived").parent_class(&base).build();
+///
+/// let ctx = JSContext::default();
+/// let o = JSObject::new_with_class(&ctx, &derived);
+/// assert!(o.is_object_of_class(&base));
+/// ```
+///
+/// [`JSClass`]: struct.JSClass.html
+pub struct JSClassBuilder {
+    name: String,
+    parent: Option<JSClass>,
+    automatic_prototype: bool,
+    callbacks: class::Callbacks,
+}
+
+/// Configures and creates a [`JSContext`].
+///
+/// Besides the options that are also available through
+/// [`JSContextGroup::new_context_with_class`], the builder can set the
+/// runtime options of JavaScriptCore, such as whether to use the JIT.
+///
+/// ```
+/// # use javascriptcore::*;
+/// // Runtime options only take effect for the first context of the
+/// // process, so this may fail in a program that created contexts
+/// // before.
+/// let r = ContextBuilder::new().jit(false).build();
+/// # let _ = r;
+/// ```
+///
+/// [`JSContext`]: struct.JSContext.html
+/// [`JSContextGroup::new_context_with_class`]: struct.JSContextGroup.html#method.new_context_with_class
+#[derive(Default)]
+pub struct ContextBuilder<'a> {
+    name: Option<String>,
+    group: Option<&'a JSContextGroup>,
+    global_class: Option<&'a JSClass>,
+    options: Vec<(String, String)>,
+}
+
+/// An error from building a context with a [`ContextBuilder`].
+///
+/// [`ContextBuilder`]: struct.ContextBuilder.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContextBuilderError {
+    /// Runtime options were set, but the engine has already read its
+    /// options because a context or context group was created before.
+    EngineStarted,
+    /// The name or value of the named runtime option is not valid, or
+    /// the engine has no such option.
+    InvalidOption(String),
+    /// Runtime options were set, but this build of JavaScriptCore can
+    /// not set them. See [`ContextBuilder::option`].
+    ///
+    /// [`ContextBuilder::option`]: struct.ContextBuilder.html#method.option
+    Unsupported,
 }
 
 /// A JavaScript execution context.
@@ -46,6 +372,7 @@ pub struct JSClass {
 /// Holds the global object and other execution state.
 pub struct JSContext {
     raw: sys::JSGlobalContextRef,
+    hooks: Arc<hooks::Hooks>,
 }
 
 /// A group that associates JavaScript contexts with one another.
@@ -54,17 +381,227 @@ pub struct JSContext {
 /// objects. Sharing and/or exchanging JavaScript objects between
 /// contexts in different groups will produce undefined behavior.
 /// When objects from the same context group are used in multiple
-/// threads, explicit synchronization is required.
+/// threads, explicit synchronization is required, such as that of a
+/// [`JSVirtualMachine`].
+///
+/// [`JSVirtualMachine`]: struct.JSVirtualMachine.html
 pub struct JSContextGroup {
     raw: sys::JSContextGroupRef,
 }
 
 /// A wrapper for a [`JSValue`] that contains an exception.
 ///
+/// Besides the value that was thrown, a `JSException` holds the details
+/// that were read from it when it was caught: the `name`, `message` and
+/// `stack` of an error, and the place in the source where it was thrown.
+///
 /// [`JSValue`]: struct.JSValue.html
 #[derive(Debug)]
 pub struct JSException {
     value: JSValue,
+    details: Box<exception::Details>,
+}
+
+/// A [`JSException`] that can be sent to and shared with other threads.
+///
+/// A `JSException` can not leave the thread that uses its context, which
+/// keeps it out of error types that must be `Send` and `Sync`, such as
+/// `anyhow::Error`. A `JSError` keeps the details of the exception as
+/// Rust strings, along with the thrown value, which stays alive until
+/// the `JSError` is dropped. The value can be taken back out with
+/// [`to_exception`] on the thread that runs the context.
+///
+/// This makes `JSError` fit for `#[from]` in `thiserror` enums, and for
+/// `?` in functions that return an `anyhow::Result`:
+///
+/// ```
+/// # use javascriptcore::*;
+/// fn run(ctx: &JSContext) -> Result<f64, Box<dyn std::error::Error + Send + Sync>> {
+///     let r = evaluate_script(ctx, "null.x", None, "app.js", 1).map_err(JSError::from)?;
+///     Ok(r.as_number().map_err(JSError::from)?)
+/// }
+///
+/// let ctx = JSContext::default();
+/// let e = run(&ctx).unwrap_err();
+/// assert_eq!(e.downcast_ref::<JSError>().expect("JSError").name(), Some("TypeError"));
+/// ```
+///
+/// [`JSException`]: struct.JSException.html
+/// [`to_exception`]: #method.to_exception
+pub struct JSError {
+    /// The global context of the value. It is retained.
+    ctx: sys::JSGlobalContextRef,
+    /// The value that was thrown. It is protected from garbage
+    /// collection.
+    value: sys::JSValueRef,
+    details: Box<exception::Details>,
+}
+
+/// The kind of a value that was thrown, as given by
+/// [`JSException::kind`].
+///
+/// Errors belong to the kind of the first of the standard error classes
+/// that they are an instance of, so instances of subclasses of
+/// `TypeError` are type errors too.
+///
+/// [`JSException::kind`]: struct.JSException.html#method.kind
+#[derive(Debug)]
+pub enum JSErrorKind {
+    /// A `TypeError`, such as one thrown when calling something that is
+    /// not a function.
+    TypeError,
+    /// A `RangeError`, such as one thrown for an invalid array length.
+    RangeError,
+    /// A `SyntaxError`, thrown for scripts that can not be parsed.
+    SyntaxError,
+    /// A `ReferenceError`, such as one thrown when reading an undeclared
+    /// variable.
+    ReferenceError,
+    /// An `EvalError`.
+    EvalError,
+    /// Any other `Error`, such as a plain `Error` or an instance of a
+    /// class that extends `Error`.
+    Custom(JSObject),
+    /// A value that is not an `Error`, such as a string.
+    NonError(JSValue),
+}
+
+/// Options that control how a script is evaluated.
+///
+/// Used with [`evaluate_script_with_options`]. The default options
+/// evaluate a classic script in sloppy mode, like [`evaluate_script`].
+///
+/// [`evaluate_script`]: fn.evaluate_script.html
+/// [`evaluate_script_with_options`]: fn.evaluate_script_with_options.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EvaluateOptions {
+    /// Whether to evaluate the script in strict mode, as if it began
+    /// with a `"use strict"` directive.
+    pub strict: bool,
+    /// Whether the source is a classic script or a module.
+    pub source_type: SourceType,
+}
+
+/// The kind of source code being evaluated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SourceType {
+    /// A classic script, which runs in the global scope.
+    #[default]
+    Script,
+    /// An ECMAScript module.
+    ///
+    /// The C API of JavaScriptCore can not load modules, so evaluating a
+    /// module fails with an exception.
+    Module,
+}
+
+/// Statistics about the memory used by the heap of a context group.
+///
+/// Returned by [`JSContext::memory_usage`]. All contexts in a group
+/// share one heap, so the statistics cover the whole group.
+///
+/// Requires the `memory-usage` feature.
+///
+/// [`JSContext::memory_usage`]: struct.JSContext.html#method.memory_usage
+#[cfg(feature = "memory-usage")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The number of bytes of the heap used by live objects.
+    pub heap_size: usize,
+    /// The number of bytes allocated for the heap.
+    pub heap_capacity: usize,
+    /// The number of bytes allocated outside of the heap on behalf of
+    /// heap objects, such as the contents of array buffers.
+    pub extra_memory_size: usize,
+    /// The number of objects in the heap.
+    pub object_count: usize,
+    /// The number of objects protected from garbage collection.
+    pub protected_object_count: usize,
+    /// The number of global objects in the heap.
+    pub global_object_count: usize,
+    /// The number of global objects protected from garbage collection.
+    pub protected_global_object_count: usize,
+}
+
+/// Collects the names of the properties of an object.
+///
+/// Given to the `get_property_names` callback of a [`JSClassBuilder`],
+/// which adds the names of the properties that it provides.
+///
+/// [`JSClassBuilder`]: struct.JSClassBuilder.html
+pub struct JSPropertyNameAccumulator {
+    raw: sys::JSPropertyNameAccumulatorRef,
+}
+
+/// The attributes of a property.
+///
+/// Attributes can be combined with `|`:
+///
+/// ```
+/// # use javascriptcore::PropertyAttributes;
+/// let attributes = PropertyAttributes::READ_ONLY | PropertyAttributes::DONT_ENUM;
+/// assert!(attributes.contains(PropertyAttributes::DONT_ENUM));
+/// assert!(!attributes.contains(PropertyAttributes::DONT_DELETE));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PropertyAttributes(sys::JSPropertyAttributes);
+
+/// A key of properties, which is a string or a symbol, that is kept
+/// ready to get and set properties with.
+///
+/// Names given as [`JSString`]s have to be turned into keys each time
+/// that a property is used. A `PropertyKey` is already a key, so using
+/// it again and again, such as in a loop, skips that work. Symbols can
+/// only be used as keys through a `PropertyKey`.
+///
+/// The key is protected from garbage collection for as long as the
+/// `PropertyKey` exists. It must only be used with objects of the
+/// context for which it was made, or of contexts in the same group.
+///
+/// Requires the `property-keys` feature, as older versions of
+/// JavaScriptCore lack the functions that it uses. Without it, names
+/// that are converted to [`JSString`]s once can still be used again
+/// with [`JSObject::get_property_key`].
+///
+/// ```
+/// # use javascriptcore::*;
+/// let ctx = JSContext::default();
+/// let id = PropertyKey::new(&ctx, "id");
+/// let o = JSObject::new(&ctx);
+/// o.set_property_for_key(&id, JSValue::new_number(&ctx, 1.0)).expect("set");
+/// assert_eq!(o.get_property("id").expect("id").as_number().expect("number"), 1.0);
+/// ```
+///
+/// [`JSString`]: struct.JSString.html
+/// [`JSObject::get_property_key`]: struct.JSObject.html#method.get_property_key
+#[cfg(feature = "property-keys")]
+pub struct PropertyKey {
+    ctx: JSContext,
+    raw: sys::JSValueRef,
+}
+
+/// A property for [`JSObject::read`] to get.
+///
+/// The names of properties are converted to [`JSString`]s when the
+/// specs are made, so that specs made once can read any number of
+/// objects without converting the names again.
+///
+/// ```
+/// # use javascriptcore::PropertySpec;
+/// let columns: Vec<PropertySpec> = vec!["id".into(), "name".into(), 0.into()];
+/// ```
+///
+/// [`JSObject::read`]: struct.JSObject.html#method.read
+/// [`JSString`]: struct.JSString.html
+#[derive(Debug)]
+pub enum PropertySpec {
+    /// The property with a name.
+    Name(JSString),
+    /// The property with a numeric index, which is read like with
+    /// [`JSObject::get_property_at_index`].
+    ///
+    /// [`JSObject::get_property_at_index`]: struct.JSObject.html#method.get_property_at_index
+    Index(u32),
 }
 
 /// A JavaScript object.
@@ -74,11 +611,79 @@ pub struct JSException {
 /// expects a `JSValue` can receive a `JSObject` as well.
 ///
 /// [`JSValue`]: struct.JSValue.html
+#[derive(Debug)]
 pub struct JSObject {
     raw: sys::JSObjectRef,
     value: JSValue,
 }
 
+/// A JavaScript typed array, such as a `Uint8Array` or a
+/// `Float64Array`.
+///
+/// A `JSTypedArray` is a [`JSObject`], which it dereferences to. The
+/// kind of array is given by a [`JSTypedArrayType`].
+///
+/// [`JSObject`]: struct.JSObject.html
+/// [`JSTypedArrayType`]: enum.JSTypedArrayType.html
+#[derive(Debug)]
+pub struct JSTypedArray {
+    object: JSObject,
+}
+
+/// A JavaScript `ArrayBuffer`, which holds the bytes that typed arrays
+/// are views of.
+///
+/// A `JSArrayBuffer` is a [`JSObject`], which it dereferences to.
+///
+/// [`JSObject`]: struct.JSObject.html
+#[derive(Debug)]
+pub struct JSArrayBuffer {
+    object: JSObject,
+}
+
+/// A JavaScript `DataView`, which reads and writes numbers of any type
+/// and byte order in the bytes of an `ArrayBuffer`.
+///
+/// A `JSDataView` is a [`JSObject`], which it dereferences to.
+///
+/// [`JSObject`]: struct.JSObject.html
+#[derive(Debug)]
+pub struct JSDataView {
+    object: JSObject,
+}
+
+/// A JavaScript promise.
+///
+/// A `JSPromise` is a [`JSObject`], which it dereferences to. Rust code
+/// can wait for it to settle with [`on_settled`].
+///
+/// [`JSObject`]: struct.JSObject.html
+/// [`on_settled`]: #method.on_settled
+#[derive(Debug)]
+pub struct JSPromise {
+    object: JSObject,
+}
+
+/// The means to settle a pending [`JSPromise`], which host code can
+/// keep until its result is known.
+///
+/// It is created with [`JSPromise::deferred`], and settles the promise
+/// with [`resolve`] or [`reject`]. Its functions are protected from
+/// garbage collection until then. If it is dropped before the promise is
+/// settled, the promise is rejected.
+///
+/// A `JSDeferred` must be used on the thread of its context.
+///
+/// [`JSPromise`]: struct.JSPromise.html
+/// [`JSPromise::deferred`]: struct.JSPromise.html#method.deferred
+/// [`resolve`]: #method.resolve
+/// [`reject`]: #method.reject
+pub struct JSDeferred {
+    ctx: JSContext,
+    /// The resolve and reject functions, until the promise is settled.
+    functions: Option<(sys::JSObjectRef, sys::JSObjectRef)>,
+}
+
 /// A UTF16 character buffer.
 ///
 /// The fundamental string representation in JavaScript. Since
@@ -111,6 +716,11 @@ pub struct JSObject {
 /// caller to pass an `&str` or `String`, or to cache a
 /// previously converted `JSString` and pass that directly.
 ///
+/// Short strings, such as the names of properties, are also
+/// cached by each thread when they are converted, so that
+/// converting a recently used one again does not create
+/// another `JSString`.
+///
 /// A `JSString` is not a [`JSValue`] and so it can not be
 /// passed where a `JSValue` is expected. Instead, it must
 /// be boxed using [`JSValue::new_string`].
@@ -122,6 +732,82 @@ pub struct JSString {
     raw: sys::JSStringRef,
 }
 
+/// A handle for terminating scripts from another thread.
+///
+/// Obtained from [`JSContext::termination_handle`]. A `TerminationHandle`
+/// can be sent to and shared between threads, which makes it suitable for
+/// implementing cancel buttons and request timeouts:
+///
+/// ```
+/// # use javascriptcore::*;
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// let ctx = JSContext::default();
+/// let handle = ctx.termination_handle();
+///
+/// thread::spawn(move || {
+///     thread::sleep(Duration::from_millis(50));
+///     handle.terminate();
+/// });
+///
+/// let r = evaluate_script(&ctx, "while (true) {}", None, "loop.js", 1);
+/// assert!(r.is_err());
+/// ```
+///
+/// [`JSContext::termination_handle`]: struct.JSContext.html#method.termination_handle
+#[derive(Clone, Debug)]
+pub struct TerminationHandle {
+    flags: Arc<watchdog::WatchdogFlags>,
+}
+
+/// A context that can be shared between threads.
+///
+/// A context, and the values and objects that belong to it, can not
+/// leave the thread that created it. A `JSVirtualMachine` instead runs
+/// a context on a thread of its own, and lets other threads send it
+/// jobs with [`enter`]. The jobs run one at a time, each with exclusive
+/// use of the context. This lets servers keep one warm context, with
+/// its host functions and compiled code, and share it among worker
+/// threads:
+///
+/// ```
+/// # use javascriptcore::*;
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let vm = Arc::new(JSVirtualMachine::new());
+/// let workers: Vec<_> = (0..4)
+///     .map(|i| {
+///         let vm = vm.clone();
+///         thread::spawn(move || {
+///             vm.enter(|ctx| {
+///                 let script = format!("{} * 2", i);
+///                 let r = evaluate_script(ctx, script.as_str(), None, "job.js", 1);
+///                 r.expect("evaluated").as_number().expect("number")
+///             })
+///         })
+///     })
+///     .collect();
+/// let results: Vec<f64> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+/// assert_eq!(results, [0.0, 2.0, 4.0, 6.0]);
+/// ```
+///
+/// Values, objects and host state of the context, such as the
+/// [`Timers`] of its host shims, can not be sent to other threads, so
+/// they never leave the thread of the virtual machine. Jobs return Rust
+/// values instead.
+///
+/// [`enter`]: struct.JSVirtualMachine.html#method.enter
+/// [`Timers`]: struct.Timers.html
+pub struct JSVirtualMachine {
+    /// Sends jobs to the thread of the virtual machine. It is only
+    /// `None` while the virtual machine is dropped.
+    jobs: Option<mpsc::Sender<vm::Job>>,
+    thread: Option<thread::JoinHandle<()>>,
+    termination: TerminationHandle,
+}
+
 /// A JavaScript value.
 ///
 /// The base type for all JavaScript values, and polymorphic functions
@@ -0,0 +1,157 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{JSContext, JSException, JSTypedArray, TypedArrayElement};
+use ndarray::{Array1, Array2, ArrayView1, ArrayView2};
+
+/// Views of typed arrays as arrays of
+/// [`ndarray`](https://crates.io/crates/ndarray), for numerical code.
+///
+/// The views borrow the elements of the typed array without copying
+/// them, as [`as_elements`] does, so they are unsafe in the same way.
+/// The methods that return owned arrays copy the elements instead, and
+/// are safe. Matrices are laid out in row-major order, like the nested
+/// arrays of scripts, so a `Float64Array` of 6 elements with the shape
+/// `(2, 3)` has 2 rows of 3 elements.
+///
+/// Requires the `ndarray` feature.
+///
+/// [`as_elements`]: #method.as_elements
+impl JSTypedArray {
+    /// Views the elements of the array as a vector of `T`.
+    ///
+    /// Fails with a `TypeError` if the elements of the array are not of
+    /// the type `T`.
+    ///
+    /// # Safety
+    ///
+    /// As for [`as_elements`], the view must not be used after
+    /// JavaScript runs.
+    ///
+    /// [`as_elements`]: #method.as_elements
+    pub unsafe fn as_array_view1<T: TypedArrayElement>(
+        &self,
+    ) -> Result<ArrayView1<'_, T>, JSException> {
+        Ok(ArrayView1::from(self.as_elements::<T>()?))
+    }
+
+    /// Views the elements of the array as a matrix of `T`, with
+    /// `shape` as its numbers of rows and columns.
+    ///
+    /// Fails with a `TypeError` if the elements of the array are not of
+    /// the type `T`, and with a `RangeError` if their number does not
+    /// match the shape.
+    ///
+    /// # Safety
+    ///
+    /// As for [`as_elements`], the view must not be used after
+    /// JavaScript runs.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let v = evaluate_script(&ctx, "new Float64Array([1, 2, 3, 4, 5, 6])", None, "test.js", 1)
+    ///     .expect("array");
+    /// let array = v.as_typed_array().expect("typed array");
+    /// let matrix = unsafe { array.as_array_view2::<f64>((2, 3)) }.expect("matrix");
+    /// assert_eq!(matrix.row(1).sum(), 15.0);
+    /// assert_eq!(matrix.column(0).to_vec(), [1.0, 4.0]);
+    /// ```
+    ///
+    /// [`as_elements`]: #method.as_elements
+    pub unsafe fn as_array_view2<T: TypedArrayElement>(
+        &self,
+        shape: (usize, usize),
+    ) -> Result<ArrayView2<'_, T>, JSException> {
+        let elements = self.as_elements::<T>()?;
+        ArrayView2::from_shape(shape, elements).map_err(|_| self.shape_error(shape))
+    }
+
+    /// Copies the elements of the array to a vector of `T`.
+    ///
+    /// Fails with a `TypeError` if the elements of the array are not of
+    /// the type `T`.
+    pub fn to_array1<T: TypedArrayElement>(&self) -> Result<Array1<T>, JSException> {
+        // The elements are copied before JavaScript can run.
+        Ok(unsafe { self.as_array_view1()? }.to_owned())
+    }
+
+    /// Copies the elements of the array to a matrix of `T`, with
+    /// `shape` as its numbers of rows and columns.
+    ///
+    /// Fails with a `TypeError` if the elements of the array are not of
+    /// the type `T`, and with a `RangeError` if their number does not
+    /// match the shape.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let v = evaluate_script(&ctx, "new Float32Array(6).fill(0.5)", None, "test.js", 1)
+    ///     .expect("array");
+    /// let array = v.as_typed_array().expect("typed array");
+    /// let matrix = array.to_array2::<f32>((3, 2)).expect("matrix");
+    /// assert_eq!(matrix.dot(&matrix.t()).dim(), (3, 3));
+    /// assert!(array.to_array2::<f32>((4, 2)).is_err());
+    /// ```
+    pub fn to_array2<T: TypedArrayElement>(
+        &self,
+        shape: (usize, usize),
+    ) -> Result<Array2<T>, JSException> {
+        // The elements are copied before JavaScript can run.
+        Ok(unsafe { self.as_array_view2(shape)? }.to_owned())
+    }
+
+    fn shape_error(&self, (rows, columns): (usize, usize)) -> JSException {
+        let ctx = unsafe { JSContext::from_context_ref(self.value.ctx) };
+        let message = format!(
+            "Expected {} rows of {} elements, got {} elements",
+            rows,
+            columns,
+            self.len()
+        );
+        JSException::range_error(&ctx, &message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSContext};
+
+    #[test]
+    fn views() {
+        let ctx = JSContext::default();
+        let v = evaluate_script(
+            &ctx,
+            "new Float32Array([1, 2, 3, 4, 5, 6, 7]).subarray(1)",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        let array = v.as_typed_array().unwrap();
+
+        let vector = unsafe { array.as_array_view1::<f32>() }.unwrap();
+        assert_eq!(vector.len(), 6);
+        assert_eq!(vector.sum(), 27.0);
+        let matrix = unsafe { array.as_array_view2::<f32>((3, 2)) }.unwrap();
+        assert_eq!(matrix.row(0).to_vec(), [2.0, 3.0]);
+        assert_eq!(matrix.column(1).to_vec(), [3.0, 5.0, 7.0]);
+
+        let e = unsafe { array.as_array_view2::<f32>((4, 2)) }.unwrap_err();
+        assert_eq!(e.name(), Some("RangeError"));
+        assert_eq!(
+            e.message(),
+            Some("Expected 4 rows of 2 elements, got 6 elements")
+        );
+        let e = array.to_array1::<f64>().unwrap_err();
+        assert_eq!(e.name(), Some("TypeError"));
+
+        let owned = array.to_array2::<f32>((2, 3)).unwrap();
+        evaluate_script(&ctx, "v = null", None, "test.js", 1).unwrap();
+        assert_eq!(owned.row(1).to_vec(), [5.0, 6.0, 7.0]);
+        assert_eq!(array.to_array1::<f32>().unwrap().len(), 6);
+    }
+}
@@ -4,12 +4,140 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use super::{JSObject, JSString, JSValue};
+use super::class::{instance, new_instance};
+use super::watchdog::Watchdog;
+#[cfg(feature = "property-keys")]
+use super::PropertyKey;
+use super::{
+    JSClass, JSContext, JSException, JSObject, JSString, JSValue, PropertyAttributes, PropertySpec,
+    ValueScope,
+};
 use crate::sys;
-use std::ops::Deref;
+use std::any::Any;
+use std::ops::{BitOr, BitOrAssign, Deref};
 use std::ptr;
 
 impl JSObject {
+    /// Creates a new, empty JavaScript object.
+    ///
+    /// * `ctx`: The execution context to use.
+    ///
+    /// The object's prototype is the default object prototype.
+    ///
+    /// ```
+    /// # use javascriptcore::{JSContext, JSObject};
+    /// let ctx = JSContext::default();
+    /// let o = JSObject::new(&ctx);
+    /// assert!(o.is_object());
+    /// assert_eq!(o.property_names().count(), 0);
+    /// ```
+    pub fn new(ctx: &JSContext) -> Self {
+        let raw = unsafe { sys::JSObjectMake(ctx.raw, ptr::null_mut(), ptr::null_mut()) };
+        JSObject::from_raw(ctx.raw, raw)
+    }
+
+    /// Creates a new object of a class.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `class`: The class of the object.
+    ///
+    /// The `initialize` callbacks of the class are called before this
+    /// returns.
+    pub fn new_with_class(ctx: &JSContext, class: &JSClass) -> Self {
+        let raw = unsafe { sys::JSObjectMake(ctx.raw, class.raw, new_instance(class, None)) };
+        JSObject::from_raw(ctx.raw, raw)
+    }
+
+    /// Creates a new object of a class that holds a Rust value.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `class`: The class of the object.
+    /// * `data`: The Rust value, which can be got back with
+    ///   [`get_native`].
+    ///
+    /// The value is dropped when the object is garbage collected, after
+    /// the `finalize` callbacks of the class have been called with it.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// struct Image {
+    ///     width: u32,
+    /// }
+    ///
+    /// let class = JSClassBuilder::new("Image").build();
+    /// let ctx = JSContext::default();
+    /// let o = JSObject::new_with_class_and_data(&ctx, &class, Image { width: 640 });
+    /// assert_eq!(o.get_native::<Image>().expect("image").width, 640);
+    /// ```
+    ///
+    /// [`get_native`]: #method.get_native
+    pub fn new_with_class_and_data<T>(ctx: &JSContext, class: &JSClass, data: T) -> Self
+    where
+        T: Any,
+    {
+        let instance = new_instance(class, Some(Box::new(data)));
+        let raw = unsafe { sys::JSObjectMake(ctx.raw, class.raw, instance) };
+        JSObject::from_raw(ctx.raw, raw)
+    }
+
+    /// Gives the object a Rust value, which can be got back with
+    /// [`get_native`].
+    ///
+    /// Only objects of classes built by a [`JSClassBuilder`] can hold a
+    /// value, including the global object of a context that was created
+    /// with such a class. An object holds a single value for its whole
+    /// life, so that references to it stay valid. If the object can not
+    /// hold a value or already has one, `value` is given back.
+    ///
+    /// The value is dropped when the object is garbage collected, like
+    /// the value of [`new_with_class_and_data`].
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let class = JSClassBuilder::new("Session").build();
+    /// let ctx = JSContext::default();
+    /// let o = JSObject::new_with_class(&ctx, &class);
+    /// assert_eq!(o.set_native(String::from("alice")), Ok(()));
+    /// assert_eq!(o.set_native(String::from("bob")), Err(String::from("bob")));
+    /// assert_eq!(o.get_native::<String>().map(String::as_str), Some("alice"));
+    /// assert_eq!(o.get_native::<u32>(), None);
+    ///
+    /// assert_eq!(JSObject::new(&ctx).set_native(1), Err(1));
+    /// ```
+    ///
+    /// [`get_native`]: #method.get_native
+    /// [`JSClassBuilder`]: struct.JSClassBuilder.html
+    /// [`new_with_class_and_data`]: #method.new_with_class_and_data
+    pub fn set_native<T>(&self, value: T) -> Result<(), T>
+    where
+        T: Any,
+    {
+        match unsafe { instance(self.value.ctx, self.raw) } {
+            Some(instance) => instance.set_data(value),
+            None => Err(value),
+        }
+    }
+
+    /// Gets the Rust value of the object.
+    ///
+    /// Returns `None` if the object has no value, or if its value is not
+    /// a `T`. The type of the value is checked, so a value is never
+    /// mistaken for one of another type.
+    pub fn get_native<T>(&self) -> Option<&T>
+    where
+        T: Any,
+    {
+        unsafe { instance(self.value.ctx, self.raw) }?.data()
+    }
+
+    /// Wraps a raw object belonging to `ctx`.
+    pub(crate) fn from_raw(ctx: sys::JSContextRef, raw: sys::JSObjectRef) -> Self {
+        JSObject {
+            raw,
+            value: JSValue { raw, ctx },
+        }
+    }
+
     /// Gets an iterator over the names of an object's enumerable properties.
     ///
     /// ```
@@ -61,7 +189,8 @@ impl JSObject {
     ///   the property's name.
     ///
     /// Returns the property's value if object has the property, otherwise
-    /// the undefined value.
+    /// the undefined value. Returns an exception if one was thrown, for
+    /// example by a getter.
     ///
     /// ```
     /// # use javascriptcore::{JSContext, JSObject, JSString, JSValue};
@@ -69,23 +198,136 @@ impl JSObject {
     /// let v = JSValue::new_from_json(&ctx, "{\"id\": 123}").expect("valid object");
     /// let o = v.as_object().expect("object");
     ///
-    /// let n = o.get_property("id");
+    /// let n = o.get_property("id").expect("property");
     /// assert!(n.is_number());
     /// // Remember that this will be an f64 now!
     /// assert_eq!(n.as_number().expect("number"), 123.0);
     /// ```
     ///
     /// [`JSString`]: struct.JSString.html
-    pub fn get_property<S>(&self, name: S) -> JSValue
+    pub fn get_property<S>(&self, name: S) -> Result<JSValue, JSException>
     where
         S: Into<JSString>,
     {
+        self.get_property_key(&name.into())
+    }
+
+    /// Gets a property from an object, by a name that has already been
+    /// converted to a [`JSString`].
+    ///
+    /// This is [`get_property`], but it borrows the name rather than
+    /// taking it, so a name can be kept and used for many objects
+    /// without converting it each time.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let id = JSString::from("id");
+    /// for json in ["{\"id\": 1}", "{\"id\": 2}"] {
+    ///     let o = JSValue::new_from_json(&ctx, json).expect("valid object").as_object().expect("object");
+    ///     assert!(o.get_property_key(&id).expect("property").is_number());
+    /// }
+    /// ```
+    ///
+    /// [`JSString`]: struct.JSString.html
+    /// [`get_property`]: #method.get_property
+    pub fn get_property_key(&self, name: &JSString) -> Result<JSValue, JSException> {
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        let v = unsafe { sys::JSObjectGetProperty(self.value.ctx, self.raw, name.raw, &mut e) };
+        self.value_or_exception(v, e)
+    }
+
+    /// Tests whether an object has the property with the key `key`,
+    /// like the `in` operator.
+    ///
+    /// Returns an exception if one was thrown, for example by a proxy.
+    ///
+    /// Requires the `property-keys` feature.
+    #[cfg(feature = "property-keys")]
+    pub fn has_property_for_key(&self, key: &PropertyKey) -> Result<bool, JSException> {
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        let has =
+            unsafe { sys::JSObjectHasPropertyForKey(self.value.ctx, self.raw, key.raw, &mut e) };
+        if e.is_null() {
+            Ok(has)
+        } else {
+            Err(JSException::from_raw(self.value.ctx, e))
+        }
+    }
+
+    /// Gets the property of an object with the key `key`.
+    ///
+    /// This is [`get_property`], but with a key that is ready to use.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let tag = evaluate_script(&ctx, "Symbol.toStringTag", None, "test.js", 1).expect("symbol");
+    /// let tag = PropertyKey::from_value(&tag).expect("key");
+    ///
+    /// let map = evaluate_script(&ctx, "new Map()", None, "test.js", 1).expect("map");
+    /// let name = map.as_object().expect("object").get_property_for_key(&tag).expect("tag");
+    /// assert_eq!(name.as_string().expect("string"), "Map");
+    /// ```
+    ///
+    /// Requires the `property-keys` feature.
+    ///
+    /// [`get_property`]: #method.get_property
+    #[cfg(feature = "property-keys")]
+    pub fn get_property_for_key(&self, key: &PropertyKey) -> Result<JSValue, JSException> {
         let mut e: sys::JSValueRef = ptr::null_mut();
         let v =
-            unsafe { sys::JSObjectGetProperty(self.value.ctx, self.raw, name.into().raw, &mut e) };
-        JSValue {
-            raw: v,
-            ctx: self.value.ctx,
+            unsafe { sys::JSObjectGetPropertyForKey(self.value.ctx, self.raw, key.raw, &mut e) };
+        self.value_or_exception(v, e)
+    }
+
+    /// Sets the property of an object with the key `key`.
+    ///
+    /// This is [`set_property`], but with a key that is ready to use.
+    ///
+    /// Requires the `property-keys` feature.
+    ///
+    /// [`set_property`]: #method.set_property
+    #[cfg(feature = "property-keys")]
+    pub fn set_property_for_key(
+        &self,
+        key: &PropertyKey,
+        value: JSValue,
+    ) -> Result<(), JSException> {
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        unsafe {
+            sys::JSObjectSetPropertyForKey(
+                self.value.ctx,
+                self.raw,
+                key.raw,
+                value.raw,
+                PropertyAttributes::NONE.raw(),
+                &mut e,
+            );
+        }
+        if e.is_null() {
+            Ok(())
+        } else {
+            Err(JSException::from_raw(self.value.ctx, e))
+        }
+    }
+
+    /// Deletes the property of an object with the key `key`, like the
+    /// `delete` operator.
+    ///
+    /// Returns whether the property is gone, which it is not if it can
+    /// not be deleted, or an exception if one was thrown.
+    ///
+    /// Requires the `property-keys` feature.
+    #[cfg(feature = "property-keys")]
+    pub fn delete_property_for_key(&self, key: &PropertyKey) -> Result<bool, JSException> {
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        let deleted =
+            unsafe { sys::JSObjectDeletePropertyForKey(self.value.ctx, self.raw, key.raw, &mut e) };
+        if e.is_null() {
+            Ok(deleted)
+        } else {
+            Err(JSException::from_raw(self.value.ctx, e))
         }
     }
 
@@ -94,7 +336,8 @@ impl JSObject {
     /// * `index`: An integer value that is the property's name.
     ///
     /// Returns the property's value if object has the property,
-    /// otherwise the undefined value.
+    /// otherwise the undefined value. Returns an exception if one was
+    /// thrown, for example by a getter.
     ///
     /// Calling `get_property_at_index` is equivalent to calling
     /// `get_property` with a string containing `index`,
@@ -107,9 +350,9 @@ impl JSObject {
     /// let v = JSValue::new_from_json(&ctx, "[3, true, \"abc\"]").expect("valid array");
     /// let o = v.as_object().expect("object");
     ///
-    /// let n = o.get_property_at_index(0).as_number().expect("number");
-    /// let b = o.get_property_at_index(1).as_boolean();
-    /// let s = o.get_property_at_index(2).as_string().expect("string");
+    /// let n = o.get_property_at_index(0).expect("property").as_number().expect("number");
+    /// let b = o.get_property_at_index(1).expect("property").as_boolean();
+    /// let s = o.get_property_at_index(2).expect("property").as_string().expect("string");
     ///
     /// assert_eq!(n, 3.0);
     /// assert_eq!(b, true);
@@ -125,18 +368,329 @@ impl JSObject {
     /// let o = v.as_object().expect("object");
     ///
     /// // There is no property "0", so this will be `undefined`:
-    /// assert!(o.get_property_at_index(0).is_undefined());
-    /// assert_eq!(o.get_property_at_index(1).as_boolean(), true);
-    /// assert_eq!(o.get_property_at_index(2).as_string().expect("string"), "abc");
+    /// assert!(o.get_property_at_index(0).expect("property").is_undefined());
+    /// assert_eq!(o.get_property_at_index(1).expect("property").as_boolean(), true);
+    /// let s = o.get_property_at_index(2).expect("property").as_string().expect("string");
+    /// assert_eq!(s, "abc");
     /// ```
-    pub fn get_property_at_index(&self, index: u32) -> JSValue {
+    pub fn get_property_at_index(&self, index: u32) -> Result<JSValue, JSException> {
         let mut e: sys::JSValueRef = ptr::null_mut();
         let v = unsafe { sys::JSObjectGetPropertyAtIndex(self.value.ctx, self.raw, index, &mut e) };
+        self.value_or_exception(v, e)
+    }
+
+    /// Gets the properties of an object that `properties` lists, in
+    /// the same order.
+    ///
+    /// This is like calling [`get_property`] or
+    /// [`get_property_at_index`] for each property, but the names have
+    /// already been converted, so reading the same properties of many
+    /// objects does not convert them again for each object.
+    ///
+    /// * `scope`: Keeps the values alive, since a getter of a later
+    ///   property may collect garbage while the values are in a `Vec`.
+    ///   One scope can keep the values of many objects.
+    /// * `properties`: The properties to get.
+    ///
+    /// Returns the first exception that is thrown, for example by a
+    /// getter.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let rows = evaluate_script(&ctx, "[{id: 1, name: 'a'}, {id: 2, name: 'b'}]", None, "test.js", 1)
+    ///     .expect("rows")
+    ///     .as_object()
+    ///     .expect("array");
+    /// let columns = [PropertySpec::from("id"), PropertySpec::from("name")];
+    /// let scope = ValueScope::new(&ctx);
+    ///
+    /// for i in 0..2 {
+    ///     let row = rows.get_property_at_index(i).expect("row").as_object().expect("object");
+    ///     let values = row.read(&scope, &columns).expect("values");
+    ///     assert_eq!(values[0].as_number().expect("number"), f64::from(i + 1));
+    ///     assert!(values[1].is_string());
+    /// }
+    /// ```
+    ///
+    /// [`get_property`]: #method.get_property
+    /// [`get_property_at_index`]: #method.get_property_at_index
+    pub fn read(
+        &self,
+        scope: &ValueScope,
+        properties: &[PropertySpec],
+    ) -> Result<Vec<JSValue>, JSException> {
+        let mut values = Vec::with_capacity(properties.len());
+        for property in properties {
+            let mut e: sys::JSValueRef = ptr::null_mut();
+            let v = unsafe {
+                match property {
+                    PropertySpec::Name(name) => {
+                        sys::JSObjectGetProperty(self.value.ctx, self.raw, name.raw, &mut e)
+                    }
+                    PropertySpec::Index(index) => {
+                        sys::JSObjectGetPropertyAtIndex(self.value.ctx, self.raw, *index, &mut e)
+                    }
+                }
+            };
+            values.push(scope.keep(self.value_or_exception(v, e)?)?);
+        }
+        Ok(values)
+    }
+
+    /// Sets a property on an object.
+    ///
+    /// * `name`: A value that can be converted to a [`JSString`] containing
+    ///   the property's name.
+    /// * `value`: A [`JSValue`] to use as the property's value.
+    ///
+    /// Returns an exception if one was thrown, for example by a setter.
+    ///
+    /// ```
+    /// # use javascriptcore::{JSContext, JSObject, JSValue};
+    /// let ctx = JSContext::default();
+    /// let o = JSObject::new(&ctx);
+    ///
+    /// o.set_property("id", JSValue::new_number(&ctx, 123.0)).expect("set");
+    /// assert_eq!(o.get_property("id").expect("property").as_number().expect("number"), 123.0);
+    /// ```
+    ///
+    /// [`JSString`]: struct.JSString.html
+    /// [`JSValue`]: struct.JSValue.html
+    pub fn set_property<S>(&self, name: S, value: JSValue) -> Result<(), JSException>
+    where
+        S: Into<JSString>,
+    {
+        self.set_property_key(&name.into(), value)
+    }
+
+    /// Sets a property on an object, by a name that has already been
+    /// converted to a [`JSString`].
+    ///
+    /// This is [`set_property`], but it borrows the name rather than
+    /// taking it, so a name can be kept and used for many objects
+    /// without converting it each time.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let id = JSString::from("id");
+    /// let objects = [JSObject::new(&ctx), JSObject::new(&ctx)];
+    /// for (i, o) in objects.iter().enumerate() {
+    ///     o.set_property_key(&id, JSValue::new_number(&ctx, i as f64)).expect("set");
+    /// }
+    /// assert_eq!(objects[1].get_property_key(&id).expect("id").as_number().expect("number"), 1.0);
+    /// ```
+    ///
+    /// [`JSString`]: struct.JSString.html
+    /// [`set_property`]: #method.set_property
+    pub fn set_property_key(&self, name: &JSString, value: JSValue) -> Result<(), JSException> {
+        self.set_property_key_with_attributes(name, value, PropertyAttributes::NONE)
+    }
+
+    /// Sets a property on an object, with attributes.
+    ///
+    /// * `name`: A value that can be converted to a [`JSString`] containing
+    ///   the property's name.
+    /// * `value`: A [`JSValue`] to use as the property's value.
+    /// * `attributes`: The attributes of the property.
+    ///
+    /// The attributes only apply if the object does not have the
+    /// property yet. Otherwise the property is assigned to, like with
+    /// [`set_property`].
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let o = JSObject::new(&ctx);
+    /// let v = JSValue::new_number(&ctx, 1.0);
+    /// o.set_property_with_attributes("version", v, PropertyAttributes::READ_ONLY)
+    ///     .expect("set");
+    ///
+    /// o.set_property("version", JSValue::new_number(&ctx, 2.0)).expect("set");
+    /// assert_eq!(o.get_property("version").expect("version").as_number().expect("number"), 1.0);
+    /// ```
+    ///
+    /// [`JSString`]: struct.JSString.html
+    /// [`JSValue`]: struct.JSValue.html
+    /// [`set_property`]: #method.set_property
+    pub fn set_property_with_attributes<S>(
+        &self,
+        name: S,
+        value: JSValue,
+        attributes: PropertyAttributes,
+    ) -> Result<(), JSException>
+    where
+        S: Into<JSString>,
+    {
+        self.set_property_key_with_attributes(&name.into(), value, attributes)
+    }
+
+    /// Sets a property on an object, with attributes, by a name that
+    /// has already been converted to a [`JSString`].
+    ///
+    /// This is [`set_property_with_attributes`], but it borrows the
+    /// name rather than taking it.
+    ///
+    /// [`JSString`]: struct.JSString.html
+    /// [`set_property_with_attributes`]: #method.set_property_with_attributes
+    pub fn set_property_key_with_attributes(
+        &self,
+        name: &JSString,
+        value: JSValue,
+        attributes: PropertyAttributes,
+    ) -> Result<(), JSException> {
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        unsafe {
+            sys::JSObjectSetProperty(
+                self.value.ctx,
+                self.raw,
+                name.raw,
+                value.raw,
+                attributes.raw(),
+                &mut e,
+            );
+        }
+        if e.is_null() {
+            Ok(())
+        } else {
+            Err(JSException::from_raw(self.value.ctx, e))
+        }
+    }
+
+    /// Gets the prototype of the object.
+    ///
+    /// This is `null` for objects without a prototype.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let o = evaluate_script(&ctx, "Object.create(null)", None, "test.js", 1).expect("object");
+    /// assert!(o.as_object().expect("object").prototype().is_null());
+    /// ```
+    pub fn prototype(&self) -> JSValue {
         JSValue {
-            raw: v,
+            raw: unsafe { sys::JSObjectGetPrototype(self.value.ctx, self.raw) },
             ctx: self.value.ctx,
         }
     }
+
+    /// Sets the prototype of the object.
+    ///
+    /// * `prototype`: The new prototype, which must be an object or
+    ///   `null`. Other values are ignored.
+    ///
+    /// Objects inherit the properties of their prototype, so this lets
+    /// an object of a class take on the methods of a JavaScript class.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let base = JSObject::new(&ctx);
+    /// base.set_property("greeting", JSValue::new_string(&ctx, "hi")).expect("set");
+    ///
+    /// let o = JSObject::new(&ctx);
+    /// o.set_prototype(&base);
+    /// assert_eq!(o.get_property("greeting").expect("greeting").as_string().expect("string"), "hi");
+    /// ```
+    pub fn set_prototype(&self, prototype: &JSValue) {
+        unsafe { sys::JSObjectSetPrototype(self.value.ctx, self.raw, prototype.raw) }
+    }
+
+    /// Tests whether an object can be called as a function.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let f = evaluate_script(&ctx, "(function () {})", None, "test.js", 1).expect("function");
+    /// assert!(f.as_object().expect("object").is_function());
+    /// assert!(!JSObject::new(&ctx).is_function());
+    /// ```
+    pub fn is_function(&self) -> bool {
+        unsafe { sys::JSObjectIsFunction(self.value.ctx, self.raw) }
+    }
+
+    /// Calls an object as a function.
+    ///
+    /// * `this`: The object to use as `this`, or `None` to use the
+    ///   global object as `this`.
+    /// * `arguments`: The arguments to pass to the function.
+    ///
+    /// Returns the value that the function returned, or the exception
+    /// that it threw. Calling an object that is not a function throws
+    /// a `TypeError`.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let f = evaluate_script(&ctx, "(function (a, b) { return a + b; })", None, "test.js", 1)
+    ///     .expect("function");
+    /// let f = f.as_object().expect("object");
+    ///
+    /// let args = [JSValue::new_number(&ctx, 1.0), JSValue::new_number(&ctx, 2.0)];
+    /// let sum = f.call_as_function(None, &args).expect("called");
+    /// assert_eq!(sum.as_number().expect("number"), 3.0);
+    /// ```
+    pub fn call_as_function(
+        &self,
+        this: Option<&JSObject>,
+        arguments: &[JSValue],
+    ) -> Result<JSValue, JSException> {
+        let arguments: Vec<sys::JSValueRef> = arguments.iter().map(|a| a.raw).collect();
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        let watchdog = Watchdog::for_group(unsafe { sys::JSContextGetGroup(self.value.ctx) });
+        let _running = watchdog.enter();
+        let v = unsafe {
+            sys::JSObjectCallAsFunction(
+                self.value.ctx,
+                self.raw,
+                this.map_or(ptr::null_mut(), |t| t.raw),
+                arguments.len(),
+                arguments.as_ptr(),
+                &mut e,
+            )
+        };
+        self.value_or_exception(v, e)
+    }
+
+    /// Calls an object as a constructor, as with `new`.
+    pub(crate) fn call_as_constructor(
+        &self,
+        arguments: &[JSValue],
+    ) -> Result<JSObject, JSException> {
+        let arguments: Vec<sys::JSValueRef> = arguments.iter().map(|a| a.raw).collect();
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        let watchdog = Watchdog::for_group(unsafe { sys::JSContextGetGroup(self.value.ctx) });
+        let _running = watchdog.enter();
+        let o = unsafe {
+            sys::JSObjectCallAsConstructor(
+                self.value.ctx,
+                self.raw,
+                arguments.len(),
+                arguments.as_ptr(),
+                &mut e,
+            )
+        };
+        self.value_or_exception(o as sys::JSValueRef, e)
+            .map(|_| JSObject::from_raw(self.value.ctx, o))
+    }
+
+    /// Turns the result of a JavaScriptCore call that may have thrown an
+    /// exception into a value or an exception.
+    fn value_or_exception(
+        &self,
+        v: sys::JSValueRef,
+        e: sys::JSValueRef,
+    ) -> Result<JSValue, JSException> {
+        if v.is_null() || !e.is_null() {
+            Err(JSException::from_raw(self.value.ctx, e))
+        } else {
+            Ok(JSValue {
+                raw: v,
+                ctx: self.value.ctx,
+            })
+        }
+    }
 }
 
 /// A `JSObject` can be dereferenced to return the underlying `JSValue`.
@@ -151,6 +705,122 @@ impl Deref for JSObject {
     }
 }
 
+/// A `JSObject` can be converted into the underlying `JSValue`.
+///
+/// This lets a `JSObject` be passed by value where a `JSValue`
+/// is expected, for example as the value of a property.
+impl From<JSObject> for JSValue {
+    fn from(object: JSObject) -> Self {
+        object.value
+    }
+}
+
+impl PropertyAttributes {
+    /// No special attributes.
+    pub const NONE: PropertyAttributes = PropertyAttributes(sys::kJSPropertyAttributeNone);
+    /// The property can not be assigned to.
+    pub const READ_ONLY: PropertyAttributes = PropertyAttributes(sys::kJSPropertyAttributeReadOnly);
+    /// The property is left out of `for...in` loops and `Object.keys`.
+    pub const DONT_ENUM: PropertyAttributes = PropertyAttributes(sys::kJSPropertyAttributeDontEnum);
+    /// The property can not be deleted.
+    pub const DONT_DELETE: PropertyAttributes =
+        PropertyAttributes(sys::kJSPropertyAttributeDontDelete);
+
+    /// Tests whether all of the attributes in `other` are set.
+    pub fn contains(self, other: PropertyAttributes) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Gets the attributes as JavaScriptCore takes them.
+    pub(crate) fn raw(self) -> sys::JSPropertyAttributes {
+        self.0
+    }
+}
+
+impl BitOr for PropertyAttributes {
+    type Output = PropertyAttributes;
+
+    fn bitor(self, other: PropertyAttributes) -> PropertyAttributes {
+        PropertyAttributes(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for PropertyAttributes {
+    fn bitor_assign(&mut self, other: PropertyAttributes) {
+        self.0 |= other.0;
+    }
+}
+
+#[cfg(feature = "property-keys")]
+impl PropertyKey {
+    /// Creates a key for the properties named `name`.
+    pub fn new<S: Into<JSString>>(ctx: &JSContext, name: S) -> Self {
+        let raw = unsafe { sys::JSValueMakeString(ctx.raw, name.into().raw) };
+        unsafe { sys::JSValueProtect(ctx.raw, raw) };
+        PropertyKey {
+            ctx: ctx.clone(),
+            raw,
+        }
+    }
+
+    /// Creates a key from a value, which must be a string or a symbol.
+    ///
+    /// Fails with a `TypeError` if the value is neither.
+    pub fn from_value(value: &JSValue) -> Result<Self, JSException> {
+        let ctx = unsafe { JSContext::from_context_ref(value.ctx) };
+        if !value.is_string() && !value.is_symbol() {
+            return Err(JSException::type_error(
+                &ctx,
+                "Expected a string or a symbol",
+            ));
+        }
+        unsafe { sys::JSValueProtect(ctx.raw, value.raw) };
+        Ok(PropertyKey {
+            ctx,
+            raw: value.raw,
+        })
+    }
+
+    /// Gets the key as a value, which is a string or a symbol.
+    pub fn value(&self) -> JSValue {
+        JSValue {
+            raw: self.raw,
+            ctx: self.ctx.raw,
+        }
+    }
+}
+
+#[cfg(feature = "property-keys")]
+impl Drop for PropertyKey {
+    fn drop(&mut self) {
+        unsafe { sys::JSValueUnprotect(self.ctx.raw, self.raw) };
+    }
+}
+
+impl From<JSString> for PropertySpec {
+    fn from(name: JSString) -> Self {
+        PropertySpec::Name(name)
+    }
+}
+
+impl<'s> From<&'s str> for PropertySpec {
+    fn from(name: &'s str) -> Self {
+        PropertySpec::Name(name.into())
+    }
+}
+
+impl From<String> for PropertySpec {
+    fn from(name: String) -> Self {
+        PropertySpec::Name(name.into())
+    }
+}
+
+impl From<u32> for PropertySpec {
+    fn from(index: u32) -> Self {
+        PropertySpec::Index(index)
+    }
+}
+
 pub struct JSObjectPropertyNameIter {
     raw: sys::JSPropertyNameArrayRef,
     idx: usize,
@@ -175,9 +845,26 @@ impl Iterator for JSObjectPropertyNameIter {
     }
 }
 
+/// Creates an array holding `values`.
+pub(crate) fn new_array(ctx: &JSContext, values: &[JSValue]) -> Result<JSObject, JSException> {
+    let values: Vec<sys::JSValueRef> = values.iter().map(|v| v.raw).collect();
+    let mut e: sys::JSValueRef = ptr::null_mut();
+    let raw = unsafe { sys::JSObjectMakeArray(ctx.raw, values.len(), values.as_ptr(), &mut e) };
+    if raw.is_null() {
+        Err(JSException::from_raw(ctx.raw, e))
+    } else {
+        Ok(JSObject::from_raw(ctx.raw, raw))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::super::{JSContext, JSValue};
+    #[cfg(feature = "property-keys")]
+    use super::super::PropertyKey;
+    use super::super::{
+        evaluate_script, garbage_collect, JSContext, JSObject, JSString, JSValue,
+        PropertyAttributes, PropertySpec, ValueScope,
+    };
 
     #[test]
     fn can_has_property() {
@@ -193,8 +880,96 @@ mod tests {
         let ctx = JSContext::default();
         let v = JSValue::new_from_json(&ctx, "{\"id\": 123}").expect("value");
         let o = v.as_object().expect("object");
-        assert!(o.get_property("id").is_number());
-        assert!(o.get_property("no-such-value").is_undefined());
+        assert!(o.get_property("id").expect("property").is_number());
+        assert!(o
+            .get_property("no-such-value")
+            .expect("property")
+            .is_undefined());
+
+        let v = evaluate_script(
+            &ctx,
+            "({ get id() { throw new Error('No id'); } })",
+            None,
+            "test.js",
+            1,
+        )
+        .expect("object");
+        let e = v
+            .as_object()
+            .expect("object")
+            .get_property("id")
+            .unwrap_err();
+        assert_eq!(e.message(), Some("No id"));
+    }
+
+    #[test]
+    fn can_use_property_keys() {
+        let ctx = JSContext::default();
+        let o = JSObject::new(&ctx);
+        let id = JSString::from("id");
+        assert!(o.get_property_key(&id).expect("property").is_undefined());
+
+        o.set_property_key(&id, JSValue::new_number(&ctx, 1.0))
+            .expect("set");
+        o.set_property_key_with_attributes(
+            &JSString::from("version"),
+            JSValue::new_number(&ctx, 2.0),
+            PropertyAttributes::READ_ONLY,
+        )
+        .expect("set");
+        o.set_property("version", JSValue::new_number(&ctx, 3.0))
+            .expect("set");
+
+        // The key can still be used after it has been borrowed.
+        assert_eq!(id, "id");
+        assert_eq!(
+            o.get_property_key(&id)
+                .expect("property")
+                .as_number()
+                .expect("number"),
+            1.0
+        );
+        assert_eq!(
+            o.get_property("version")
+                .expect("property")
+                .as_number()
+                .expect("number"),
+            2.0
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "property-keys")]
+    fn can_use_property_keys_for_values() {
+        let ctx = JSContext::default();
+        let o = JSObject::new(&ctx);
+        let name = PropertyKey::new(&ctx, "name");
+        let symbol = PropertyKey::from_value(&JSValue::new_symbol(&ctx, "hidden")).expect("key");
+        assert!(PropertyKey::from_value(&JSValue::new_number(&ctx, 1.0)).is_err());
+        assert!(symbol.value().is_symbol());
+        assert_eq!(name.value().as_string().expect("string"), "name");
+
+        assert!(!o.has_property_for_key(&name).expect("has"));
+        o.set_property_for_key(&name, JSValue::new_number(&ctx, 1.0))
+            .expect("set");
+        o.set_property_for_key(&symbol, JSValue::new_number(&ctx, 2.0))
+            .expect("set");
+        garbage_collect(&ctx);
+
+        assert!(o.has_property_for_key(&name).expect("has"));
+        assert!(o.has_property("name"));
+        assert_eq!(
+            o.get_property_for_key(&symbol)
+                .expect("property")
+                .as_number()
+                .expect("number"),
+            2.0
+        );
+        // Symbols are not names.
+        assert_eq!(o.property_names().count(), 1);
+
+        assert!(o.delete_property_for_key(&symbol).expect("deleted"));
+        assert!(!o.has_property_for_key(&symbol).expect("has"));
     }
 
     #[test]
@@ -202,10 +977,91 @@ mod tests {
         let ctx = JSContext::default();
         let v = JSValue::new_from_json(&ctx, "[3, true, \"abc\"]").expect("value");
         let o = v.as_object().expect("object");
-        assert!(o.get_property_at_index(0).is_number());
-        assert!(o.get_property_at_index(1).is_boolean());
-        assert!(o.get_property_at_index(2).is_string());
-        assert!(o.get_property_at_index(5).is_undefined());
+        assert!(o.get_property_at_index(0).expect("property").is_number());
+        assert!(o.get_property_at_index(1).expect("property").is_boolean());
+        assert!(o.get_property_at_index(2).expect("property").is_string());
+        assert!(o.get_property_at_index(5).expect("property").is_undefined());
+    }
+
+    #[test]
+    fn can_read() {
+        let ctx = JSContext::default();
+        let v = evaluate_script(
+            &ctx,
+            "({ id: 1, 0: 'zero', get broken() { throw new Error('Broken'); } })",
+            None,
+            "test.js",
+            1,
+        )
+        .expect("object");
+        let o = v.as_object().expect("object");
+
+        let properties = [
+            PropertySpec::from("id"),
+            PropertySpec::from(0),
+            PropertySpec::from(String::from("missing")),
+            PropertySpec::from(JSString::from("id")),
+        ];
+        let scope = ValueScope::new(&ctx);
+        let values = o.read(&scope, &properties).expect("values");
+        garbage_collect(&ctx);
+        assert_eq!(values.len(), 4);
+        assert_eq!(values[0].as_number().expect("number"), 1.0);
+        assert_eq!(values[1].as_string().expect("string"), "zero");
+        assert!(values[2].is_undefined());
+        assert_eq!(values[3].as_number().expect("number"), 1.0);
+        assert_eq!(scope.len(), 4);
+        assert!(o.read(&scope, &[]).expect("values").is_empty());
+
+        let e = o
+            .read(
+                &scope,
+                &[PropertySpec::from("id"), PropertySpec::from("broken")],
+            )
+            .unwrap_err();
+        assert_eq!(e.message(), Some("Broken"));
+    }
+
+    #[test]
+    fn can_set_property() {
+        let ctx = JSContext::default();
+        let o = JSObject::new(&ctx);
+        assert!(!o.has_property("id"));
+        o.set_property("id", JSValue::new_number(&ctx, 123.0))
+            .expect("set");
+        assert!(o.has_property("id"));
+        assert_eq!(
+            o.get_property("id")
+                .expect("property")
+                .as_number()
+                .expect("number"),
+            123.0
+        );
+    }
+
+    #[test]
+    fn can_call_as_function() {
+        let ctx = JSContext::default();
+        let v = evaluate_script(
+            &ctx,
+            "(function (x) { return this.y + x; })",
+            None,
+            "test.js",
+            1,
+        )
+        .expect("function");
+        let f = v.as_object().expect("object");
+        assert!(f.is_function());
+
+        let this = JSObject::new(&ctx);
+        this.set_property("y", JSValue::new_number(&ctx, 1.0))
+            .expect("set");
+        let r = f.call_as_function(Some(&this), &[JSValue::new_number(&ctx, 2.0)]);
+        assert_eq!(r.expect("called").as_number().expect("number"), 3.0);
+
+        let o = JSObject::new(&ctx);
+        assert!(!o.is_function());
+        assert!(o.call_as_function(None, &[]).is_err());
     }
 
     #[test]
@@ -0,0 +1,248 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::base::evaluate_script;
+use super::exception::error;
+use super::{JSContext, JSException, JSObject};
+
+/// Records the state of the global object and the built-in objects, and
+/// returns a function that restores it.
+///
+/// The built-ins that the restore function needs are looked up while
+/// recording, so that scripts can not interfere with a reset by
+/// replacing them. The same goes for the descriptors, which get a `null`
+/// prototype so that properties added to `Object.prototype` do not leak
+/// into them. Arrays are walked by index for the same reason.
+const SNAPSHOT: &str = r#"(function (global) {
+    'use strict';
+
+    const { apply, defineProperty, deleteProperty, getPrototypeOf, ownKeys, set, setPrototypeOf } =
+        Reflect;
+    const { getOwnPropertyDescriptor } = Object;
+    const has = Set.prototype.has;
+    const add = Set.prototype.add;
+
+    const seen = new Set();
+    const records = [];
+    const record = (object) => {
+        if (object === null || (typeof object !== 'object' && typeof object !== 'function') ||
+            apply(has, seen, [object])) {
+            return;
+        }
+        apply(add, seen, [object]);
+        const keys = ownKeys(object);
+        const descriptors = [];
+        for (let i = 0; i < keys.length; i++) {
+            const descriptor = getOwnPropertyDescriptor(object, keys[i]);
+            setPrototypeOf(descriptor, null);
+            descriptors[i] = descriptor;
+        }
+        records[records.length] = {
+            object,
+            keys,
+            keySet: new Set(keys),
+            descriptors,
+            prototype: getPrototypeOf(object),
+        };
+    };
+
+    record(global);
+    const globals = ownKeys(global);
+    for (let i = 0; i < globals.length; i++) {
+        const descriptor = getOwnPropertyDescriptor(global, globals[i]);
+        if ('value' in descriptor) {
+            record(descriptor.value);
+            if (typeof descriptor.value === 'function') {
+                record(descriptor.value.prototype);
+            }
+        }
+    }
+    // Intrinsics that are not reachable from a global.
+    const iterator = getPrototypeOf([][Symbol.iterator]());
+    record(iterator);
+    record(getPrototypeOf(iterator));
+    for (const f of [function* () {}, async function () {}, async function* () {}]) {
+        record(getPrototypeOf(f));
+        record(getPrototypeOf(f.prototype));
+    }
+
+    return () => {
+        let restored = true;
+        for (let i = 0; i < records.length; i++) {
+            const { object, keys, keySet, descriptors, prototype } = records[i];
+            const current = ownKeys(object);
+            for (let j = 0; j < current.length; j++) {
+                const key = current[j];
+                // Global variables declared with `var` can not be
+                // deleted, so they are cleared instead.
+                if (!apply(has, keySet, [key]) && !deleteProperty(object, key)) {
+                    set(object, key, undefined);
+                }
+            }
+            for (let j = 0; j < keys.length; j++) {
+                restored = defineProperty(object, keys[j], descriptors[j]) && restored;
+            }
+            restored = setPrototypeOf(object, prototype) && restored;
+        }
+        return restored;
+    };
+})"#;
+
+impl JSContext {
+    /// Records the current state of this context as the state that
+    /// [`reset`] restores.
+    ///
+    /// A context can only be reset once this has been called. Recording
+    /// the state walks the global object and the built-in objects, so it
+    /// is left to the contexts that are to be reset. Once the host has
+    /// set up the context, such as by installing host functions, calling
+    /// this records that as well, so that it survives a reset. It is
+    /// meant to be called before any untrusted scripts run.
+    ///
+    /// The state is recorded for the context, and so is shared by all of
+    /// the `JSContext`s that refer to it.
+    ///
+    /// [`reset`]: #method.reset
+    pub fn mark_pristine(&self) -> Result<(), JSException> {
+        let snapshot = evaluate_script(self, SNAPSHOT, None, "snapshot.js", 1)?.as_object()?;
+        let restore = snapshot
+            .call_as_function(None, &[self.global_object().into()])?
+            .as_object()?;
+        self.hooks.set_pristine(restore.raw);
+        Ok(())
+    }
+
+    /// Restores this context to its pristine state, which is the state
+    /// that was last recorded by [`mark_pristine`].
+    ///
+    /// Global variables that scripts have added are removed, and the
+    /// properties and prototypes of the global object and the built-in
+    /// objects, such as `Array.prototype` or `JSON`, are restored. This
+    /// lets a context be reused for one job after another without state
+    /// leaking from one job to the next.
+    ///
+    /// There are some limits to what can be restored:
+    ///
+    /// * Global variables declared with `var` can not be removed, so they
+    ///   are set to `undefined` instead.
+    /// * Top-level `let`, `const` and `class` declarations can not be
+    ///   reached at all. Scripts that are to run again after a reset
+    ///   should not declare these at the top level.
+    /// * Objects that a script has frozen or sealed can not be changed
+    ///   back. In that case, `reset` restores what it can and then fails.
+    ///
+    /// Fails with an `Error` if no state has been recorded with
+    /// `mark_pristine`.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let host = JSValue::new_number(&ctx, 1.0);
+    /// ctx.global_object().set_property("host", host).expect("set");
+    /// ctx.mark_pristine().expect("marked");
+    ///
+    /// evaluate_script(&ctx, "globalThis.leak = 1; Array.prototype.x = 2", None, "job.js", 1)
+    ///     .expect("job ran");
+    /// ctx.reset().expect("reset");
+    ///
+    /// let script = "typeof host + ' ' + typeof leak + ' ' + typeof [].x";
+    /// let r = evaluate_script(&ctx, script, None, "test.js", 1).expect("evaluated");
+    /// assert_eq!(r.as_string().expect("string"), "number undefined undefined");
+    /// ```
+    ///
+    /// [`mark_pristine`]: #method.mark_pristine
+    pub fn reset(&self) -> Result<(), JSException> {
+        let restore = self.hooks.pristine();
+        if restore.is_null() {
+            return Err(error(self, "The context has not been marked as pristine"));
+        }
+        let restored = JSObject::from_raw(self.raw, restore).call_as_function(None, &[])?;
+        if restored.as_boolean() {
+            Ok(())
+        } else {
+            Err(error(self, "Some built-in objects could not be restored"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSContext, JSContextGroup, JSValue};
+
+    fn eval(ctx: &JSContext, script: &str) -> String {
+        evaluate_script(ctx, script, None, "test.js", 1)
+            .unwrap()
+            .as_string()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn reset() {
+        let ctx = JSContext::default();
+        // Contexts can only be reset once they are marked.
+        assert!(ctx.reset().is_err());
+        ctx.mark_pristine().unwrap();
+        eval(&ctx, "globalThis.added = 1; Math.max = null; ''");
+        ctx.reset().unwrap();
+        assert_eq!(
+            eval(&ctx, "typeof added + ' ' + typeof Math.max"),
+            "undefined function"
+        );
+
+        // Host modifications made before marking are kept.
+        ctx.global_object()
+            .set_property("host", JSValue::new_number(&ctx, 1.0))
+            .unwrap();
+        ctx.mark_pristine().unwrap();
+
+        eval(
+            &ctx,
+            "globalThis.added = 1;
+             var declared = 2;
+             host = 3;
+             Array.prototype.map = null;
+             Object.prototype.polluted = true;
+             Object.setPrototypeOf(Math, null);
+             delete globalThis.JSON;
+             Reflect.defineProperty = null;
+             ''",
+        );
+        ctx.reset().unwrap();
+        assert_eq!(
+            eval(
+                &ctx,
+                "[typeof added, typeof declared, host, typeof [].map, typeof ({}).polluted,
+                  Object.getPrototypeOf(Math) === Object.prototype, typeof JSON].join()"
+            ),
+            "undefined,undefined,1,function,undefined,true,object"
+        );
+
+        // Contexts can be reset again and again, through any
+        // `JSContext` for them.
+        eval(&ctx, "globalThis.added = 1; ''");
+        ctx.clone().reset().unwrap();
+        assert_eq!(eval(&ctx, "typeof added + ' ' + host"), "undefined 1");
+
+        let group = JSContextGroup::new();
+        let ctx = group.new_context();
+        ctx.mark_pristine().unwrap();
+        eval(&ctx, "globalThis.added = 1; ''");
+        ctx.reset().unwrap();
+        assert_eq!(eval(&ctx, "typeof added"), "undefined");
+    }
+
+    #[test]
+    fn reset_frozen() {
+        let ctx = JSContext::default();
+        ctx.mark_pristine().unwrap();
+        eval(
+            &ctx,
+            "String.prototype.x = 1; Object.freeze(String.prototype); ''",
+        );
+        assert!(ctx.reset().is_err());
+    }
+}
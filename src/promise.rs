@@ -0,0 +1,1065 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::exception::error;
+use super::function::new_function;
+use super::object::new_array;
+use super::{IntoJsResult, JSContext, JSDeferred, JSException, JSObject, JSPromise, JSValue};
+use crate::sys;
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+#[cfg(feature = "async")]
+use std::future::IntoFuture;
+use std::ops::Deref;
+use std::pin::Pin;
+#[cfg(feature = "deferred-promises")]
+use std::ptr;
+use std::rc::{Rc, Weak};
+#[cfg(feature = "async")]
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+/// Runs the futures of async native functions.
+///
+/// JavaScriptCore has no event loop of its own, so the host must provide
+/// one. The futures settle promises of a context, so they are not `Send`
+/// and must be polled on the thread that owns the context, for example
+/// by a `LocalSet` of Tokio or a `LocalPool` of the `futures` crate.
+pub trait Spawner {
+    /// Starts running `future` in the background.
+    ///
+    /// If the future is dropped before it completes, its promise is
+    /// rejected.
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()>>>);
+}
+
+/// Makes a function that creates a promise along with the functions
+/// that settle it, for JavaScriptCores without
+/// `JSObjectMakeDeferredPromise`.
+///
+/// The promise constructor is that of the promises of async functions,
+/// so a script that replaces the global `Promise` does not change it.
+#[cfg(not(feature = "deferred-promises"))]
+const MAKE_DEFERRED: &str = r#"(function () {
+    'use strict';
+    const Promise = (async () => {})().constructor;
+    return function () {
+        let resolve, reject;
+        const promise = new Promise((res, rej) => {
+            resolve = res;
+            reject = rej;
+        });
+        return [promise, resolve, reject];
+    };
+})()"#;
+
+impl JSDeferred {
+    /// Creates a pending promise and the means to settle it.
+    fn new(ctx: &JSContext) -> Result<(JSPromise, JSDeferred), JSException> {
+        let (promise, resolve, reject) = JSDeferred::make(ctx)?;
+        unsafe {
+            sys::JSValueProtect(ctx.raw, resolve);
+            sys::JSValueProtect(ctx.raw, reject);
+        }
+        let deferred = JSDeferred {
+            ctx: ctx.clone(),
+            functions: Some((resolve, reject)),
+        };
+        let promise = JSPromise {
+            object: JSObject::from_raw(ctx.raw, promise),
+        };
+        Ok((promise, deferred))
+    }
+
+    /// Creates a pending promise and its resolve and reject functions.
+    #[cfg(feature = "deferred-promises")]
+    fn make(
+        ctx: &JSContext,
+    ) -> Result<(sys::JSObjectRef, sys::JSObjectRef, sys::JSObjectRef), JSException> {
+        let mut resolve: sys::JSObjectRef = ptr::null_mut();
+        let mut reject: sys::JSObjectRef = ptr::null_mut();
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        let promise =
+            unsafe { sys::JSObjectMakeDeferredPromise(ctx.raw, &mut resolve, &mut reject, &mut e) };
+        if promise.is_null() {
+            return Err(JSException::from_raw(ctx.raw, e));
+        }
+        Ok((promise, resolve, reject))
+    }
+
+    /// Creates a pending promise and its resolve and reject functions.
+    #[cfg(not(feature = "deferred-promises"))]
+    fn make(
+        ctx: &JSContext,
+    ) -> Result<(sys::JSObjectRef, sys::JSObjectRef, sys::JSObjectRef), JSException> {
+        let make = ctx.hooks.function(ctx, MAKE_DEFERRED, "promise.js")?;
+        let parts = make.call_as_function(None, &[])?.as_object()?;
+        let part = |i| -> Result<sys::JSObjectRef, JSException> {
+            Ok(parts.get_property_at_index(i)?.as_object()?.raw)
+        };
+        Ok((part(0)?, part(1)?, part(2)?))
+    }
+
+    /// Resolves the promise with `value`.
+    ///
+    /// If `value` is a promise or another thenable, the promise follows
+    /// it instead.
+    pub fn resolve(self, value: JSValue) {
+        self.settle(Ok(value));
+    }
+
+    /// Rejects the promise with the value of `error`.
+    pub fn reject(self, error: JSException) {
+        self.settle(Err(error));
+    }
+
+    /// Resolves the promise with the value of `result`, or rejects it
+    /// with its exception.
+    pub fn settle(mut self, result: Result<JSValue, JSException>) {
+        self.settle_with(result);
+    }
+
+    /// Spawns `future`, and settles the promise with its output once it
+    /// completes.
+    fn settle_with_future<S, T>(self, future: T, spawner: &S)
+    where
+        S: Spawner + ?Sized,
+        T: Future + 'static,
+        T::Output: IntoJsResult,
+    {
+        spawner.spawn(Box::pin(async move {
+            let output = future.await;
+            let result = output.into_js_result(&self.ctx);
+            self.settle(result);
+        }));
+    }
+
+    fn settle_with(&mut self, result: Result<JSValue, JSException>) {
+        if let Some((resolve, reject)) = self.functions.take() {
+            let ctx = self.ctx.raw;
+            let (function, value) = match result {
+                Ok(value) => (resolve, value),
+                Err(e) => (reject, e.into_value()),
+            };
+            // The functions of a deferred promise do not throw.
+            let _ = JSObject::from_raw(ctx, function).call_as_function(None, &[value]);
+            unsafe {
+                sys::JSValueUnprotect(ctx, resolve);
+                sys::JSValueUnprotect(ctx, reject);
+            }
+        }
+    }
+}
+
+impl Drop for JSDeferred {
+    fn drop(&mut self) {
+        if self.functions.is_some() {
+            let e = error(&self.ctx, "The operation was dropped before it completed");
+            self.settle_with(Err(e));
+        }
+    }
+}
+
+impl JSPromise {
+    /// Creates a pending promise, and the [`JSDeferred`] that settles it.
+    ///
+    /// This lets host APIs that take callbacks return a promise, which
+    /// they settle once their result is known.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let (promise, deferred) = JSPromise::deferred(&ctx).expect("promise");
+    /// ctx.global_object().set_property("ready", promise.into()).expect("set");
+    /// evaluate_script(&ctx, "ready.then(v => globalThis.answer = v)", None, "test.js", 1)
+    ///     .expect("then");
+    ///
+    /// deferred.resolve(JSValue::new_number(&ctx, 42.0));
+    /// let answer = evaluate_script(&ctx, "answer", None, "test.js", 1).expect("answer");
+    /// assert_eq!(answer.as_number().expect("number"), 42.0);
+    /// ```
+    ///
+    /// [`JSDeferred`]: struct.JSDeferred.html
+    pub fn deferred(ctx: &JSContext) -> Result<(JSPromise, JSDeferred), JSException> {
+        JSDeferred::new(ctx)
+    }
+
+    /// Creates a promise that a Rust future settles.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `future`: The future to run. Its output is converted with
+    ///   [`IntoJsResult`] to resolve the promise, or to reject it with an
+    ///   error.
+    /// * `spawner`: Runs the future.
+    ///
+    /// As with [`JSObject::new_async_function`], the future should not
+    /// hold JavaScript values, and the promise is rejected if the future
+    /// is dropped before it completes.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// # use std::cell::RefCell;
+    /// # use std::future::Future;
+    /// # use std::pin::Pin;
+    /// # use std::task::{Context, Waker};
+    /// #[derive(Default)]
+    /// struct Queue(RefCell<Vec<Pin<Box<dyn Future<Output = ()>>>>>);
+    ///
+    /// impl Spawner for Queue {
+    ///     fn spawn(&self, future: Pin<Box<dyn Future<Output = ()>>>) {
+    ///         self.0.borrow_mut().push(future);
+    ///     }
+    /// }
+    ///
+    /// let ctx = JSContext::default();
+    /// let queue = Queue::default();
+    /// let config = JSPromise::from_future(&ctx, async { "loaded".to_owned() }, &queue)
+    ///     .expect("promise");
+    /// ctx.global_object().set_property("config", config.into()).expect("set");
+    /// evaluate_script(&ctx, "config.then(c => globalThis.c = c)", None, "test.js", 1)
+    ///     .expect("then");
+    ///
+    /// for mut future in queue.0.take() {
+    ///     assert!(future.as_mut().poll(&mut Context::from_waker(Waker::noop())).is_ready());
+    /// }
+    /// let c = evaluate_script(&ctx, "c", None, "test.js", 1).expect("c");
+    /// assert_eq!(c.as_string().expect("string").to_string(), "loaded");
+    /// ```
+    ///
+    /// [`IntoJsResult`]: trait.IntoJsResult.html
+    /// [`JSObject::new_async_function`]: struct.JSObject.html#method.new_async_function
+    pub fn from_future<S, T>(
+        ctx: &JSContext,
+        future: T,
+        spawner: &S,
+    ) -> Result<JSPromise, JSException>
+    where
+        S: Spawner + ?Sized,
+        T: Future + 'static,
+        T::Output: IntoJsResult,
+    {
+        let (promise, deferred) = JSDeferred::new(ctx)?;
+        deferred.settle_with_future(future, spawner);
+        Ok(promise)
+    }
+
+    /// Creates a promise that is fulfilled with an array of the values of
+    /// `promises` once they are all fulfilled, or rejected with the
+    /// reason of the first of them that is rejected.
+    ///
+    /// This calls `all` of the `Promise` constructor of the context,
+    /// found through the prototype of its promises, so a script that
+    /// replaces the global `Promise` does not change it.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let (first, resolve_first) = JSPromise::deferred(&ctx).expect("promise");
+    /// let (second, resolve_second) = JSPromise::deferred(&ctx).expect("promise");
+    /// let all = JSPromise::all(&ctx, [first, second]).expect("all");
+    /// ctx.global_object().set_property("all", all.into()).expect("set");
+    /// evaluate_script(&ctx, "all.then(v => globalThis.values = v.join())", None, "test.js", 1)
+    ///     .expect("then");
+    ///
+    /// resolve_second.resolve(JSValue::new_number(&ctx, 2.0));
+    /// resolve_first.resolve(JSValue::new_number(&ctx, 1.0));
+    /// let values = evaluate_script(&ctx, "values", None, "test.js", 1).expect("values");
+    /// assert_eq!(values.as_string().expect("string"), "1,2");
+    /// ```
+    pub fn all<I>(ctx: &JSContext, promises: I) -> Result<JSPromise, JSException>
+    where
+        I: IntoIterator<Item = JSPromise>,
+    {
+        JSPromise::combine(ctx, "all", promises)
+    }
+
+    /// Creates a promise that is settled in the same way as the first of
+    /// `promises` to settle.
+    ///
+    /// Like [`all`], this uses the `Promise` constructor of the context.
+    /// The promise never settles if `promises` is empty.
+    ///
+    /// [`all`]: #method.all
+    pub fn race<I>(ctx: &JSContext, promises: I) -> Result<JSPromise, JSException>
+    where
+        I: IntoIterator<Item = JSPromise>,
+    {
+        JSPromise::combine(ctx, "race", promises)
+    }
+
+    /// Calls the combinator `name` of the `Promise` constructor with an
+    /// array of `promises`.
+    fn combine<I>(ctx: &JSContext, name: &str, promises: I) -> Result<JSPromise, JSException>
+    where
+        I: IntoIterator<Item = JSPromise>,
+    {
+        let promises: Vec<JSValue> = promises.into_iter().map(JSValue::from).collect();
+        let array = new_array(ctx, &promises)?;
+        // Any promise will do to find the constructor. It is resolved so
+        // that it is not reported as an unhandled rejection.
+        let (promise, deferred) = JSDeferred::new(ctx)?;
+        deferred.resolve(JSValue::new_undefined(ctx));
+        let constructor = promise
+            .prototype()
+            .as_object()?
+            .get_property("constructor")?;
+        let constructor = constructor.as_object()?;
+        let combinator = constructor.get_property(name)?.as_object()?;
+        let combined = combinator.call_as_function(Some(&constructor), &[array.into()])?;
+        // The global `Promise` may have been replaced, so the result is
+        // checked against the prototype that was found instead.
+        if !inherits_from(ctx.raw, combined.raw, promise.prototype().raw) {
+            return Err(JSException::type_error(ctx, "Value is not a promise"));
+        }
+        Ok(JSPromise {
+            object: combined.as_object()?,
+        })
+    }
+
+    /// Calls `callback` once the promise is settled, with its value if
+    /// it is fulfilled or its reason if it is rejected.
+    ///
+    /// The callback is registered with `then`, so it runs as a job of
+    /// the context, after the code that is running when the promise is
+    /// settled. It is dropped without being called if the promise never
+    /// settles.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// # use std::cell::Cell;
+    /// # use std::rc::Rc;
+    /// let ctx = JSContext::default();
+    /// let v = evaluate_script(&ctx, "Promise.resolve(42)", None, "test.js", 1).expect("promise");
+    /// let promise = v.as_promise().expect("promise");
+    ///
+    /// let answer = Rc::new(Cell::new(0.0));
+    /// let result = answer.clone();
+    /// promise
+    ///     .on_settled(move |_ctx, value| result.set(value.unwrap().as_number().unwrap()))
+    ///     .expect("registered");
+    /// assert_eq!(answer.get(), 42.0);
+    /// ```
+    pub fn on_settled<F>(&self, callback: F) -> Result<(), JSException>
+    where
+        F: FnOnce(&JSContext, Result<JSValue, JSException>) + 'static,
+    {
+        let ctx = unsafe { JSContext::from_context_ref(self.value.ctx) };
+        // Only one of the functions is ever called, and only once.
+        let callback = Rc::new(Cell::new(Some(callback)));
+        let reaction = |fulfilled: bool| {
+            let callback = callback.clone();
+            new_function(&ctx, move |ctx, _this, arguments| {
+                let value = arguments.first().map_or_else(
+                    || JSValue::new_undefined(ctx),
+                    |value| JSValue {
+                        raw: value.raw,
+                        ctx: ctx.raw,
+                    },
+                );
+                if let Some(callback) = callback.take() {
+                    callback(
+                        ctx,
+                        if fulfilled {
+                            Ok(value)
+                        } else {
+                            Err(JSException::new(value))
+                        },
+                    );
+                }
+                Ok(JSValue::new_undefined(ctx))
+            })
+        };
+        let then = self.get_property("then")?.as_object()?;
+        then.call_as_function(Some(self), &[reaction(true).into(), reaction(false).into()])?;
+        Ok(())
+    }
+}
+
+/// Evaluates to a promise whose prototype is the `Promise.prototype`
+/// that the context was created with, since async functions always use
+/// it, even if a script has replaced the global `Promise`.
+const INTRINSIC_PROMISE: &str = "(async () => {})()";
+
+/// Tests whether `prototype` is on the prototype chain of `value`, as
+/// `instanceof` does, without reading properties that scripts can
+/// replace.
+fn inherits_from(
+    ctx: sys::JSContextRef,
+    value: sys::JSValueRef,
+    prototype: sys::JSValueRef,
+) -> bool {
+    let mut object = value;
+    while unsafe { sys::JSValueIsObject(ctx, object) } {
+        object = unsafe { sys::JSObjectGetPrototype(ctx, object as sys::JSObjectRef) };
+        if object == prototype {
+            return true;
+        }
+    }
+    false
+}
+
+impl JSValue {
+    /// Gets the promise that this value is.
+    ///
+    /// A value is a promise if the `Promise.prototype` that its context
+    /// was created with is on its prototype chain. Replacing the global
+    /// `Promise` does not change that.
+    ///
+    /// Fails with a `TypeError` if the value is not a `Promise`.
+    pub fn as_promise(&self) -> Result<JSPromise, JSException> {
+        let ctx = unsafe { JSContext::from_context_ref(self.ctx) };
+        let intrinsic = ctx.hooks.function(&ctx, INTRINSIC_PROMISE, "promise.js")?;
+        if !inherits_from(self.ctx, self.raw, intrinsic.prototype().raw) {
+            return Err(JSException::type_error(&ctx, "Value is not a promise"));
+        }
+        Ok(JSPromise {
+            object: JSObject::from_raw(self.ctx, self.raw as sys::JSObjectRef),
+        })
+    }
+}
+
+/// A future that completes when a promise settles, with its value if it
+/// is fulfilled or its reason if it is rejected.
+///
+/// It is created by awaiting a [`JSPromise`], or by calling
+/// `into_future` on one. The promise is settled by the jobs of its
+/// context, which JavaScriptCore runs whenever the host calls into it,
+/// such as when a timer fires or a script is evaluated. The future must
+/// be polled on the thread of the context.
+///
+/// Requires the `async` feature.
+///
+/// ```
+/// # use javascriptcore::*;
+/// # use std::future::{Future, IntoFuture};
+/// # use std::pin::pin;
+/// # use std::task::{Context, Poll, Waker};
+/// let ctx = JSContext::default();
+/// let (promise, deferred) = JSPromise::deferred(&ctx).expect("promise");
+/// let mut future = pin!(promise.into_future());
+/// let mut cx = Context::from_waker(Waker::noop());
+/// assert!(future.as_mut().poll(&mut cx).is_pending());
+///
+/// deferred.resolve(JSValue::new_number(&ctx, 42.0));
+/// let value = match future.poll(&mut cx) {
+///     Poll::Ready(result) => result.expect("fulfilled"),
+///     Poll::Pending => unreachable!(),
+/// };
+/// assert_eq!(value.as_number().expect("number"), 42.0);
+/// ```
+///
+/// [`JSPromise`]: struct.JSPromise.html
+#[cfg(feature = "async")]
+pub struct PromiseFuture {
+    settlement: Rc<RefCell<Settlement>>,
+}
+
+/// The result of a promise, kept by a reaction to it until Rust code
+/// that waits for it takes it.
+#[derive(Default)]
+struct Settlement {
+    /// Whether the promise was fulfilled, and its value or reason, which
+    /// is protected from garbage collection until it is taken.
+    result: Option<(bool, JSValue)>,
+    /// The task that waits for the result.
+    #[cfg(feature = "async")]
+    waker: Option<Waker>,
+}
+
+impl Settlement {
+    /// Starts watching `promise`, for as long as the returned settlement
+    /// is kept.
+    fn watch(promise: &JSPromise) -> Rc<RefCell<Settlement>> {
+        let settlement = Rc::new(RefCell::new(Settlement::default()));
+        // The reactions only hold on to the settlement while the waiter
+        // does, so that nothing is protected once it is dropped.
+        let weak: Weak<RefCell<Settlement>> = Rc::downgrade(&settlement);
+        let registered = promise.on_settled(move |ctx, result| {
+            if let Some(settlement) = weak.upgrade() {
+                settlement.borrow_mut().settle(ctx, result);
+            }
+        });
+        if let Err(e) = registered {
+            let ctx = unsafe { JSContext::from_context_ref(promise.value.ctx) };
+            settlement.borrow_mut().settle(&ctx, Err(e));
+        }
+        settlement
+    }
+
+    /// Keeps the result of the promise, and wakes the task that waits
+    /// for it.
+    fn settle(&mut self, ctx: &JSContext, result: Result<JSValue, JSException>) {
+        let result = match result {
+            Ok(value) => (true, value),
+            Err(e) => (false, e.into_value()),
+        };
+        unsafe { sys::JSValueProtect(ctx.raw, result.1.raw) };
+        if let Some((_, old)) = self.result.replace(result) {
+            unsafe { sys::JSValueUnprotect(old.ctx, old.raw) };
+        }
+        #[cfg(feature = "async")]
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Takes the result of the promise, if it has settled.
+    fn take(&mut self) -> Option<Result<JSValue, JSException>> {
+        let (fulfilled, value) = self.result.take()?;
+        unsafe { sys::JSValueUnprotect(value.ctx, value.raw) };
+        Some(if fulfilled {
+            Ok(value)
+        } else {
+            Err(JSException::new(value))
+        })
+    }
+}
+
+impl Drop for Settlement {
+    fn drop(&mut self) {
+        // A result that nobody took is no longer protected.
+        self.take();
+    }
+}
+
+#[cfg(feature = "async")]
+impl IntoFuture for JSPromise {
+    type Output = Result<JSValue, JSException>;
+    type IntoFuture = PromiseFuture;
+
+    fn into_future(self) -> PromiseFuture {
+        PromiseFuture {
+            settlement: Settlement::watch(&self),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl Future for PromiseFuture {
+    type Output = Result<JSValue, JSException>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut settlement = self.settlement.borrow_mut();
+        match settlement.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                settlement.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl JSContext {
+    /// Waits for `promise` to settle, without an async runtime, and
+    /// returns its value if it is fulfilled or its reason if it is
+    /// rejected.
+    ///
+    /// * `promise`: The promise to wait for.
+    /// * `timeout`: How long to wait for. If the promise has not settled
+    ///   by then, this fails with an `Error`.
+    /// * `pump`: Runs the work of the host that may settle the promise,
+    ///   such as timers that are due or futures that were spawned. It is
+    ///   called until the promise settles, and may block until there is
+    ///   work to do. An error that it returns stops the wait.
+    ///
+    /// JavaScriptCore runs the jobs of the context, such as the
+    /// reactions to promises, whenever the host calls into it, so each
+    /// round of work also runs the jobs that it queues. This suits
+    /// command line tools and tests.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// # use std::time::Duration;
+    /// let ctx = JSContext::default();
+    /// let (promise, deferred) = JSPromise::deferred(&ctx).expect("promise");
+    /// let mut deferred = Some(deferred);
+    /// let value = ctx
+    ///     .block_on(promise, Duration::from_secs(1), || {
+    ///         if let Some(deferred) = deferred.take() {
+    ///             deferred.resolve(JSValue::new_number(&ctx, 42.0));
+    ///         }
+    ///         Ok(())
+    ///     })
+    ///     .expect("fulfilled");
+    /// assert_eq!(value.as_number().expect("number"), 42.0);
+    /// ```
+    pub fn block_on<F>(
+        &self,
+        promise: JSPromise,
+        timeout: Duration,
+        mut pump: F,
+    ) -> Result<JSValue, JSException>
+    where
+        F: FnMut() -> Result<(), JSException>,
+    {
+        let deadline = Instant::now() + timeout;
+        let settlement = Settlement::watch(&promise);
+        loop {
+            if let Some(result) = settlement.borrow_mut().take() {
+                return result;
+            }
+            if Instant::now() >= deadline {
+                return Err(JSException::error(
+                    self,
+                    "The promise did not settle in time",
+                ));
+            }
+            pump()?;
+        }
+    }
+}
+
+/// A `JSPromise` can be dereferenced to return the underlying
+/// `JSObject`.
+impl Deref for JSPromise {
+    type Target = JSObject;
+
+    fn deref(&self) -> &JSObject {
+        &self.object
+    }
+}
+
+impl From<JSPromise> for JSObject {
+    fn from(promise: JSPromise) -> Self {
+        promise.object
+    }
+}
+
+impl From<JSPromise> for JSValue {
+    fn from(promise: JSPromise) -> Self {
+        promise.object.into()
+    }
+}
+
+impl JSObject {
+    /// Creates a function that returns a promise, which a Rust future
+    /// settles.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `spawner`: Runs the futures.
+    /// * `callback`: The closure to call. It is given the context, the
+    ///   `this` object and the arguments of the call, and returns the
+    ///   future to run.
+    ///
+    /// The closure runs when the function is called, so it can convert
+    /// the arguments into Rust values for the future to use. The
+    /// future should not hold JavaScript values, as they are not kept
+    /// from being garbage collected while it waits. When it completes,
+    /// its output is converted with [`IntoJsResult`] to resolve the
+    /// promise, or to reject it with an error. An error returned by the
+    /// closure rejects the promise too.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// # use std::cell::RefCell;
+    /// # use std::future::Future;
+    /// # use std::pin::Pin;
+    /// # use std::rc::Rc;
+    /// # use std::task::{Context, Waker};
+    /// #[derive(Clone, Default)]
+    /// struct Queue(Rc<RefCell<Vec<Pin<Box<dyn Future<Output = ()>>>>>>);
+    ///
+    /// impl Spawner for Queue {
+    ///     fn spawn(&self, future: Pin<Box<dyn Future<Output = ()>>>) {
+    ///         self.0.borrow_mut().push(future);
+    ///     }
+    /// }
+    ///
+    /// let ctx = JSContext::default();
+    /// let queue = Queue::default();
+    /// let double = JSObject::new_async_function(&ctx, queue.clone(), |ctx, _this, arguments| {
+    ///     let n = f64::from_js(ctx, &arguments[0])?;
+    ///     Ok(async move { n * 2.0 })
+    /// });
+    /// ctx.global_object().set_property("double", double.into()).expect("set");
+    ///
+    /// evaluate_script(&ctx, "double(21).then(n => globalThis.n = n)", None, "test.js", 1)
+    ///     .expect("called");
+    /// let futures = queue.0.take();
+    /// for mut future in futures {
+    ///     assert!(future.as_mut().poll(&mut Context::from_waker(Waker::noop())).is_ready());
+    /// }
+    ///
+    /// let n = evaluate_script(&ctx, "n", None, "test.js", 1).expect("n");
+    /// assert_eq!(n.as_number().expect("number"), 42.0);
+    /// ```
+    ///
+    /// [`IntoJsResult`]: trait.IntoJsResult.html
+    pub fn new_async_function<S, F, T>(ctx: &JSContext, spawner: S, callback: F) -> JSObject
+    where
+        S: Spawner + 'static,
+        F: Fn(&JSContext, &JSObject, &[JSValue]) -> Result<T, JSException> + 'static,
+        T: Future + 'static,
+        T::Output: IntoJsResult,
+    {
+        new_function(ctx, move |ctx, this, arguments| {
+            let (promise, deferred) = JSDeferred::new(ctx)?;
+            match callback(ctx, this, arguments) {
+                Ok(future) => deferred.settle_with_future(future, &spawner),
+                Err(e) => deferred.settle(Err(e)),
+            }
+            Ok(promise.into())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSContext, JSException, JSObject, JSPromise, JSValue};
+    use super::Spawner;
+    use std::cell::RefCell;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::task::{Context, Poll, Waker};
+    use std::time::Duration;
+
+    type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+    #[derive(Clone, Default)]
+    struct Queue(Rc<RefCell<Vec<Task>>>);
+
+    impl Spawner for Queue {
+        fn spawn(&self, future: Task) {
+            self.0.borrow_mut().push(future);
+        }
+    }
+
+    impl Queue {
+        /// Polls every future once, and keeps those that are pending.
+        fn run(&self) {
+            let mut pending = vec![];
+            for mut future in self.0.take() {
+                let mut cx = Context::from_waker(Waker::noop());
+                if future.as_mut().poll(&mut cx).is_pending() {
+                    pending.push(future);
+                }
+            }
+            self.0.borrow_mut().extend(pending);
+        }
+    }
+
+    /// A future that is pending the first time it is polled.
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, _cx: &mut Context) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                Poll::Pending
+            }
+        }
+    }
+
+    fn eval(ctx: &JSContext, script: &str) -> String {
+        let r = evaluate_script(ctx, script, None, "test.js", 1).unwrap();
+        r.to_json_string(0).unwrap().to_string()
+    }
+
+    #[test]
+    fn async_function() {
+        let ctx = JSContext::default();
+        let queue = Queue::default();
+        let divide = JSObject::new_async_function(&ctx, queue.clone(), |ctx, _this, arguments| {
+            let a = arguments[0].as_number()?;
+            let b = arguments[1].as_number()?;
+            if b == 0.0 {
+                return Err(JSException::range_error(ctx, "Division by zero"));
+            }
+            Ok(async move {
+                YieldOnce(false).await;
+                if a.fract() != 0.0 {
+                    "x".parse::<u32>().map(|_| 0.0)
+                } else {
+                    Ok(a / b)
+                }
+            })
+        });
+        ctx.global_object()
+            .set_property("divide", divide.into())
+            .unwrap();
+
+        eval(
+            &ctx,
+            "globalThis.results = [];
+             const record = (p) => p.then(v => results.push(v), e => results.push(e.message));
+             record(divide(6, 3)); record(divide(1, 0)); record(divide(0.5, 1)); 0",
+        );
+        assert_eq!(eval(&ctx, "results"), r#"["Division by zero"]"#);
+        queue.run();
+        assert_eq!(eval(&ctx, "results"), r#"["Division by zero"]"#);
+        queue.run();
+        assert_eq!(
+            eval(&ctx, "results"),
+            r#"["Division by zero",2,"invalid digit found in string"]"#
+        );
+        assert!(queue.0.borrow().is_empty());
+    }
+
+    #[test]
+    fn on_settled() {
+        let ctx = JSContext::default();
+        let results = Rc::new(RefCell::new(vec![]));
+        let v = evaluate_script(
+            &ctx,
+            "globalThis.fulfilled = new Promise(r => globalThis.resolve = r);
+             globalThis.rejected = Promise.reject(new RangeError('no'));
+             fulfilled",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        let record = |promise: JSValue| {
+            let results = results.clone();
+            promise
+                .as_promise()
+                .unwrap()
+                .on_settled(move |_ctx, result| {
+                    results.borrow_mut().push(match result {
+                        Ok(value) => value.as_number().unwrap().to_string(),
+                        Err(e) => e.name().unwrap_or_default().to_owned(),
+                    })
+                })
+                .unwrap();
+        };
+        record(v);
+        assert!(results.borrow().is_empty());
+        record(evaluate_script(&ctx, "rejected", None, "test.js", 1).unwrap());
+        assert_eq!(*results.borrow(), ["RangeError"]);
+        evaluate_script(&ctx, "resolve(7)", None, "test.js", 1).unwrap();
+        assert_eq!(*results.borrow(), ["RangeError", "7"]);
+
+        let e = JSValue::new_number(&ctx, 1.0).as_promise().unwrap_err();
+        assert_eq!(e.name(), Some("TypeError"));
+    }
+
+    #[test]
+    fn from_future() {
+        let ctx = JSContext::default();
+        let queue = Queue::default();
+        let global = ctx.global_object();
+        let promise = JSPromise::from_future(
+            &ctx,
+            async {
+                YieldOnce(false).await;
+                "1.5".parse::<f64>()
+            },
+            &queue,
+        )
+        .unwrap();
+        global.set_property("a", promise.into()).unwrap();
+        let promise = JSPromise::from_future(&ctx, async { "x".parse::<f64>() }, &queue).unwrap();
+        global.set_property("b", promise.into()).unwrap();
+        eval(
+            &ctx,
+            "globalThis.results = [];
+             a.then(v => results.push(v));
+             b.catch(e => results.push(e.message));
+             0",
+        );
+        queue.run();
+        assert_eq!(eval(&ctx, "results"), r#"["invalid float literal"]"#);
+        queue.run();
+        assert_eq!(eval(&ctx, "results"), r#"["invalid float literal",1.5]"#);
+    }
+
+    #[test]
+    fn deferred() {
+        let ctx = JSContext::default();
+        let global = ctx.global_object();
+        let mut deferreds = vec![];
+        for name in ["a", "b", "c", "d"] {
+            let (promise, deferred) = JSPromise::deferred(&ctx).unwrap();
+            global.set_property(name, promise.into()).unwrap();
+            deferreds.push(deferred);
+        }
+        eval(
+            &ctx,
+            "globalThis.results = [];
+             for (const p of [a, b, c, d]) {
+                 p.then(v => results.push(v), e => results.push(e.message ?? e));
+             }
+             0",
+        );
+        let mut deferreds = deferreds.into_iter();
+        let (a, b, c, d) = (
+            deferreds.next().unwrap(),
+            deferreds.next().unwrap(),
+            deferreds.next().unwrap(),
+            deferreds.next().unwrap(),
+        );
+        c.reject(JSException::type_error(&ctx, "failed"));
+        a.resolve(JSValue::new_number(&ctx, 1.0));
+        drop(d);
+        b.settle(Ok(JSValue::new_string(&ctx, "b")));
+        assert_eq!(
+            eval(&ctx, "results"),
+            r#"["failed",1,"The operation was dropped before it completed","b"]"#
+        );
+    }
+
+    #[test]
+    fn as_promise() {
+        let ctx = JSContext::default();
+        let script = "globalThis.real = Promise.resolve(1);
+                      globalThis.Fake = function () {};
+                      globalThis.fake = new Fake();
+                      globalThis.Promise = Fake;
+                      real";
+        let real = evaluate_script(&ctx, script, None, "test.js", 1).unwrap();
+        assert!(real.as_promise().is_ok());
+        let fake = evaluate_script(&ctx, "fake", None, "test.js", 1).unwrap();
+        assert_eq!(fake.as_promise().err().unwrap().name(), Some("TypeError"));
+        let other = evaluate_script(&ctx, "(async () => 2)()", None, "test.js", 1).unwrap();
+        assert!(other.as_promise().is_ok());
+    }
+
+    #[test]
+    fn combinators() {
+        let ctx = JSContext::default();
+        eval(&ctx, "globalThis.Promise = null; 0");
+
+        let (a, resolve_a) = JSPromise::deferred(&ctx).unwrap();
+        let (b, reject_b) = JSPromise::deferred(&ctx).unwrap();
+        let all = JSPromise::all(&ctx, [a, b]).unwrap();
+        ctx.global_object().set_property("all", all.into()).unwrap();
+        eval(&ctx, "all.catch(e => globalThis.all = e.message); 0");
+        reject_b.reject(JSException::error(&ctx, "b failed"));
+        resolve_a.resolve(JSValue::new_number(&ctx, 1.0));
+        assert_eq!(eval(&ctx, "all"), r#""b failed""#);
+
+        let (slow, _pending) = JSPromise::deferred(&ctx).unwrap();
+        let (fast, resolve_fast) = JSPromise::deferred(&ctx).unwrap();
+        let race = JSPromise::race(&ctx, vec![slow, fast]).unwrap();
+        ctx.global_object()
+            .set_property("race", race.into())
+            .unwrap();
+        eval(&ctx, "race.then(v => globalThis.race = v); 0");
+        resolve_fast.resolve(JSValue::new_number(&ctx, 2.0));
+        assert_eq!(eval(&ctx, "race"), "2");
+
+        let empty = JSPromise::all(&ctx, []).unwrap();
+        ctx.global_object()
+            .set_property("empty", empty.into())
+            .unwrap();
+        eval(&ctx, "empty.then(v => globalThis.empty = v); 0");
+        assert_eq!(eval(&ctx, "empty"), "[]");
+    }
+
+    #[test]
+    fn block_on() {
+        let ctx = JSContext::default();
+        let v = evaluate_script(
+            &ctx,
+            "globalThis.steps = 0;
+             new Promise((resolve, reject) => globalThis.step = () => {
+                 if (++steps === 3) reject(new Error('third'));
+             })",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        let e = ctx
+            .block_on(v.as_promise().unwrap(), Duration::from_secs(10), || {
+                evaluate_script(&ctx, "step()", None, "test.js", 1).map(|_| ())
+            })
+            .unwrap_err();
+        assert_eq!(e.message(), Some("third"));
+        assert_eq!(eval(&ctx, "steps"), "3");
+
+        let (promise, _deferred) = JSPromise::deferred(&ctx).unwrap();
+        let e = ctx
+            .block_on(promise, Duration::from_millis(10), || Ok(()))
+            .unwrap_err();
+        assert_eq!(e.message(), Some("The promise did not settle in time"));
+
+        let (promise, _deferred) = JSPromise::deferred(&ctx).unwrap();
+        let e = ctx
+            .block_on(promise, Duration::from_secs(10), || {
+                Err(JSException::type_error(&ctx, "stopped"))
+            })
+            .unwrap_err();
+        assert_eq!(e.message(), Some("stopped"));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn into_future() {
+        use std::future::IntoFuture;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use std::task::Wake;
+
+        #[derive(Default)]
+        struct Count(AtomicUsize);
+
+        impl Wake for Count {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let ctx = JSContext::default();
+        let count = Arc::new(Count::default());
+        let waker = Waker::from(count.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let (promise, deferred) = JSPromise::deferred(&ctx).unwrap();
+        let mut future = Box::pin(promise.into_future());
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        deferred.reject(JSException::range_error(&ctx, "late"));
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(Err(e)) => assert_eq!(e.message(), Some("late")),
+            _ => panic!("the promise should be rejected"),
+        }
+
+        let v = evaluate_script(&ctx, "Promise.resolve('done')", None, "test.js", 1).unwrap();
+        let mut future = Box::pin(v.as_promise().unwrap().into_future());
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(Ok(v)) => assert_eq!(v.as_string().unwrap().to_string(), "done"),
+            _ => panic!("the promise should be fulfilled"),
+        }
+
+        // A future that is dropped before it settles is not woken.
+        let (promise, deferred) = JSPromise::deferred(&ctx).unwrap();
+        let mut future = Box::pin(promise.into_future());
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        drop(future);
+        deferred.resolve(JSValue::new_null(&ctx));
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn dropped_future() {
+        let ctx = JSContext::default();
+        let queue = Queue::default();
+        let never = JSObject::new_async_function(&ctx, queue.clone(), |_ctx, _this, _arguments| {
+            Ok(std::future::pending::<()>())
+        });
+        ctx.global_object()
+            .set_property("never", never.into())
+            .unwrap();
+
+        eval(
+            &ctx,
+            "never().catch(e => globalThis.message = e.message); 0",
+        );
+        queue.run();
+        queue.0.borrow_mut().clear();
+        assert_eq!(
+            eval(&ctx, "message"),
+            r#""The operation was dropped before it completed""#
+        );
+    }
+}
@@ -0,0 +1,299 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{IntoJsResult, JSContext, JSDeferred, JSException, JSPromise, JSValue, ToJs};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Produces the result of a promise on the thread of its context.
+type Settle = Box<dyn FnOnce(&JSContext) -> Result<JSValue, JSException> + Send>;
+
+/// A settlement sent to a `DeferredQueue`, or `None` if its
+/// `RemoteDeferred` was dropped.
+type Message = (u64, Option<Settle>);
+
+/// The promises that other threads settle, by way of
+/// [`RemoteDeferred`] handles.
+///
+/// A context must only be used on the thread that owns it, so a
+/// background thread can not settle a promise itself. It sends the
+/// result to the queue instead, which settles the promise the next time
+/// that the thread of the context calls [`pump`], for example from its
+/// event loop.
+///
+/// ```
+/// # use javascriptcore::*;
+/// # use std::thread;
+/// let ctx = JSContext::default();
+/// let queue = DeferredQueue::new(&ctx);
+/// let (promise, deferred) = queue.deferred().expect("promise");
+///
+/// thread::spawn(move || deferred.resolve(42.0)).join().expect("resolved");
+/// assert_eq!(queue.pump(), 1);
+///
+/// ctx.global_object().set_property("promise", promise.into()).expect("set");
+/// evaluate_script(&ctx, "promise.then(n => globalThis.n = n)", None, "test.js", 1)
+///     .expect("evaluated");
+/// let n = evaluate_script(&ctx, "n", None, "test.js", 1).expect("n");
+/// assert_eq!(n.as_number().expect("number"), 42.0);
+/// ```
+///
+/// [`RemoteDeferred`]: struct.RemoteDeferred.html
+/// [`pump`]: #method.pump
+pub struct DeferredQueue {
+    ctx: JSContext,
+    sender: mpsc::Sender<Message>,
+    receiver: mpsc::Receiver<Message>,
+    /// The promises that have not been settled yet, by number.
+    pending: RefCell<HashMap<u64, JSDeferred>>,
+    next_id: Cell<u64>,
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl DeferredQueue {
+    /// Creates a queue for the promises of `ctx`.
+    pub fn new(ctx: &JSContext) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        DeferredQueue {
+            ctx: ctx.clone(),
+            sender,
+            receiver,
+            pending: RefCell::new(HashMap::new()),
+            next_id: Cell::new(0),
+            waker: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Creates a pending promise, and a handle that any thread can use
+    /// to settle it.
+    pub fn deferred(&self) -> Result<(JSPromise, RemoteDeferred), JSException> {
+        let (promise, deferred) = JSPromise::deferred(&self.ctx)?;
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.pending.borrow_mut().insert(id, deferred);
+        let remote = RemoteDeferred {
+            id,
+            sender: Some(self.sender.clone()),
+            waker: self.waker.clone(),
+        };
+        Ok((promise, remote))
+    }
+
+    /// Gets the context of the promises of the queue.
+    pub(crate) fn context(&self) -> &JSContext {
+        &self.ctx
+    }
+
+    /// Settles the promises whose results have been sent, and returns
+    /// how many were settled.
+    ///
+    /// The reactions to the promises run before this returns.
+    pub fn pump(&self) -> usize {
+        let mut settled = 0;
+        while let Ok((id, settle)) = self.receiver.try_recv() {
+            let deferred = self.pending.borrow_mut().remove(&id);
+            if let Some(deferred) = deferred {
+                settled += 1;
+                match settle {
+                    Some(settle) => deferred.settle(settle(&self.ctx)),
+                    // The deferred rejects the promise as it is dropped.
+                    None => drop(deferred),
+                }
+            }
+        }
+        settled
+    }
+
+    /// Tests whether any promises of the queue are still pending.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.borrow().is_empty()
+    }
+
+    /// Settles the promises whose results have been sent, and completes
+    /// once none are pending.
+    ///
+    /// Until then, the task is woken whenever a result is sent. This
+    /// lets an async runtime drive the queue without polling it.
+    pub fn poll_pump(&self, cx: &mut Context<'_>) -> Poll<()> {
+        // The waker is stored first, so that a result that is sent while
+        // pumping still wakes the task.
+        *self.waker.lock().unwrap_or_else(|e| e.into_inner()) = Some(cx.waker().clone());
+        self.pump();
+        if self.has_pending() {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+}
+
+impl fmt::Debug for DeferredQueue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DeferredQueue")
+            .field("pending", &self.pending.borrow().len())
+            .finish()
+    }
+}
+
+/// Settles a promise of a [`DeferredQueue`] from any thread.
+///
+/// The result is converted and applied on the thread of the context,
+/// the next time that its queue is pumped. If the handle is dropped
+/// without settling the promise, the promise is rejected.
+///
+/// [`DeferredQueue`]: struct.DeferredQueue.html
+pub struct RemoteDeferred {
+    id: u64,
+    /// The channel to the queue, until the result has been sent.
+    sender: Option<mpsc::Sender<Message>>,
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl RemoteDeferred {
+    /// Resolves the promise with `value`, once it has been converted
+    /// with [`ToJs`].
+    ///
+    /// [`ToJs`]: trait.ToJs.html
+    pub fn resolve<T>(self, value: T)
+    where
+        T: ToJs + Send + 'static,
+    {
+        self.send(Box::new(move |ctx| value.to_js(ctx)));
+    }
+
+    /// Rejects the promise with `error`, converted as by
+    /// [`JSException::from_error`].
+    ///
+    /// [`JSException::from_error`]: struct.JSException.html#method.from_error
+    pub fn reject<E>(self, error: E)
+    where
+        E: Error + Send + 'static,
+    {
+        self.send(Box::new(move |ctx| {
+            Err(JSException::from_error(ctx, error))
+        }));
+    }
+
+    /// Settles the promise with `result`, converted with
+    /// [`IntoJsResult`].
+    ///
+    /// [`IntoJsResult`]: trait.IntoJsResult.html
+    pub fn settle<T>(self, result: T)
+    where
+        T: IntoJsResult + Send + 'static,
+    {
+        self.send(Box::new(move |ctx| result.into_js_result(ctx)));
+    }
+
+    fn send(mut self, settle: Settle) {
+        self.finish(Some(settle));
+    }
+
+    /// Sends the result to the queue, unless that has been done, and
+    /// wakes the task that pumps it. A queue that has been dropped has
+    /// already rejected the promise.
+    fn finish(&mut self, settle: Option<Settle>) {
+        if let Some(sender) = self.sender.take() {
+            if sender.send((self.id, settle)).is_ok() {
+                if let Some(waker) = self.waker.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+impl Drop for RemoteDeferred {
+    fn drop(&mut self) {
+        self.finish(None);
+    }
+}
+
+impl fmt::Debug for RemoteDeferred {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RemoteDeferred")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSContext, JSPromise};
+    use super::{DeferredQueue, RemoteDeferred};
+    use std::io;
+    use std::task::{Context, Waker};
+    use std::thread;
+
+    fn eval(ctx: &JSContext, script: &str) -> String {
+        let r = evaluate_script(ctx, script, None, "test.js", 1).unwrap();
+        r.to_json_string(0).unwrap().to_string()
+    }
+
+    fn watch(ctx: &JSContext, name: &str, promise: JSPromise) {
+        ctx.global_object()
+            .set_property(name, promise.into())
+            .unwrap();
+        eval(
+            ctx,
+            &format!(
+                "{0}.then(v => globalThis.{0} = v, e => globalThis.{0} = e.message); 0",
+                name
+            ),
+        );
+    }
+
+    #[test]
+    fn remote_deferred() {
+        fn is_send_sync<T: Send + Sync>() {}
+        is_send_sync::<RemoteDeferred>();
+
+        let ctx = JSContext::default();
+        let queue = DeferredQueue::new(&ctx);
+        let (resolved, resolve) = queue.deferred().unwrap();
+        let (rejected, reject) = queue.deferred().unwrap();
+        let (settled, settle) = queue.deferred().unwrap();
+        let (dropped, drop_it) = queue.deferred().unwrap();
+        watch(&ctx, "resolved", resolved);
+        watch(&ctx, "rejected", rejected);
+        watch(&ctx, "settled", settled);
+        watch(&ctx, "dropped", dropped);
+
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(queue.poll_pump(&mut cx).is_pending());
+        thread::spawn(move || {
+            resolve.resolve("done");
+            reject.reject(io::Error::other("failed"));
+            settle.settle(Ok::<_, io::Error>(Some(2.0)));
+            drop(drop_it);
+        })
+        .join()
+        .unwrap();
+        assert!(queue.has_pending());
+        assert!(queue.poll_pump(&mut cx).is_ready());
+        assert!(!queue.has_pending());
+        assert_eq!(
+            eval(&ctx, "[resolved, rejected, settled, dropped]"),
+            r#"["done","failed",2,"The operation was dropped before it completed"]"#
+        );
+        assert_eq!(queue.pump(), 0);
+
+        // Settling after the queue is gone does nothing.
+        let (promise, late) = queue.deferred().unwrap();
+        watch(&ctx, "late", promise);
+        drop(queue);
+        late.resolve(1.0);
+        assert_eq!(
+            eval(&ctx, "late"),
+            r#""The operation was dropped before it completed""#
+        );
+    }
+}
@@ -0,0 +1,321 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{evaluate_script, FromJs, JSContext, JSError, JSException, JSValue, ToJs};
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread;
+use tokio::sync::{mpsc, oneshot};
+
+/// A task for the thread of a runtime.
+type Command = Box<dyn FnOnce(&JSContext) + Send>;
+
+/// A context that runs on a thread of its own, which other threads use
+/// through `Send` handles.
+///
+/// A context must only be used on one thread at a time, which makes it
+/// awkward to use from async servers that move tasks between threads.
+/// A `JsRuntime` owns a context on a dedicated thread instead, and runs
+/// the work that it is given there, one task at a time. Each method
+/// returns a [`RuntimeFuture`] of the result, which Tokio tasks can
+/// await without blocking the threads of the executor.
+///
+/// The handles can be cloned. The thread stops, and drops the context,
+/// once every handle has been dropped and the work queued before then
+/// has run.
+///
+/// Requires the `tokio` feature.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let executor = tokio::runtime::Builder::new_current_thread().build().expect("executor");
+/// let runtime = JsRuntime::new();
+/// let answer: f64 = executor.block_on(async {
+///     runtime.set_global("base", 40.0).await.expect("set");
+///     runtime.eval("base + 2").await.expect("evaluated")
+/// });
+/// assert_eq!(answer, 42.0);
+/// ```
+///
+/// [`RuntimeFuture`]: struct.RuntimeFuture.html
+#[derive(Clone, Debug)]
+pub struct JsRuntime {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl JsRuntime {
+    /// Starts a runtime with a new context.
+    pub fn new() -> Self {
+        JsRuntime::with_setup(|_ctx| {})
+    }
+
+    /// Starts a runtime with a new context, which `setup` prepares on the
+    /// thread of the runtime before any other work runs.
+    ///
+    /// This is where the host installs the functions and globals that
+    /// its scripts use.
+    pub fn with_setup<F>(setup: F) -> Self
+    where
+        F: FnOnce(&JSContext) + Send + 'static,
+    {
+        let (commands, mut receiver) = mpsc::unbounded_channel::<Command>();
+        thread::Builder::new()
+            .name("javascriptcore".to_owned())
+            .spawn(move || {
+                let ctx = JSContext::default();
+                setup(&ctx);
+                while let Some(command) = receiver.blocking_recv() {
+                    command(&ctx);
+                }
+            })
+            .expect("the runtime thread could not be started");
+        JsRuntime { commands }
+    }
+
+    /// Runs `f` with the context, on the thread of the runtime.
+    ///
+    /// This is how to do anything that the other methods do not cover.
+    /// Values of the context can not leave its thread, so `f` converts
+    /// what it needs into Rust values.
+    ///
+    /// If `f` panics, the future fails with [`RuntimeError::Panicked`],
+    /// and the runtime goes on with the work after it.
+    ///
+    /// [`RuntimeError::Panicked`]: enum.RuntimeError.html#variant.Panicked
+    pub fn run<F, T>(&self, f: F) -> RuntimeFuture<T>
+    where
+        F: FnOnce(&JSContext) -> Result<T, JSException> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (sender, result) = oneshot::channel();
+        let command: Command = Box::new(move |ctx| {
+            let result = match panic::catch_unwind(AssertUnwindSafe(|| f(ctx))) {
+                Ok(r) => r.map_err(|e| RuntimeError::Exception(JSError::from(e))),
+                Err(_) => Err(RuntimeError::Panicked),
+            };
+            sender.send(result).ok();
+        });
+        // If the thread has stopped, the command is dropped, and so is
+        // its sender, which fails the future.
+        let _ = self.commands.send(command);
+        RuntimeFuture { result }
+    }
+
+    /// Evaluates `script`, and converts its result with [`FromJs`].
+    ///
+    /// [`FromJs`]: trait.FromJs.html
+    pub fn eval<T>(&self, script: impl Into<String>) -> RuntimeFuture<T>
+    where
+        T: FromJs + Send + 'static,
+    {
+        let script = script.into();
+        self.run(move |ctx| {
+            let value = evaluate_script(ctx, script, None, "runtime.js", 1)?;
+            T::from_js(ctx, &value)
+        })
+    }
+
+    /// Calls the global function called `function` with `arguments`, and
+    /// converts its result with [`FromJs`].
+    ///
+    /// Fails with a `TypeError` if there is no such function.
+    ///
+    /// [`FromJs`]: trait.FromJs.html
+    pub fn call<A, T>(&self, function: impl Into<String>, arguments: Vec<A>) -> RuntimeFuture<T>
+    where
+        A: ToJs + Send + 'static,
+        T: FromJs + Send + 'static,
+    {
+        let name = function.into();
+        self.run(move |ctx| {
+            let function = ctx.global_object().get_property(name.as_str())?;
+            let function = match function.as_object() {
+                Ok(function) if function.is_function() => function,
+                _ => {
+                    return Err(JSException::type_error(
+                        ctx,
+                        &format!("{} is not a function", name),
+                    ))
+                }
+            };
+            let arguments = arguments
+                .iter()
+                .map(|argument| argument.to_js(ctx))
+                .collect::<Result<Vec<JSValue>, JSException>>()?;
+            let value = function.call_as_function(None, &arguments)?;
+            T::from_js(ctx, &value)
+        })
+    }
+
+    /// Sets the global variable called `name` to `value`.
+    pub fn set_global<V>(&self, name: impl Into<String>, value: V) -> RuntimeFuture<()>
+    where
+        V: ToJs + Send + 'static,
+    {
+        let name = name.into();
+        self.run(move |ctx| {
+            let value = value.to_js(ctx)?;
+            ctx.global_object().set_property(name.as_str(), value)
+        })
+    }
+}
+
+impl Default for JsRuntime {
+    fn default() -> Self {
+        JsRuntime::new()
+    }
+}
+
+/// The reason that work given to a [`JsRuntime`] failed.
+///
+/// [`JsRuntime`]: struct.JsRuntime.html
+#[derive(Debug)]
+pub enum RuntimeError {
+    /// The work threw an exception.
+    Exception(JSError),
+    /// The work panicked.
+    Panicked,
+    /// The thread of the runtime stopped before the work ran, because
+    /// the setup of the runtime panicked.
+    Stopped,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::Exception(e) => e.fmt(f),
+            RuntimeError::Panicked => {
+                f.write_str("The work given to the JavaScript runtime panicked")
+            }
+            RuntimeError::Stopped => f.write_str("The JavaScript runtime has stopped"),
+        }
+    }
+}
+
+impl Error for RuntimeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RuntimeError::Exception(e) => Some(e),
+            RuntimeError::Panicked | RuntimeError::Stopped => None,
+        }
+    }
+}
+
+/// The result of work given to a [`JsRuntime`], which completes once
+/// the work has run.
+///
+/// Dropping the future does not cancel the work.
+///
+/// [`JsRuntime`]: struct.JsRuntime.html
+pub struct RuntimeFuture<T> {
+    result: oneshot::Receiver<Result<T, RuntimeError>>,
+}
+
+impl<T> Future for RuntimeFuture<T> {
+    type Output = Result<T, RuntimeError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut self.result)
+            .poll(cx)
+            .map(|r| r.unwrap_or(Err(RuntimeError::Stopped)))
+    }
+}
+
+impl<T> fmt::Debug for RuntimeFuture<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RuntimeFuture").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{JSException, JSObject, JSValue};
+    use super::{JsRuntime, RuntimeError};
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread::{self, Thread};
+
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Runs a future to completion on this thread.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn runtime() {
+        let runtime = JsRuntime::with_setup(|ctx| {
+            let double = JSObject::new_function(ctx, |ctx, _this, arguments| {
+                let n = arguments[0].as_number()?;
+                Ok(JSValue::new_number(ctx, n * 2.0))
+            });
+            ctx.global_object()
+                .set_property("double", double.into())
+                .unwrap();
+        });
+        block_on(runtime.set_global("name", "world")).unwrap();
+        let greeting: String = block_on(runtime.eval("`hello ${name}`")).unwrap();
+        assert_eq!(greeting, "hello world");
+
+        let handle = runtime.clone();
+        let n: f64 = thread::spawn(move || block_on(handle.call("double", vec![21.0])))
+            .join()
+            .unwrap()
+            .unwrap();
+        assert_eq!(n, 42.0);
+
+        match block_on(runtime.eval::<f64>("null.x")) {
+            Err(RuntimeError::Exception(e)) => assert_eq!(e.name(), Some("TypeError")),
+            r => panic!("unexpected result {:?}", r),
+        }
+        let e = block_on(runtime.call::<f64, f64>("missing", vec![])).unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with("TypeError: missing is not a function"));
+
+        let r = block_on(runtime.run(|ctx| -> Result<(), JSException> {
+            Err(JSException::range_error(ctx, "from run"))
+        }));
+        assert!(matches!(r, Err(RuntimeError::Exception(_))));
+    }
+
+    #[test]
+    fn panicked() {
+        let runtime = JsRuntime::new();
+        let r = block_on(runtime.run(|_ctx| -> Result<(), JSException> { panic!("oops") }));
+        assert!(matches!(r, Err(RuntimeError::Panicked)));
+        // The runtime keeps running.
+        let n: f64 = block_on(runtime.eval("1")).unwrap();
+        assert_eq!(n, 1.0);
+    }
+
+    #[test]
+    fn stopped() {
+        let runtime = JsRuntime::with_setup(|_ctx| panic!("stop"));
+        let r = block_on(runtime.eval::<f64>("1"));
+        assert!(matches!(r, Err(RuntimeError::Stopped)));
+    }
+}
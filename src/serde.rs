@@ -0,0 +1,1930 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::object::new_array;
+use super::typedarray::{new_uint8_array, typed_array_bytes, typed_array_type};
+use super::value::{is_bigint, new_bigint};
+use super::{JSContext, JSException, JSObject, JSString, JSTypedArrayType, JSValue};
+use crate::sys;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize, SerializeMap as _, SerializeSeq as _};
+use std::cell::RefCell;
+use std::fmt;
+#[cfg(any(feature = "serde_json", feature = "rmp-serde", feature = "ciborium"))]
+use std::io;
+use std::ptr;
+
+/// The largest integer that a number holds exactly, `2^53 - 1`.
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+/// Options for [`to_value_with_options`] and
+/// [`from_value_with_options`].
+///
+/// The default options convert as [`to_value`] and [`from_value`] do.
+///
+/// Requires the `serde` feature.
+///
+/// [`from_value`]: fn.from_value.html
+/// [`from_value_with_options`]: fn.from_value_with_options.html
+/// [`to_value`]: fn.to_value.html
+/// [`to_value_with_options`]: fn.to_value_with_options.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SerdeOptions {
+    /// How integers that a number can not hold exactly are converted.
+    pub large_integers: LargeIntegers,
+}
+
+/// How integers beyond `2^53 - 1`, the largest that a number holds
+/// exactly, are converted by [`to_value_with_options`] and
+/// [`from_value_with_options`].
+///
+/// Smaller integers always become numbers. The other representations
+/// are also read back into integers of any size, from `i8` to `u128`.
+///
+/// Requires the `serde` feature.
+///
+/// [`from_value_with_options`]: fn.from_value_with_options.html
+/// [`to_value_with_options`]: fn.to_value_with_options.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LargeIntegers {
+    /// Large integers become the nearest number, losing precision.
+    #[default]
+    Number,
+    /// Large integers become `BigInt`s.
+    BigInt,
+    /// Large integers become strings of their decimal digits, like
+    /// `"18446744073709551615"`.
+    String,
+}
+
+/// Converts a Rust value to a JavaScript value with its `Serialize`
+/// implementation.
+///
+/// Structs and maps become objects, sequences and tuples become arrays,
+/// and unit variants of enums become strings. Other variants become
+/// objects with a single property, named for the variant, as with
+/// `serde_json`. `None` becomes `undefined`, like with [`ToJs`], while
+/// `()` becomes `null`. Bytes, such as those of the fields that are
+/// marked with `#[serde(with = "serde_bytes")]`, become `Uint8Array`s.
+/// The values are made with the API of JavaScriptCore directly, without
+/// going through JSON.
+///
+/// Integers become numbers, so those beyond `2^53 - 1` lose precision,
+/// unless [`to_value_with_options`] is asked to convert them otherwise.
+/// Keys of maps must be strings or numbers. Other values that can not
+/// be converted give a `TypeError`.
+///
+/// Requires the `serde` feature.
+///
+/// ```
+/// # use javascriptcore::*;
+/// # use serde::Serialize;
+/// #[derive(Serialize)]
+/// struct Point {
+///     x: f64,
+///     y: f64,
+/// }
+///
+/// let ctx = JSContext::default();
+/// let v = to_value(&ctx, &Point { x: 1.0, y: 2.5 }).expect("value");
+/// assert_eq!(v.to_json_string(0).expect("json"), r#"{"x":1,"y":2.5}"#);
+/// ```
+///
+/// [`ToJs`]: trait.ToJs.html
+/// [`to_value_with_options`]: fn.to_value_with_options.html
+pub fn to_value<T>(ctx: &JSContext, value: &T) -> Result<JSValue, JSException>
+where
+    T: Serialize + ?Sized,
+{
+    to_value_with_options(ctx, value, SerdeOptions::default())
+}
+
+/// Converts a Rust value to a JavaScript value with its `Serialize`
+/// implementation, as [`to_value`] does, with options.
+///
+/// Requires the `serde` feature.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let ctx = JSContext::default();
+/// let options = SerdeOptions {
+///     large_integers: LargeIntegers::BigInt,
+/// };
+/// let v = to_value_with_options(&ctx, &[1, u64::MAX], options).expect("value");
+/// ctx.global_object().set_property("v", v).expect("set");
+/// let script = "typeof v[0] === 'number' && v[1] === 18446744073709551615n";
+/// let r = evaluate_script(&ctx, script, None, "test.js", 1).expect("compared");
+/// assert!(r.as_boolean());
+/// ```
+///
+/// [`to_value`]: fn.to_value.html
+pub fn to_value_with_options<T>(
+    ctx: &JSContext,
+    value: &T,
+    options: SerdeOptions,
+) -> Result<JSValue, JSException>
+where
+    T: Serialize + ?Sized,
+{
+    value
+        .serialize(Serializer { ctx, options })
+        .map_err(|e| e.into_exception(ctx))
+}
+
+/// Assigns the fields of a Rust struct, or the entries of a map, to the
+/// properties of an existing object, with their `Serialize`
+/// implementations.
+///
+/// This is like `Object.assign(target, value)`: the properties that
+/// are named by the fields are replaced, and the others are left as
+/// they are. The values of the fields are converted as with
+/// [`to_value`], and are not merged into the values that they replace.
+/// Fields that are `None` are left out, so that they do not replace
+/// what is already there.
+///
+/// Values that are not structs or maps give a `TypeError`, as do keys
+/// of maps that are not strings or numbers. Exceptions thrown while
+/// assigning, such as by setters, are returned, and
+/// the fields before them stay assigned.
+///
+/// Requires the `serde` feature.
+///
+/// ```
+/// # use javascriptcore::*;
+/// # use serde::Serialize;
+/// #[derive(Serialize)]
+/// struct Defaults {
+///     timeout: u32,
+///     retries: Option<u8>,
+/// }
+///
+/// let ctx = JSContext::default();
+/// let options = evaluate_script(&ctx, "({ url: '/a', retries: 3 })", None, "test.js", 1)
+///     .expect("value")
+///     .as_object()
+///     .expect("object");
+/// to_value_into(&ctx, &Defaults { timeout: 100, retries: None }, &options).expect("assigned");
+/// assert_eq!(
+///     JSValue::from(options).to_json_string(0).expect("json"),
+///     r#"{"url":"/a","retries":3,"timeout":100}"#
+/// );
+/// ```
+///
+/// [`to_value`]: fn.to_value.html
+pub fn to_value_into<T>(ctx: &JSContext, value: &T, target: &JSObject) -> Result<(), JSException>
+where
+    T: Serialize + ?Sized,
+{
+    value
+        .serialize(MergeSerializer {
+            ctx,
+            target,
+            options: SerdeOptions::default(),
+        })
+        .map_err(|e| e.into_exception(ctx))?;
+    Ok(())
+}
+
+/// Converts a JavaScript value to a Rust value with its `Deserialize`
+/// implementation.
+///
+/// This is the reverse of [`to_value`]: objects are read as structs or
+/// maps, arrays as sequences or tuples, and enums from strings or from
+/// objects with a single property. `null` and `undefined` are read as
+/// `None`, or as `()`. Numbers are read as integers when they are whole
+/// and exact. Typed arrays are read as sequences of their elements, or
+/// as bytes by types that ask for them, like those of `serde_bytes`.
+///
+/// Values of the wrong type give a `TypeError`, as do functions and
+/// symbols, and exceptions thrown while reading, such as by getters,
+/// are returned.
+///
+/// Requires the `serde` feature.
+///
+/// ```
+/// # use javascriptcore::*;
+/// # use serde::Deserialize;
+/// #[derive(Deserialize, Debug, PartialEq)]
+/// struct Request {
+///     path: String,
+///     retries: Option<u8>,
+/// }
+///
+/// let ctx = JSContext::default();
+/// let v = evaluate_script(&ctx, "({ path: '/index.html' })", None, "test.js", 1)
+///     .expect("value");
+/// let request: Request = from_value(&v).expect("request");
+/// assert_eq!(request, Request { path: "/index.html".into(), retries: None });
+/// ```
+///
+/// [`to_value`]: fn.to_value.html
+pub fn from_value<T>(value: &JSValue) -> Result<T, JSException>
+where
+    T: DeserializeOwned,
+{
+    from_value_with_options(value, SerdeOptions::default())
+}
+
+/// Converts a JavaScript value to a Rust value with its `Deserialize`
+/// implementation, as [`from_value`] does, with options.
+///
+/// With [`LargeIntegers::BigInt`], integers are also read from
+/// `BigInt`s, and with [`LargeIntegers::String`], from strings of
+/// decimal digits.
+///
+/// Requires the `serde` feature.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let ctx = JSContext::default();
+/// let options = SerdeOptions {
+///     large_integers: LargeIntegers::String,
+/// };
+/// let v = evaluate_script(&ctx, "['18446744073709551615', 2]", None, "test.js", 1)
+///     .expect("value");
+/// let n: Vec<u64> = from_value_with_options(&v, options).expect("integers");
+/// assert_eq!(n, [u64::MAX, 2]);
+/// ```
+///
+/// [`from_value`]: fn.from_value.html
+/// [`LargeIntegers::BigInt`]: enum.LargeIntegers.html#variant.BigInt
+/// [`LargeIntegers::String`]: enum.LargeIntegers.html#variant.String
+pub fn from_value_with_options<T>(value: &JSValue, options: SerdeOptions) -> Result<T, JSException>
+where
+    T: DeserializeOwned,
+{
+    let ctx = unsafe { JSContext::from_context_ref(value.ctx) };
+    T::deserialize(Deserializer::new(&ctx, copy(value), options))
+        .map_err(|e| e.into_exception(&ctx))
+}
+
+/// Makes another handle to `value`.
+fn copy(value: &JSValue) -> JSValue {
+    JSValue {
+        raw: value.raw,
+        ctx: value.ctx,
+    }
+}
+
+/// An error of a conversion, which becomes an exception once it leaves
+/// serde.
+#[derive(Debug)]
+enum Error {
+    /// An exception thrown while converting.
+    Exception(JSException),
+    /// The message of a `TypeError`.
+    Message(String),
+}
+
+impl Error {
+    fn into_exception(self, ctx: &JSContext) -> JSException {
+        match self {
+            Error::Exception(e) => e,
+            Error::Message(message) => JSException::type_error(ctx, &message),
+        }
+    }
+}
+
+impl From<JSException> for Error {
+    fn from(e: JSException) -> Self {
+        Error::Exception(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Exception(e) => fmt::Display::fmt(e, f),
+            Error::Message(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+/// Wraps the value of an enum variant in an object with a single
+/// property, named for the variant.
+fn variant_object(ctx: &JSContext, variant: &str, value: JSValue) -> Result<JSValue, Error> {
+    let object = JSObject::new(ctx);
+    object.set_property(variant, value)?;
+    Ok(object.into())
+}
+
+struct Serializer<'a> {
+    ctx: &'a JSContext,
+    options: SerdeOptions,
+}
+
+impl Serializer<'_> {
+    /// Converts an integer beyond `MAX_SAFE_INTEGER` as the options
+    /// say, given as a number and as something that formats its digits.
+    fn serialize_large_integer(self, n: f64, digits: impl fmt::Display) -> Result<JSValue, Error> {
+        match self.options.large_integers {
+            LargeIntegers::Number => Ok(JSValue::new_number(self.ctx, n)),
+            LargeIntegers::BigInt => Ok(new_bigint(self.ctx, &digits.to_string())?),
+            LargeIntegers::String => Ok(JSValue::new_string(self.ctx, digits.to_string())),
+        }
+    }
+}
+
+impl<'a> ser::Serializer for Serializer<'a> {
+    type Ok = JSValue;
+    type Error = Error;
+    type SerializeSeq = SerializeArray<'a>;
+    type SerializeTuple = SerializeArray<'a>;
+    type SerializeTupleStruct = SerializeArray<'a>;
+    type SerializeTupleVariant = SerializeVariant<'a, SerializeArray<'a>>;
+    type SerializeMap = SerializeObject<'a>;
+    type SerializeStruct = SerializeObject<'a>;
+    type SerializeStructVariant = SerializeVariant<'a, SerializeObject<'a>>;
+
+    fn serialize_bool(self, v: bool) -> Result<JSValue, Error> {
+        Ok(JSValue::new_boolean(self.ctx, v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<JSValue, Error> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<JSValue, Error> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<JSValue, Error> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<JSValue, Error> {
+        self.serialize_i128(i128::from(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<JSValue, Error> {
+        if (v.unsigned_abs() as f64) <= MAX_SAFE_INTEGER {
+            return self.serialize_f64(v as f64);
+        }
+        self.serialize_large_integer(v as f64, v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<JSValue, Error> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<JSValue, Error> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<JSValue, Error> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<JSValue, Error> {
+        self.serialize_u128(u128::from(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<JSValue, Error> {
+        if (v as f64) <= MAX_SAFE_INTEGER {
+            return self.serialize_f64(v as f64);
+        }
+        self.serialize_large_integer(v as f64, v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<JSValue, Error> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<JSValue, Error> {
+        Ok(JSValue::new_number(self.ctx, v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<JSValue, Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<JSValue, Error> {
+        Ok(JSValue::new_string(
+            self.ctx,
+            JSString::from_str_lossless(v),
+        ))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<JSValue, Error> {
+        Ok(new_uint8_array(self.ctx, v)?.into())
+    }
+
+    fn serialize_none(self) -> Result<JSValue, Error> {
+        Ok(JSValue::new_undefined(self.ctx))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<JSValue, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<JSValue, Error> {
+        Ok(JSValue::new_null(self.ctx))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<JSValue, Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<JSValue, Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<JSValue, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<JSValue, Error> {
+        let ctx = self.ctx;
+        variant_object(ctx, variant, value.serialize(self)?)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<SerializeArray<'a>, Error> {
+        SerializeArray::new(self.ctx, self.options)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<SerializeArray<'a>, Error> {
+        SerializeArray::new(self.ctx, self.options)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<SerializeArray<'a>, Error> {
+        SerializeArray::new(self.ctx, self.options)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeVariant<'a, SerializeArray<'a>>, Error> {
+        Ok(SerializeVariant {
+            ctx: self.ctx,
+            variant,
+            inner: SerializeArray::new(self.ctx, self.options)?,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeObject<'a>, Error> {
+        Ok(SerializeObject::new(self.ctx, self.options))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<SerializeObject<'a>, Error> {
+        Ok(SerializeObject::new(self.ctx, self.options))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeVariant<'a, SerializeObject<'a>>, Error> {
+        Ok(SerializeVariant {
+            ctx: self.ctx,
+            variant,
+            inner: SerializeObject::new(self.ctx, self.options),
+        })
+    }
+}
+
+/// Serializes the elements of a sequence into an array.
+///
+/// Each element is stored as soon as it is made, so that the array
+/// keeps it from being collected while the rest are made.
+struct SerializeArray<'a> {
+    ctx: &'a JSContext,
+    options: SerdeOptions,
+    array: JSObject,
+    index: u32,
+}
+
+impl<'a> SerializeArray<'a> {
+    fn new(ctx: &'a JSContext, options: SerdeOptions) -> Result<Self, Error> {
+        Ok(SerializeArray {
+            ctx,
+            options,
+            array: new_array(ctx, &[])?,
+            index: 0,
+        })
+    }
+
+    fn push(&mut self, value: JSValue) -> Result<(), Error> {
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        unsafe {
+            sys::JSObjectSetPropertyAtIndex(
+                self.ctx.raw,
+                self.array.raw,
+                self.index,
+                value.raw,
+                &mut e,
+            );
+        }
+        if !e.is_null() {
+            return Err(JSException::from_raw(self.ctx.raw, e).into());
+        }
+        self.index += 1;
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for SerializeArray<'_> {
+    type Ok = JSValue;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let value = value.serialize(Serializer {
+            ctx: self.ctx,
+            options: self.options,
+        })?;
+        self.push(value)
+    }
+
+    fn end(self) -> Result<JSValue, Error> {
+        Ok(self.array.into())
+    }
+}
+
+impl ser::SerializeTuple for SerializeArray<'_> {
+    type Ok = JSValue;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<JSValue, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray<'_> {
+    type Ok = JSValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<JSValue, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+/// Serializes the entries of a map or the fields of a struct into an
+/// object.
+struct SerializeObject<'a> {
+    ctx: &'a JSContext,
+    object: JSObject,
+    /// The key of the entry whose value is next.
+    key: Option<JSString>,
+    options: SerdeOptions,
+    /// Whether `undefined` values are left out, so that they do not
+    /// replace the properties of an existing object.
+    merge: bool,
+}
+
+impl<'a> SerializeObject<'a> {
+    fn new(ctx: &'a JSContext, options: SerdeOptions) -> Self {
+        SerializeObject {
+            ctx,
+            object: JSObject::new(ctx),
+            key: None,
+            options,
+            merge: false,
+        }
+    }
+
+    /// Serializes into `object`, which already has properties.
+    fn merge(ctx: &'a JSContext, object: &JSObject, options: SerdeOptions) -> Self {
+        SerializeObject {
+            ctx,
+            object: JSObject {
+                raw: object.raw,
+                value: copy(&object.value),
+            },
+            key: None,
+            options,
+            merge: true,
+        }
+    }
+
+    fn set(&self, key: JSString, value: JSValue) -> Result<(), Error> {
+        if !(self.merge && value.is_undefined()) {
+            self.object.set_property(key, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for SerializeObject<'_> {
+    type Ok = JSValue;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        let key = key.serialize(Serializer {
+            ctx: self.ctx,
+            options: self.options,
+        })?;
+        if !key.is_string() && !key.is_number() {
+            return Err(Error::Message(
+                "Keys of maps must be strings or numbers".to_owned(),
+            ));
+        }
+        self.key = Some(key.as_string()?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error::Message("A value was given before its key".to_owned()))?;
+        let value = value.serialize(Serializer {
+            ctx: self.ctx,
+            options: self.options,
+        })?;
+        self.set(key, value)
+    }
+
+    fn end(self) -> Result<JSValue, Error> {
+        Ok(self.object.into())
+    }
+}
+
+impl ser::SerializeStruct for SerializeObject<'_> {
+    type Ok = JSValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        let value = value.serialize(Serializer {
+            ctx: self.ctx,
+            options: self.options,
+        })?;
+        self.set(key.into(), value)
+    }
+
+    fn end(self) -> Result<JSValue, Error> {
+        Ok(self.object.into())
+    }
+}
+
+/// Serializes the fields of a tuple or struct variant, which are then
+/// wrapped in an object named for the variant.
+struct SerializeVariant<'a, S> {
+    ctx: &'a JSContext,
+    variant: &'static str,
+    inner: S,
+}
+
+impl ser::SerializeTupleVariant for SerializeVariant<'_, SerializeArray<'_>> {
+    type Ok = JSValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(&mut self.inner, value)
+    }
+
+    fn end(self) -> Result<JSValue, Error> {
+        let value = ser::SerializeSeq::end(self.inner)?;
+        variant_object(self.ctx, self.variant, value)
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeVariant<'_, SerializeObject<'_>> {
+    type Ok = JSValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<JSValue, Error> {
+        let value = ser::SerializeStruct::end(self.inner)?;
+        variant_object(self.ctx, self.variant, value)
+    }
+}
+
+/// Serializes the fields of a struct, or the entries of a map, into an
+/// existing object. Other values can not be serialized into an object.
+struct MergeSerializer<'a> {
+    ctx: &'a JSContext,
+    target: &'a JSObject,
+    options: SerdeOptions,
+}
+
+impl MergeSerializer<'_> {
+    fn unsupported<T>(self, what: &str) -> Result<T, Error> {
+        Err(Error::Message(format!(
+            "Expected a struct or a map to assign to an object, got {}",
+            what
+        )))
+    }
+}
+
+impl<'a> ser::Serializer for MergeSerializer<'a> {
+    type Ok = JSValue;
+    type Error = Error;
+    type SerializeSeq = ser::Impossible<JSValue, Error>;
+    type SerializeTuple = ser::Impossible<JSValue, Error>;
+    type SerializeTupleStruct = ser::Impossible<JSValue, Error>;
+    type SerializeTupleVariant = ser::Impossible<JSValue, Error>;
+    type SerializeMap = SerializeObject<'a>;
+    type SerializeStruct = SerializeObject<'a>;
+    type SerializeStructVariant = ser::Impossible<JSValue, Error>;
+
+    fn serialize_bool(self, _v: bool) -> Result<JSValue, Error> {
+        self.unsupported("a boolean")
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<JSValue, Error> {
+        self.unsupported("a number")
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<JSValue, Error> {
+        self.unsupported("a number")
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<JSValue, Error> {
+        self.unsupported("a number")
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<JSValue, Error> {
+        self.unsupported("a number")
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<JSValue, Error> {
+        self.unsupported("a number")
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<JSValue, Error> {
+        self.unsupported("a number")
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<JSValue, Error> {
+        self.unsupported("a number")
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<JSValue, Error> {
+        self.unsupported("a number")
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<JSValue, Error> {
+        self.unsupported("a number")
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<JSValue, Error> {
+        self.unsupported("a number")
+    }
+
+    fn serialize_char(self, _v: char) -> Result<JSValue, Error> {
+        self.unsupported("a string")
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<JSValue, Error> {
+        self.unsupported("a string")
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<JSValue, Error> {
+        self.unsupported("bytes")
+    }
+
+    fn serialize_none(self) -> Result<JSValue, Error> {
+        self.unsupported("None")
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<JSValue, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<JSValue, Error> {
+        self.unsupported("()")
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<JSValue, Error> {
+        self.unsupported("a unit struct")
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<JSValue, Error> {
+        self.unsupported("an enum")
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<JSValue, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<JSValue, Error> {
+        self.unsupported("an enum")
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        self.unsupported("a sequence")
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        self.unsupported("a tuple")
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        self.unsupported("a tuple struct")
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        self.unsupported("an enum")
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeObject<'a>, Error> {
+        Ok(SerializeObject::merge(self.ctx, self.target, self.options))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<SerializeObject<'a>, Error> {
+        Ok(SerializeObject::merge(self.ctx, self.target, self.options))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        self.unsupported("an enum")
+    }
+}
+
+struct Deserializer<'a> {
+    ctx: &'a JSContext,
+    value: JSValue,
+    options: SerdeOptions,
+}
+
+impl<'a> Deserializer<'a> {
+    fn new(ctx: &'a JSContext, value: JSValue, options: SerdeOptions) -> Self {
+        Deserializer {
+            ctx,
+            value,
+            options,
+        }
+    }
+
+    fn is_nullish(&self) -> bool {
+        self.value.is_null() || self.value.is_undefined()
+    }
+
+    /// Gets the digits of an integer that is given in another way than
+    /// a number, as the options allow.
+    fn large_integer(&self) -> Result<Option<String>, Error> {
+        let value = &self.value;
+        let allowed = match self.options.large_integers {
+            LargeIntegers::Number => false,
+            LargeIntegers::BigInt => is_bigint(value),
+            LargeIntegers::String => value.is_string(),
+        };
+        Ok(if allowed {
+            Some(value.as_string()?.to_string_lossy())
+        } else {
+            None
+        })
+    }
+
+    /// Reads an integer, from a number or as the options allow.
+    fn deserialize_integer<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.large_integer()? {
+            Some(digits) => visit_integer(&digits, visitor),
+            None => de::Deserializer::deserialize_any(self, visitor),
+        }
+    }
+
+    fn is_typed_array(&self) -> bool {
+        !matches!(
+            typed_array_type(&self.value),
+            JSTypedArrayType::None | JSTypedArrayType::ArrayBuffer
+        )
+    }
+
+    /// Gets the value as an object, unless it is a primitive or a
+    /// function.
+    fn as_object(&self) -> Result<Option<JSObject>, Error> {
+        if !self.value.is_object() {
+            return Ok(None);
+        }
+        let object = self.value.as_object()?;
+        Ok(if object.is_function() {
+            None
+        } else {
+            Some(object)
+        })
+    }
+}
+
+/// Visits the integer with the given decimal digits, as the smallest
+/// type that holds it.
+fn visit_integer<'de, V: Visitor<'de>>(digits: &str, visitor: V) -> Result<V::Value, Error> {
+    if let Ok(n) = digits.parse::<u64>() {
+        visitor.visit_u64(n)
+    } else if let Ok(n) = digits.parse::<i64>() {
+        visitor.visit_i64(n)
+    } else if let Ok(n) = digits.parse::<u128>() {
+        visitor.visit_u128(n)
+    } else if let Ok(n) = digits.parse::<i128>() {
+        visitor.visit_i128(n)
+    } else {
+        Err(Error::Message(format!(
+            "Expected an integer, got {:?}",
+            digits
+        )))
+    }
+}
+
+/// Implements the methods of `Deserializer` that read integers, which
+/// may also be given as the options allow.
+macro_rules! deserialize_integers {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                self.deserialize_integer(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let value = &self.value;
+        if self.is_nullish() {
+            visitor.visit_unit()
+        } else if value.is_boolean() {
+            visitor.visit_bool(value.as_boolean())
+        } else if value.is_number() {
+            let n = value.as_number()?;
+            if n.fract() != 0.0 || n.abs() > MAX_SAFE_INTEGER {
+                visitor.visit_f64(n)
+            } else if n < 0.0 {
+                visitor.visit_i64(n as i64)
+            } else {
+                visitor.visit_u64(n as u64)
+            }
+        } else if value.is_string() {
+            visitor.visit_string(value.as_string()?.to_string_lossy())
+        } else if value.is_array() || self.is_typed_array() {
+            self.deserialize_seq(visitor)
+        } else if self.as_object()?.is_some() {
+            self.deserialize_map(visitor)
+        } else if let Some(digits) = self.large_integer()? {
+            visit_integer(&digits, visitor)
+        } else {
+            Err(Error::Message(
+                "Functions and symbols can not be deserialized".to_owned(),
+            ))
+        }
+    }
+
+    deserialize_integers! {
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_i128
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    /// Typed arrays give their bytes, and other values are read as they
+    /// would be otherwise, so that arrays of numbers can still be read.
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if !self.is_typed_array() {
+            return self.deserialize_any(visitor);
+        }
+        // The bytes are copied before control can return to a script.
+        let bytes = unsafe { typed_array_bytes(&self.value.as_object()?)?.to_vec() };
+        visitor.visit_byte_buf(bytes)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.is_nullish() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if !self.value.is_array() && !self.is_typed_array() {
+            return Err(Error::Message("Expected an array".to_owned()));
+        }
+        let array = self.value.as_object()?;
+        let length = array.get_property("length")?.as_number()? as u32;
+        visitor.visit_seq(SeqAccess {
+            ctx: self.ctx,
+            options: self.options,
+            array,
+            index: 0,
+            length,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let object = self
+            .as_object()?
+            .ok_or_else(|| Error::Message("Expected an object".to_owned()))?;
+        let names: Vec<JSString> = object.property_names().collect();
+        visitor.visit_map(MapAccess {
+            ctx: self.ctx,
+            options: self.options,
+            object,
+            names: names.into_iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        if self.value.is_string() {
+            return visitor.visit_enum(Enum {
+                ctx: self.ctx,
+                options: self.options,
+                variant: self.value.as_string()?.to_string_lossy(),
+                value: None,
+            });
+        }
+        if let Some(object) = self.as_object()? {
+            let mut names = object.property_names();
+            if let (Some(name), None) = (names.next(), names.next()) {
+                let variant = name.to_string_lossy();
+                let value = object.get_property(name)?;
+                return visitor.visit_enum(Enum {
+                    ctx: self.ctx,
+                    options: self.options,
+                    variant,
+                    value: Some(value),
+                });
+            }
+        }
+        Err(Error::Message(
+            "Expected a string or an object with a single property".to_owned(),
+        ))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool f32 f64 char str string unit unit_struct identifier
+    }
+}
+
+/// Reads the elements of an array.
+struct SeqAccess<'a> {
+    ctx: &'a JSContext,
+    options: SerdeOptions,
+    array: JSObject,
+    index: u32,
+    length: u32,
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess<'_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if self.index >= self.length {
+            return Ok(None);
+        }
+        let value = self.array.get_property_at_index(self.index)?;
+        self.index += 1;
+        seed.deserialize(Deserializer::new(self.ctx, value, self.options))
+            .map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some((self.length - self.index) as usize)
+    }
+}
+
+/// Reads the enumerable properties of an object.
+struct MapAccess<'a> {
+    ctx: &'a JSContext,
+    options: SerdeOptions,
+    object: JSObject,
+    names: std::vec::IntoIter<JSString>,
+    /// The value of the property whose key was read last.
+    value: Option<JSValue>,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        let Some(name) = self.names.next() else {
+            return Ok(None);
+        };
+        let key = name.to_string_lossy();
+        self.value = Some(self.object.get_property(name)?);
+        seed.deserialize(KeyDeserializer { key }).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let value = self
+            .value
+            .take()
+            .ok_or_else(|| Error::Message("A value was read before its key".to_owned()))?;
+        seed.deserialize(Deserializer::new(self.ctx, value, self.options))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.names.len())
+    }
+}
+
+/// Reads the name of a property, which may stand for a number.
+struct KeyDeserializer {
+    key: String,
+}
+
+/// Implements the methods of `KeyDeserializer` that read numbers from
+/// the key, or otherwise read it as a string.
+macro_rules! deserialize_parsed_key {
+    ($($method:ident => $visit:ident($t:ty),)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                match self.key.parse::<$t>() {
+                    Ok(n) => visitor.$visit(n),
+                    Err(_) => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for KeyDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_string(self.key)
+    }
+
+    deserialize_parsed_key! {
+        deserialize_i8 => visit_i8(i8),
+        deserialize_i16 => visit_i16(i16),
+        deserialize_i32 => visit_i32(i32),
+        deserialize_i64 => visit_i64(i64),
+        deserialize_i128 => visit_i128(i128),
+        deserialize_u8 => visit_u8(u8),
+        deserialize_u16 => visit_u16(u16),
+        deserialize_u32 => visit_u32(u32),
+        deserialize_u64 => visit_u64(u64),
+        deserialize_u128 => visit_u128(u128),
+        deserialize_f32 => visit_f32(f32),
+        deserialize_f64 => visit_f64(f64),
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool char str string bytes byte_buf option unit unit_struct
+        newtype_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// Reads an enum from the name of its variant, and the value of the
+/// variant if it has one.
+struct Enum<'a> {
+    ctx: &'a JSContext,
+    options: SerdeOptions,
+    variant: String,
+    value: Option<JSValue>,
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for Enum<'a> {
+    type Error = Error;
+    type Variant = Variant<'a>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Variant<'a>), Error> {
+        let variant: de::value::StringDeserializer<Error> = self.variant.into_deserializer();
+        let variant = seed.deserialize(variant)?;
+        Ok((
+            variant,
+            Variant {
+                ctx: self.ctx,
+                options: self.options,
+                value: self.value,
+            },
+        ))
+    }
+}
+
+struct Variant<'a> {
+    ctx: &'a JSContext,
+    options: SerdeOptions,
+    value: Option<JSValue>,
+}
+
+impl<'a> Variant<'a> {
+    fn value(self, expected: &str) -> Result<Deserializer<'a>, Error> {
+        match self.value {
+            Some(value) => Ok(Deserializer::new(self.ctx, value, self.options)),
+            None => Err(de::Error::invalid_type(
+                de::Unexpected::UnitVariant,
+                &expected,
+            )),
+        }
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Variant<'_> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.value {
+            Some(value) => {
+                de::Deserialize::deserialize(Deserializer::new(self.ctx, value, self.options))
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self.value("newtype variant")?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(self.value("tuple variant")?, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(self.value("struct variant")?, visitor)
+    }
+}
+
+/// Serializes the value as `JSON.stringify` would.
+///
+/// Properties whose values are `undefined`, functions or symbols are
+/// left out of objects, and such elements of arrays are serialized as
+/// `None`. Objects with a `toJSON` method are serialized as what it
+/// returns. Numbers are serialized as integers when they are whole and
+/// exact, and `Uint8Array`s as bytes. Cyclic values, `BigInt`s and
+/// exceptions thrown while reading, such as by getters, give errors.
+///
+/// Requires the `serde` feature.
+impl Serialize for JSValue {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let parents = RefCell::new(Vec::new());
+        Value {
+            value: copy(self),
+            parents: &parents,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl JSValue {
+    /// Writes the value as JSON to `writer`.
+    ///
+    /// * `writer`: Where to write the JSON to.
+    /// * `pretty`: Whether to indent the JSON by two spaces, like
+    ///   `JSON.stringify(value, null, 2)`.
+    ///
+    /// The JSON is written as the value is read, through its
+    /// `Serialize` implementation, without making a string of all of it
+    /// first. `undefined` is written as `null`.
+    ///
+    /// Requires the `serde_json` feature.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let v = evaluate_script(&ctx, "({ a: [1, 'b'], c: undefined })", None, "test.js", 1)
+    ///     .expect("value");
+    /// let mut json = Vec::new();
+    /// v.write_json_to(&mut json, false).expect("written");
+    /// assert_eq!(json, br#"{"a":[1,"b"]}"#);
+    /// ```
+    pub fn write_json_to<W>(&self, writer: &mut W, pretty: bool) -> io::Result<()>
+    where
+        W: io::Write + ?Sized,
+    {
+        let r = if pretty {
+            serde_json::to_writer_pretty(writer, self)
+        } else {
+            serde_json::to_writer(writer, self)
+        };
+        r.map_err(io::Error::from)
+    }
+}
+
+#[cfg(any(feature = "rmp-serde", feature = "ciborium"))]
+impl JSValue {
+    /// Writes the value as MessagePack to `writer`.
+    ///
+    /// The value is encoded as it is read, through its `Serialize`
+    /// implementation, so objects become maps, arrays become arrays and
+    /// `Uint8Array`s become binary data. `undefined` is written as nil.
+    ///
+    /// Requires the `rmp-serde` feature.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let v = evaluate_script(&ctx, "({ a: [1, 'b'] })", None, "test.js", 1).expect("value");
+    /// let mut msgpack = Vec::new();
+    /// v.write_msgpack_to(&mut msgpack).expect("written");
+    /// assert_eq!(msgpack, b"\x81\xa1a\x92\x01\xa1b");
+    /// ```
+    #[cfg(feature = "rmp-serde")]
+    pub fn write_msgpack_to<W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        W: io::Write + ?Sized,
+    {
+        rmp_serde::encode::write(writer, self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes the value as CBOR to `writer`.
+    ///
+    /// The value is encoded as it is read, through its `Serialize`
+    /// implementation, so objects become maps, arrays become arrays and
+    /// `Uint8Array`s become byte strings. `undefined` is written as
+    /// null.
+    ///
+    /// Requires the `ciborium` feature.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let v = evaluate_script(&ctx, "({ a: [1, 'b'] })", None, "test.js", 1).expect("value");
+    /// let mut cbor = Vec::new();
+    /// v.write_cbor_to(&mut cbor).expect("written");
+    /// assert_eq!(cbor, b"\xa1\x61a\x82\x01\x61b");
+    /// ```
+    #[cfg(feature = "ciborium")]
+    pub fn write_cbor_to<W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        W: io::Write + ?Sized,
+    {
+        ciborium::into_writer(self, writer).map_err(|e| match e {
+            ciborium::ser::Error::Io(e) => e,
+            ciborium::ser::Error::Value(message) => {
+                io::Error::new(io::ErrorKind::InvalidData, message)
+            }
+        })
+    }
+}
+
+/// A value that is being serialized.
+struct Value<'a> {
+    value: JSValue,
+    /// The objects that contain the value, to find cycles.
+    parents: &'a RefCell<Vec<sys::JSObjectRef>>,
+}
+
+fn custom<E: ser::Error>(message: impl fmt::Display) -> E {
+    E::custom(message)
+}
+
+impl Value<'_> {
+    /// Tests whether the value is left out of objects.
+    fn is_skipped(value: &JSValue) -> bool {
+        value.is_undefined()
+            || value.is_symbol()
+            || (value.is_object() && value.as_object().is_ok_and(|o| o.is_function()))
+    }
+
+    fn child(&self, value: JSValue) -> Self {
+        Value {
+            value,
+            parents: self.parents,
+        }
+    }
+
+    fn serialize_object<S: ser::Serializer>(
+        &self,
+        object: &JSObject,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match typed_array_type(object) {
+            JSTypedArrayType::Uint8Array => {
+                // The serializer does not run scripts, which could
+                // change the bytes.
+                let bytes = unsafe { typed_array_bytes(object) }.map_err(custom)?;
+                return serializer.serialize_bytes(bytes);
+            }
+            JSTypedArrayType::None | JSTypedArrayType::ArrayBuffer => {}
+            _ => return self.serialize_elements(object, serializer),
+        }
+        if object.is_array() {
+            return self.serialize_elements(object, serializer);
+        }
+        // The properties are read first so that the length of the map is
+        // known, which some formats, like MessagePack, need.
+        let mut entries = vec![];
+        for name in object.property_names().collect::<Vec<JSString>>() {
+            let key = name.to_string_lossy();
+            let value = object.get_property(name).map_err(custom)?;
+            if !Self::is_skipped(&value) {
+                entries.push((key, value));
+            }
+        }
+        let mut map = serializer.serialize_map(Some(entries.len()))?;
+        for (key, value) in entries {
+            map.serialize_entry(&key, &self.child(value))?;
+        }
+        map.end()
+    }
+
+    fn serialize_elements<S: ser::Serializer>(
+        &self,
+        object: &JSObject,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let length = object
+            .get_property("length")
+            .and_then(|length| length.as_number())
+            .map_err(custom)? as u32;
+        let mut seq = serializer.serialize_seq(Some(length as usize))?;
+        for index in 0..length {
+            let value = object.get_property_at_index(index).map_err(custom)?;
+            seq.serialize_element(&self.child(value))?;
+        }
+        seq.end()
+    }
+}
+
+impl Serialize for Value<'_> {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let value = &self.value;
+        if value.is_null() {
+            serializer.serialize_unit()
+        } else if Self::is_skipped(value) {
+            serializer.serialize_none()
+        } else if value.is_boolean() {
+            serializer.serialize_bool(value.as_boolean())
+        } else if value.is_number() {
+            let n = value.as_number().map_err(custom)?;
+            if n.fract() == 0.0 && n.abs() <= MAX_SAFE_INTEGER {
+                serializer.serialize_i64(n as i64)
+            } else {
+                serializer.serialize_f64(n)
+            }
+        } else if value.is_string() {
+            let s = value.as_string().map_err(custom)?;
+            serializer.serialize_str(&s.to_string_lossy())
+        } else if value.is_object() {
+            let object = value.as_object().map_err(custom)?;
+            if self.parents.borrow().contains(&object.raw) {
+                return Err(custom("Cyclic values can not be serialized"));
+            }
+            let to_json = object.get_property("toJSON").map_err(custom)?;
+            if to_json.is_object() {
+                let to_json = to_json.as_object().map_err(custom)?;
+                if to_json.is_function() {
+                    let value = to_json
+                        .call_as_function(Some(&object), &[])
+                        .map_err(custom)?;
+                    self.parents.borrow_mut().push(object.raw);
+                    let r = self.child(value).serialize(serializer);
+                    self.parents.borrow_mut().pop();
+                    return r;
+                }
+            }
+            self.parents.borrow_mut().push(object.raw);
+            let r = self.serialize_object(&object, serializer);
+            self.parents.borrow_mut().pop();
+            r
+        } else {
+            Err(custom("BigInts can not be serialized"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSContext, JSObject, JSValue};
+    use super::{
+        from_value, from_value_with_options, to_value, to_value_into, to_value_with_options,
+        LargeIntegers, SerdeOptions,
+    };
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Line(f64, f64),
+        Rect { width: f64, height: f64 },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Drawing {
+        name: String,
+        id: u64,
+        visible: bool,
+        layer: Option<i32>,
+        shapes: Vec<Shape>,
+        origin: (f64, f64),
+        tags: BTreeMap<String, u8>,
+    }
+
+    fn eval(ctx: &JSContext, script: &str) -> JSValue {
+        evaluate_script(ctx, script, None, "test.js", 1).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let ctx = JSContext::default();
+        let drawing = Drawing {
+            name: "Plan".to_owned(),
+            id: 7,
+            visible: true,
+            layer: None,
+            shapes: vec![
+                Shape::Empty,
+                Shape::Circle(1.5),
+                Shape::Line(0.0, 2.0),
+                Shape::Rect {
+                    width: 3.0,
+                    height: 4.0,
+                },
+            ],
+            origin: (-1.0, 0.5),
+            tags: [("a".to_owned(), 1), ("b".to_owned(), 2)].into(),
+        };
+        let v = to_value(&ctx, &drawing).unwrap();
+        assert_eq!(
+            v.to_json_string(0).unwrap().to_string(),
+            concat!(
+                r#"{"name":"Plan","id":7,"visible":true,"shapes":["Empty",{"Circle":1.5},"#,
+                r#"{"Line":[0,2]},{"Rect":{"width":3,"height":4}}],"origin":[-1,0.5],"#,
+                r#""tags":{"a":1,"b":2}}"#
+            )
+        );
+        assert_eq!(from_value::<Drawing>(&v).unwrap(), drawing);
+
+        let keys: BTreeMap<u32, ()> = [(1, ()), (20, ())].into();
+        let v = to_value(&ctx, &keys).unwrap();
+        assert_eq!(v.to_json_string(0).unwrap(), r#"{"1":null,"20":null}"#);
+        assert_eq!(from_value::<BTreeMap<u32, ()>>(&v).unwrap(), keys);
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Packet {
+        #[serde(with = "serde_bytes")]
+        payload: Vec<u8>,
+        checksum: Vec<u8>,
+    }
+
+    #[test]
+    fn bytes() {
+        let ctx = JSContext::default();
+        let packet = Packet {
+            payload: vec![1, 2, 255],
+            checksum: vec![7],
+        };
+        let v = to_value(&ctx, &packet).unwrap();
+        ctx.global_object().set_property("packet", v).unwrap();
+        let r = eval(
+            &ctx,
+            "[packet.payload instanceof Uint8Array, Array.from(packet.payload), packet.checksum]",
+        );
+        assert_eq!(r.to_json_string(0).unwrap(), "[true,[1,2,255],[7]]");
+        let v = ctx.global_object().get_property("packet").unwrap();
+        assert_eq!(from_value::<Packet>(&v).unwrap(), packet);
+
+        let v = eval(&ctx, "({ payload: [4, 5], checksum: new Uint8Array([6]) })");
+        assert_eq!(
+            from_value::<Packet>(&v).unwrap(),
+            Packet {
+                payload: vec![4, 5],
+                checksum: vec![6],
+            }
+        );
+        let v = eval(
+            &ctx,
+            "({ payload: new Uint16Array([0x0102]), checksum: [] })",
+        );
+        assert_eq!(
+            from_value::<Packet>(&v).unwrap().payload,
+            0x0102u16.to_ne_bytes()
+        );
+    }
+
+    #[test]
+    fn serialize() {
+        let ctx = JSContext::default();
+        let script = "({
+            a: [1, undefined, () => 0, 2.5, 'x'],
+            b: undefined,
+            c: () => 0,
+            d: { toJSON() { return 'D'; } },
+            e: new Uint8Array([1, 2]),
+            f: new Float32Array([0.5]),
+            g: null,
+        })";
+        let v = eval(&ctx, script);
+        let copy = to_value(&ctx, &v).unwrap();
+        assert_eq!(
+            copy.to_json_string(0).unwrap(),
+            r#"{"a":[1,null,null,2.5,"x"],"d":"D","e":{"0":1,"1":2},"f":[0.5],"g":null}"#
+        );
+
+        let e = to_value(&ctx, &eval(&ctx, "const o = {}; o.self = [o]; o")).unwrap_err();
+        assert_eq!(e.message(), Some("Cyclic values can not be serialized"));
+        let e = to_value(
+            &ctx,
+            &eval(&ctx, "({ get a() { throw new Error('No a'); } })"),
+        )
+        .unwrap_err();
+        assert!(e.message().unwrap().contains("No a"));
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn write_json() {
+        let ctx = JSContext::default();
+        let script = "({ a: [1, -0, 0.25, NaN], b: { c: 'd\\n' }, e: {}, f: [] })";
+        let v = eval(&ctx, script);
+        for (pretty, indent) in [(false, 0), (true, 2)] {
+            let mut json = Vec::new();
+            v.write_json_to(&mut json, pretty).unwrap();
+            let expected = v.to_json_string(indent).unwrap().to_string();
+            assert_eq!(String::from_utf8(json).unwrap(), expected);
+        }
+    }
+
+    #[cfg(all(feature = "rmp-serde", feature = "ciborium"))]
+    #[test]
+    fn write_binary() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Record {
+            name: String,
+            #[serde(with = "serde_bytes")]
+            data: Vec<u8>,
+            scores: Vec<f64>,
+            missing: Option<u8>,
+        }
+
+        let ctx = JSContext::default();
+        let script = "({ name: 'x', data: new Uint8Array([7, 8]), scores: [1, 0.5], missing: null, f() {} })";
+        let v = eval(&ctx, script);
+        let expected = Record {
+            name: "x".to_owned(),
+            data: vec![7, 8],
+            scores: vec![1.0, 0.5],
+            missing: None,
+        };
+
+        let mut msgpack = Vec::new();
+        v.write_msgpack_to(&mut msgpack).unwrap();
+        let record: Record = rmp_serde::from_slice(&msgpack).unwrap();
+        assert_eq!(record, expected);
+
+        let mut cbor = Vec::new();
+        v.write_cbor_to(&mut cbor).unwrap();
+        let record: Record = ciborium::from_reader(&cbor[..]).unwrap();
+        assert_eq!(record, expected);
+
+        let e = eval(&ctx, "const o = {}; o.self = o; o")
+            .write_msgpack_to(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn large_integers() {
+        let ctx = JSContext::default();
+        let values = (
+            9_007_199_254_740_993u64,
+            -9_007_199_254_740_993i64,
+            1u64 << 53,
+            i128::MIN,
+        );
+
+        let v = to_value(&ctx, &values).unwrap();
+        assert_eq!(
+            v.to_json_string(0).unwrap(),
+            "[9007199254740992,-9007199254740992,9007199254740991,-1.7014118346046923e+38]"
+        );
+        assert!(from_value::<(u64, i64, u64, i128)>(&v).is_err());
+
+        let options = SerdeOptions {
+            large_integers: LargeIntegers::String,
+        };
+        let v = to_value_with_options(&ctx, &values, options).unwrap();
+        assert_eq!(
+            v.to_json_string(0).unwrap(),
+            r#"["9007199254740993","-9007199254740993",9007199254740991,"-170141183460469231731687303715884105728"]"#
+        );
+        assert_eq!(from_value_with_options(&v, options).ok(), Some(values));
+        let e = from_value_with_options::<u64>(&eval(&ctx, "'12a'"), options).unwrap_err();
+        assert_eq!(e.message(), Some(r#"Expected an integer, got "12a""#));
+        let e = from_value_with_options::<u8>(&eval(&ctx, "'256'"), options).unwrap_err();
+        assert_eq!(e.name(), Some("TypeError"));
+
+        let options = SerdeOptions {
+            large_integers: LargeIntegers::BigInt,
+        };
+        let v = to_value_with_options(&ctx, &values, options).unwrap();
+        ctx.global_object().set_property("v", v).unwrap();
+        let script = "v[0] === 9007199254740993n && v[1] === -9007199254740993n \
+            && v[2] === 9007199254740991 && v[3] === -(2n ** 127n)";
+        assert!(eval(&ctx, script).as_boolean());
+        let v = ctx.global_object().get_property("v").unwrap();
+        assert_eq!(from_value_with_options(&v, options).ok(), Some(values));
+        let v = eval(&ctx, "({ a: 1n, b: 2 })");
+        let map: BTreeMap<String, u8> = from_value_with_options(&v, options).unwrap();
+        assert_eq!(
+            map,
+            BTreeMap::from([("a".to_owned(), 1), ("b".to_owned(), 2)])
+        );
+        assert!(from_value::<BTreeMap<String, u8>>(&v).is_err());
+    }
+
+    #[test]
+    fn assign() {
+        #[derive(Serialize)]
+        struct Options {
+            timeout: u32,
+            retries: Option<u8>,
+            headers: BTreeMap<String, String>,
+        }
+
+        let ctx = JSContext::default();
+        let target = eval(
+            &ctx,
+            "({ url: '/a', timeout: 5, retries: 2, headers: { a: 'b' } })",
+        )
+        .as_object()
+        .unwrap();
+        let options = Options {
+            timeout: 100,
+            retries: None,
+            headers: BTreeMap::from([("c".to_owned(), "d".to_owned())]),
+        };
+        to_value_into(&ctx, &options, &target).unwrap();
+        assert_eq!(
+            JSValue::from(target).to_json_string(0).unwrap(),
+            r#"{"url":"/a","timeout":100,"retries":2,"headers":{"c":"d"}}"#
+        );
+
+        let target = eval(&ctx, "({ set b(v) { throw new RangeError('No b'); } })")
+            .as_object()
+            .unwrap();
+        let map = BTreeMap::from([("a", 2), ("b", 3)]);
+        let e = to_value_into(&ctx, &map, &target).unwrap_err();
+        assert_eq!(e.name(), Some("RangeError"));
+        assert_eq!(target.get_property("a").unwrap().as_number().unwrap(), 2.0);
+        let e = to_value_into(&ctx, &[1, 2], &JSObject::new(&ctx)).unwrap_err();
+        assert_eq!(e.name(), Some("TypeError"));
+    }
+
+    #[test]
+    fn from_script_values() {
+        let ctx = JSContext::default();
+        let v = eval(&ctx, "({ a: [1, -2, 3.5], b: null, c: undefined, d: 'x' })");
+        let map: BTreeMap<String, Option<Vec<f64>>> =
+            from_value(&eval(&ctx, "({ a: [1, -2, 3.5], b: null })")).unwrap();
+        assert_eq!(map["a"], Some(vec![1.0, -2.0, 3.5]));
+        assert_eq!(map["b"], None);
+
+        let e = from_value::<BTreeMap<String, Vec<f64>>>(&v).unwrap_err();
+        assert_eq!(e.name(), Some("TypeError"));
+        let e = from_value::<u8>(&eval(&ctx, "300")).unwrap_err();
+        assert_eq!(e.name(), Some("TypeError"));
+        let e = from_value::<String>(&eval(&ctx, "(function () {})")).unwrap_err();
+        assert_eq!(e.name(), Some("TypeError"));
+
+        let e = from_value::<BTreeMap<String, f64>>(&eval(
+            &ctx,
+            "({ get a() { throw new RangeError('No a'); } })",
+        ))
+        .unwrap_err();
+        assert_eq!(e.name(), Some("RangeError"));
+    }
+}
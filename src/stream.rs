@@ -0,0 +1,479 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::typedarray::new_uint8_array;
+#[cfg(feature = "async")]
+use super::typedarray::typed_array_bytes;
+#[cfg(feature = "async")]
+use super::{AsyncIteratorStream, JSError};
+use super::{JSContext, JSException, JSObject, JSValue, Spawner, ToJs};
+#[cfg(feature = "async")]
+use std::future;
+use std::io;
+#[cfg(feature = "async")]
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Makes a `ReadableStream` that pulls its chunks from an async
+/// iterator.
+const MAKE_STREAM: &str = r#"(function (iterator) {
+    'use strict';
+    if (typeof ReadableStream !== 'function') {
+        throw new TypeError('ReadableStream is not defined');
+    }
+    return new ReadableStream({
+        async pull(controller) {
+            const { value, done } = await iterator.next();
+            if (done) {
+                controller.close();
+            } else {
+                controller.enqueue(value);
+            }
+        },
+        cancel() {
+            return iterator.return();
+        },
+    });
+})"#;
+
+/// A chunk of a Rust stream, which becomes a `Uint8Array`.
+struct Chunk(Vec<u8>);
+
+impl ToJs for Chunk {
+    fn to_js(&self, ctx: &JSContext) -> Result<JSValue, JSException> {
+        Ok(new_uint8_array(ctx, &self.0)?.into())
+    }
+}
+
+impl JSObject {
+    /// Creates a `ReadableStream` whose chunks a Rust stream of bytes
+    /// produces.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `spawner`: Runs the future that polls the stream.
+    /// * `poll_next`: Polls the stream for its next chunk, in the way of
+    ///   `poll_next` of the `Stream` trait of the `futures` crate, and
+    ///   returns `None` once the stream has ended.
+    ///
+    /// Each chunk becomes a `Uint8Array`, and an error errors the stream.
+    /// The stream is only polled when the `ReadableStream` pulls, so
+    /// chunks are produced as the script reads them. Cancelling the
+    /// `ReadableStream` drops the stream.
+    ///
+    /// JavaScriptCore does not have `ReadableStream` itself, so this
+    /// fails with a `TypeError` unless the host has defined it, for
+    /// example with a polyfill.
+    ///
+    /// [`JSObject::new_async_iterator`] can make an async iterable of
+    /// chunks instead, which needs no `ReadableStream`.
+    ///
+    /// [`JSObject::new_async_iterator`]: #method.new_async_iterator
+    pub fn new_readable_stream<S, F>(
+        ctx: &JSContext,
+        spawner: S,
+        mut poll_next: F,
+    ) -> Result<JSObject, JSException>
+    where
+        S: Spawner + 'static,
+        F: FnMut(&mut Context<'_>) -> Poll<Option<io::Result<Vec<u8>>>> + 'static,
+    {
+        let iterator = JSObject::new_async_iterator(ctx, spawner, move |cx| {
+            poll_next(cx).map(|chunk| chunk.map(|chunk| chunk.map(Chunk)))
+        })?;
+        let make = ctx.hooks.function(ctx, MAKE_STREAM, "stream.js")?;
+        make.call_as_function(None, &[iterator.into()])?.as_object()
+    }
+}
+
+/// Reads the bytes of a JavaScript `ReadableStream` from Rust.
+///
+/// This is made by [`JSValue::get_reader`]. It can be read by chunk,
+/// with [`poll_next`], or as bytes, with [`poll_read`]. Each chunk must
+/// be a typed array, such as a `Uint8Array`. Dropping the reader before
+/// the end cancels the stream.
+///
+/// The reader implements the `Stream` trait of `futures-core` with the
+/// `futures-core` feature, the `AsyncRead` trait of `futures-io` with
+/// the `futures-io` feature, and the `AsyncRead` trait of Tokio with the
+/// `tokio` feature, so that it can be piped into Rust IO.
+///
+/// Like the stream, the reader must stay on the thread of its context.
+///
+/// [`JSValue::get_reader`]: struct.JSValue.html#method.get_reader
+/// [`poll_next`]: #method.poll_next
+/// [`poll_read`]: #method.poll_read
+#[cfg(feature = "async")]
+pub struct ReadableStreamReader {
+    chunks: AsyncIteratorStream,
+    /// The rest of a chunk that has been partly read.
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+#[cfg(feature = "async")]
+impl ReadableStreamReader {
+    /// Polls for the next chunk of the stream.
+    ///
+    /// Returns `None` once the stream is closed. An error of the stream
+    /// ends it after it is returned, while a chunk that is not a typed
+    /// array fails with a `TypeError` without ending it.
+    pub fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Vec<u8>, JSException>>> {
+        if self.position < self.buffer.len() {
+            let position = self.position;
+            self.position = 0;
+            let mut chunk = std::mem::take(&mut self.buffer);
+            chunk.drain(..position);
+            return Poll::Ready(Some(Ok(chunk)));
+        }
+        let chunk = match Pin::new(&mut self.chunks).poll_next(cx) {
+            Poll::Ready(chunk) => chunk,
+            Poll::Pending => return Poll::Pending,
+        };
+        Poll::Ready(chunk.map(|chunk| {
+            let chunk = chunk?.as_object()?;
+            Ok(unsafe { typed_array_bytes(&chunk)? }.to_vec())
+        }))
+    }
+
+    /// Polls for bytes of the stream, and copies as many as fit into
+    /// `buf`.
+    ///
+    /// Returns the number of bytes copied, which is `0` once the stream
+    /// is closed or if `buf` is empty. The errors of [`poll_next`] are
+    /// returned as `io::Error`s, whose source is a [`JSError`].
+    ///
+    /// [`poll_next`]: #method.poll_next
+    /// [`JSError`]: struct.JSError.html
+    pub fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        while self.position == self.buffer.len() {
+            match self.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    self.buffer = chunk;
+                    self.position = 0;
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    return Poll::Ready(Err(io::Error::other(JSError::from(e))))
+                }
+                Poll::Ready(None) => return Poll::Ready(Ok(0)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let n = buf.len().min(self.buffer.len() - self.position);
+        let start = self.position;
+        buf[..n].copy_from_slice(&self.buffer[start..start + n]);
+        self.position += n;
+        Poll::Ready(Ok(n))
+    }
+
+    /// Waits for the next chunk of the stream.
+    ///
+    /// See [`poll_next`] for how the stream ends.
+    ///
+    /// [`poll_next`]: #method.poll_next
+    pub async fn next(&mut self) -> Option<Result<Vec<u8>, JSException>> {
+        future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Waits for bytes of the stream, and copies as many as fit into
+    /// `buf`.
+    ///
+    /// See [`poll_read`] for what is returned.
+    ///
+    /// [`poll_read`]: #method.poll_read
+    pub async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        future::poll_fn(|cx| Pin::new(&mut *self).poll_read(cx, buf)).await
+    }
+}
+
+/// Requires the `futures-core` feature.
+#[cfg(feature = "futures-core")]
+impl futures_core::Stream for ReadableStreamReader {
+    type Item = Result<Vec<u8>, JSException>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        ReadableStreamReader::poll_next(self, cx)
+    }
+}
+
+/// Requires the `futures-io` feature.
+#[cfg(feature = "futures-io")]
+impl futures_io::AsyncRead for ReadableStreamReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        ReadableStreamReader::poll_read(self, cx, buf)
+    }
+}
+
+/// Requires the `tokio` feature.
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncRead for ReadableStreamReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let n = std::task::ready!(ReadableStreamReader::poll_read(
+            self,
+            cx,
+            buf.initialize_unfilled()
+        ))?;
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "async")]
+impl JSValue {
+    /// Locks this `ReadableStream` to a reader, so that Rust code can
+    /// read its bytes.
+    ///
+    /// Any object whose `getReader` method returns a reader with `read`
+    /// and `cancel` methods will do. Fails with a `TypeError` if the
+    /// value has no such method, or the exception that it throws, such
+    /// as if the stream is already locked.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// # use std::future::Future;
+    /// # use std::pin::pin;
+    /// # use std::task::{Context, Poll, Waker};
+    /// let ctx = JSContext::default();
+    /// // A stand-in for a `ReadableStream`.
+    /// let script = "({ getReader() {
+    ///     const chunks = [new Uint8Array([1, 2, 3]), new Uint8Array([4])];
+    ///     return {
+    ///         read: async () => chunks.length ? { value: chunks.shift(), done: false } : { done: true },
+    ///         cancel: async () => {},
+    ///     };
+    /// } })";
+    /// let stream = evaluate_script(&ctx, script, None, "test.js", 1).expect("stream");
+    /// let mut reader = stream.get_reader().expect("reader");
+    ///
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// let mut buf = [0; 2];
+    /// let mut bytes = vec![];
+    /// loop {
+    ///     let read = pin!(reader.read(&mut buf)).poll(&mut cx);
+    ///     let Poll::Ready(Ok(n @ 1..)) = read else { break };
+    ///     bytes.extend_from_slice(&buf[..n]);
+    /// }
+    /// assert_eq!(bytes, [1, 2, 3, 4]);
+    /// ```
+    pub fn get_reader(&self) -> Result<ReadableStreamReader, JSException> {
+        let ctx = unsafe { JSContext::from_context_ref(self.ctx) };
+        let stream = self.as_object()?;
+        let get_reader = stream.get_property("getReader")?;
+        let get_reader = match get_reader.as_object() {
+            Ok(get_reader) if get_reader.is_function() => get_reader,
+            _ => {
+                return Err(JSException::type_error(
+                    &ctx,
+                    "Value is not a ReadableStream",
+                ))
+            }
+        };
+        let reader = get_reader
+            .call_as_function(Some(&stream), &[])?
+            .as_object()?;
+        Ok(ReadableStreamReader {
+            chunks: AsyncIteratorStream::new(&ctx, &reader, "read", "cancel"),
+            buffer: Vec::new(),
+            position: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSContext, JSObject, Spawner};
+    use std::cell::RefCell;
+    use std::future::Future;
+    use std::io;
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::task::{Context, Poll, Waker};
+
+    type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+    #[derive(Clone, Default)]
+    struct Queue(Rc<RefCell<Vec<Task>>>);
+
+    impl Spawner for Queue {
+        fn spawn(&self, future: Task) {
+            self.0.borrow_mut().push(future);
+        }
+    }
+
+    impl Queue {
+        /// Polls the futures until none are left.
+        fn run(&self) {
+            loop {
+                let future = self.0.borrow_mut().pop();
+                let Some(mut future) = future else { break };
+                let mut cx = Context::from_waker(Waker::noop());
+                assert!(future.as_mut().poll(&mut cx).is_ready());
+            }
+        }
+    }
+
+    fn eval(ctx: &JSContext, script: &str) -> String {
+        let r = evaluate_script(ctx, script, None, "test.js", 1).unwrap();
+        r.to_json_string(0).unwrap().to_string()
+    }
+
+    /// Just enough of `ReadableStream` to pull every chunk.
+    const FAKE_STREAM: &str = "globalThis.ReadableStream = class {
+        constructor(source) {
+            this.source = source;
+        }
+
+        async drain() {
+            const chunks = [];
+            let closed = false;
+            const controller = {
+                enqueue: (chunk) => chunks.push(Array.from(chunk)),
+                close: () => closed = true,
+            };
+            while (!closed) {
+                await this.source.pull(controller);
+            }
+            return chunks;
+        }
+    }; 0";
+
+    #[test]
+    fn new_readable_stream() {
+        let ctx = JSContext::default();
+        let queue = Queue::default();
+        let mut chunks = vec![Ok(vec![1, 2]), Ok(vec![]), Ok(vec![3])].into_iter();
+        let e = JSObject::new_readable_stream(&ctx, queue.clone(), |_cx| Poll::Ready(None))
+            .unwrap_err();
+        assert_eq!(e.name(), Some("TypeError"));
+
+        eval(&ctx, FAKE_STREAM);
+        let stream = JSObject::new_readable_stream(&ctx, queue.clone(), move |_cx| {
+            Poll::Ready(chunks.next())
+        })
+        .unwrap();
+        ctx.global_object()
+            .set_property("stream", stream.into())
+            .unwrap();
+        eval(&ctx, "stream.drain().then(c => globalThis.chunks = c); 0");
+        queue.run();
+        assert_eq!(eval(&ctx, "chunks"), "[[1,2],[],[3]]");
+
+        let stream = JSObject::new_readable_stream(&ctx, queue.clone(), |_cx| {
+            Poll::Ready(Some(Err(io::Error::other("broken"))))
+        })
+        .unwrap();
+        ctx.global_object()
+            .set_property("broken", stream.into())
+            .unwrap();
+        eval(
+            &ctx,
+            "broken.drain().catch(e => globalThis.error = e.message); 0",
+        );
+        queue.run();
+        assert_eq!(eval(&ctx, "error"), r#""broken""#);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn get_reader() {
+        use std::pin::pin;
+
+        let ctx = JSContext::default();
+        let mut cx = Context::from_waker(Waker::noop());
+        let stream = evaluate_script(
+            &ctx,
+            "globalThis.log = [];
+             ({ getReader() {
+                 const chunks = [new Uint8Array([1, 2, 3]), new Uint16Array([0x0504]), 'text'];
+                 return {
+                     read: async () => chunks.length ? { value: chunks.shift(), done: false } : { done: true },
+                     cancel: async () => log.push('cancelled'),
+                 };
+             } })",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        let mut reader = stream.get_reader().unwrap();
+        let mut buf = [0; 2];
+        let read = pin!(reader.read(&mut buf)).poll(&mut cx);
+        match read {
+            Poll::Ready(Ok(2)) => assert_eq!(buf, [1, 2]),
+            r => panic!("unexpected read {:?}", r),
+        }
+        match pin!(reader.next()).poll(&mut cx) {
+            Poll::Ready(Some(Ok(chunk))) => assert_eq!(chunk, [3]),
+            _ => panic!("the rest of the chunk should be next"),
+        }
+        match pin!(reader.next()).poll(&mut cx) {
+            Poll::Ready(Some(Ok(chunk))) => assert_eq!(chunk, 0x0504u16.to_ne_bytes()),
+            _ => panic!("typed arrays are read as bytes"),
+        }
+        match pin!(reader.read(&mut buf)).poll(&mut cx) {
+            Poll::Ready(Err(e)) => assert_eq!(e.kind(), io::ErrorKind::Other),
+            r => panic!("unexpected read {:?}", r),
+        }
+        drop(reader);
+        assert_eq!(eval(&ctx, "log"), r#"["cancelled"]"#);
+
+        let e = evaluate_script(&ctx, "({})", None, "test.js", 1)
+            .unwrap()
+            .get_reader()
+            .err()
+            .unwrap();
+        assert_eq!(e.name(), Some("TypeError"));
+    }
+
+    #[cfg(feature = "futures-io")]
+    #[test]
+    fn async_read() {
+        use futures_io::AsyncRead;
+
+        let ctx = JSContext::default();
+        let mut cx = Context::from_waker(Waker::noop());
+        let stream = evaluate_script(
+            &ctx,
+            "({ getReader() {
+                 const chunks = [new Uint8Array([1, 2, 3]), new Uint8Array([4])];
+                 return {
+                     read: async () => chunks.length ? { value: chunks.shift(), done: false } : { done: true },
+                     cancel: async () => {},
+                 };
+             } })",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        let mut reader = stream.get_reader().unwrap();
+        let mut buf = [0; 8];
+        let mut bytes = vec![];
+        while let Poll::Ready(Ok(n @ 1..)) =
+            AsyncRead::poll_read(Pin::new(&mut reader), &mut cx, &mut buf)
+        {
+            bytes.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(bytes, [1, 2, 3, 4]);
+    }
+}
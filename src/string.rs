@@ -4,12 +4,173 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use super::builder::start_engine;
 use super::JSString;
 use crate::sys;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ffi::CString;
 use std::fmt;
+use std::slice;
+use std::str;
+
+/// The most strings that the cache of each thread keeps.
+const CACHE_CAPACITY: usize = 256;
+
+/// The length in bytes of the longest strings that are cached.
+const CACHE_MAX_LEN: usize = 64;
+
+/// The length in bytes of the longest strings that are converted
+/// without going through UTF-8, if they are ASCII.
+const ASCII_MAX_LEN: usize = 128;
+
+thread_local! {
+    /// The short strings that were converted most recently on this
+    /// thread, which are mostly the names of properties.
+    ///
+    /// The cache is kept by thread rather than by context, as strings
+    /// do not belong to a context, and converting a name does not know
+    /// which context it is for. All of the contexts that a thread uses
+    /// share its cache.
+    static CACHE: RefCell<StringCache> = RefCell::new(StringCache::default());
+}
+
+/// Marks the ends of the list of cached strings.
+const NONE: usize = usize::MAX;
+
+/// A cache of converted strings, which drops the least recently used
+/// string once it is full.
+///
+/// The strings are kept in a list from the most to the least recently
+/// used, by index, so that finding, using and dropping a string each
+/// take the same time however many strings there are. Once the cache
+/// is full, the names of dropped strings are reused, so that it does
+/// not allocate either.
+struct StringCache {
+    /// The indices of the strings in `entries`, by name.
+    slots: HashMap<String, usize>,
+    entries: Vec<Entry>,
+    /// The most recently used string.
+    head: usize,
+    /// The least recently used string.
+    tail: usize,
+}
+
+/// A string in the cache.
+struct Entry {
+    name: String,
+    string: JSString,
+    /// The string that was used after this one.
+    prev: usize,
+    /// The string that was used before this one.
+    next: usize,
+}
+
+impl Default for StringCache {
+    fn default() -> Self {
+        StringCache {
+            slots: HashMap::with_capacity(CACHE_CAPACITY),
+            entries: Vec::with_capacity(CACHE_CAPACITY),
+            head: NONE,
+            tail: NONE,
+        }
+    }
+}
+
+impl StringCache {
+    fn get(&mut self, s: &str) -> JSString {
+        if let Some(&i) = self.slots.get(s) {
+            self.unlink(i);
+            self.push_front(i);
+            return self.entries[i].string.retain();
+        }
+        let string = JSString::create(s);
+        let (i, mut name) = if self.entries.len() < CACHE_CAPACITY {
+            self.entries.push(Entry {
+                name: String::with_capacity(CACHE_MAX_LEN),
+                string: string.retain(),
+                prev: NONE,
+                next: NONE,
+            });
+            (self.entries.len() - 1, String::with_capacity(CACHE_MAX_LEN))
+        } else {
+            let i = self.tail;
+            self.unlink(i);
+            let entry = &mut self.entries[i];
+            let (name, _) = self
+                .slots
+                .remove_entry(entry.name.as_str())
+                .expect("cached string");
+            entry.string = string.retain();
+            (i, name)
+        };
+        name.clear();
+        name.push_str(s);
+        self.entries[i].name.clone_from(&name);
+        self.slots.insert(name, i);
+        self.push_front(i);
+        string
+    }
+
+    /// Takes the string at `i` out of the list.
+    fn unlink(&mut self, i: usize) {
+        let Entry { prev, next, .. } = self.entries[i];
+        match prev {
+            NONE => self.head = next,
+            prev => self.entries[prev].next = next,
+        }
+        match next {
+            NONE => self.tail = prev,
+            next => self.entries[next].prev = prev,
+        }
+    }
+
+    /// Puts the string at `i` at the front of the list, as the most
+    /// recently used.
+    fn push_front(&mut self, i: usize) {
+        self.entries[i].prev = NONE;
+        self.entries[i].next = self.head;
+        match self.head {
+            NONE => self.tail = i,
+            head => self.entries[head].prev = i,
+        }
+        self.head = i;
+    }
+}
 
 impl JSString {
+    /// Creates a `JSString` from a Rust string, without the cache.
+    fn create(s: &str) -> Self {
+        // ASCII is the same in UTF-16, so short ASCII strings, which
+        // most names are, can skip the copy to a `CString` and the
+        // decoding of UTF-8.
+        if s.len() <= ASCII_MAX_LEN {
+            let mut units = [0; ASCII_MAX_LEN];
+            let mut ascii = true;
+            for (unit, &b) in units.iter_mut().zip(s.as_bytes()) {
+                // `NUL`s go the slow way, which rejects them.
+                ascii &= b != 0 && b.is_ascii();
+                *unit = u16::from(b);
+            }
+            if ascii {
+                return JSString::from_utf16(&units[..s.len()]);
+            }
+        }
+        let c = CString::new(s.as_bytes()).unwrap();
+        start_engine();
+        JSString {
+            raw: unsafe { sys::JSStringCreateWithUTF8CString(c.as_ptr()) },
+        }
+    }
+
+    /// Gets another reference to this `JSString`.
+    fn retain(&self) -> Self {
+        JSString {
+            raw: unsafe { sys::JSStringRetain(self.raw) },
+        }
+    }
+
     /// Convert this `JSString` to a `String`.
     pub fn to_string(&self) -> String {
         unsafe {
@@ -24,6 +185,86 @@ impl JSString {
             String::from_utf8(buffer).unwrap()
         }
     }
+
+    /// Converts this `JSString` to a Rust string, without allocating
+    /// one if it is ASCII and fits in `buffer`.
+    ///
+    /// This suits strings that are only looked at briefly, such as
+    /// messages that are logged or names that are matched, which are
+    /// usually short and ASCII. Other strings are converted to a
+    /// `String`, with unpaired surrogates replaced with U+FFFD.
+    ///
+    /// ```
+    /// # use javascriptcore::JSString;
+    /// use std::borrow::Cow;
+    ///
+    /// let mut buffer = [0; 64];
+    /// let s = JSString::from("click");
+    /// assert!(matches!(s.to_str_in(&mut buffer), Cow::Borrowed("click")));
+    ///
+    /// let s = JSString::from("déjà vu");
+    /// assert!(matches!(s.to_str_in(&mut buffer), Cow::Owned(_)));
+    /// ```
+    pub fn to_str_in<'b>(&self, buffer: &'b mut [u8]) -> Cow<'b, str> {
+        let units = self.utf16();
+        if units.len() <= buffer.len() && units.iter().all(|&unit| unit < 0x80) {
+            let bytes = &mut buffer[..units.len()];
+            for (byte, &unit) in bytes.iter_mut().zip(units) {
+                *byte = unit as u8;
+            }
+            // ASCII is valid UTF-8.
+            return Cow::Borrowed(unsafe { str::from_utf8_unchecked(bytes) });
+        }
+        Cow::Owned(self.to_string_lossy())
+    }
+
+    /// Tests whether this `JSString` holds the same characters as `s`,
+    /// without converting either of them.
+    fn eq_str(&self, s: &str) -> bool {
+        self.utf16().iter().copied().eq(s.encode_utf16())
+    }
+
+    /// Creates a `JSString` from UTF-16 code units.
+    ///
+    /// Unlike the conversion from `&str`, this allows the string to
+    /// contain `NUL` characters.
+    pub(crate) fn from_utf16(units: &[u16]) -> Self {
+        start_engine();
+        JSString {
+            raw: unsafe { sys::JSStringCreateWithCharacters(units.as_ptr(), units.len()) },
+        }
+    }
+
+    /// Creates a `JSString` from a Rust string that may contain `NUL`
+    /// characters.
+    pub(crate) fn from_str_lossless(s: &str) -> Self {
+        let units: Vec<u16> = s.encode_utf16().collect();
+        JSString::from_utf16(&units)
+    }
+
+    /// Converts this `JSString` to a `String`, replacing unpaired
+    /// surrogates with U+FFFD.
+    ///
+    /// Unlike `to_string`, this keeps any `NUL` characters.
+    pub(crate) fn to_string_lossy(&self) -> String {
+        char::decode_utf16(self.utf16().iter().copied())
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
+    }
+
+    /// Gets the UTF-16 code units of this `JSString`.
+    ///
+    /// The code units are not necessarily valid UTF-16, as JavaScript
+    /// strings may contain unpaired surrogates.
+    pub(crate) fn utf16(&self) -> &[u16] {
+        unsafe {
+            let len = sys::JSStringGetLength(self.raw);
+            if len == 0 {
+                return &[];
+            }
+            slice::from_raw_parts(sys::JSStringGetCharactersPtr(self.raw), len)
+        }
+    }
 }
 
 impl fmt::Debug for JSString {
@@ -46,47 +287,43 @@ impl PartialEq for JSString {
 
 impl<'s> PartialEq<&'s str> for JSString {
     fn eq(&self, other: &&'s str) -> bool {
-        let utf8 = CString::new(other.as_bytes()).unwrap();
-        unsafe { sys::JSStringIsEqualToUTF8CString(self.raw, utf8.as_ptr()) }
+        self.eq_str(other)
     }
 }
 
 impl PartialEq<String> for JSString {
     fn eq(&self, other: &String) -> bool {
-        let utf8 = CString::new(other.as_bytes()).unwrap();
-        unsafe { sys::JSStringIsEqualToUTF8CString(self.raw, utf8.as_ptr()) }
+        self.eq_str(other)
     }
 }
 
 impl<'s> PartialEq<JSString> for &'s str {
     fn eq(&self, other: &JSString) -> bool {
-        let utf8 = CString::new(self.as_bytes()).unwrap();
-        unsafe { sys::JSStringIsEqualToUTF8CString(other.raw, utf8.as_ptr()) }
+        other.eq_str(self)
     }
 }
 
 impl PartialEq<JSString> for String {
     fn eq(&self, other: &JSString) -> bool {
-        let utf8 = CString::new(self.as_bytes()).unwrap();
-        unsafe { sys::JSStringIsEqualToUTF8CString(other.raw, utf8.as_ptr()) }
+        other.eq_str(self)
     }
 }
 
 impl<'s> From<&'s str> for JSString {
     fn from(s: &'s str) -> Self {
-        let c = CString::new(s.as_bytes()).unwrap();
-        JSString {
-            raw: unsafe { sys::JSStringCreateWithUTF8CString(c.as_ptr()) },
+        if s.len() > CACHE_MAX_LEN {
+            return JSString::create(s);
         }
+        // The cache is gone once the thread is being torn down.
+        CACHE
+            .try_with(|cache| cache.borrow_mut().get(s))
+            .unwrap_or_else(|_| JSString::create(s))
     }
 }
 
 impl From<String> for JSString {
     fn from(s: String) -> Self {
-        let c = CString::new(s.as_bytes()).unwrap();
-        JSString {
-            raw: unsafe { sys::JSStringCreateWithUTF8CString(c.as_ptr()) },
-        }
+        JSString::from(s.as_str())
     }
 }
 
@@ -98,7 +335,8 @@ impl<'s> From<&'s JSString> for String {
 
 #[cfg(test)]
 mod tests {
-    use super::JSString;
+    use super::{JSString, ASCII_MAX_LEN, CACHE, CACHE_CAPACITY, CACHE_MAX_LEN};
+    use std::borrow::Cow;
 
     #[test]
     fn from_conversion() {
@@ -118,6 +356,78 @@ mod tests {
         assert_eq!(e, "abcdef");
     }
 
+    #[test]
+    fn to_str_in() {
+        let mut buffer = [0; 8];
+        let s = JSString::from("abc");
+        assert!(matches!(s.to_str_in(&mut buffer), Cow::Borrowed("abc")));
+        let s = JSString::from("");
+        assert!(matches!(s.to_str_in(&mut buffer), Cow::Borrowed("")));
+
+        // Too long for the buffer.
+        let s = JSString::from("abcdefghi");
+        assert_eq!(
+            s.to_str_in(&mut buffer),
+            Cow::<str>::Owned("abcdefghi".into())
+        );
+        let s = JSString::from("日本");
+        assert_eq!(s.to_str_in(&mut buffer), Cow::<str>::Owned("日本".into()));
+        let s = JSString::from_utf16(&[0x61, 0xd800]);
+        assert_eq!(s.to_str_in(&mut buffer), "a\u{fffd}");
+        let s = JSString::from_str_lossless("a\0b");
+        assert_eq!(s.to_str_in(&mut buffer), "a\0b");
+    }
+
+    #[test]
+    fn ascii() {
+        let names = ["", "id", "camelCase_42", "é", "ascii then é", "日本語"];
+        for name in names {
+            let a = JSString::create(name);
+            assert_eq!(a, name);
+            assert_eq!(a.to_string(), name);
+            assert_eq!(a.utf16().len(), name.encode_utf16().count());
+        }
+        let long = "a".repeat(ASCII_MAX_LEN + 1);
+        assert_eq!(JSString::create(&long), long);
+        let longest = "a".repeat(ASCII_MAX_LEN);
+        assert_eq!(JSString::create(&longest), longest);
+    }
+
+    #[test]
+    fn cache() {
+        let a: JSString = "id".into();
+        let b: JSString = "id".to_owned().into();
+        assert_eq!(a.raw, b.raw);
+        drop(a);
+        assert_eq!(b, "id");
+
+        let long = "x".repeat(CACHE_MAX_LEN + 1);
+        let c: JSString = long.as_str().into();
+        let d: JSString = long.as_str().into();
+        assert_ne!(c.raw, d.raw);
+        assert_eq!(c, d);
+
+        for i in 0..CACHE_CAPACITY * 2 {
+            let name: JSString = format!("name{}", i).into();
+            assert_eq!(name, format!("name{}", i));
+            // A string that is used all of the time stays.
+            assert_eq!(JSString::from("id").raw, b.raw);
+        }
+        CACHE.with(|cache| {
+            let cache = cache.borrow();
+            assert_eq!(cache.slots.len(), CACHE_CAPACITY);
+            // The least recently used strings were dropped.
+            assert!(!cache.slots.contains_key("name0"));
+            assert!(!cache
+                .slots
+                .contains_key(format!("name{}", CACHE_CAPACITY).as_str()));
+            assert!(cache
+                .slots
+                .contains_key(format!("name{}", CACHE_CAPACITY + 1).as_str()));
+            assert!(cache.slots.contains_key("id"));
+        });
+    }
+
     #[test]
     fn equality() {
         let a: JSString = "abc".into();
@@ -128,5 +438,11 @@ mod tests {
 
         assert_eq!("abc", a);
         assert_eq!(s, a);
+
+        let b: JSString = "日本語".into();
+        assert_eq!(b, "日本語");
+        assert_ne!(b, "日本");
+        assert_ne!(a, "");
+        assert_eq!(JSString::from_str_lossless("a\0b"), "a\0b");
     }
 }
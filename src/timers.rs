@@ -0,0 +1,495 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::function::new_function;
+use super::{JSContext, JSException, JSObject, JSValue};
+use crate::sys;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// The longest delay a timer can have, in milliseconds.
+///
+/// Longer delays are clamped to this, which is the largest delay that
+/// browsers support.
+const MAX_DELAY: f64 = i32::MAX as f64;
+
+/// Identifies a timer created by `setTimeout` or `setInterval`.
+///
+/// The identifier is the number that the script sees as the return
+/// value of `setTimeout` or `setInterval`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimerId(u32);
+
+impl TimerId {
+    /// Gets the number that identifies the timer to scripts.
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+/// Decides when the timers of a context fire.
+///
+/// JavaScriptCore has no event loop of its own, so the host must provide
+/// one. When a script creates a timer, the scheduler is asked to fire it
+/// after a delay. Once the delay has passed, the host calls
+/// [`Timers::fire`] from the thread that owns the context.
+///
+/// [`Timers::fire`]: struct.Timers.html#method.fire
+pub trait TimerScheduler {
+    /// Requests that `timer` fires once `delay` has passed.
+    ///
+    /// This is called again for each repetition of an interval.
+    fn schedule(&mut self, timer: TimerId, delay: Duration);
+
+    /// Tells the scheduler that `timer` has been cleared and no longer
+    /// needs to fire.
+    ///
+    /// Firing a cleared timer anyway is harmless.
+    fn cancel(&mut self, timer: TimerId);
+}
+
+/// A pending timer. Its callback and arguments are protected from
+/// garbage collection until the timer finishes or is cleared.
+struct Timer {
+    callback: sys::JSObjectRef,
+    arguments: Vec<sys::JSValueRef>,
+    interval: Option<Duration>,
+}
+
+impl Timer {
+    unsafe fn protect(&self, ctx: sys::JSContextRef) {
+        sys::JSValueProtect(ctx, self.callback);
+        for &argument in &self.arguments {
+            sys::JSValueProtect(ctx, argument);
+        }
+    }
+
+    unsafe fn unprotect(&self, ctx: sys::JSContextRef) {
+        sys::JSValueUnprotect(ctx, self.callback);
+        for &argument in &self.arguments {
+            sys::JSValueUnprotect(ctx, argument);
+        }
+    }
+}
+
+/// State shared by the timer functions of a context and its `Timers`.
+struct TimerState {
+    scheduler: RefCell<Box<dyn TimerScheduler>>,
+    timers: RefCell<HashMap<TimerId, Timer>>,
+    next_id: Cell<u32>,
+    /// Set once the `Timers` are dropped, after which timers can no
+    /// longer fire.
+    closed: Cell<bool>,
+}
+
+impl TimerState {
+    fn add(
+        &self,
+        ctx: &JSContext,
+        arguments: &[JSValue],
+        repeat: bool,
+    ) -> Result<JSValue, JSException> {
+        let callback = match arguments.first().and_then(|a| a.as_object().ok()) {
+            Some(callback) if callback.is_function() => callback,
+            _ => {
+                return Err(JSException::type_error(
+                    ctx,
+                    "Timer callback is not a function",
+                ))
+            }
+        };
+        let delay = match arguments.get(1) {
+            Some(delay) => delay.to_number()?,
+            None => 0.0,
+        };
+        // Like browsers, treat negative and invalid delays as `0`.
+        let delay = if delay > 0.0 {
+            delay.min(MAX_DELAY)
+        } else {
+            0.0
+        };
+        let delay = Duration::from_secs_f64(delay / 1000.0);
+
+        let id = self.next_id();
+        // Nothing can fire the timer anymore, so there is no need to
+        // keep its callback alive.
+        if self.closed.get() {
+            return Ok(JSValue::new_number(ctx, f64::from(id.0)));
+        }
+        let timer = Timer {
+            callback: callback.raw,
+            arguments: arguments.iter().skip(2).map(|a| a.raw).collect(),
+            interval: if repeat { Some(delay) } else { None },
+        };
+        unsafe { timer.protect(ctx.raw) };
+        self.timers.borrow_mut().insert(id, timer);
+        self.scheduler.borrow_mut().schedule(id, delay);
+        Ok(JSValue::new_number(ctx, f64::from(id.0)))
+    }
+
+    /// Picks the identifier of a new timer, skipping those of pending
+    /// timers once the identifiers wrap around.
+    fn next_id(&self) -> TimerId {
+        let timers = self.timers.borrow();
+        let mut id = self.next_id.get();
+        while timers.contains_key(&TimerId(id)) {
+            id = id.checked_add(1).unwrap_or(1);
+        }
+        self.next_id.set(id.checked_add(1).unwrap_or(1));
+        TimerId(id)
+    }
+
+    fn clear(&self, ctx: &JSContext, arguments: &[JSValue]) -> JSValue {
+        let id = arguments.first().and_then(|a| a.as_number().ok());
+        if let Some(id) = id.filter(|&id| id >= 1.0 && id <= f64::from(u32::MAX)) {
+            let id = TimerId(id as u32);
+            let timer = self.timers.borrow_mut().remove(&id);
+            if let Some(timer) = timer {
+                unsafe { timer.unprotect(ctx.raw) };
+                self.scheduler.borrow_mut().cancel(id);
+            }
+        }
+        JSValue::new_undefined(ctx)
+    }
+}
+
+/// The timers of a context, as installed by [`install_timers`].
+///
+/// The host uses this to run timers once their [`TimerScheduler`]
+/// decides that they are due.
+///
+/// Dropping the `Timers` cancels all pending timers and releases their
+/// callbacks. Timers that scripts create afterwards never fire.
+///
+/// [`install_timers`]: fn.install_timers.html
+/// [`TimerScheduler`]: trait.TimerScheduler.html
+pub struct Timers {
+    ctx: JSContext,
+    state: Rc<TimerState>,
+}
+
+impl Timers {
+    /// Runs the callback of a timer whose delay has passed.
+    ///
+    /// * `timer`: The timer, as given to [`TimerScheduler::schedule`].
+    ///
+    /// A timeout is finished afterwards, while an interval is scheduled
+    /// to run again. Timers that have been cleared are ignored.
+    ///
+    /// Returns the exception thrown by the callback, if any. An interval
+    /// keeps running even if its callback throws.
+    ///
+    /// [`TimerScheduler::schedule`]: trait.TimerScheduler.html#tymethod.schedule
+    pub fn fire(&self, timer: TimerId) -> Result<(), JSException> {
+        self.ctx.hooks.run(|| self.run(timer))
+    }
+
+    fn run(&self, timer: TimerId) -> Result<(), JSException> {
+        let ctx = self.ctx.raw;
+        let (callback, arguments, interval) = {
+            let mut timers = self.state.timers.borrow_mut();
+            let t = match timers.get(&timer) {
+                Some(t) => t,
+                None => return Ok(()),
+            };
+            let arguments: Vec<JSValue> = t
+                .arguments
+                .iter()
+                .map(|&raw| JSValue { raw, ctx })
+                .collect();
+            let call = (JSObject::from_raw(ctx, t.callback), arguments, t.interval);
+            // A timeout is finished once it fires, so clearing it from its
+            // own callback does nothing. Its callback and arguments stay
+            // protected until the call is over.
+            if call.2.is_none() {
+                timers.remove(&timer);
+            }
+            call
+        };
+        let result = callback.call_as_function(None, &arguments);
+        match interval {
+            Some(interval) => {
+                if self.state.timers.borrow().contains_key(&timer) {
+                    self.state.scheduler.borrow_mut().schedule(timer, interval);
+                }
+            }
+            None => unsafe {
+                sys::JSValueUnprotect(ctx, callback.raw);
+                for argument in &arguments {
+                    sys::JSValueUnprotect(ctx, argument.raw);
+                }
+            },
+        }
+        result.map(|_| ())
+    }
+
+    /// Tests whether any timers are still pending.
+    ///
+    /// Hosts can use this to decide when a script has finished all of
+    /// its work.
+    pub fn has_pending(&self) -> bool {
+        !self.state.timers.borrow().is_empty()
+    }
+}
+
+impl Drop for Timers {
+    fn drop(&mut self) {
+        self.state.closed.set(true);
+        let timers: Vec<(TimerId, Timer)> = self.state.timers.borrow_mut().drain().collect();
+        for (id, timer) in timers {
+            unsafe { timer.unprotect(self.ctx.raw) };
+            self.state.scheduler.borrow_mut().cancel(id);
+        }
+    }
+}
+
+/// Installs `setTimeout`, `setInterval`, `clearTimeout` and
+/// `clearInterval` into a context.
+///
+/// * `ctx`: The execution context to install the timer functions into.
+/// * `scheduler`: Decides when timers fire.
+///
+/// Returns the [`Timers`] that the host uses to run timers once they
+/// are due. Timer callbacks are kept alive until they have run or the
+/// timer is cleared.
+///
+/// The timer functions behave like their browser counterparts, except
+/// that the callback must be a function; passing a string of code to
+/// evaluate throws an exception.
+///
+/// ```
+/// # use javascriptcore::*;
+/// use std::cell::RefCell;
+/// use std::rc::Rc;
+/// use std::time::Duration;
+///
+/// // A scheduler that only records which timers are due.
+/// #[derive(Clone, Default)]
+/// struct Queue(Rc<RefCell<Vec<TimerId>>>);
+///
+/// impl TimerScheduler for Queue {
+///     fn schedule(&mut self, timer: TimerId, _delay: Duration) {
+///         self.0.borrow_mut().push(timer);
+///     }
+///
+///     fn cancel(&mut self, timer: TimerId) {
+///         self.0.borrow_mut().retain(|&t| t != timer);
+///     }
+/// }
+///
+/// let ctx = JSContext::default();
+/// let queue = Queue::default();
+/// let timers = install_timers(&ctx, queue.clone()).expect("timers installed");
+///
+/// evaluate_script(&ctx, "var done = false; setTimeout(() => done = true, 10)", None, "test.js", 1)
+///     .expect("timer created");
+///
+/// // Run timers until there are none left.
+/// while let Some(timer) = queue.0.borrow_mut().pop() {
+///     timers.fire(timer).expect("timer ran");
+/// }
+/// let done = evaluate_script(&ctx, "done", None, "test.js", 1).expect("done");
+/// assert!(done.as_boolean());
+/// ```
+///
+/// [`Timers`]: struct.Timers.html
+pub fn install_timers<S>(ctx: &JSContext, scheduler: S) -> Result<Timers, JSException>
+where
+    S: TimerScheduler + 'static,
+{
+    let state = Rc::new(TimerState {
+        scheduler: RefCell::new(Box::new(scheduler)),
+        timers: RefCell::new(HashMap::new()),
+        next_id: Cell::new(1),
+        closed: Cell::new(false),
+    });
+    let global = ctx.global_object();
+    for (name, repeat) in [("setTimeout", false), ("setInterval", true)] {
+        let state = state.clone();
+        let function = new_function(ctx, move |ctx, _this, arguments| {
+            state.add(ctx, arguments, repeat)
+        });
+        global.set_property(name, function.into())?;
+    }
+    for name in ["clearTimeout", "clearInterval"] {
+        let state = state.clone();
+        let function = new_function(ctx, move |ctx, _this, arguments| {
+            Ok(state.clear(ctx, arguments))
+        });
+        global.set_property(name, function.into())?;
+    }
+    Ok(Timers {
+        ctx: ctx.clone(),
+        state,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSContext};
+    use super::{install_timers, TimerId, TimerScheduler};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    #[derive(Clone, Default)]
+    struct Recorder {
+        scheduled: Rc<RefCell<Vec<(TimerId, Duration)>>>,
+        cancelled: Rc<RefCell<Vec<TimerId>>>,
+    }
+
+    impl TimerScheduler for Recorder {
+        fn schedule(&mut self, timer: TimerId, delay: Duration) {
+            self.scheduled.borrow_mut().push((timer, delay));
+        }
+
+        fn cancel(&mut self, timer: TimerId) {
+            self.cancelled.borrow_mut().push(timer);
+        }
+    }
+
+    fn eval(ctx: &JSContext, script: &str) -> f64 {
+        evaluate_script(ctx, script, None, "test.js", 1)
+            .unwrap()
+            .as_number()
+            .unwrap()
+    }
+
+    #[test]
+    fn timeout() {
+        let ctx = JSContext::default();
+        let recorder = Recorder::default();
+        let timers = install_timers(&ctx, recorder.clone()).unwrap();
+
+        let id = eval(
+            &ctx,
+            "var sum = 0; setTimeout((a, b) => sum += a + b, 25, 1, 2)",
+        );
+        let (timer, delay) = recorder.scheduled.borrow()[0];
+        assert_eq!(f64::from(timer.as_u32()), id);
+        assert_eq!(delay, Duration::from_millis(25));
+        assert!(timers.has_pending());
+
+        timers.fire(timer).unwrap();
+        assert_eq!(eval(&ctx, "sum"), 3.0);
+        assert!(!timers.has_pending());
+
+        // A timeout only runs once.
+        timers.fire(timer).unwrap();
+        assert_eq!(eval(&ctx, "sum"), 3.0);
+    }
+
+    #[test]
+    fn interval() {
+        let ctx = JSContext::default();
+        let recorder = Recorder::default();
+        let timers = install_timers(&ctx, recorder.clone()).unwrap();
+
+        eval(
+            &ctx,
+            "var n = 0; var id = setInterval(() => { if (++n == 3) clearInterval(id); }, -5)",
+        );
+        let (timer, delay) = recorder.scheduled.borrow()[0];
+        assert_eq!(delay, Duration::ZERO);
+        for _ in 0..3 {
+            timers.fire(timer).unwrap();
+        }
+        assert_eq!(eval(&ctx, "n"), 3.0);
+        assert_eq!(recorder.scheduled.borrow().len(), 3);
+        assert_eq!(*recorder.cancelled.borrow(), vec![timer]);
+        assert!(!timers.has_pending());
+
+        eval(&ctx, "setTimeout(() => {}, 'soon')");
+        let (_, delay) = recorder.scheduled.borrow()[3];
+        assert_eq!(delay, Duration::ZERO);
+    }
+
+    #[test]
+    fn clear_timeout() {
+        let ctx = JSContext::default();
+        let recorder = Recorder::default();
+        let timers = install_timers(&ctx, recorder.clone()).unwrap();
+
+        eval(
+            &ctx,
+            "var ran = 0; clearTimeout(setTimeout(() => ran++)); 0",
+        );
+        let (timer, _) = recorder.scheduled.borrow()[0];
+        assert_eq!(*recorder.cancelled.borrow(), vec![timer]);
+        timers.fire(timer).unwrap();
+        assert_eq!(eval(&ctx, "ran"), 0.0);
+
+        // Clearing unknown timers is not an error.
+        eval(
+            &ctx,
+            "clearTimeout(12345); clearTimeout('x'); clearTimeout(); 0",
+        );
+    }
+
+    #[test]
+    fn callback_exceptions() {
+        let ctx = JSContext::default();
+        let recorder = Recorder::default();
+        let timers = install_timers(&ctx, recorder.clone()).unwrap();
+
+        assert!(evaluate_script(&ctx, "setTimeout('code')", None, "test.js", 1).is_err());
+        eval(&ctx, "setTimeout(() => { throw new Error('oops'); })");
+        let (timer, _) = recorder.scheduled.borrow()[0];
+        assert!(timers.fire(timer).is_err());
+        assert!(!timers.has_pending());
+    }
+
+    #[test]
+    fn exceptions_are_reported() {
+        let ctx = JSContext::default();
+        let recorder = Recorder::default();
+        let timers = install_timers(&ctx, recorder.clone()).unwrap();
+        let reported = Rc::new(RefCell::new(Vec::new()));
+        let r = reported.clone();
+        ctx.set_unhandled_exception_handler(move |e| r.borrow_mut().push(e.to_string()))
+            .unwrap();
+
+        eval(&ctx, "setTimeout(() => { throw new Error('late'); }, 0)");
+        let (timer, _) = recorder.scheduled.borrow()[0];
+        let e = timers.fire(timer).unwrap_err();
+        assert_eq!(e.message(), Some("late"));
+        assert_eq!(reported.borrow().len(), 1);
+        assert!(reported.borrow()[0].starts_with("Error: late"));
+    }
+
+    #[test]
+    fn wrapped_ids_skip_pending_timers() {
+        let ctx = JSContext::default();
+        let recorder = Recorder::default();
+        let timers = install_timers(&ctx, recorder.clone()).unwrap();
+
+        assert_eq!(eval(&ctx, "var first = setTimeout(() => {}); first"), 1.0);
+        timers.state.next_id.set(u32::MAX);
+        assert_eq!(eval(&ctx, "setTimeout(() => {})"), f64::from(u32::MAX));
+        // The identifiers wrap around, and `1` is still in use.
+        assert_eq!(eval(&ctx, "setTimeout(() => {})"), 2.0);
+        assert_eq!(eval(&ctx, "clearTimeout(first); setTimeout(() => {})"), 3.0);
+    }
+
+    #[test]
+    fn drop_cancels_pending_timers() {
+        let ctx = JSContext::default();
+        let recorder = Recorder::default();
+        let timers = install_timers(&ctx, recorder.clone()).unwrap();
+
+        eval(&ctx, "setTimeout(() => {}); setInterval(() => {}, 10)");
+        drop(timers);
+        let mut cancelled = recorder.cancelled.borrow().clone();
+        cancelled.sort();
+        assert_eq!(cancelled, vec![TimerId(1), TimerId(2)]);
+
+        // Timers created afterwards are never scheduled.
+        eval(&ctx, "setTimeout(() => {})");
+        assert_eq!(recorder.scheduled.borrow().len(), 2);
+    }
+}
@@ -0,0 +1,1529 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::convert::{invalid, FromNumber};
+use super::{
+    FromJs, JSArrayBuffer, JSContext, JSException, JSObject, JSTypedArray, JSTypedArrayType,
+    JSValue,
+};
+use crate::sys;
+use std::ops::{Deref, DerefMut};
+use std::os::raw::c_void;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::AtomicU8;
+
+/// A type of the elements of typed arrays, which Rust can view the
+/// elements of an array as.
+///
+/// # Safety
+///
+/// Any bytes of the size of the type must be a valid value, and
+/// `TYPES` must only list kinds of arrays whose elements have the same
+/// size and representation as the type.
+pub unsafe trait TypedArrayElement: Copy {
+    /// The kinds of typed arrays whose elements are of this type.
+    const TYPES: &'static [JSTypedArrayType];
+}
+
+macro_rules! elements {
+    ($($t:ty => $($kind:ident)|*;)*) => {
+        $(
+            unsafe impl TypedArrayElement for $t {
+                const TYPES: &'static [JSTypedArrayType] = &[$(JSTypedArrayType::$kind),*];
+            }
+        )*
+    };
+}
+
+elements! {
+    i8 => Int8Array;
+    u8 => Uint8Array | Uint8ClampedArray;
+    i16 => Int16Array;
+    u16 => Uint16Array;
+    i32 => Int32Array;
+    u32 => Uint32Array;
+    f32 => Float32Array;
+    f64 => Float64Array;
+    i64 => BigInt64Array;
+    u64 => BigUint64Array;
+}
+
+/// An allocation of bytes that Rust owns, which can back a buffer
+/// without being copied.
+///
+/// This is implemented for anything that can be dereferenced to a
+/// mutable slice of bytes and sent to another thread, such as
+/// `Vec<u8>`, `Box<[u8]>` or a writable memory map. The buffer takes
+/// ownership of the store, and drops it once the buffer has been
+/// garbage collected, which may happen on any thread.
+///
+/// Scripts can write to the bytes of a buffer, so allocations that are
+/// shared and immutable, such as `Arc<[u8]>`, can not back one.
+pub trait BackingStore: Send + 'static {
+    /// Gets the bytes of the store.
+    ///
+    /// This is only called before the store is handed to the buffer.
+    fn bytes(&mut self) -> &mut [u8];
+}
+
+impl<T: DerefMut<Target = [u8]> + Send + 'static> BackingStore for T {
+    fn bytes(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+/// Turns the exception set by a JavaScriptCore call into an error.
+fn check(ctx: sys::JSContextRef, e: sys::JSValueRef) -> Result<(), JSException> {
+    if e.is_null() {
+        Ok(())
+    } else {
+        Err(JSException::from_raw(ctx, e))
+    }
+}
+
+impl JSTypedArray {
+    /// Creates a typed array with all elements set to zero.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `kind`: The kind of array to create. `JSTypedArrayType::None`
+    ///   and `JSTypedArrayType::ArrayBuffer` are not kinds of typed
+    ///   arrays, and fail with a `TypeError`.
+    /// * `length`: The number of elements of the array.
+    ///
+    /// `BigInt64Array` and `BigUint64Array` need a version of
+    /// JavaScriptCore that supports them.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let samples = JSTypedArray::new(&ctx, JSTypedArrayType::Float32Array, 128).expect("array");
+    /// ctx.global_object().set_property("samples", samples.into()).expect("set");
+    ///
+    /// let r = evaluate_script(&ctx, "samples.length", None, "test.js", 1).expect("length");
+    /// assert_eq!(r.as_number().expect("number"), 128.0);
+    /// ```
+    pub fn new(
+        ctx: &JSContext,
+        kind: JSTypedArrayType,
+        length: usize,
+    ) -> Result<JSTypedArray, JSException> {
+        if matches!(kind, JSTypedArrayType::None | JSTypedArrayType::ArrayBuffer) {
+            return Err(JSException::type_error(ctx, "Not a kind of typed array"));
+        }
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        let raw = unsafe { sys::JSObjectMakeTypedArray(ctx.raw, kind, length, &mut e) };
+        check(ctx.raw, e)?;
+        if raw.is_null() {
+            return Err(JSException::type_error(
+                ctx,
+                &format!("{:?} is not supported", kind),
+            ));
+        }
+        Ok(JSTypedArray {
+            object: JSObject::from_raw(ctx.raw, raw),
+        })
+    }
+
+    /// Creates a `Uint8Array` whose elements are the bytes of `bytes`,
+    /// without copying them.
+    ///
+    /// The array takes ownership of the vector, which is dropped when
+    /// the array's buffer is garbage collected. This lets large buffers,
+    /// such as audio or images, be handed to scripts without a copy.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let pixels = JSTypedArray::from_vec_no_copy(&ctx, vec![255; 64 * 64 * 4]).expect("array");
+    /// ctx.global_object().set_property("pixels", pixels.into()).expect("set");
+    ///
+    /// let r = evaluate_script(&ctx, "pixels.length", None, "test.js", 1).expect("length");
+    /// assert_eq!(r.as_number().expect("number"), 16384.0);
+    /// ```
+    pub fn from_vec_no_copy(ctx: &JSContext, bytes: Vec<u8>) -> Result<JSTypedArray, JSException> {
+        JSTypedArray::from_backing_store(ctx, JSTypedArrayType::Uint8Array, bytes)
+    }
+
+    /// Creates a typed array whose elements are the bytes of `store`,
+    /// without copying them.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `kind`: The kind of array to create.
+    /// * `store`: The bytes of the elements, whose length must be a
+    ///   multiple of the size of the elements.
+    ///
+    /// The array takes ownership of the store, which is dropped when the
+    /// array's buffer is garbage collected.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let samples: Box<[u8]> = 0.5f32.to_ne_bytes().repeat(4).into();
+    /// let array = JSTypedArray::from_backing_store(&ctx, JSTypedArrayType::Float32Array, samples)
+    ///     .expect("array");
+    /// assert_eq!(array.len(), 4);
+    /// ```
+    pub fn from_backing_store<S: BackingStore>(
+        ctx: &JSContext,
+        kind: JSTypedArrayType,
+        store: S,
+    ) -> Result<JSTypedArray, JSException> {
+        let size = match element_size(kind) {
+            Some(size) => size,
+            None => return Err(JSException::type_error(ctx, "Not a kind of typed array")),
+        };
+        let mut store = store;
+        if !store.bytes().len().is_multiple_of(size) {
+            return Err(JSException::range_error(
+                ctx,
+                "The length of the store is not a multiple of the size of the elements",
+            ));
+        }
+        let raw = make_no_copy(ctx, store, |data, len, deallocator, context, e| unsafe {
+            sys::JSObjectMakeTypedArrayWithBytesNoCopy(
+                ctx.raw,
+                kind,
+                data,
+                len,
+                deallocator,
+                context,
+                e,
+            )
+        })?;
+        Ok(JSTypedArray {
+            object: JSObject::from_raw(ctx.raw, raw),
+        })
+    }
+}
+
+impl JSTypedArray {
+    /// Gets the kind of the array.
+    pub fn array_type(&self) -> JSTypedArrayType {
+        typed_array_type(self)
+    }
+
+    /// Gets the number of elements of the array, which scripts see as
+    /// its `length`.
+    ///
+    /// Arrays whose buffer has been detached have no elements.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let v = evaluate_script(&ctx, "new Int32Array(new ArrayBuffer(16), 4, 2)", None, "test.js", 1)
+    ///     .expect("array");
+    /// let array = v.as_typed_array().expect("typed array");
+    /// assert_eq!(array.len(), 2);
+    /// assert_eq!(array.byte_length(), 8);
+    /// assert_eq!(array.byte_offset(), 4);
+    /// ```
+    pub fn len(&self) -> usize {
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        unsafe { sys::JSObjectGetTypedArrayLength(self.value.ctx, self.raw, &mut e) }
+    }
+
+    /// Tests whether the array has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets the number of bytes of the elements of the array.
+    pub fn byte_length(&self) -> usize {
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        unsafe { sys::JSObjectGetTypedArrayByteLength(self.value.ctx, self.raw, &mut e) }
+    }
+
+    /// Gets the offset in bytes of the first element of the array from
+    /// the start of its buffer.
+    pub fn byte_offset(&self) -> usize {
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        unsafe { sys::JSObjectGetTypedArrayByteOffset(self.value.ctx, self.raw, &mut e) }
+    }
+
+    /// Gets the `ArrayBuffer` that holds the elements of the array.
+    ///
+    /// The buffer may be larger than the array, and shared with other
+    /// arrays. The elements start at [`byte_offset`] in the buffer.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let v = evaluate_script(&ctx, "new Uint8Array(new ArrayBuffer(10), 2, 4)", None, "test.js", 1)
+    ///     .expect("array");
+    /// let array = v.as_typed_array().expect("typed array");
+    /// assert_eq!(array.buffer().expect("buffer").byte_length(), 10);
+    /// ```
+    ///
+    /// [`byte_offset`]: #method.byte_offset
+    pub fn buffer(&self) -> Result<JSArrayBuffer, JSException> {
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        let raw = unsafe { sys::JSObjectGetTypedArrayBuffer(self.value.ctx, self.raw, &mut e) };
+        check(self.value.ctx, e)?;
+        Ok(JSArrayBuffer {
+            object: JSObject::from_raw(self.value.ctx, raw),
+        })
+    }
+
+    /// Gets the bytes of the elements of the array.
+    ///
+    /// Arrays whose buffer has been detached have no bytes.
+    ///
+    /// # Safety
+    ///
+    /// The bytes are those of the array's buffer, which scripts can
+    /// modify or detach, and which the garbage collector may move. The
+    /// slice must not be used after any JavaScript runs, such as by
+    /// evaluating a script or calling a function, or after any other
+    /// call into JavaScriptCore with the same context.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let v = evaluate_script(&ctx, "new Uint16Array([1, 256])", None, "test.js", 1)
+    ///     .expect("array");
+    /// let array = v.as_typed_array().expect("typed array");
+    /// let bytes = unsafe { array.as_slice() };
+    /// assert_eq!(bytes.len(), 4);
+    /// ```
+    pub unsafe fn as_slice(&self) -> &[u8] {
+        typed_array_bytes(self).unwrap_or_default()
+    }
+
+    /// Copies the bytes of the elements of the array to the start of
+    /// `bytes`, and returns the number of bytes copied.
+    ///
+    /// Fails with a `RangeError` if `bytes` is shorter than the array.
+    /// Arrays whose buffer has been detached have no bytes to copy.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let v = evaluate_script(&ctx, "new Uint8Array([1, 2, 3])", None, "test.js", 1)
+    ///     .expect("array");
+    /// let array = v.as_typed_array().expect("typed array");
+    ///
+    /// let mut bytes = [0; 8];
+    /// let n = array.copy_to(&mut bytes).expect("copied");
+    /// assert_eq!(&bytes[..n], [1, 2, 3]);
+    /// ```
+    pub fn copy_to(&self, bytes: &mut [u8]) -> Result<usize, JSException> {
+        // The bytes are not used after this returns.
+        let source = unsafe { typed_array_bytes(self)? };
+        if bytes.len() < source.len() {
+            let ctx = unsafe { JSContext::from_context_ref(self.value.ctx) };
+            return Err(JSException::range_error(
+                &ctx,
+                "The destination is shorter than the array",
+            ));
+        }
+        bytes[..source.len()].copy_from_slice(source);
+        Ok(source.len())
+    }
+
+    /// Copies `bytes` to the start of the bytes of the elements of the
+    /// array.
+    ///
+    /// Fails with a `RangeError` if `bytes` is longer than the array.
+    pub fn copy_from(&self, bytes: &[u8]) -> Result<(), JSException> {
+        // The bytes are not used after this returns.
+        let destination = unsafe { typed_array_bytes(self)? };
+        if bytes.len() > destination.len() {
+            let ctx = unsafe { JSContext::from_context_ref(self.value.ctx) };
+            return Err(JSException::range_error(
+                &ctx,
+                "The source is longer than the array",
+            ));
+        }
+        destination[..bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Gets the elements of the array, as a slice of `T`.
+    ///
+    /// This lets numbers be processed directly, such as the samples of
+    /// a `Float32Array` as `f32`. Fails with a `TypeError` if the
+    /// elements of the array are not of the type `T`.
+    ///
+    /// # Safety
+    ///
+    /// As for [`as_slice`], the slice must not be used after JavaScript
+    /// runs.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let v = evaluate_script(&ctx, "new Float32Array([0.5, 1.5, 2])", None, "test.js", 1)
+    ///     .expect("array");
+    /// let array = v.as_typed_array().expect("typed array");
+    /// let sum: f32 = unsafe { array.as_elements::<f32>() }.expect("floats").iter().sum();
+    /// assert_eq!(sum, 4.0);
+    /// assert!(unsafe { array.as_elements::<u32>() }.is_err());
+    /// ```
+    ///
+    /// [`as_slice`]: #method.as_slice
+    pub unsafe fn as_elements<T: TypedArrayElement>(&self) -> Result<&[T], JSException> {
+        let bytes = self.element_bytes::<T>()?;
+        Ok(slice::from_raw_parts(
+            bytes.as_ptr() as *const T,
+            bytes.len() / size_of::<T>(),
+        ))
+    }
+
+    /// Gets the elements of the array, as a slice of `T` that Rust can
+    /// modify in place.
+    ///
+    /// Fails with a `TypeError` if the elements of the array are not of
+    /// the type `T`.
+    ///
+    /// # Safety
+    ///
+    /// As for [`as_mut_slice`], the slice must not be used after
+    /// JavaScript runs, and must not overlap with any other slice of
+    /// the same buffer.
+    ///
+    /// [`as_mut_slice`]: #method.as_mut_slice
+    pub unsafe fn as_mut_elements<T: TypedArrayElement>(
+        &mut self,
+    ) -> Result<&mut [T], JSException> {
+        let bytes = self.element_bytes::<T>()?;
+        Ok(slice::from_raw_parts_mut(
+            bytes.as_mut_ptr() as *mut T,
+            bytes.len() / size_of::<T>(),
+        ))
+    }
+
+    /// Gets the bytes of the elements of the array, after checking that
+    /// they can be viewed as `T`.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn element_bytes<T: TypedArrayElement>(&self) -> Result<&mut [u8], JSException> {
+        let ctx = JSContext::from_context_ref(self.value.ctx);
+        let kind = self.array_type();
+        if !T::TYPES.contains(&kind) {
+            return Err(JSException::type_error(
+                &ctx,
+                &format!(
+                    "The elements of a {:?} are not {}",
+                    kind,
+                    std::any::type_name::<T>()
+                ),
+            ));
+        }
+        let bytes = typed_array_bytes(self)?;
+        // Buffers and offsets are always aligned for the elements, but
+        // this is what makes the slice sound, so check it anyway.
+        if !(bytes.as_ptr() as usize).is_multiple_of(align_of::<T>()) {
+            return Err(JSException::range_error(
+                &ctx,
+                "The elements of the array are not aligned",
+            ));
+        }
+        Ok(bytes)
+    }
+
+    /// Gets the bytes of the elements of the array, which Rust can
+    /// modify in place.
+    ///
+    /// Arrays whose buffer has been detached have no bytes.
+    ///
+    /// # Safety
+    ///
+    /// As for [`as_slice`], the slice must not be used after JavaScript
+    /// runs. It also must not overlap with any other slice of the same
+    /// buffer, which other `JSTypedArray`s for the same array, or
+    /// arrays that share its buffer, can give.
+    ///
+    /// [`as_slice`]: #method.as_slice
+    pub unsafe fn as_mut_slice(&mut self) -> &mut [u8] {
+        typed_array_bytes(self).unwrap_or_default()
+    }
+}
+
+impl JSValue {
+    /// Creates a `Uint8Array` holding a copy of `bytes`.
+    ///
+    /// This is the simplest way to hand small blobs of bytes, such as
+    /// hashes or keys, to scripts. Use [`JSTypedArray::from_vec_no_copy`]
+    /// to hand over large ones without a copy.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let digest = JSValue::new_uint8_array(&ctx, &[0xde, 0xad, 0xbe, 0xef]).expect("array");
+    /// ctx.global_object().set_property("digest", digest).expect("set");
+    ///
+    /// let r = evaluate_script(&ctx, "digest[1]", None, "test.js", 1).expect("byte");
+    /// assert_eq!(r.as_number().expect("number"), 173.0);
+    /// ```
+    ///
+    /// [`JSTypedArray::from_vec_no_copy`]: struct.JSTypedArray.html#method.from_vec_no_copy
+    pub fn new_uint8_array(ctx: &JSContext, bytes: &[u8]) -> Result<Self, JSException> {
+        Ok(new_uint8_array(ctx, bytes)?.into())
+    }
+
+    /// Creates an array holding the numbers of `numbers`.
+    ///
+    /// The numbers are converted in one pass and the array is created
+    /// with all of them at once, without the error handling of each
+    /// element that converting a slice with [`ToJs`] goes through. No
+    /// scripts run, so this does not depend on the global `Array`.
+    /// Scripts get a plain `Array`, which they can change like any
+    /// other. Use [`JSTypedArray`] to give them a typed array instead.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let samples: Vec<f64> = (0..1000).map(|i| f64::from(i) / 2.0).collect();
+    /// let v = JSValue::new_array_from_f64(&ctx, &samples).expect("array");
+    /// ctx.global_object().set_property("samples", v).expect("set");
+    ///
+    /// let r = evaluate_script(&ctx, "Array.isArray(samples) && samples[999]", None, "test.js", 1)
+    ///     .expect("number");
+    /// assert_eq!(r.as_number().expect("number"), 499.5);
+    /// ```
+    ///
+    /// [`ToJs`]: trait.ToJs.html
+    /// [`JSTypedArray`]: struct.JSTypedArray.html
+    pub fn new_array_from_f64(ctx: &JSContext, numbers: &[f64]) -> Result<Self, JSException> {
+        new_number_array(ctx, numbers)
+    }
+
+    /// Creates an array holding the numbers of `numbers`.
+    ///
+    /// This is [`new_array_from_f64`], for `i32`s.
+    ///
+    /// [`new_array_from_f64`]: #method.new_array_from_f64
+    pub fn new_array_from_i32(ctx: &JSContext, numbers: &[i32]) -> Result<Self, JSException> {
+        new_number_array(ctx, numbers)
+    }
+
+    /// Creates an array holding the numbers of `numbers`.
+    ///
+    /// This is [`new_array_from_f64`], for bytes. Unlike
+    /// [`new_uint8_array`], it creates a plain `Array`.
+    ///
+    /// [`new_array_from_f64`]: #method.new_array_from_f64
+    /// [`new_uint8_array`]: #method.new_uint8_array
+    pub fn new_array_from_u8(ctx: &JSContext, numbers: &[u8]) -> Result<Self, JSException> {
+        new_number_array(ctx, numbers)
+    }
+
+    /// Gets the kind of typed array that this value is.
+    ///
+    /// This tells apart the kinds of views that a native function may be
+    /// given where scripts can pass any of them. `ArrayBuffer`s are
+    /// given as `JSTypedArrayType::ArrayBuffer`.
+    ///
+    /// Returns `None` if the value is not a typed array or an
+    /// `ArrayBuffer`.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let v = evaluate_script(&ctx, "new Float32Array(2)", None, "test.js", 1).expect("array");
+    /// assert_eq!(v.typed_array_type(), Some(JSTypedArrayType::Float32Array));
+    /// let v = evaluate_script(&ctx, "[1, 2]", None, "test.js", 1).expect("array");
+    /// assert_eq!(v.typed_array_type(), None);
+    /// ```
+    pub fn typed_array_type(&self) -> Option<JSTypedArrayType> {
+        match typed_array_type(self) {
+            JSTypedArrayType::None => None,
+            kind => Some(kind),
+        }
+    }
+
+    /// Gets the typed array that this value is.
+    ///
+    /// Fails with a `TypeError` if the value is not a typed array, such
+    /// as an `ArrayBuffer` or any other value.
+    pub fn as_typed_array(&self) -> Result<JSTypedArray, JSException> {
+        if matches!(
+            typed_array_type(self),
+            JSTypedArrayType::None | JSTypedArrayType::ArrayBuffer
+        ) {
+            let ctx = unsafe { JSContext::from_context_ref(self.ctx) };
+            return Err(JSException::type_error(&ctx, "Value is not a typed array"));
+        }
+        Ok(JSTypedArray {
+            object: JSObject::from_raw(self.ctx, self.raw as sys::JSObjectRef),
+        })
+    }
+
+    /// Gets the `ArrayBuffer` that this value is.
+    ///
+    /// Fails with a `TypeError` if the value is not an `ArrayBuffer`,
+    /// such as a typed array or any other value.
+    pub fn as_array_buffer(&self) -> Result<JSArrayBuffer, JSException> {
+        if typed_array_type(self) != JSTypedArrayType::ArrayBuffer {
+            let ctx = unsafe { JSContext::from_context_ref(self.ctx) };
+            return Err(JSException::type_error(&ctx, "Value is not an ArrayBuffer"));
+        }
+        Ok(JSArrayBuffer {
+            object: JSObject::from_raw(self.ctx, self.raw as sys::JSObjectRef),
+        })
+    }
+}
+
+impl JSObject {
+    /// Copies the numbers of an array or typed array to a vector.
+    ///
+    /// The elements of typed arrays are read from their bytes, rather
+    /// than one at a time, so this is much faster for them. Arrays are
+    /// converted like with [`FromJs`], so an element that is not a
+    /// number gives a `TypeError`, as does an object that is not an
+    /// array. So do `BigInt64Array` and `BigUint64Array`, whose
+    /// elements are not numbers.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let v = evaluate_script(&ctx, "Float64Array.from({length: 1000}, (_, i) => i / 2)", None, "test.js", 1)
+    ///     .expect("array");
+    /// let samples = v.as_object().expect("object").to_f64_vec().expect("numbers");
+    /// assert_eq!(samples.len(), 1000);
+    /// assert_eq!(samples[999], 499.5);
+    /// ```
+    ///
+    /// [`FromJs`]: trait.FromJs.html
+    pub fn to_f64_vec(&self) -> Result<Vec<f64>, JSException> {
+        self.to_number_vec()
+    }
+
+    /// Copies the numbers of an array or typed array to a vector of
+    /// `i32`.
+    ///
+    /// This is like [`to_f64_vec`], but fails with a `TypeError` if an
+    /// element is not an integer, or with a `RangeError` if it does not
+    /// fit in an `i32`.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let v = evaluate_script(&ctx, "[1, -2, 3]", None, "test.js", 1).expect("array");
+    /// assert_eq!(v.as_object().expect("object").to_i32_vec().expect("numbers"), [1, -2, 3]);
+    /// ```
+    ///
+    /// [`to_f64_vec`]: #method.to_f64_vec
+    pub fn to_i32_vec(&self) -> Result<Vec<i32>, JSException> {
+        self.to_number_vec()
+    }
+
+    /// Copies the numbers of an array or typed array to a vector of
+    /// bytes.
+    ///
+    /// This is like [`to_f64_vec`], but fails with a `TypeError` if an
+    /// element is not an integer, or with a `RangeError` if it is not
+    /// from 0 to 255.
+    ///
+    /// [`to_f64_vec`]: #method.to_f64_vec
+    pub fn to_u8_vec(&self) -> Result<Vec<u8>, JSException> {
+        self.to_number_vec()
+    }
+
+    fn to_number_vec<T>(&self) -> Result<Vec<T>, JSException>
+    where
+        T: TypedArrayElement + FromJs + FromNumber,
+    {
+        let ctx = unsafe { JSContext::from_context_ref(self.value.ctx) };
+        let kind = typed_array_type(self);
+        if element_size(kind).is_none() {
+            return Vec::<T>::from_js(&ctx, self);
+        }
+        let array = self.as_typed_array()?;
+        if T::TYPES.contains(&kind) {
+            // The elements are copied before JavaScript can run.
+            return Ok(unsafe { array.as_elements::<T>()? }.to_vec());
+        }
+        let numbers = match kind {
+            JSTypedArrayType::Int8Array => numbers::<i8>(&array)?,
+            JSTypedArrayType::Uint8Array | JSTypedArrayType::Uint8ClampedArray => {
+                numbers::<u8>(&array)?
+            }
+            JSTypedArrayType::Int16Array => numbers::<i16>(&array)?,
+            JSTypedArrayType::Uint16Array => numbers::<u16>(&array)?,
+            JSTypedArrayType::Int32Array => numbers::<i32>(&array)?,
+            JSTypedArrayType::Uint32Array => numbers::<u32>(&array)?,
+            JSTypedArrayType::Float32Array => numbers::<f32>(&array)?,
+            JSTypedArrayType::Float64Array => numbers::<f64>(&array)?,
+            _ => {
+                let message = format!("The elements of a {:?} are not numbers", kind);
+                return Err(JSException::type_error(&ctx, &message));
+            }
+        };
+        numbers
+            .into_iter()
+            .enumerate()
+            .map(|(index, n)| {
+                T::from_number(&ctx, n).map_err(|e| invalid(&ctx, e, &format!("element {}", index)))
+            })
+            .collect()
+    }
+}
+
+/// Creates an array holding `numbers`.
+fn new_number_array<T: Copy + Into<f64>>(
+    ctx: &JSContext,
+    numbers: &[T],
+) -> Result<JSValue, JSException> {
+    // Numbers are not allocated by the garbage collector, so they need
+    // no protection while they are only held by the vector.
+    let values: Vec<sys::JSValueRef> = numbers
+        .iter()
+        .map(|&n| unsafe { sys::JSValueMakeNumber(ctx.raw, n.into()) })
+        .collect();
+    let mut e: sys::JSValueRef = ptr::null_mut();
+    let raw = unsafe { sys::JSObjectMakeArray(ctx.raw, values.len(), values.as_ptr(), &mut e) };
+    if raw.is_null() {
+        Err(JSException::from_raw(ctx.raw, e))
+    } else {
+        Ok(JSObject::from_raw(ctx.raw, raw).into())
+    }
+}
+
+/// Copies the elements of `array`, which are of the type `T`, to a
+/// vector of numbers.
+fn numbers<T: TypedArrayElement + Into<f64>>(
+    array: &JSTypedArray,
+) -> Result<Vec<f64>, JSException> {
+    // The elements are copied before JavaScript can run.
+    let elements = unsafe { array.as_elements::<T>()? };
+    Ok(elements.iter().map(|&n| n.into()).collect())
+}
+
+impl JSArrayBuffer {
+    /// Creates an `ArrayBuffer` whose bytes are `bytes`, without copying
+    /// them.
+    ///
+    /// The buffer takes ownership of the bytes, which are dropped when
+    /// the buffer is garbage collected. Use [`from_slice`] to give the
+    /// buffer a copy instead.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let bytes: Box<[u8]> = vec![1, 2, 3, 4].into_boxed_slice();
+    /// let buffer = JSArrayBuffer::from_boxed_slice_no_copy(&ctx, bytes).expect("buffer");
+    /// ctx.global_object().set_property("buffer", buffer.into()).expect("set");
+    ///
+    /// let r = evaluate_script(&ctx, "new Uint8Array(buffer)[3]", None, "test.js", 1).expect("byte");
+    /// assert_eq!(r.as_number().expect("number"), 4.0);
+    /// ```
+    ///
+    /// [`from_slice`]: #method.from_slice
+    pub fn from_boxed_slice_no_copy(
+        ctx: &JSContext,
+        bytes: Box<[u8]>,
+    ) -> Result<JSArrayBuffer, JSException> {
+        JSArrayBuffer::from_backing_store(ctx, bytes)
+    }
+
+    /// Creates an `ArrayBuffer` whose bytes are those of `store`,
+    /// without copying them.
+    ///
+    /// The buffer takes ownership of the store, which is dropped when
+    /// the buffer is garbage collected.
+    pub fn from_backing_store<S: BackingStore>(
+        ctx: &JSContext,
+        store: S,
+    ) -> Result<JSArrayBuffer, JSException> {
+        let raw = make_no_copy(ctx, store, |data, len, deallocator, context, e| unsafe {
+            sys::JSObjectMakeArrayBufferWithBytesNoCopy(ctx.raw, data, len, deallocator, context, e)
+        })?;
+        Ok(JSArrayBuffer {
+            object: JSObject::from_raw(ctx.raw, raw),
+        })
+    }
+
+    /// Creates an `ArrayBuffer` holding a copy of `bytes`.
+    ///
+    /// The copy is owned by JavaScriptCore, like the buffers that
+    /// scripts create. Use [`from_boxed_slice_no_copy`] to hand over
+    /// bytes that Rust owns instead.
+    ///
+    /// [`from_boxed_slice_no_copy`]: #method.from_boxed_slice_no_copy
+    pub fn from_slice(ctx: &JSContext, bytes: &[u8]) -> Result<JSArrayBuffer, JSException> {
+        let array = new_uint8_array(ctx, bytes)?;
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        let raw = unsafe { sys::JSObjectGetTypedArrayBuffer(ctx.raw, array.raw, &mut e) };
+        check(ctx.raw, e)?;
+        Ok(JSArrayBuffer {
+            object: JSObject::from_raw(ctx.raw, raw),
+        })
+    }
+
+    /// Creates a `SharedArrayBuffer` with all bytes set to zero.
+    ///
+    /// Shared buffers can be shared with workers, which may read and
+    /// write them at the same time as the host. Fails with a `TypeError`
+    /// if the context does not have `SharedArrayBuffer`, as
+    /// [`JSContext::has_shared_array_buffer`] tells.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// if ctx.has_shared_array_buffer() {
+    ///     let buffer = JSArrayBuffer::new_shared(&ctx, 64).expect("buffer");
+    ///     assert!(buffer.is_shared());
+    /// }
+    /// ```
+    ///
+    /// [`JSContext::has_shared_array_buffer`]: struct.JSContext.html#method.has_shared_array_buffer
+    pub fn new_shared(ctx: &JSContext, byte_length: usize) -> Result<JSArrayBuffer, JSException> {
+        let constructor = shared_array_buffer(ctx)
+            .ok_or_else(|| JSException::type_error(ctx, "SharedArrayBuffer is not supported"))?;
+        let length = JSValue::new_number(ctx, byte_length as f64);
+        constructor
+            .call_as_constructor(&[length])?
+            .as_array_buffer()
+    }
+
+    /// Tests whether the buffer is a `SharedArrayBuffer`.
+    pub fn is_shared(&self) -> bool {
+        let ctx = unsafe { JSContext::from_context_ref(self.value.ctx) };
+        shared_array_buffer(&ctx).is_some_and(|constructor| unsafe {
+            sys::JSValueIsInstanceOfConstructor(
+                ctx.raw,
+                self.value.raw,
+                constructor.raw,
+                ptr::null_mut(),
+            )
+        })
+    }
+
+    /// Gets the bytes of the buffer, as atomics.
+    ///
+    /// Unlike [`JSTypedArray::as_slice`], this can be used for shared
+    /// buffers, which workers may change at the same time. It can be
+    /// used for other buffers as well.
+    ///
+    /// # Safety
+    ///
+    /// The bytes belong to the buffer, which the garbage collector may
+    /// free once it is no longer used, and which scripts can detach if
+    /// it is not shared. The slice must not be used after any
+    /// JavaScript runs, or after any other call into JavaScriptCore
+    /// with the same context.
+    ///
+    /// [`JSTypedArray::as_slice`]: struct.JSTypedArray.html#method.as_slice
+    pub unsafe fn as_atomic_slice(&self) -> &[AtomicU8] {
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        let bytes = sys::JSObjectGetArrayBufferBytesPtr(self.value.ctx, self.raw, &mut e);
+        let len = self.byte_length();
+        if bytes.is_null() || len == 0 {
+            return &[];
+        }
+        slice::from_raw_parts(bytes as *const AtomicU8, len)
+    }
+
+    /// Moves the bytes of the buffer to a new buffer, and detaches this
+    /// one.
+    ///
+    /// Once a buffer is detached, it and the typed arrays that view it
+    /// have no bytes, so scripts can no longer observe what it held.
+    /// This lets the host take back a buffer that it lent to a script.
+    ///
+    /// This uses `ArrayBuffer.prototype.transfer`, and fails with a
+    /// `TypeError` in versions of JavaScriptCore that do not have it.
+    /// Shared buffers can not be detached.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let lent = JSArrayBuffer::from_slice(&ctx, &[1, 2, 3]).expect("buffer");
+    /// if let Ok(buffer) = lent.transfer() {
+    ///     assert_eq!(lent.byte_length(), 0);
+    ///     assert_eq!(buffer.byte_length(), 3);
+    /// }
+    /// ```
+    pub fn transfer(&self) -> Result<JSArrayBuffer, JSException> {
+        let ctx = unsafe { JSContext::from_context_ref(self.value.ctx) };
+        let transfer = ctx
+            .global_object()
+            .get_property("ArrayBuffer")?
+            .as_object()?
+            .get_property("prototype")?
+            .as_object()?
+            .get_property("transfer")?;
+        if !transfer.is_object() || !transfer.as_object()?.is_function() {
+            return Err(JSException::type_error(
+                &ctx,
+                "Detaching buffers is not supported",
+            ));
+        }
+        let buffer = transfer
+            .as_object()?
+            .call_as_function(Some(self), &[])?
+            .as_array_buffer()?;
+        // A script may have replaced `transfer`, so make sure that the
+        // bytes are really gone.
+        if self.byte_length() != 0 {
+            return Err(JSException::error(&ctx, "The buffer could not be detached"));
+        }
+        Ok(buffer)
+    }
+
+    /// Detaches the buffer, so that it and the typed arrays that view it
+    /// have no bytes.
+    ///
+    /// This is [`transfer`], without keeping the bytes.
+    ///
+    /// [`transfer`]: #method.transfer
+    pub fn detach(&self) -> Result<(), JSException> {
+        self.transfer().map(|_| ())
+    }
+
+    /// Gets the number of bytes of the buffer.
+    ///
+    /// Buffers that have been detached have no bytes.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    /// let v = evaluate_script(&ctx, "new ArrayBuffer(12)", None, "test.js", 1).expect("buffer");
+    /// let buffer = v.as_array_buffer().expect("buffer");
+    /// assert_eq!(buffer.byte_length(), 12);
+    /// ```
+    pub fn byte_length(&self) -> usize {
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        unsafe { sys::JSObjectGetArrayBufferByteLength(self.value.ctx, self.raw, &mut e) }
+    }
+}
+
+/// Gets the size in bytes of the elements of arrays of the kind `kind`,
+/// or `None` if it is not a kind of typed array.
+fn element_size(kind: JSTypedArrayType) -> Option<usize> {
+    match kind {
+        JSTypedArrayType::Int8Array
+        | JSTypedArrayType::Uint8Array
+        | JSTypedArrayType::Uint8ClampedArray => Some(1),
+        JSTypedArrayType::Int16Array | JSTypedArrayType::Uint16Array => Some(2),
+        JSTypedArrayType::Int32Array
+        | JSTypedArrayType::Uint32Array
+        | JSTypedArrayType::Float32Array => Some(4),
+        JSTypedArrayType::Float64Array
+        | JSTypedArrayType::BigInt64Array
+        | JSTypedArrayType::BigUint64Array => Some(8),
+        JSTypedArrayType::ArrayBuffer | JSTypedArrayType::None => None,
+    }
+}
+
+/// Hands the bytes of `store` to `make`, which creates an object with
+/// them and the deallocator that drops the store.
+fn make_no_copy<S: BackingStore>(
+    ctx: &JSContext,
+    store: S,
+    make: impl FnOnce(
+        *mut c_void,
+        usize,
+        sys::JSTypedArrayBytesDeallocator,
+        *mut c_void,
+        *mut sys::JSValueRef,
+    ) -> sys::JSObjectRef,
+) -> Result<sys::JSObjectRef, JSException> {
+    // The store is boxed before its bytes are taken, so that it does
+    // not move while they are used.
+    let mut store = Box::new(store);
+    let bytes = store.bytes();
+    let (data, len) = (bytes.as_mut_ptr(), bytes.len());
+    let mut e: sys::JSValueRef = ptr::null_mut();
+    // The store is dropped by the deallocator, even if this fails.
+    let raw = make(
+        data as *mut c_void,
+        len,
+        Some(drop_boxed::<S>),
+        Box::into_raw(store) as *mut c_void,
+        &mut e,
+    );
+    check(ctx.raw, e)?;
+    if raw.is_null() {
+        return Err(JSException::error(ctx, "The buffer could not be created"));
+    }
+    Ok(raw)
+}
+
+/// Drops the bytes that back a buffer, once it has been garbage
+/// collected. This may run on any thread.
+unsafe extern "C" fn drop_boxed<T>(_bytes: *mut c_void, context: *mut c_void) {
+    drop(Box::from_raw(context as *mut T));
+}
+
+/// A `JSTypedArray` can be dereferenced to return the underlying
+/// `JSObject`.
+impl Deref for JSTypedArray {
+    type Target = JSObject;
+
+    fn deref(&self) -> &JSObject {
+        &self.object
+    }
+}
+
+impl From<JSTypedArray> for JSObject {
+    fn from(array: JSTypedArray) -> Self {
+        array.object
+    }
+}
+
+impl From<JSTypedArray> for JSValue {
+    fn from(array: JSTypedArray) -> Self {
+        array.object.into()
+    }
+}
+
+impl JSContext {
+    /// Tests whether scripts in the context can create
+    /// `SharedArrayBuffer`s.
+    ///
+    /// This depends on the version of JavaScriptCore and on how it is
+    /// configured.
+    pub fn has_shared_array_buffer(&self) -> bool {
+        shared_array_buffer(self).is_some()
+    }
+}
+
+/// Gets the `SharedArrayBuffer` constructor of `ctx`, if it has one.
+fn shared_array_buffer(ctx: &JSContext) -> Option<JSObject> {
+    let constructor = ctx.global_object().get_property("SharedArrayBuffer").ok()?;
+    if !constructor.is_object() {
+        return None;
+    }
+    let constructor = constructor.as_object().ok()?;
+    constructor.is_function().then_some(constructor)
+}
+
+/// A `JSArrayBuffer` can be dereferenced to return the underlying
+/// `JSObject`.
+impl Deref for JSArrayBuffer {
+    type Target = JSObject;
+
+    fn deref(&self) -> &JSObject {
+        &self.object
+    }
+}
+
+impl From<JSArrayBuffer> for JSObject {
+    fn from(buffer: JSArrayBuffer) -> Self {
+        buffer.object
+    }
+}
+
+impl From<JSArrayBuffer> for JSValue {
+    fn from(buffer: JSArrayBuffer) -> Self {
+        buffer.object.into()
+    }
+}
+
+/// Gets the kind of typed array that `value` is.
+///
+/// Returns `JSTypedArrayType::None` if `value` is not a typed array or
+/// an `ArrayBuffer`.
+pub(crate) fn typed_array_type(value: &JSValue) -> JSTypedArrayType {
+    // This only looks at the type of the object, which never throws.
+    unsafe { sys::JSValueGetTypedArrayType(value.ctx, value.raw, ptr::null_mut()) }
+}
+
+/// Creates a `Uint8Array` holding a copy of `bytes`.
+pub(crate) fn new_uint8_array(ctx: &JSContext, bytes: &[u8]) -> Result<JSObject, JSException> {
+    let mut e: sys::JSValueRef = ptr::null_mut();
+    unsafe {
+        let raw =
+            sys::JSObjectMakeTypedArray(ctx.raw, JSTypedArrayType::Uint8Array, bytes.len(), &mut e);
+        check(ctx.raw, e)?;
+        let array = JSObject::from_raw(ctx.raw, raw);
+        if !bytes.is_empty() {
+            typed_array_bytes(&array)?.copy_from_slice(bytes);
+        }
+        Ok(array)
+    }
+}
+
+/// Gets the bytes of the elements of a typed array.
+///
+/// Fails if `array` is not a typed array. Arrays whose buffer has been
+/// detached have no bytes.
+///
+/// # Safety
+///
+/// The bytes alias the backing store of the array, which scripts can
+/// modify or detach. The slice must not be used after control returns
+/// to JavaScript.
+#[allow(clippy::mut_from_ref)]
+pub(crate) unsafe fn typed_array_bytes(array: &JSObject) -> Result<&mut [u8], JSException> {
+    let ctx = array.value.ctx;
+    let mut e: sys::JSValueRef = ptr::null_mut();
+    if matches!(
+        typed_array_type(array),
+        JSTypedArrayType::None | JSTypedArrayType::ArrayBuffer
+    ) {
+        let ctx = JSContext::from_context_ref(ctx);
+        return Err(JSException::type_error(&ctx, "Value is not a typed array"));
+    }
+    // The bytes pointer of a typed array may or may not include its
+    // offset into the buffer, depending on the version of JavaScriptCore,
+    // so go through the buffer instead.
+    let buffer = sys::JSObjectGetTypedArrayBuffer(ctx, array.raw, &mut e);
+    check(ctx, e)?;
+    let offset = sys::JSObjectGetTypedArrayByteOffset(ctx, array.raw, &mut e);
+    check(ctx, e)?;
+    let len = sys::JSObjectGetTypedArrayByteLength(ctx, array.raw, &mut e);
+    check(ctx, e)?;
+    let bytes = sys::JSObjectGetArrayBufferBytesPtr(ctx, buffer, &mut e);
+    check(ctx, e)?;
+    if bytes.is_null() || len == 0 {
+        return Ok(&mut []);
+    }
+    Ok(slice::from_raw_parts_mut(
+        (bytes as *mut u8).add(offset),
+        len,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        evaluate_script, garbage_collect, JSArrayBuffer, JSContext, JSTypedArray, JSTypedArrayType,
+        JSValue,
+    };
+    use std::ops::{Deref, DerefMut};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn new() {
+        let ctx = JSContext::default();
+        let kinds = [
+            (JSTypedArrayType::Int8Array, "Int8Array"),
+            (JSTypedArrayType::Uint8ClampedArray, "Uint8ClampedArray"),
+            (JSTypedArrayType::Uint32Array, "Uint32Array"),
+            (JSTypedArrayType::Float64Array, "Float64Array"),
+        ];
+        for (kind, name) in kinds {
+            let array = JSTypedArray::new(&ctx, kind, 3).unwrap();
+            ctx.global_object().set_property("a", array.into()).unwrap();
+            let r = evaluate_script(
+                &ctx,
+                "[a.constructor.name, a.length, a.every(x => x === 0)]",
+                None,
+                "test.js",
+                1,
+            )
+            .unwrap();
+            assert_eq!(
+                r.to_json_string(0).unwrap().to_string(),
+                format!(r#"["{}",3,true]"#, name)
+            );
+        }
+
+        let array = JSTypedArray::new(&ctx, JSTypedArrayType::Int16Array, 1).unwrap();
+        assert_eq!(array.array_type(), JSTypedArrayType::Int16Array);
+        assert_eq!(array.typed_array_type(), Some(JSTypedArrayType::Int16Array));
+
+        let e = JSTypedArray::new(&ctx, JSTypedArrayType::ArrayBuffer, 3).unwrap_err();
+        assert_eq!(e.name(), Some("TypeError"));
+        assert!(JSTypedArray::new(&ctx, JSTypedArrayType::None, 0).is_err());
+    }
+
+    #[test]
+    fn from_vec_no_copy() {
+        let ctx = JSContext::default();
+        let array = JSTypedArray::from_vec_no_copy(&ctx, vec![1, 2, 3]).unwrap();
+        ctx.global_object().set_property("a", array.into()).unwrap();
+        let r = evaluate_script(
+            &ctx,
+            "a[0] = 10; [a.constructor.name, a.reduce((x, y) => x + y)]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            r.to_json_string(0).unwrap().to_string(),
+            r#"["Uint8Array",15]"#
+        );
+
+        let empty = JSTypedArray::from_vec_no_copy(&ctx, vec![]).unwrap();
+        ctx.global_object()
+            .set_property("empty", empty.into())
+            .unwrap();
+        let r = evaluate_script(&ctx, "empty.length", None, "test.js", 1).unwrap();
+        assert_eq!(r.as_number().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn slices() {
+        let ctx = JSContext::default();
+        let v = evaluate_script(
+            &ctx,
+            "globalThis.buffer = new ArrayBuffer(8);
+             new Uint8Array(buffer).set([1, 2, 3, 4, 5, 6, 7, 8]);
+             new Uint8Array(buffer, 2, 4)",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        let mut array = v.as_typed_array().unwrap();
+        assert_eq!(unsafe { array.as_slice() }, [3, 4, 5, 6]);
+        let bytes = unsafe { array.as_mut_slice() };
+        bytes[0] = 30;
+        let r = evaluate_script(&ctx, "new Uint8Array(buffer)[2]", None, "test.js", 1).unwrap();
+        assert_eq!(r.as_number().unwrap(), 30.0);
+
+        let v = evaluate_script(&ctx, "buffer", None, "test.js", 1).unwrap();
+        assert_eq!(v.typed_array_type(), Some(JSTypedArrayType::ArrayBuffer));
+        assert_eq!(v.as_typed_array().unwrap_err().name(), Some("TypeError"));
+        assert!(JSValue::new_number(&ctx, 1.0).as_typed_array().is_err());
+    }
+
+    #[test]
+    fn elements() {
+        let ctx = JSContext::default();
+        let v = evaluate_script(
+            &ctx,
+            "globalThis.a = new Int16Array([1, -2, 3, -4]).subarray(1); a",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        let mut array = v.as_typed_array().unwrap();
+        assert_eq!(unsafe { array.as_elements::<i16>() }.unwrap(), [-2, 3, -4]);
+        for x in unsafe { array.as_mut_elements::<i16>() }.unwrap() {
+            *x *= 10;
+        }
+        let r = evaluate_script(&ctx, "a.join()", None, "test.js", 1).unwrap();
+        assert_eq!(r.as_string().unwrap().to_string(), "-20,30,-40");
+
+        let e = unsafe { array.as_elements::<u16>() }.unwrap_err();
+        assert_eq!(e.name(), Some("TypeError"));
+
+        let clamped = JSTypedArray::new(&ctx, JSTypedArrayType::Uint8ClampedArray, 2).unwrap();
+        assert_eq!(unsafe { clamped.as_elements::<u8>() }.unwrap(), [0, 0]);
+    }
+
+    #[test]
+    fn number_vecs() {
+        let ctx = JSContext::default();
+        let to_object = |script: &str| {
+            evaluate_script(&ctx, script, None, "test.js", 1)
+                .unwrap()
+                .as_object()
+                .unwrap()
+        };
+
+        let v = to_object("new Float64Array([1, 2.5, -3])");
+        assert_eq!(v.to_f64_vec().unwrap(), [1.0, 2.5, -3.0]);
+        let e = v.to_i32_vec().unwrap_err();
+        assert_eq!(e.name(), Some("TypeError"));
+        assert_eq!(e.message(), Some("Invalid element 1: Expected an integer"));
+
+        let v = to_object("new Int16Array([1, -2, 300]).subarray(1)");
+        assert_eq!(v.to_f64_vec().unwrap(), [-2.0, 300.0]);
+        assert_eq!(v.to_i32_vec().unwrap(), [-2, 300]);
+        let e = v.to_u8_vec().unwrap_err();
+        assert_eq!(e.name(), Some("RangeError"));
+        assert_eq!(
+            e.message(),
+            Some("Invalid element 0: Expected an integer from 0 to 255")
+        );
+
+        let v = to_object("new Uint8ClampedArray([0, 255])");
+        assert_eq!(v.to_u8_vec().unwrap(), [0, 255]);
+        let v = to_object("new Int32Array([7, 8])");
+        assert_eq!(v.to_i32_vec().unwrap(), [7, 8]);
+        assert_eq!(v.to_u8_vec().unwrap(), [7, 8]);
+
+        let v = to_object("[1, 2, 3]");
+        assert_eq!(v.to_f64_vec().unwrap(), [1.0, 2.0, 3.0]);
+        assert_eq!(v.to_u8_vec().unwrap(), [1, 2, 3]);
+        let e = to_object("[1, '2']").to_f64_vec().unwrap_err();
+        assert_eq!(e.message(), Some("Invalid element 1: Expected a number"));
+        let e = to_object("({length: 1, 0: 1})").to_f64_vec().unwrap_err();
+        assert_eq!(e.message(), Some("Expected an array"));
+        let e = to_object("new BigInt64Array(1)").to_f64_vec().unwrap_err();
+        assert_eq!(e.name(), Some("TypeError"));
+        assert!(to_object("new Float32Array(0)")
+            .to_f64_vec()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn new_number_arrays() {
+        let ctx = JSContext::default();
+        let check = |v: JSValue, expected: &str| {
+            ctx.global_object().set_property("a", v).unwrap();
+            let r = evaluate_script(
+                &ctx,
+                "Array.isArray(a) ? JSON.stringify(a) : 'not an array'",
+                None,
+                "test.js",
+                1,
+            )
+            .unwrap();
+            assert_eq!(r.as_string().unwrap(), expected);
+        };
+
+        check(
+            JSValue::new_array_from_f64(&ctx, &[0.5, -1.0, 1e21]).unwrap(),
+            "[0.5,-1,1e+21]",
+        );
+        check(
+            JSValue::new_array_from_i32(&ctx, &[i32::MIN, 0, i32::MAX]).unwrap(),
+            "[-2147483648,0,2147483647]",
+        );
+        check(
+            JSValue::new_array_from_u8(&ctx, &[0, 255]).unwrap(),
+            "[0,255]",
+        );
+        check(JSValue::new_array_from_f64(&ctx, &[]).unwrap(), "[]");
+
+        let numbers: Vec<i32> = (0..10_000).collect();
+        let v = JSValue::new_array_from_i32(&ctx, &numbers).unwrap();
+        assert_eq!(v.as_object().unwrap().to_i32_vec().unwrap(), numbers);
+
+        // Scripts can not get in the way.
+        evaluate_script(&ctx, "Array = null", None, "test.js", 1).unwrap();
+        let v = JSValue::new_array_from_u8(&ctx, &[1, 2]).unwrap();
+        assert!(v.is_array());
+        assert_eq!(v.to_json_string(0).unwrap(), "[1,2]");
+    }
+
+    #[test]
+    fn copies() {
+        let ctx = JSContext::default();
+        let v = evaluate_script(
+            &ctx,
+            "globalThis.a = new Uint16Array([1, 2, 3]); a",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        let array = v.as_typed_array().unwrap();
+
+        let mut bytes = [0xff; 6];
+        assert_eq!(array.copy_to(&mut bytes).unwrap(), 6);
+        assert_eq!(unsafe { array.as_slice() }, bytes);
+        let e = array.copy_to(&mut [0; 5]).unwrap_err();
+        assert_eq!(e.name(), Some("RangeError"));
+
+        array.copy_from(&[0, 1]).unwrap();
+        let r = evaluate_script(&ctx, "a.join()", None, "test.js", 1).unwrap();
+        assert_eq!(
+            r.as_string().unwrap().to_string(),
+            format!("{},2,3", u16::from_ne_bytes([0, 1]))
+        );
+        assert!(array.copy_from(&[0; 7]).is_err());
+        array.copy_from(&[]).unwrap();
+    }
+
+    #[test]
+    fn shared_array_buffers() {
+        let ctx = JSContext::default();
+        let buffer = JSArrayBuffer::from_slice(&ctx, &[1, 2]).unwrap();
+        assert!(!buffer.is_shared());
+        let bytes = unsafe { buffer.as_atomic_slice() };
+        assert_eq!(bytes[1].load(Ordering::Relaxed), 2);
+
+        if !ctx.has_shared_array_buffer() {
+            let e = JSArrayBuffer::new_shared(&ctx, 4).unwrap_err();
+            assert_eq!(e.name(), Some("TypeError"));
+            return;
+        }
+        let shared = JSArrayBuffer::new_shared(&ctx, 4).unwrap();
+        assert!(shared.is_shared());
+        assert_eq!(shared.byte_length(), 4);
+        let bytes = unsafe { shared.as_atomic_slice() };
+        bytes[3].store(7, Ordering::Relaxed);
+        ctx.global_object()
+            .set_property("shared", shared.into())
+            .unwrap();
+        let r = evaluate_script(
+            &ctx,
+            "Atomics.load(new Uint8Array(shared), 3)",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(r.as_number().unwrap(), 7.0);
+    }
+
+    #[test]
+    fn backing_stores() {
+        struct Counted(Vec<u8>, Arc<AtomicUsize>);
+
+        impl Deref for Counted {
+            type Target = [u8];
+
+            fn deref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl DerefMut for Counted {
+            fn deref_mut(&mut self) -> &mut [u8] {
+                &mut self.0
+            }
+        }
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.1.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let ctx = JSContext::default();
+        let drops = Arc::new(AtomicUsize::new(0));
+        let store = Counted(vec![0; 8], drops.clone());
+        let array =
+            JSTypedArray::from_backing_store(&ctx, JSTypedArrayType::Int32Array, store).unwrap();
+        assert_eq!(array.len(), 2);
+        let buffer = JSArrayBuffer::from_backing_store(&ctx, vec![1, 2, 3]).unwrap();
+        assert_eq!(buffer.byte_length(), 3);
+
+        // Stores that can not back an array are dropped straight away.
+        let store = Counted(vec![0; 3], drops.clone());
+        let e = JSTypedArray::from_backing_store(&ctx, JSTypedArrayType::Float64Array, store)
+            .unwrap_err();
+        assert_eq!(e.name(), Some("RangeError"));
+        let store = Counted(vec![], drops.clone());
+        assert!(
+            JSTypedArray::from_backing_store(&ctx, JSTypedArrayType::ArrayBuffer, store).is_err()
+        );
+        assert_eq!(drops.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn new_uint8_array() {
+        let ctx = JSContext::default();
+        let v = JSValue::new_uint8_array(&ctx, &[1, 2, 3]).unwrap();
+        assert_eq!(v.typed_array_type(), Some(JSTypedArrayType::Uint8Array));
+        assert_eq!(unsafe { v.as_typed_array().unwrap().as_slice() }, [1, 2, 3]);
+        let empty = JSValue::new_uint8_array(&ctx, &[]).unwrap();
+        assert!(empty.as_typed_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn detach() {
+        let ctx = JSContext::default();
+        let buffer = JSArrayBuffer::from_slice(&ctx, &[1, 2, 3, 4]).unwrap();
+        ctx.global_object()
+            .set_property("buffer", buffer.into())
+            .unwrap();
+        let v = evaluate_script(
+            &ctx,
+            "globalThis.view = new Uint8Array(buffer); buffer",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        let buffer = v.as_array_buffer().unwrap();
+        let supported = evaluate_script(
+            &ctx,
+            "typeof ArrayBuffer.prototype.transfer === 'function'",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap()
+        .as_boolean();
+        if !supported {
+            let e = buffer.detach().unwrap_err();
+            assert_eq!(e.name(), Some("TypeError"));
+            return;
+        }
+
+        let moved = buffer.transfer().unwrap();
+        assert_eq!(moved.byte_length(), 4);
+        assert_eq!(buffer.byte_length(), 0);
+        let r =
+            evaluate_script(&ctx, "[view.length, buffer.detached]", None, "test.js", 1).unwrap();
+        assert_eq!(r.to_json_string(0).unwrap().to_string(), "[0,true]");
+        assert!(buffer.detach().is_err());
+
+        // A replaced `transfer` that does not detach is caught.
+        let other = JSArrayBuffer::from_slice(&ctx, &[1]).unwrap();
+        evaluate_script(
+            &ctx,
+            "ArrayBuffer.prototype.transfer = function () { return new ArrayBuffer(1); }",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert!(other.detach().is_err());
+        assert_eq!(other.byte_length(), 1);
+    }
+
+    #[test]
+    fn geometry() {
+        let ctx = JSContext::default();
+        let v = evaluate_script(
+            &ctx,
+            "globalThis.buffer = new ArrayBuffer(24); new Float32Array(buffer, 8, 3)",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        let array = v.as_typed_array().unwrap();
+        assert_eq!(array.len(), 3);
+        assert!(!array.is_empty());
+        assert_eq!(array.byte_length(), 12);
+        assert_eq!(array.byte_offset(), 8);
+
+        let buffer = array.buffer().unwrap();
+        assert_eq!(buffer.byte_length(), 24);
+        ctx.global_object()
+            .set_property("b", buffer.into())
+            .unwrap();
+        let r = evaluate_script(&ctx, "b === buffer", None, "test.js", 1).unwrap();
+        assert!(r.as_boolean());
+
+        let v = evaluate_script(&ctx, "buffer", None, "test.js", 1).unwrap();
+        assert_eq!(v.as_array_buffer().unwrap().byte_length(), 24);
+        assert_eq!(
+            JSValue::from(array).as_array_buffer().unwrap_err().name(),
+            Some("TypeError")
+        );
+
+        let empty = JSTypedArray::new(&ctx, JSTypedArrayType::Uint16Array, 0).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.byte_length(), 0);
+    }
+
+    #[test]
+    fn array_buffers() {
+        let ctx = JSContext::default();
+        let bytes: Box<[u8]> = Box::new([1, 2, 3]);
+        let owned = JSArrayBuffer::from_boxed_slice_no_copy(&ctx, bytes).unwrap();
+        assert_eq!(owned.byte_length(), 3);
+        let copied = JSArrayBuffer::from_slice(&ctx, &[4, 5]).unwrap();
+        assert_eq!(copied.byte_length(), 2);
+        let empty = JSArrayBuffer::from_slice(&ctx, &[]).unwrap();
+        assert_eq!(empty.byte_length(), 0);
+
+        ctx.global_object().set_property("a", owned.into()).unwrap();
+        ctx.global_object()
+            .set_property("b", copied.into())
+            .unwrap();
+        let r = evaluate_script(
+            &ctx,
+            "[a instanceof ArrayBuffer, b instanceof ArrayBuffer, ...new Uint8Array(a), ...new Uint8Array(b)]",
+            None,
+            "test.js",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            r.to_json_string(0).unwrap().to_string(),
+            "[true,true,1,2,3,4,5]"
+        );
+        garbage_collect(&ctx);
+    }
+}
@@ -4,9 +4,59 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use super::exception::error_in;
 use super::{JSClass, JSContext, JSException, JSObject, JSString, JSType, JSValue};
 use crate::sys;
 use std::ptr;
+use std::str;
+
+/// Makes a `BigInt` from its decimal digits, with a leading `-` if it
+/// is negative.
+///
+/// The C API of JavaScriptCore has no functions for `BigInt`s in the
+/// versions that this crate supports. Scripts may have replaced the
+/// global `BigInt` function, so the `BigInt` is put together from
+/// literals instead, splitting the digits in halves.
+#[cfg(any(feature = "num-bigint", feature = "serde"))]
+const NEW_BIGINT: &str = r#"(function () {
+    'use strict';
+
+    const digits = {
+        __proto__: null,
+        0: 0n, 1: 1n, 2: 2n, 3: 3n, 4: 4n, 5: 5n, 6: 6n, 7: 7n, 8: 8n, 9: 9n,
+    };
+    // `powers[k]` is `10n ** (2 ** k)`.
+    const powers = { __proto__: null, 0: 10n };
+    const power = (k) => {
+        if (!(k in powers)) {
+            const p = power(k - 1);
+            powers[k] = p * p;
+        }
+        return powers[k];
+    };
+    // Converts the `2 ** k` digits of `text` that end before `end`,
+    // where the digits before `start` count as zeros.
+    const convert = (text, start, end, k) => {
+        if (end <= start) {
+            return 0n;
+        }
+        if (k === 0) {
+            return digits[text[end - 1]];
+        }
+        const half = 2 ** (k - 1);
+        return convert(text, start, end - half, k - 1) * power(k - 1) +
+            convert(text, start, end, k - 1);
+    };
+    return (text) => {
+        const start = text[0] === '-' ? 1 : 0;
+        let k = 0;
+        while (2 ** k < text.length - start) {
+            k++;
+        }
+        const n = convert(text, start, text.length, k);
+        return start === 1 ? -n : n;
+    };
+})()"#;
 
 impl JSValue {
     /// Creates a JavaScript value of the `undefined` type.
@@ -176,6 +226,51 @@ impl JSValue {
         }
     }
 
+    /// Creates a JavaScript value from JSON encoded as UTF-8 bytes.
+    ///
+    /// * `ctx`: The execution context to use.
+    /// * `json`: The bytes of the JSON text to be parsed, such as the body
+    ///   of a request.
+    ///
+    /// JavaScriptCore takes strings as UTF-16, or as UTF-8 that ends with
+    /// a `NUL`. The bytes are decoded to UTF-16 in one pass, rather than
+    /// being copied to a `String` and then a `CString` first, as
+    /// converting them for [`new_from_json`] would.
+    ///
+    /// Returns a `Result` with the `JSValue` containing the parsed value,
+    /// or a `SyntaxError` if the bytes are not UTF-8 or not valid JSON.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let ctx = JSContext::default();
+    ///
+    /// let body: &[u8] = br#"{"items": [1, 2, 3]}"#;
+    /// let v = JSValue::new_from_json_bytes(&ctx, body).expect("value");
+    /// assert!(v.as_object().expect("object").get_property("items").expect("items").is_array());
+    /// let e = JSValue::new_from_json_bytes(&ctx, b"\xff").unwrap_err();
+    /// assert_eq!(e.name(), Some("SyntaxError"));
+    /// ```
+    ///
+    /// [`new_from_json`]: #method.new_from_json
+    pub fn new_from_json_bytes(ctx: &JSContext, json: &[u8]) -> Result<Self, JSException> {
+        let json = str::from_utf8(json)
+            .map_err(|e| JSException::syntax_error(ctx, &format!("Invalid JSON: {}", e)))?;
+        let mut units = Vec::with_capacity(json.len());
+        units.extend(json.encode_utf16());
+        let string = JSString::from_utf16(&units);
+        drop(units);
+        let v = unsafe { sys::JSValueMakeFromJSONString(ctx.raw, string.raw) };
+        if v.is_null() {
+            // JavaScriptCore does not say where the JSON is invalid.
+            Err(JSException::syntax_error(ctx, "Invalid JSON"))
+        } else {
+            Ok(JSValue {
+                raw: v,
+                ctx: ctx.raw,
+            })
+        }
+    }
+
     /// Creates a JavaScript string containing the JSON serialized representation of a JS value.
     ///
     /// * `indent`: The number of spaces to indent when nesting.
@@ -183,7 +278,8 @@ impl JSValue {
     ///   The size of the indent is clamped to `10` spaces.
     ///
     /// Returns either a [`JSString`] with the result of serialization, or an
-    /// exception if one was thrown.
+    /// exception if one was thrown. Values that have no JSON form, such as
+    /// `undefined` or functions, fail with an `Error`.
     ///
     /// ```
     /// # use javascriptcore::*;
@@ -198,13 +294,10 @@ impl JSValue {
     pub fn to_json_string(&self, indent: u32) -> Result<JSString, JSException> {
         let mut e: sys::JSValueRef = ptr::null_mut();
         let v = unsafe { sys::JSValueCreateJSONString(self.ctx, self.raw, indent, &mut e) };
-        if v.is_null() {
-            Err(JSException {
-                value: JSValue {
-                    raw: e,
-                    ctx: self.ctx,
-                },
-            })
+        if !e.is_null() {
+            Err(JSException::from_raw(self.ctx, e))
+        } else if v.is_null() {
+            Err(error_in(self.ctx, "The value can not be converted to JSON"))
         } else {
             Ok(JSString { raw: v })
         }
@@ -372,6 +465,21 @@ impl JSValue {
         unsafe { sys::JSValueIsDate(self.ctx, self.raw) }
     }
 
+    /// Tests whether a JavaScript value is an instance of the global
+    /// constructor called `constructor`, as with `instanceof`.
+    pub(crate) fn is_instance_of(&self, constructor: &str) -> Result<bool, JSException> {
+        let ctx = unsafe { JSContext::from_context_ref(self.ctx) };
+        let constructor = ctx.global_object().get_property(constructor)?.as_object()?;
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        let is_instance = unsafe {
+            sys::JSValueIsInstanceOfConstructor(self.ctx, self.raw, constructor.raw, &mut e)
+        };
+        if !e.is_null() {
+            return Err(JSException::from_raw(self.ctx, e));
+        }
+        Ok(is_instance)
+    }
+
     /// Converts a JavaScript value to boolean and returns the resulting boolean.
     ///
     /// Returns the boolean result of conversion.
@@ -390,7 +498,8 @@ impl JSValue {
     /// Converts a JavaScript value to number and returns the resulting number.
     ///
     /// Returns either the numeric result of conversion, or an exception
-    /// if one was thrown.
+    /// if one was thrown, for example by `valueOf`. Values that convert
+    /// to `NaN` fail with an `Error`.
     ///
     /// ```
     /// # use javascriptcore::*;
@@ -401,20 +510,26 @@ impl JSValue {
     /// assert_eq!(n, 5.0);
     /// ```
     pub fn as_number(&self) -> Result<f64, JSException> {
-        let mut e: sys::JSValueRef = ptr::null_mut();
-        let f = unsafe { sys::JSValueToNumber(self.ctx, self.raw, &mut e) };
+        let f = self.to_number()?;
         if f.is_nan() {
-            Err(JSException {
-                value: JSValue {
-                    raw: e,
-                    ctx: self.ctx,
-                },
-            })
+            Err(error_in(self.ctx, "The value is not a number"))
         } else {
             Ok(f)
         }
     }
 
+    /// Converts a JavaScript value to number like `Number(value)` does,
+    /// so that values which are not numbers give `NaN`.
+    pub(crate) fn to_number(&self) -> Result<f64, JSException> {
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        let f = unsafe { sys::JSValueToNumber(self.ctx, self.raw, &mut e) };
+        if e.is_null() {
+            Ok(f)
+        } else {
+            Err(JSException::from_raw(self.ctx, e))
+        }
+    }
+
     /// Converts a JavaScript value to string and copies the result into a JavaScript string.
     ///
     /// Returns either [`JSString`] with the result of conversion, or an
@@ -434,12 +549,7 @@ impl JSValue {
         let mut e: sys::JSValueRef = ptr::null_mut();
         let s = unsafe { sys::JSValueToStringCopy(self.ctx, self.raw, &mut e) };
         if s.is_null() {
-            Err(JSException {
-                value: JSValue {
-                    raw: e,
-                    ctx: self.ctx,
-                },
-            })
+            Err(JSException::from_raw(self.ctx, e))
         } else {
             Ok(JSString { raw: s })
         }
@@ -462,12 +572,7 @@ impl JSValue {
         let mut e: sys::JSValueRef = ptr::null_mut();
         let o = unsafe { sys::JSValueToObject(self.ctx, self.raw, &mut e) };
         if o.is_null() {
-            Err(JSException {
-                value: JSValue {
-                    raw: e,
-                    ctx: self.ctx,
-                },
-            })
+            Err(JSException::from_raw(self.ctx, e))
         } else {
             Ok(JSObject {
                 raw: o,
@@ -484,6 +589,27 @@ impl JSValue {
 ///
 /// These are performed in the same manner as `===` (strict
 /// equality) in JavaScript.
+/// Makes a `BigInt` from its decimal digits, with a leading `-` if it
+/// is negative.
+#[cfg(any(feature = "num-bigint", feature = "serde"))]
+pub(crate) fn new_bigint(ctx: &JSContext, digits: &str) -> Result<JSValue, JSException> {
+    let f = ctx.hooks.function(ctx, NEW_BIGINT, "bigint.js")?;
+    f.call_as_function(None, &[JSValue::new_string(ctx, digits)])
+}
+
+/// Tests whether `value` is a `BigInt`, which is the only type that
+/// `JSType` does not cover.
+#[cfg(any(feature = "num-bigint", feature = "serde"))]
+pub(crate) fn is_bigint(value: &JSValue) -> bool {
+    !(value.is_undefined()
+        || value.is_null()
+        || value.is_boolean()
+        || value.is_number()
+        || value.is_string()
+        || value.is_symbol()
+        || value.is_object())
+}
+
 impl PartialEq for JSValue {
     fn eq(&self, other: &JSValue) -> bool {
         unsafe { sys::JSValueIsStrictEqual(self.ctx, self.raw, other.raw) }
@@ -492,8 +618,29 @@ impl PartialEq for JSValue {
 
 #[cfg(test)]
 mod tests {
+    use super::super::evaluate_script;
     use super::{JSContext, JSType, JSValue};
 
+    #[test]
+    fn new_from_json_bytes() {
+        let ctx = JSContext::default();
+        let v = JSValue::new_from_json_bytes(&ctx, "{\"name\": \"日本\", \"n\": [1.5]}".as_bytes())
+            .expect("value");
+        assert_eq!(
+            v.to_json_string(0).expect("json").to_string(),
+            "{\"name\":\"日本\",\"n\":[1.5]}"
+        );
+        let e = JSValue::new_from_json_bytes(&ctx, b"[1,").unwrap_err();
+        assert_eq!(e.name(), Some("SyntaxError"));
+        assert_eq!(e.message(), Some("Invalid JSON"));
+        assert!(JSValue::new_from_json_bytes(&ctx, b"").is_err());
+        let e = JSValue::new_from_json_bytes(&ctx, b"\"\xc3\"").unwrap_err();
+        assert!(e
+            .message()
+            .unwrap()
+            .starts_with("Invalid JSON: invalid utf-8"));
+    }
+
     #[test]
     fn strict_equality() {
         let ctx = JSContext::default();
@@ -653,4 +800,43 @@ mod tests {
         let v = JSValue::new_from_json(&ctx, "3 +");
         assert!(v.is_err());
     }
+
+    #[test]
+    fn conversion_exceptions() {
+        let ctx = JSContext::default();
+
+        let v = evaluate_script(
+            &ctx,
+            "({ valueOf() { throw new Error('No number'); } })",
+            None,
+            "test.js",
+            1,
+        )
+        .expect("object");
+        assert_eq!(v.as_number().unwrap_err().message(), Some("No number"));
+
+        let v = evaluate_script(
+            &ctx,
+            "({ toString() { throw new Error('No string'); } })",
+            None,
+            "test.js",
+            1,
+        )
+        .expect("object");
+        assert_eq!(v.as_string().unwrap_err().message(), Some("No string"));
+
+        let v = evaluate_script(
+            &ctx,
+            "({ toJSON() { throw new Error('No JSON'); } })",
+            None,
+            "test.js",
+            1,
+        )
+        .expect("object");
+        assert_eq!(v.to_json_string(0).unwrap_err().message(), Some("No JSON"));
+
+        let v = JSValue::new_undefined(&ctx);
+        assert!(v.to_json_string(0).unwrap_err().message().is_some());
+        assert!(v.as_number().unwrap_err().message().is_some());
+    }
 }
@@ -0,0 +1,124 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{JSContext, JSException, JSObject, JSValue};
+use crate::sys;
+use std::cell::Cell;
+use std::ptr;
+
+/// An arena that keeps temporary values from being garbage collected
+/// until it is dropped.
+///
+/// The garbage collector finds values that are on the stack, but not
+/// values that Rust keeps elsewhere, such as in a `Vec`. Such values
+/// must be protected. Protecting and unprotecting each of them is
+/// costly when there are thousands, so a scope instead holds them all
+/// in one object that is protected once, and lets go of all of them at
+/// once when it is dropped.
+///
+/// ```
+/// # use javascriptcore::*;
+/// let ctx = JSContext::default();
+/// let scope = ValueScope::new(&ctx);
+/// let rows: Vec<JSValue> = (0..1000)
+///     .map(|i| scope.keep(JSValue::new_string(&ctx, format!("row {}", i))))
+///     .collect::<Result<_, _>>()
+///     .expect("kept");
+///
+/// garbage_collect(&ctx);
+/// assert_eq!(rows[999].as_string().expect("string"), "row 999");
+/// assert_eq!(scope.len(), 1000);
+/// ```
+///
+/// Values kept by a scope must not be used after it is dropped, unless
+/// something else keeps them alive.
+pub struct ValueScope {
+    ctx: JSContext,
+    /// The object that holds the values, by index. It has no prototype,
+    /// so that scripts can not intercept the values with setters.
+    values: sys::JSObjectRef,
+    len: Cell<u32>,
+}
+
+impl ValueScope {
+    /// Creates an empty scope.
+    pub fn new(ctx: &JSContext) -> Self {
+        let values = JSObject::new(ctx);
+        values.set_prototype(&JSValue::new_null(ctx));
+        unsafe { sys::JSValueProtect(ctx.raw, values.raw) };
+        ValueScope {
+            ctx: ctx.clone(),
+            values: values.raw,
+            len: Cell::new(0),
+        }
+    }
+
+    /// Keeps `value` alive until the scope is dropped, and returns it.
+    ///
+    /// A scope can keep up to `u32::MAX` values, after which this fails
+    /// with a `RangeError`. It also fails with the exception that is
+    /// thrown if the engine runs out of memory.
+    pub fn keep(&self, value: JSValue) -> Result<JSValue, JSException> {
+        let index = self.len.get();
+        let len = index
+            .checked_add(1)
+            .ok_or_else(|| JSException::range_error(&self.ctx, "The scope is full"))?;
+        let mut e: sys::JSValueRef = ptr::null_mut();
+        unsafe {
+            sys::JSObjectSetPropertyAtIndex(self.ctx.raw, self.values, index, value.raw, &mut e);
+        }
+        if !e.is_null() {
+            return Err(JSException::from_raw(self.ctx.raw, e));
+        }
+        self.len.set(len);
+        Ok(value)
+    }
+
+    /// Gets the number of values that the scope keeps.
+    pub fn len(&self) -> usize {
+        self.len.get() as usize
+    }
+
+    /// Tests whether the scope keeps no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for ValueScope {
+    fn drop(&mut self) {
+        unsafe { sys::JSValueUnprotect(self.ctx.raw, self.values) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{garbage_collect, JSContext, JSObject, JSValue};
+    use super::ValueScope;
+
+    #[test]
+    fn keep() {
+        let ctx = JSContext::default();
+        let scope = ValueScope::new(&ctx);
+        assert!(scope.is_empty());
+
+        let objects: Vec<JSValue> = (0..1000)
+            .map(|i| {
+                let o = JSObject::new(&ctx);
+                o.set_property("i", JSValue::new_number(&ctx, f64::from(i)))
+                    .unwrap();
+                scope.keep(o.into()).unwrap()
+            })
+            .collect();
+        assert_eq!(scope.len(), 1000);
+
+        garbage_collect(&ctx);
+        for (i, o) in objects.iter().enumerate() {
+            let n = o.as_object().unwrap().get_property("i").unwrap();
+            assert_eq!(n.as_number().unwrap(), i as f64);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{JSContext, JSVirtualMachine, TerminationHandle};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+
+/// A job that runs on the thread of a virtual machine.
+pub(crate) type Job = Box<dyn FnOnce(&JSContext) + Send + 'static>;
+
+impl JSVirtualMachine {
+    /// Creates a virtual machine with a new context in a group of its
+    /// own.
+    pub fn new() -> Self {
+        JSVirtualMachine::default()
+    }
+
+    /// Creates a virtual machine that runs the context that `create`
+    /// returns.
+    ///
+    /// `create` runs on the thread of the virtual machine, since the
+    /// context can not leave the thread that creates it. Other contexts
+    /// of the same group must not be used outside of the virtual machine
+    /// while it exists. If `create` panics, so does this.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let vm = JSVirtualMachine::with_context(|| {
+    ///     ContextBuilder::new().name("worker").build().expect("context")
+    /// });
+    /// let name = vm.enter(|ctx| ctx.name().map(|n| n.to_string()));
+    /// assert_eq!(name.as_deref(), Some("worker"));
+    /// ```
+    pub fn with_context<F>(create: F) -> Self
+    where
+        F: FnOnce() -> JSContext + Send + 'static,
+    {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let (started, start) = mpsc::sync_channel(1);
+        let thread = thread::Builder::new()
+            .name("javascriptcore-vm".to_string())
+            .spawn(move || {
+                let ctx = match panic::catch_unwind(AssertUnwindSafe(create)) {
+                    Ok(ctx) => ctx,
+                    Err(p) => {
+                        started.send(Err(p)).ok();
+                        return;
+                    }
+                };
+                started.send(Ok(ctx.termination_handle())).ok();
+                for job in queue {
+                    job(&ctx);
+                }
+            })
+            .expect("The thread of the virtual machine could not be spawned");
+        let termination = match start.recv() {
+            Ok(Ok(termination)) => termination,
+            Ok(Err(p)) => panic::resume_unwind(p),
+            Err(_) => panic!("The virtual machine has stopped"),
+        };
+        JSVirtualMachine {
+            jobs: Some(jobs),
+            thread: Some(thread),
+            termination,
+        }
+    }
+
+    /// Runs `f` with exclusive access to the context of the virtual
+    /// machine, and returns its result.
+    ///
+    /// `f` runs on the thread of the virtual machine, after the jobs
+    /// of other threads that entered before, and this blocks until it
+    /// has run. `f` must not enter the same virtual machine again, as
+    /// that would deadlock. If `f` panics, so does this, and the virtual
+    /// machine goes on to run the next job.
+    ///
+    /// `f` and its result must be `Send`. This keeps JavaScript values,
+    /// which can only be used on the thread of the virtual machine, from
+    /// being returned. Convert them to Rust values instead.
+    ///
+    /// ```
+    /// # use javascriptcore::*;
+    /// let vm = JSVirtualMachine::new();
+    /// let n = vm.enter(|ctx| {
+    ///     let r = evaluate_script(ctx, "6 * 7", None, "test.js", 1).expect("evaluated");
+    ///     r.as_number().expect("number")
+    /// });
+    /// assert_eq!(n, 42.0);
+    /// ```
+    pub fn enter<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&JSContext) -> R + Send,
+        R: Send,
+    {
+        let (done, result) = mpsc::sync_channel(1);
+        let job: Box<dyn FnOnce(&JSContext) + Send + '_> = Box::new(move |ctx| {
+            done.send(panic::catch_unwind(AssertUnwindSafe(|| f(ctx))))
+                .ok();
+        });
+        // The job may borrow from the caller. That is sound because this
+        // blocks until the job has either run or been dropped, which
+        // drops the sender of `result`.
+        let job: Job = unsafe { mem::transmute(job) };
+        let sent = self.jobs.as_ref().map(|jobs| jobs.send(job).is_ok());
+        match (sent, result.recv()) {
+            (Some(true), Ok(Ok(r))) => r,
+            (Some(true), Ok(Err(p))) => panic::resume_unwind(p),
+            _ => panic!("The virtual machine has stopped"),
+        }
+    }
+
+    /// Gets a handle that can terminate the script that is running
+    /// inside the virtual machine, from any thread.
+    ///
+    /// Unlike [`enter`], this does not wait for the virtual machine to
+    /// be free, so it can be used to stop a script that runs for too long.
+    ///
+    /// [`enter`]: #method.enter
+    pub fn termination_handle(&self) -> TerminationHandle {
+        self.termination.clone()
+    }
+}
+
+impl Default for JSVirtualMachine {
+    fn default() -> Self {
+        JSVirtualMachine::with_context(JSContext::default)
+    }
+}
+
+impl Drop for JSVirtualMachine {
+    /// Lets the thread of the virtual machine run the jobs that are
+    /// left, and waits for it to drop the context.
+    fn drop(&mut self) {
+        self.jobs.take();
+        if let Some(thread) = self.thread.take() {
+            // A job that holds the last reference to the virtual machine
+            // drops it on its own thread, which can not wait for itself.
+            if thread.thread().id() != thread::current().id() {
+                thread.join().ok();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSVirtualMachine};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn shared_between_threads() {
+        let vm = Arc::new(JSVirtualMachine::new());
+        vm.enter(|ctx| {
+            evaluate_script(ctx, "var count = 0;", None, "test.js", 1).unwrap();
+        });
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let vm = vm.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        vm.enter(|ctx| {
+                            evaluate_script(ctx, "count++", None, "test.js", 1).unwrap();
+                        });
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        let count = vm.enter(|ctx| {
+            let r = evaluate_script(ctx, "count", None, "test.js", 1).unwrap();
+            r.as_number().unwrap()
+        });
+        assert_eq!(count, 400.0);
+    }
+
+    #[test]
+    fn job_panics() {
+        let vm = JSVirtualMachine::new();
+        let r = panic::catch_unwind(AssertUnwindSafe(|| vm.enter(|_| panic!("job"))));
+        assert!(r.is_err());
+        let n = vm.enter(|ctx| {
+            let r = evaluate_script(ctx, "1 + 1", None, "test.js", 1).unwrap();
+            r.as_number().unwrap()
+        });
+        assert_eq!(n, 2.0);
+    }
+
+    #[test]
+    fn terminate_while_entered() {
+        let vm = Arc::new(JSVirtualMachine::new());
+        let handle = vm.termination_handle();
+        let t = thread::spawn(move || {
+            thread::sleep(std::time::Duration::from_millis(100));
+            handle.terminate();
+        });
+        let failed =
+            vm.enter(|ctx| evaluate_script(ctx, "while (true) {}", None, "test.js", 1).is_err());
+        assert!(failed);
+        t.join().unwrap();
+    }
+}
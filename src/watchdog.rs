@@ -0,0 +1,219 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::TerminationHandle;
+use crate::sys;
+use std::collections::BTreeMap;
+use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+/// How often, in seconds of script execution time, a running script
+/// checks whether it has been asked to terminate.
+const POLL_INTERVAL: f64 = 0.01;
+
+/// The watchdogs of all live context groups, keyed by group address.
+static WATCHDOGS: Mutex<BTreeMap<usize, Weak<Watchdog>>> = Mutex::new(BTreeMap::new());
+
+/// State shared between a group's watchdog and its termination handles.
+#[derive(Debug, Default)]
+pub(crate) struct WatchdogFlags {
+    terminate: AtomicBool,
+    /// How many evaluations of the host are running in the group.
+    running: AtomicUsize,
+}
+
+/// The execution watchdog of a context group.
+///
+/// JavaScriptCore only lets a host interrupt a running script from the
+/// execution time limit callback of a context group. The watchdog installs
+/// that callback with a short limit and uses it to poll for requests made
+/// through a [`TerminationHandle`].
+///
+/// There is one watchdog per context group. Every [`JSContext`] holds a
+/// reference to the watchdog of its group, and the watchdog retains the
+/// group, so the time limit can be cleared after the last context has
+/// gone away.
+///
+/// [`JSContext`]: struct.JSContext.html
+/// [`TerminationHandle`]: struct.TerminationHandle.html
+pub(crate) struct Watchdog {
+    group: sys::JSContextGroupRef,
+    flags: Arc<WatchdogFlags>,
+    armed: AtomicBool,
+}
+
+// The group is only ever passed to JavaScriptCore functions that take
+// the API lock of the group themselves.
+unsafe impl Send for Watchdog {}
+unsafe impl Sync for Watchdog {}
+
+impl Watchdog {
+    /// Gets the watchdog of `group`, creating it if needed.
+    pub(crate) fn for_group(group: sys::JSContextGroupRef) -> Arc<Watchdog> {
+        let mut watchdogs = WATCHDOGS.lock().unwrap();
+        if let Some(watchdog) = watchdogs.get(&(group as usize)).and_then(Weak::upgrade) {
+            return watchdog;
+        }
+        let watchdog = Arc::new(Watchdog {
+            group: unsafe { sys::JSContextGroupRetain(group) },
+            flags: Arc::default(),
+            armed: AtomicBool::new(false),
+        });
+        watchdogs.insert(group as usize, Arc::downgrade(&watchdog));
+        watchdog
+    }
+
+    /// Gets a handle that can terminate scripts running in this group.
+    pub(crate) fn termination_handle(&self) -> TerminationHandle {
+        self.arm();
+        TerminationHandle {
+            flags: self.flags.clone(),
+        }
+    }
+
+    /// Marks an evaluation of the host as running in the group until
+    /// the returned guard is dropped.
+    ///
+    /// Termination requests that were made while no evaluation was
+    /// running are dropped when the outermost evaluation starts, so that
+    /// they do not stop an unrelated script.
+    pub(crate) fn enter(&self) -> Running<'_> {
+        if self.flags.running.fetch_add(1, Ordering::AcqRel) == 0 {
+            self.flags.terminate.store(false, Ordering::Release);
+        }
+        Running(&self.flags)
+    }
+
+    /// Installs the polling time limit on the group, if not done already.
+    fn arm(&self) {
+        let _watchdogs = WATCHDOGS.lock().unwrap();
+        if !self.armed.swap(true, Ordering::AcqRel) {
+            unsafe {
+                sys::JSContextGroupSetExecutionTimeLimit(
+                    self.group,
+                    POLL_INTERVAL,
+                    Some(should_terminate),
+                    Arc::as_ptr(&self.flags) as *mut c_void,
+                );
+            }
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        let mut watchdogs = WATCHDOGS.lock().unwrap();
+        let key = self.group as usize;
+        // A new watchdog may already have replaced this one.
+        if watchdogs.get(&key).is_some_and(|w| w.strong_count() == 0) {
+            watchdogs.remove(&key);
+        }
+        unsafe {
+            if *self.armed.get_mut() {
+                sys::JSContextGroupClearExecutionTimeLimit(self.group);
+            }
+            sys::JSContextGroupRelease(self.group);
+        }
+    }
+}
+
+/// Marks an evaluation as running in a group until dropped.
+pub(crate) struct Running<'a>(&'a WatchdogFlags);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.0.running.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+unsafe extern "C" fn should_terminate(ctx: sys::JSContextRef, context: *mut c_void) -> bool {
+    // Unwinding into JavaScriptCore would abort the process.
+    panic::catch_unwind(AssertUnwindSafe(|| poll(ctx, context))).unwrap_or(false)
+}
+
+/// Decides whether the script running in `ctx` is to be terminated.
+///
+/// This runs while the script is interrupted, so it must not call into
+/// the engine, which could allocate or collect garbage.
+unsafe fn poll(_ctx: sys::JSContextRef, context: *mut c_void) -> bool {
+    let flags = &*(context as *const WatchdogFlags);
+    flags.terminate.swap(false, Ordering::AcqRel)
+}
+
+impl TerminationHandle {
+    /// Requests termination of the script currently running in the
+    /// context group.
+    ///
+    /// The script is stopped the next time it polls the watchdog, and
+    /// the evaluation that started it returns an exception. Termination
+    /// can not be caught by the script.
+    ///
+    /// If no script is running, the request is dropped when the next
+    /// evaluation starts, and does not stop it.
+    pub fn terminate(&self) {
+        self.flags.terminate.store(true, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{evaluate_script, JSContext};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn terminate_from_another_thread() {
+        let ctx = JSContext::default();
+        let handle = ctx.termination_handle();
+
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            handle.terminate();
+        });
+        let r = evaluate_script(&ctx, "while (true) {}", None, "test.js", 1);
+        assert!(r.is_err());
+        t.join().unwrap();
+
+        // The context is still usable afterwards.
+        let r = evaluate_script(&ctx, "2 + 2", None, "test.js", 1);
+        assert_eq!(r.unwrap().as_number().unwrap(), 4.0);
+    }
+
+    #[test]
+    fn handles_share_a_group() {
+        let ctx = JSContext::default();
+        let a = ctx.termination_handle();
+        let b = ctx.termination_handle();
+        drop(a);
+
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            b.terminate();
+        });
+        let r = evaluate_script(&ctx, "while (true) {}", None, "test.js", 1);
+        assert!(r.is_err());
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn terminate_while_idle() {
+        let ctx = JSContext::default();
+        let handle = ctx.termination_handle();
+        handle.terminate();
+
+        // The request was made before the script started, so it is dropped.
+        let r = evaluate_script(
+            &ctx,
+            "let n = 0; for (let i = 0; i < 1e8; i++) n += i; 2 + 2",
+            None,
+            "test.js",
+            1,
+        );
+        assert_eq!(r.unwrap().as_number().unwrap(), 4.0);
+    }
+}